@@ -0,0 +1,68 @@
+//! Example that prints complete CR/LF terminated lines received on a serial port
+use log::error;
+use mio::{Events, Interest, Poll, Token};
+
+use std::env;
+use std::io;
+use std::time::Duration;
+
+use mio_serial::{DelimitedReader, Delimiter, SerialPortBuilderExt};
+
+const SERIAL_TOKEN: Token = Token(0);
+
+#[cfg(unix)]
+const DEFAULT_TTY: &str = "/dev/ttyUSB0";
+#[cfg(windows)]
+const DEFAULT_TTY: &str = "COM6";
+
+const DEFAULT_BAUD: u32 = 9600;
+const MAX_LINE_LENGTH: usize = 1024;
+
+// Print whatever is left of a line after this much silence
+const PARTIAL_LINE_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub fn main() -> io::Result<()> {
+    let mut args = env::args();
+    let path = args.nth(1).unwrap_or(DEFAULT_TTY.into());
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(1);
+
+    println!("Opening {path} at {DEFAULT_BAUD},8N1");
+    let rx = mio_serial::new(path, DEFAULT_BAUD).open_native_async()?;
+    let mut lines = DelimitedReader::new(rx, Delimiter::CrLf).with_max_length(MAX_LINE_LENGTH);
+
+    poll.registry()
+        .register(&mut lines, SERIAL_TOKEN, Interest::READABLE)?;
+
+    loop {
+        let timeout = lines
+            .partial_deadline(PARTIAL_LINE_TIMEOUT)
+            .map(|deadline| deadline.saturating_duration_since(std::time::Instant::now()));
+        poll.poll(&mut events, timeout)?;
+
+        if let Some(partial) = lines.flush_stale(PARTIAL_LINE_TIMEOUT) {
+            println!("(partial) {:?}", String::from_utf8_lossy(&partial));
+        }
+
+        for event in events.iter() {
+            if event.token() != SERIAL_TOKEN {
+                error!("Got event for unexpected token: {:?}", event);
+                continue;
+            }
+            loop {
+                match lines.read_record() {
+                    Ok(Some(line)) => println!("{:?}", String::from_utf8_lossy(&line)),
+                    Ok(None) => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                        println!("Discarding line: {e}");
+                    }
+                    Err(e) => {
+                        println!("Quitting due to read error: {e}");
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+}
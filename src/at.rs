@@ -0,0 +1,653 @@
+//! Non-blocking AT command engine
+//!
+//! [`AtEngine`] drives modems and radio modules that speak the Hayes/3GPP TS 27.007 AT
+//! command set.  Commands are queued and sent one at a time; each completes when the
+//! modem answers with a final result code (`OK`, `ERROR`, `+CME ERROR: …`, `NO CARRIER`,
+//! …) or when its timeout expires.  Lines the modem sends on its own initiative, such as
+//! `RING` or `+CMTI: "SM",3`, are unsolicited result codes (URCs) and are delivered
+//! separately through [`AtEngine::poll_urc`], even while a command is running.
+//!
+//! Two variations on the request/response pattern are supported:
+//!
+//! * **Prompts**: commands such as `AT+CMGS` answer with `> ` and wait for a payload
+//!   terminated by Ctrl-Z.  Give the payload with [`Command::with_prompt_data`] and the
+//!   engine sends it when the prompt appears.
+//! * **Data mode**: a `CONNECT` result completes the command successfully and switches the
+//!   engine into data mode.  Received bytes are then passed through untouched to
+//!   [`AtEngine::read_data`] until the application calls
+//!   [`AtEngine::enter_command_mode`], typically after the `+++` escape sequence.
+//!
+//! Like the other protocol engines in this crate the engine never blocks: call
+//! [`AtEngine::handle_io`] on every event for its token and whenever the deadline from
+//! [`AtEngine::next_deadline`] expires.  Output goes through a
+//! [`BufferedSerialStream`], so register for [`Interest::READABLE`] only; writable interest
+//! is added while bytes are waiting and removed again once they are written.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::at::{AtEngine, Command};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB2", 115200).open_native_async().unwrap();
+//! let mut modem = AtEngine::new(stream);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut modem, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! modem.submit(Command::new("AT+CSQ"));
+//! loop {
+//!     // Sends the next queued command as well as handling what arrived
+//!     modem.handle_io(Instant::now()).unwrap();
+//!     while let Some(completion) = modem.poll_completion() {
+//!         println!("{}: {:?}", completion.command, completion.result);
+//!     }
+//!     while let Some(urc) = modem.poll_urc() {
+//!         println!("unsolicited: {}", urc.line);
+//!     }
+//!     let timeout = modem
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//! }
+//! ```
+use crate::buffered::BufferedSerialStream;
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for the final result code of a command
+pub const DEFAULT_COMMAND_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Default longest line accepted from the modem
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 1024;
+
+/// URC prefixes recognised by [`AtEngine::new`]
+pub const DEFAULT_URC_PREFIXES: &[&str] = &[
+    "RING", "+CRING:", "+CLIP:", "+CREG:", "+CGREG:", "+CEREG:", "+CMTI:", "+CDSI:", "+CUSD:",
+    "+CGEV:",
+];
+
+/// Ctrl-Z, which terminates the payload sent after a `>` prompt
+pub const CTRL_Z: u8 = 0x1A;
+
+/// Why a command failed
+#[derive(Debug)]
+pub enum AtError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The modem answered `ERROR`
+    Error,
+    /// The modem answered `+CME ERROR:` with the given code or text
+    CmeError(String),
+    /// The modem answered `+CMS ERROR:` with the given code or text
+    CmsError(String),
+    /// The modem answered `NO CARRIER`
+    NoCarrier,
+    /// The modem answered `BUSY`
+    Busy,
+    /// The modem answered `NO ANSWER`
+    NoAnswer,
+    /// The modem answered `NO DIALTONE`
+    NoDialtone,
+    /// No final result code arrived in time
+    Timeout,
+}
+
+impl AtError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            AtError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for AtError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AtError::Io(e) => write!(f, "{e}"),
+            AtError::Error => write!(f, "ERROR"),
+            AtError::CmeError(code) => write!(f, "+CME ERROR: {code}"),
+            AtError::CmsError(code) => write!(f, "+CMS ERROR: {code}"),
+            AtError::NoCarrier => write!(f, "NO CARRIER"),
+            AtError::Busy => write!(f, "BUSY"),
+            AtError::NoAnswer => write!(f, "NO ANSWER"),
+            AtError::NoDialtone => write!(f, "NO DIALTONE"),
+            AtError::Timeout => write!(f, "no final result code from modem"),
+        }
+    }
+}
+
+impl std::error::Error for AtError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AtError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for AtError {
+    fn from(e: io::Error) -> Self {
+        AtError::Io(e)
+    }
+}
+
+/// A command to send to the modem
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    text: String,
+    timeout: Option<Duration>,
+    prompt_data: Option<Vec<u8>>,
+    response_prefix: Option<String>,
+}
+
+impl Command {
+    /// A command line such as `"AT+CSQ"`, sent followed by a carriage return
+    ///
+    /// For extended commands (`AT+NAME…`) information responses starting with `+NAME:`
+    /// belong to the command even if `+NAME:` is also a URC prefix, so `AT+CREG?` gets its
+    /// `+CREG:` line as a response.
+    pub fn new(text: impl Into<String>) -> Self {
+        let text = text.into();
+        let response_prefix = text
+            .get(..3)
+            .filter(|at| at.eq_ignore_ascii_case("AT+"))
+            .map(|_| {
+                let name = text[2..].split(['=', '?', ';']).next().unwrap_or_default();
+                format!("{}:", name.to_ascii_uppercase())
+            });
+        Self {
+            text,
+            timeout: None,
+            prompt_data: None,
+            response_prefix,
+        }
+    }
+
+    /// Use `timeout` instead of the engine's default command timeout
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Send `data` followed by Ctrl-Z when the modem prompts with `>`
+    #[must_use]
+    pub fn with_prompt_data(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.prompt_data = Some(data.into());
+        self
+    }
+
+    /// Claim information responses starting with `prefix` even if they look like URCs
+    #[must_use]
+    pub fn with_response_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.response_prefix = Some(prefix.into());
+        self
+    }
+
+    /// The command line, without the trailing carriage return
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+/// A successful final result
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    /// Information response lines, without echo and line terminators
+    pub lines: Vec<String>,
+    /// The final result code, `"OK"` or a `"CONNECT …"` line
+    pub result_code: String,
+}
+
+/// Identifies a submitted command
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandId(u64);
+
+/// The outcome of a submitted command
+#[derive(Debug)]
+pub struct Completion {
+    /// The id returned by [`AtEngine::submit`]
+    pub id: CommandId,
+    /// The command line as submitted
+    pub command: String,
+    /// The response, or why there is none
+    pub result: Result<Response, AtError>,
+}
+
+/// An unsolicited result code
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Urc {
+    /// The complete line, e.g. `+CMTI: "SM",3`
+    pub line: String,
+}
+
+impl Urc {
+    /// The part before the first `:`, e.g. `+CMTI`, or the whole line for `RING`
+    pub fn name(&self) -> &str {
+        self.line.split(':').next().unwrap_or_default()
+    }
+
+    /// The text after `:` with surrounding whitespace removed, if any
+    pub fn value(&self) -> Option<&str> {
+        self.line.split_once(':').map(|(_, value)| value.trim())
+    }
+}
+
+#[derive(Debug)]
+struct InFlight {
+    id: CommandId,
+    command: Command,
+    deadline: Instant,
+    lines: Vec<String>,
+}
+
+/// Non-blocking AT command engine over a serial stream
+#[derive(Debug)]
+pub struct AtEngine<S = SerialStream> {
+    inner: BufferedSerialStream<S>,
+    /// Command text and prompt payloads the write queue had no room for yet
+    backlog: Vec<u8>,
+    rx: Vec<u8>,
+    data: Vec<u8>,
+    data_mode: bool,
+    discarding: bool,
+    default_timeout: Duration,
+    max_line_length: usize,
+    urc_prefixes: Vec<String>,
+    queue: VecDeque<(CommandId, Command)>,
+    in_flight: Option<InFlight>,
+    completions: VecDeque<Completion>,
+    urcs: VecDeque<Urc>,
+    next_id: u64,
+}
+
+impl<S> AtEngine<S>
+where
+    S: Read + Write + Source,
+{
+    /// Wrap `inner`, recognising [`DEFAULT_URC_PREFIXES`]
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner: BufferedSerialStream::new(inner),
+            backlog: Vec::new(),
+            rx: Vec::new(),
+            data: Vec::new(),
+            data_mode: false,
+            discarding: false,
+            default_timeout: DEFAULT_COMMAND_TIMEOUT,
+            max_line_length: DEFAULT_MAX_LINE_LENGTH,
+            urc_prefixes: DEFAULT_URC_PREFIXES.iter().map(|p| p.to_string()).collect(),
+            queue: VecDeque::new(),
+            in_flight: None,
+            completions: VecDeque::new(),
+            urcs: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Set the timeout for commands without their own
+    #[must_use]
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default_timeout = timeout;
+        self
+    }
+
+    /// Set the longest line accepted from the modem; longer lines are discarded
+    #[must_use]
+    pub fn with_max_line_length(mut self, max_line_length: usize) -> Self {
+        self.max_line_length = max_line_length;
+        self
+    }
+
+    /// Treat lines starting with `prefix` as URCs
+    #[must_use]
+    pub fn with_urc_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.urc_prefixes.push(prefix.into());
+        self
+    }
+
+    /// The prefixes that mark a line as a URC
+    pub fn urc_prefixes(&self) -> &[String] {
+        &self.urc_prefixes
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.inner.get_mut()
+    }
+
+    /// Consume the engine, returning the underlying stream
+    ///
+    /// Bytes not yet written are lost.
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    /// Queue `command`
+    pub fn submit(&mut self, command: Command) -> CommandId {
+        let id = CommandId(self.next_id);
+        self.next_id += 1;
+        self.queue.push_back((id, command));
+        id
+    }
+
+    /// Number of commands queued or waiting for a final result code
+    pub fn pending(&self) -> usize {
+        self.queue.len() + usize::from(self.in_flight.is_some())
+    }
+
+    /// Returns `true` if no commands are queued or running
+    pub fn is_idle(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Take the next finished command
+    pub fn poll_completion(&mut self) -> Option<Completion> {
+        self.completions.pop_front()
+    }
+
+    /// Take the next unsolicited result code
+    pub fn poll_urc(&mut self) -> Option<Urc> {
+        self.urcs.pop_front()
+    }
+
+    /// The deadline of the running command, if any
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.in_flight.as_ref().map(|f| f.deadline)
+    }
+
+    /// Returns `true` after a `CONNECT` result until [`enter_command_mode`](Self::enter_command_mode)
+    pub fn is_data_mode(&self) -> bool {
+        self.data_mode
+    }
+
+    /// Copy received data-mode bytes into `buf`, returning how many were copied
+    pub fn read_data(&mut self, buf: &mut [u8]) -> usize {
+        let n = buf.len().min(self.data.len());
+        buf[..n].copy_from_slice(&self.data[..n]);
+        self.data.drain(..n);
+        n
+    }
+
+    /// Queue raw bytes for transmission, e.g. payload in data mode or an escape sequence
+    ///
+    /// Returns the number of bytes accepted, which is less than `data.len()`, possibly zero,
+    /// while the write queue is at its high-water mark.  Offer the rest again after the next
+    /// writable event.
+    pub fn send_data(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.flush_backlog()?;
+        if !self.backlog.is_empty() {
+            return Ok(0);
+        }
+        match self.inner.queue_write(data) {
+            Ok(n) => Ok(n),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(0),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Leave data mode and resume line parsing and command processing
+    ///
+    /// The engine does not generate the escape sequence itself, since its guard times are
+    /// modem specific.  Data bytes not yet taken with [`read_data`](Self::read_data) are
+    /// kept.
+    pub fn enter_command_mode(&mut self) {
+        self.data_mode = false;
+    }
+
+    /// Drive the engine: write queued bytes, read and classify lines, handle prompts and
+    /// timeouts, and start the next command
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; command failures are reported through
+    /// the affected command's [`Completion`].
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.inner.on_writable()?;
+        self.flush_backlog()?;
+        self.fill()?;
+        self.process();
+        if let Some(ref f) = self.in_flight {
+            if now >= f.deadline {
+                let f = self.in_flight.take().unwrap();
+                self.complete(f, Err(AtError::Timeout));
+            }
+        }
+        self.start_next(now);
+        self.flush_backlog()
+    }
+
+    /// Move as much of the backlog into the write queue as it accepts
+    fn flush_backlog(&mut self) -> io::Result<()> {
+        while !self.backlog.is_empty() {
+            match self.inner.queue_write(&self.backlog) {
+                Ok(n) => {
+                    self.backlog.drain(..n);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => return Ok(()),
+                Ok(n) if self.data_mode => self.data.extend_from_slice(&buf[..n]),
+                Ok(n) => self.rx.extend_from_slice(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn process(&mut self) {
+        while !self.data_mode {
+            // The rest of an overlong line runs up to its terminator, which may be the
+            // first byte received
+            if self.discarding {
+                match self.rx.iter().position(|&b| b == b'\r' || b == b'\n') {
+                    Some(end) => {
+                        self.rx.drain(..=end);
+                        self.discarding = false;
+                    }
+                    None => {
+                        self.rx.clear();
+                        return;
+                    }
+                }
+            }
+
+            let start = self
+                .rx
+                .iter()
+                .position(|&b| b != b'\r' && b != b'\n')
+                .unwrap_or(self.rx.len());
+            self.rx.drain(..start);
+            if self.rx.is_empty() {
+                return;
+            }
+
+            if self.rx[0] == b'>' && self.awaiting_prompt() {
+                let end = if self.rx.get(1) == Some(&b' ') { 2 } else { 1 };
+                self.rx.drain(..end);
+                let data = self.in_flight.as_mut().unwrap().command.prompt_data.take();
+                let mut data = data.unwrap_or_default();
+                data.push(CTRL_Z);
+                self.backlog.extend_from_slice(&data);
+                continue;
+            }
+
+            let end = match self.rx.iter().position(|&b| b == b'\r' || b == b'\n') {
+                Some(end) => end,
+                None => {
+                    if self.rx.len() > self.max_line_length {
+                        log::debug!("discarding overlong line from modem");
+                        self.rx.clear();
+                        self.discarding = true;
+                    }
+                    return;
+                }
+            };
+            // Take the LF of a CR LF pair too, so none is left over after CONNECT
+            let crlf = self.rx[end] == b'\r' && self.rx.get(end + 1) == Some(&b'\n');
+            let line: Vec<u8> = self.rx.drain(..=end + usize::from(crlf)).collect();
+            let line = String::from_utf8_lossy(&line[..end]).trim_end().to_string();
+            self.on_line(line);
+        }
+        // Bytes after CONNECT belong to the data stream
+        self.data.append(&mut self.rx);
+    }
+
+    fn awaiting_prompt(&self) -> bool {
+        matches!(self.in_flight, Some(ref f) if f.command.prompt_data.is_some())
+    }
+
+    fn is_urc(&self, line: &str) -> bool {
+        self.urc_prefixes
+            .iter()
+            .any(|p| line.starts_with(p.as_str()))
+    }
+
+    fn on_line(&mut self, line: String) {
+        let f = match self.in_flight {
+            Some(ref mut f) => f,
+            None => {
+                self.urcs.push_back(Urc { line });
+                return;
+            }
+        };
+
+        if line == f.command.text {
+            // Command echo
+            return;
+        }
+        let claimed = f
+            .command
+            .response_prefix
+            .as_deref()
+            .is_some_and(|prefix| line.starts_with(prefix));
+        if !claimed && self.is_urc(&line) {
+            self.urcs.push_back(Urc { line });
+            return;
+        }
+
+        let result = match final_result(&line) {
+            Some(result) => result,
+            None => {
+                self.in_flight.as_mut().unwrap().lines.push(line);
+                return;
+            }
+        };
+        let f = self.in_flight.take().unwrap();
+        let result = result.map(|result_code| {
+            if result_code.starts_with("CONNECT") {
+                self.data_mode = true;
+            }
+            Response {
+                lines: Vec::new(),
+                result_code,
+            }
+        });
+        self.complete(f, result);
+    }
+
+    fn complete(&mut self, f: InFlight, result: Result<Response, AtError>) {
+        let lines = f.lines;
+        self.completions.push_back(Completion {
+            id: f.id,
+            command: f.command.text,
+            result: result.map(|response| Response { lines, ..response }),
+        });
+    }
+
+    fn start_next(&mut self, now: Instant) {
+        if self.in_flight.is_some() || self.data_mode {
+            return;
+        }
+        let (id, command) = match self.queue.pop_front() {
+            Some(next) => next,
+            None => return,
+        };
+        self.backlog.extend_from_slice(command.text.as_bytes());
+        self.backlog.push(b'\r');
+        let timeout = command.timeout.unwrap_or(self.default_timeout);
+        self.in_flight = Some(InFlight {
+            id,
+            command,
+            deadline: now + timeout,
+            lines: Vec::new(),
+        });
+    }
+}
+
+/// Classify `line` as a final result code
+fn final_result(line: &str) -> Option<Result<String, AtError>> {
+    let result = match line {
+        "OK" => Ok(line.to_string()),
+        "ERROR" => Err(AtError::Error),
+        "NO CARRIER" => Err(AtError::NoCarrier),
+        "BUSY" => Err(AtError::Busy),
+        "NO ANSWER" => Err(AtError::NoAnswer),
+        "NO DIALTONE" => Err(AtError::NoDialtone),
+        _ if line == "CONNECT" || line.starts_with("CONNECT ") => Ok(line.to_string()),
+        _ => {
+            if let Some(code) = line.strip_prefix("+CME ERROR:") {
+                Err(AtError::CmeError(code.trim().to_string()))
+            } else if let Some(code) = line.strip_prefix("+CMS ERROR:") {
+                Err(AtError::CmsError(code.trim().to_string()))
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(result)
+}
+
+impl<S> Source for AtEngine<S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
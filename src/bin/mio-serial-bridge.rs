@@ -0,0 +1,170 @@
+//! Serial port bridge to raw TCP clients and UDP destinations
+//!
+//! Maps serial ports to TCP ports or UDP destinations as described in a configuration file.
+//! Run with `--help` for the options and the file format.
+use mio::net::{TcpListener, UdpSocket};
+use mio_serial::bridge::config::{self, PortConfig, SerialConfig};
+use mio_serial::bridge::{Bridge, BridgePort, UdpPort};
+use mio_serial::SerialStream;
+use std::net::SocketAddr;
+use std::process;
+
+const USAGE: &str = "\
+Usage: mio-serial-bridge [OPTIONS] <CONFIG>
+
+Bridge serial ports to TCP clients or UDP destinations as described in CONFIG.
+
+Options:
+      --check                 Check the configuration and exit
+  -h, --help                  Print this help
+
+CONFIG has one section per serial port, with `key = value` lines.  Every section takes
+the serial settings:
+
+  device = /dev/ttyUSB0       Serial device (required)
+  baud = 115200               Baud rate [default: 9600]
+  data-bits = 8               5, 6, 7 or 8 [default: 8]
+  parity = none               none, even or odd [default: none]
+  stop-bits = 1               1 or 2 [default: 1]
+  flow-control = none         none, software or hardware [default: none]
+
+A [port] section serves raw TCP clients:
+
+  listen = 0.0.0.0:7000       Address for read-write clients (required)
+  monitor = 0.0.0.0:7001      Address for read-only monitor clients
+  max-clients = 1             Simultaneous read-write clients [default: 1]
+  max-monitors = 4            Simultaneous monitor clients [default: 4]
+  policy = reject-new         reject-new or kick-old, for clients beyond the limit
+  idle-timeout = 600          Seconds without traffic before a client is closed
+                              [default: 0, never]
+
+A [udp] section sends datagrams:
+
+  destination = 10.0.0.255:10110
+                              Unicast, broadcast or multicast address; repeat for
+                              more destinations (at least one required)
+  bind = 0.0.0.0:10110        Local address [default: 0.0.0.0:0]
+  records = crlf              Send one record per datagram, ending in lf, crlf or cr
+                              [default: one datagram per read]
+  max-datagram = 1472         Largest datagram to send [default: 1472]
+  receive = false             Write datagrams arriving on the local address to the
+                              port [default: false]
+  multicast-ttl = 1           Time to live for multicast datagrams [default: 1]
+
+Blank lines and lines starting with # are ignored.";
+
+fn parse_args() -> Result<(String, bool), String> {
+    let mut path = None;
+    let mut check = false;
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "--check" => check = true,
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+    Ok((path.ok_or("missing configuration file")?, check))
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
+
+fn main() {
+    let (path, check) = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{USAGE}");
+        process::exit(2);
+    });
+    let text = std::fs::read_to_string(&path)
+        .unwrap_or_else(|e| fail(format!("unable to read {path}: {e}")));
+    let ports = config::parse(&text).unwrap_or_else(|e| fail(format!("{path}: {e}")));
+    if check {
+        println!("{path}: {} port(s)", ports.len());
+        return;
+    }
+
+    let mut bridge = Bridge::new().unwrap_or_else(|e| fail(e.to_string()));
+    for config in ports {
+        match config {
+            PortConfig::Tcp {
+                serial,
+                listen,
+                monitor,
+                max_clients,
+                max_monitors,
+                policy,
+                idle_timeout,
+            } => {
+                let stream = open(&serial);
+                let bind = |addr: SocketAddr| {
+                    TcpListener::bind(addr)
+                        .unwrap_or_else(|e| fail(format!("unable to listen on {addr}: {e}")))
+                };
+                let mut port = BridgePort::new(stream, bind(listen))
+                    .with_max_clients(max_clients)
+                    .with_max_monitors(max_monitors)
+                    .with_policy(policy)
+                    .with_idle_timeout(idle_timeout);
+                if let Some(monitor) = monitor {
+                    port = port.with_monitor(bind(monitor));
+                }
+                bridge.add(port).unwrap_or_else(|e| fail(e.to_string()));
+
+                match monitor {
+                    Some(monitor) => eprintln!(
+                        "{}: clients on {listen}, monitors on {monitor}",
+                        serial.device
+                    ),
+                    None => eprintln!("{}: clients on {listen}", serial.device),
+                }
+            }
+            PortConfig::Udp {
+                serial,
+                bind,
+                destinations,
+                records,
+                max_datagram,
+                receive,
+                multicast_ttl,
+            } => {
+                let stream = open(&serial);
+                let socket = UdpSocket::bind(bind)
+                    .unwrap_or_else(|e| fail(format!("unable to bind {bind}: {e}")));
+                if let Some(ttl) = multicast_ttl {
+                    socket
+                        .set_multicast_ttl_v4(ttl)
+                        .unwrap_or_else(|e| fail(format!("unable to set multicast TTL: {e}")));
+                }
+                let mut port = UdpPort::new(stream, socket)
+                    .with_max_datagram(max_datagram)
+                    .with_receive(receive);
+                for destination in &destinations {
+                    port = port.with_destination(*destination);
+                }
+                if let Some(delimiter) = records {
+                    port = port.with_records(delimiter);
+                }
+                bridge.add_udp(port).unwrap_or_else(|e| fail(e.to_string()));
+
+                let list: Vec<String> = destinations.iter().map(|d| d.to_string()).collect();
+                eprintln!("{}: datagrams to {}", serial.device, list.join(", "));
+            }
+        }
+    }
+
+    if let Err(e) = bridge.run() {
+        fail(e.to_string());
+    }
+}
+
+fn open(serial: &SerialConfig) -> SerialStream {
+    serial
+        .open()
+        .unwrap_or_else(|e| fail(format!("unable to open {}: {e}", serial.device)))
+}
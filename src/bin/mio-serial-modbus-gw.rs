@@ -0,0 +1,145 @@
+//! Modbus TCP to Modbus RTU gateway
+//!
+//! Listens for Modbus TCP connections and forwards requests to the devices on a serial
+//! line.  Run with `--help` for the options.
+use mio::net::TcpListener;
+use mio_serial::modbus::{Gateway, RtuClient};
+use mio_serial::{Parity, SerialPortBuilderExt, StopBits};
+use std::net::SocketAddr;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: mio-serial-modbus-gw [OPTIONS] <DEVICE>
+
+Forward Modbus TCP requests to the Modbus RTU devices on DEVICE.
+
+Options:
+  -l, --listen <ADDR>         Address to listen on [default: 0.0.0.0:502]
+  -b, --baud <RATE>           Baud rate [default: 19200]
+      --parity <PARITY>       none, even or odd [default: even]
+      --stop-bits <BITS>      1 or 2 [default: 1]
+  -t, --timeout <MS>          Response timeout in milliseconds [default: 1000]
+      --max-connections <N>   Simultaneous TCP connections [default: 16]
+  -h, --help                  Print this help";
+
+struct Options {
+    device: String,
+    listen: SocketAddr,
+    baud_rate: u32,
+    parity: Parity,
+    stop_bits: StopBits,
+    timeout: Duration,
+    max_connections: usize,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut device = None;
+    let mut options = Options {
+        device: String::new(),
+        listen: "0.0.0.0:502".parse().unwrap(),
+        baud_rate: 19200,
+        parity: Parity::Even,
+        stop_bits: StopBits::One,
+        timeout: Duration::from_millis(1000),
+        max_connections: mio_serial::modbus::gateway::DEFAULT_MAX_CONNECTIONS,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {name}"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "-l" | "--listen" => {
+                options.listen = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid listen address: {e}"))?
+            }
+            "-b" | "--baud" => {
+                options.baud_rate = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid baud rate: {e}"))?
+            }
+            "--parity" => {
+                options.parity = match value(&arg)?.as_str() {
+                    "none" => Parity::None,
+                    "even" => Parity::Even,
+                    "odd" => Parity::Odd,
+                    other => return Err(format!("invalid parity: {other}")),
+                }
+            }
+            "--stop-bits" => {
+                options.stop_bits = match value(&arg)?.as_str() {
+                    "1" => StopBits::One,
+                    "2" => StopBits::Two,
+                    other => return Err(format!("invalid stop bits: {other}")),
+                }
+            }
+            "-t" | "--timeout" => {
+                let ms = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid timeout: {e}"))?;
+                options.timeout = Duration::from_millis(ms);
+            }
+            "--max-connections" => {
+                options.max_connections = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid connection limit: {e}"))?
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if device.is_none() => device = Some(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    options.device = device.ok_or("missing serial device")?;
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    let stream = mio_serial::new(&options.device, options.baud_rate)
+        .parity(options.parity)
+        .stop_bits(options.stop_bits)
+        .open_native_async()
+        .unwrap_or_else(|e| {
+            eprintln!("error: unable to open {}: {e}", options.device);
+            process::exit(1);
+        });
+    let client = RtuClient::new_rtu(stream)
+        .unwrap_or_else(|e| {
+            eprintln!("error: unable to read settings of {}: {e}", options.device);
+            process::exit(1);
+        })
+        .with_response_timeout(options.timeout);
+
+    let listener = TcpListener::bind(options.listen).unwrap_or_else(|e| {
+        eprintln!("error: unable to listen on {}: {e}", options.listen);
+        process::exit(1);
+    });
+    let mut gateway = Gateway::new(listener, client)
+        .unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            process::exit(1);
+        })
+        .with_max_connections(options.max_connections);
+
+    match gateway.local_addr() {
+        Ok(addr) => eprintln!("listening on {addr}, forwarding to {}", options.device),
+        Err(_) => eprintln!("forwarding to {}", options.device),
+    }
+    if let Err(e) = gateway.run() {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
@@ -0,0 +1,108 @@
+//! RFC 2217 serial port server
+//!
+//! Shares a serial port over TCP with a client that can also change its settings and
+//! control lines.  Run with `--help` for the options.
+use mio::net::TcpListener;
+use mio_serial::rfc2217::Server;
+use mio_serial::SerialPortBuilderExt;
+use std::net::SocketAddr;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "\
+Usage: mio-serial-rfc2217 [OPTIONS] <DEVICE>
+
+Share DEVICE with an RFC 2217 (Telnet COM port control) client.
+
+Options:
+  -l, --listen <ADDR>         Address to listen on [default: 0.0.0.0:2217]
+  -b, --baud <RATE>           Baud rate until the client sets one [default: 9600]
+      --modem-poll <MS>       Modem line poll interval in milliseconds [default: 100]
+  -h, --help                  Print this help";
+
+struct Options {
+    device: String,
+    listen: SocketAddr,
+    baud_rate: u32,
+    modem_poll: Duration,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut device = None;
+    let mut options = Options {
+        device: String::new(),
+        listen: "0.0.0.0:2217".parse().unwrap(),
+        baud_rate: 9600,
+        modem_poll: mio_serial::rfc2217::server::DEFAULT_MODEM_POLL_INTERVAL,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {name}"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "-l" | "--listen" => {
+                options.listen = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid listen address: {e}"))?
+            }
+            "-b" | "--baud" => {
+                options.baud_rate = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid baud rate: {e}"))?
+            }
+            "--modem-poll" => {
+                let ms = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid poll interval: {e}"))?;
+                options.modem_poll = Duration::from_millis(ms);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if device.is_none() => device = Some(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    options.device = device.ok_or("missing serial device")?;
+    Ok(options)
+}
+
+fn main() {
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    let stream = mio_serial::new(&options.device, options.baud_rate)
+        .open_native_async()
+        .unwrap_or_else(|e| {
+            eprintln!("error: unable to open {}: {e}", options.device);
+            process::exit(1);
+        });
+
+    let listener = TcpListener::bind(options.listen).unwrap_or_else(|e| {
+        eprintln!("error: unable to listen on {}: {e}", options.listen);
+        process::exit(1);
+    });
+    let mut server = Server::new(listener, stream)
+        .unwrap_or_else(|e| {
+            eprintln!("error: {e}");
+            process::exit(1);
+        })
+        .with_modem_poll_interval(options.modem_poll);
+
+    match server.local_addr() {
+        Ok(addr) => eprintln!("listening on {addr}, sharing {}", options.device),
+        Err(_) => eprintln!("sharing {}", options.device),
+    }
+    if let Err(e) = server.run() {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
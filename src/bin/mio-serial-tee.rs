@@ -0,0 +1,141 @@
+//! Transparent serial sniffer
+//!
+//! Puts a pseudo-terminal in front of a serial device and logs the traffic between the
+//! application using the terminal and the device.  Run with `--help` for the options.
+use std::process;
+
+const USAGE: &str = "\
+Usage: mio-serial-tee [OPTIONS] <DEVICE>
+
+Create a pseudo-terminal for an application to open instead of DEVICE, relay between the
+two, and log both directions as a hex dump: `>` is application to device, `<` is device to
+application.  Baud rate changes made by the application are carried out on DEVICE.
+
+Options:
+  -b, --baud <RATE>           Baud rate until the application sets one [default: 9600]
+  -l, --link <PATH>           Create a symbolic link to the terminal at PATH, replacing
+                              an earlier link
+  -o, --output <FILE>         Append the log to FILE instead of standard output
+  -c, --capture <FILE>        Also record the session to FILE as a capture for replay
+      --settings-poll <MS>    Terminal settings poll interval in milliseconds
+                              [default: 100]
+  -h, --help                  Print this help";
+
+#[cfg(unix)]
+struct Options {
+    device: String,
+    baud_rate: u32,
+    link: Option<String>,
+    output: Option<String>,
+    capture: Option<String>,
+    settings_poll: std::time::Duration,
+}
+
+#[cfg(unix)]
+fn parse_args() -> Result<Options, String> {
+    let mut device = None;
+    let mut options = Options {
+        device: String::new(),
+        baud_rate: 9600,
+        link: None,
+        output: None,
+        capture: None,
+        settings_poll: mio_serial::tee::DEFAULT_SETTINGS_POLL_INTERVAL,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {name}"))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                process::exit(0);
+            }
+            "-b" | "--baud" => {
+                options.baud_rate = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid baud rate: {e}"))?
+            }
+            "-l" | "--link" => options.link = Some(value(&arg)?),
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "-c" | "--capture" => options.capture = Some(value(&arg)?),
+            "--settings-poll" => {
+                let ms = value(&arg)?
+                    .parse()
+                    .map_err(|e| format!("invalid poll interval: {e}"))?;
+                options.settings_poll = std::time::Duration::from_millis(ms);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {arg}")),
+            _ if device.is_none() => device = Some(arg),
+            _ => return Err(format!("unexpected argument: {arg}")),
+        }
+    }
+
+    options.device = device.ok_or("missing serial device")?;
+    Ok(options)
+}
+
+#[cfg(unix)]
+fn main() {
+    use mio_serial::tee::SerialTee;
+    use mio_serial::SerialPortBuilderExt;
+
+    let options = parse_args().unwrap_or_else(|e| {
+        eprintln!("error: {e}\n\n{USAGE}");
+        process::exit(2);
+    });
+
+    let stream = mio_serial::new(&options.device, options.baud_rate)
+        .open_native_async()
+        .unwrap_or_else(|e| fail(format!("unable to open {}: {e}", options.device)));
+    let tee = SerialTee::new(stream)
+        .unwrap_or_else(|e| fail(format!("unable to create a pseudo-terminal: {e}")))
+        .with_settings_poll_interval(options.settings_poll);
+    let tee = match &options.capture {
+        Some(path) => {
+            let file = std::fs::File::create(path)
+                .unwrap_or_else(|e| fail(format!("unable to create {path}: {e}")));
+            tee.with_capture(file)
+        }
+        None => tee,
+    };
+    let mut tee = match &options.output {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .unwrap_or_else(|e| fail(format!("unable to open {path}: {e}")));
+            tee.with_log(file)
+        }
+        None => tee.with_log(std::io::stdout()),
+    };
+
+    let slave = tee.slave_path().unwrap_or_default();
+    match &options.link {
+        Some(link) => {
+            tee.create_link(link)
+                .unwrap_or_else(|e| fail(format!("unable to create {link}: {e}")));
+            eprintln!("{link} -> {slave} relayed to {}", options.device);
+        }
+        None => eprintln!("{slave} relayed to {}", options.device),
+    }
+
+    if let Err(e) = tee.run() {
+        fail(e.to_string());
+    }
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("error: {message}");
+    process::exit(1);
+}
+
+#[cfg(not(unix))]
+fn main() {
+    let _ = USAGE;
+    fail("mio-serial-tee needs pseudo-terminals, which this platform lacks".to_string());
+}
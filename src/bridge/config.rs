@@ -0,0 +1,343 @@
+//! Configuration files for the `mio-serial-bridge` binary
+//!
+//! A configuration has one section per serial port, each a `[port]` header for raw TCP
+//! clients or a `[udp]` header for datagrams, followed by `key = value` lines.  Every
+//! section takes the serial settings `device` (required), `baud`, `data-bits`, `parity`,
+//! `stop-bits` and `flow-control`.  A `[port]` section takes `listen` (required),
+//! `monitor`, `max-clients`, `max-monitors`, `policy` and `idle-timeout`; a `[udp]` section
+//! takes `destination` (at least one), `bind`, `records`, `max-datagram`, `receive` and
+//! `multicast-ttl`.  Blank lines and lines starting with `#` are ignored.
+//!
+//! [`parse`] checks the whole file and returns one [`PortConfig`] per section.
+//!
+//! ## Example
+//!
+//! ```
+//! use mio_serial::bridge::config::{self, PortConfig};
+//!
+//! let text = "[port]\ndevice = /dev/ttyUSB0\nlisten = 0.0.0.0:7000\n";
+//! let ports = config::parse(text).unwrap();
+//! assert!(matches!(ports[0], PortConfig::Tcp { max_clients: 1, .. }));
+//!
+//! let err = config::parse("[port]\ndevice = /dev/ttyUSB0\nbaud = fast\n").unwrap_err();
+//! assert_eq!(err.line, Some(3));
+//! ```
+use super::{ConnectionPolicy, DEFAULT_MAX_CLIENTS, DEFAULT_MAX_DATAGRAM, DEFAULT_MAX_MONITORS};
+use crate::{
+    DataBits, Delimiter, FlowControl, Parity, SerialPortBuilderExt, SerialStream, StopBits,
+};
+use std::fmt;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Baud rate used when a section has no `baud` key
+pub const DEFAULT_BAUD_RATE: u32 = 9600;
+
+/// A mistake in a configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// Line the mistake was found on, if it belongs to one
+    pub line: Option<usize>,
+    /// What is wrong
+    pub message: String,
+}
+
+impl ConfigError {
+    fn new(line: Option<usize>, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {line}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Serial settings of a section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SerialConfig {
+    /// Serial device
+    pub device: String,
+    /// Baud rate
+    pub baud_rate: u32,
+    /// Data bits
+    pub data_bits: DataBits,
+    /// Parity
+    pub parity: Parity,
+    /// Stop bits
+    pub stop_bits: StopBits,
+    /// Flow control
+    pub flow_control: FlowControl,
+}
+
+impl SerialConfig {
+    /// Open the device with these settings
+    pub fn open(&self) -> crate::Result<SerialStream> {
+        crate::new(&self.device, self.baud_rate)
+            .data_bits(self.data_bits)
+            .parity(self.parity)
+            .stop_bits(self.stop_bits)
+            .flow_control(self.flow_control)
+            .open_native_async()
+    }
+}
+
+/// One section of a configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortConfig {
+    /// A `[port]` section, for a [`BridgePort`](super::BridgePort)
+    Tcp {
+        /// Serial settings
+        serial: SerialConfig,
+        /// Address for read-write clients
+        listen: SocketAddr,
+        /// Address for read-only monitor clients
+        monitor: Option<SocketAddr>,
+        /// Simultaneous read-write clients
+        max_clients: usize,
+        /// Simultaneous monitor clients
+        max_monitors: usize,
+        /// What to do with clients beyond the limit
+        policy: ConnectionPolicy,
+        /// Time without traffic before a client is closed
+        idle_timeout: Option<Duration>,
+    },
+    /// A `[udp]` section, for a [`UdpPort`](super::UdpPort)
+    Udp {
+        /// Serial settings
+        serial: SerialConfig,
+        /// Local address
+        bind: SocketAddr,
+        /// Where datagrams go
+        destinations: Vec<SocketAddr>,
+        /// Send one record per datagram instead of one read
+        records: Option<Delimiter>,
+        /// Largest datagram to send
+        max_datagram: usize,
+        /// Write datagrams arriving on `bind` to the port
+        receive: bool,
+        /// Time to live for multicast datagrams
+        multicast_ttl: Option<u32>,
+    },
+}
+
+/// A section being read; required keys are checked at the end
+#[derive(Default)]
+struct Section {
+    line: usize,
+    udp: bool,
+    device: Option<String>,
+    baud_rate: Option<u32>,
+    data_bits: Option<DataBits>,
+    parity: Option<Parity>,
+    stop_bits: Option<StopBits>,
+    flow_control: Option<FlowControl>,
+    listen: Option<SocketAddr>,
+    monitor: Option<SocketAddr>,
+    max_clients: Option<usize>,
+    max_monitors: Option<usize>,
+    policy: Option<ConnectionPolicy>,
+    idle_timeout: Option<Duration>,
+    bind: Option<SocketAddr>,
+    destinations: Vec<SocketAddr>,
+    records: Option<Delimiter>,
+    max_datagram: Option<usize>,
+    receive: Option<bool>,
+    multicast_ttl: Option<u32>,
+}
+
+impl Section {
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, String>
+        where
+            T::Err: fmt::Display,
+        {
+            value
+                .parse()
+                .map_err(|e| format!("invalid {key} {value:?}: {e}"))
+        }
+        let invalid = || format!("invalid {key} {value:?}");
+        let name = if self.udp { "[udp]" } else { "[port]" };
+        let tcp_only = [
+            "listen",
+            "monitor",
+            "max-clients",
+            "max-monitors",
+            "policy",
+            "idle-timeout",
+        ];
+        let udp_only = [
+            "bind",
+            "destination",
+            "records",
+            "max-datagram",
+            "receive",
+            "multicast-ttl",
+        ];
+        let allowed = if self.udp { &udp_only } else { &tcp_only };
+        if (tcp_only.contains(&key) || udp_only.contains(&key)) && !allowed.contains(&key) {
+            return Err(format!("{key:?} does not belong in {name}"));
+        }
+
+        match key {
+            "device" => self.device = Some(value.to_string()),
+            "baud" => self.baud_rate = Some(parse(key, value)?),
+            "data-bits" => {
+                self.data_bits = Some(match value {
+                    "5" => DataBits::Five,
+                    "6" => DataBits::Six,
+                    "7" => DataBits::Seven,
+                    "8" => DataBits::Eight,
+                    _ => return Err(invalid()),
+                })
+            }
+            "parity" => {
+                self.parity = Some(match value {
+                    "none" => Parity::None,
+                    "even" => Parity::Even,
+                    "odd" => Parity::Odd,
+                    _ => return Err(invalid()),
+                })
+            }
+            "stop-bits" => {
+                self.stop_bits = Some(match value {
+                    "1" => StopBits::One,
+                    "2" => StopBits::Two,
+                    _ => return Err(invalid()),
+                })
+            }
+            "flow-control" => {
+                self.flow_control = Some(match value {
+                    "none" => FlowControl::None,
+                    "software" => FlowControl::Software,
+                    "hardware" => FlowControl::Hardware,
+                    _ => return Err(invalid()),
+                })
+            }
+            "listen" => self.listen = Some(parse(key, value)?),
+            "monitor" => self.monitor = Some(parse(key, value)?),
+            "max-clients" => self.max_clients = Some(parse(key, value)?),
+            "max-monitors" => self.max_monitors = Some(parse(key, value)?),
+            "policy" => {
+                self.policy = Some(match value {
+                    "reject-new" => ConnectionPolicy::RejectNew,
+                    "kick-old" => ConnectionPolicy::KickOld,
+                    _ => return Err(invalid()),
+                })
+            }
+            "idle-timeout" => {
+                let seconds: u64 = parse(key, value)?;
+                self.idle_timeout = Some(Duration::from_secs(seconds));
+            }
+            "bind" => self.bind = Some(parse(key, value)?),
+            "destination" => self.destinations.push(parse(key, value)?),
+            "records" => {
+                self.records = Some(match value {
+                    "lf" => Delimiter::Byte(b'\n'),
+                    "crlf" => Delimiter::CrLf,
+                    "cr" => Delimiter::Byte(b'\r'),
+                    _ => return Err(invalid()),
+                })
+            }
+            "max-datagram" => self.max_datagram = Some(parse(key, value)?),
+            "receive" => self.receive = Some(parse(key, value)?),
+            "multicast-ttl" => self.multicast_ttl = Some(parse(key, value)?),
+            _ => return Err(format!("unknown key {key:?}")),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<PortConfig, ConfigError> {
+        let line = Some(self.line);
+        let name = if self.udp { "[udp]" } else { "[port]" };
+        let serial = SerialConfig {
+            device: self
+                .device
+                .ok_or_else(|| ConfigError::new(line, format!("{name} has no device")))?,
+            baud_rate: self.baud_rate.unwrap_or(DEFAULT_BAUD_RATE),
+            data_bits: self.data_bits.unwrap_or(DataBits::Eight),
+            parity: self.parity.unwrap_or(Parity::None),
+            stop_bits: self.stop_bits.unwrap_or(StopBits::One),
+            flow_control: self.flow_control.unwrap_or(FlowControl::None),
+        };
+        if self.udp {
+            if self.destinations.is_empty() {
+                return Err(ConfigError::new(line, "[udp] has no destination"));
+            }
+            return Ok(PortConfig::Udp {
+                serial,
+                bind: self.bind.unwrap_or_else(|| "0.0.0.0:0".parse().unwrap()),
+                destinations: self.destinations,
+                records: self.records,
+                max_datagram: self.max_datagram.unwrap_or(DEFAULT_MAX_DATAGRAM),
+                receive: self.receive.unwrap_or(false),
+                multicast_ttl: self.multicast_ttl,
+            });
+        }
+        Ok(PortConfig::Tcp {
+            serial,
+            listen: self
+                .listen
+                .ok_or_else(|| ConfigError::new(line, "[port] has no listen address"))?,
+            monitor: self.monitor,
+            max_clients: self.max_clients.unwrap_or(DEFAULT_MAX_CLIENTS),
+            max_monitors: self.max_monitors.unwrap_or(DEFAULT_MAX_MONITORS),
+            policy: self.policy.unwrap_or_default(),
+            idle_timeout: self.idle_timeout.filter(|t| !t.is_zero()),
+        })
+    }
+}
+
+/// Parse a configuration file
+///
+/// ## Errors
+///
+/// The first mistake in `text`, with its line number; or, without one, a file with no
+/// sections.
+pub fn parse(text: &str) -> Result<Vec<PortConfig>, ConfigError> {
+    let mut ports = Vec::new();
+    let mut section: Option<Section> = None;
+    for (number, line) in text.lines().enumerate() {
+        let number = number + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if line == "[port]" || line == "[udp]" {
+            if let Some(done) = section.take() {
+                ports.push(done.finish()?);
+            }
+            section = Some(Section {
+                line: number,
+                udp: line == "[udp]",
+                ..Section::default()
+            });
+            continue;
+        }
+        let current = section
+            .as_mut()
+            .ok_or_else(|| ConfigError::new(Some(number), "expected [port] or [udp]"))?;
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| ConfigError::new(Some(number), "expected key = value"))?;
+        current
+            .set(key.trim(), value.trim())
+            .map_err(|e| ConfigError::new(Some(number), e))?;
+    }
+    if let Some(done) = section {
+        ports.push(done.finish()?);
+    }
+    if ports.is_empty() {
+        return Err(ConfigError::new(None, "no [port] or [udp] sections"));
+    }
+    Ok(ports)
+}
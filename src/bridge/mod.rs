@@ -0,0 +1,842 @@
+//! Serial port bridges to raw TCP clients and UDP destinations
+//!
+//! [`Bridge`] maps serial ports to TCP listening ports, in the manner of ser2net: bytes
+//! from the serial port go to every connected client, and bytes from clients go to the
+//! serial port as they are, without any Telnet processing.  Each [`BridgePort`] has its own
+//! options:
+//!
+//! - how many read-write clients may connect, and whether a client beyond the limit is
+//!   turned away or replaces the oldest one (see [`ConnectionPolicy`]);
+//! - an optional second listener for read-only monitor clients, which receive the serial
+//!   data but whose input is discarded;
+//! - an idle timeout after which a client with no traffic in either direction is closed.
+//!
+//! Every socket and serial port has a bounded write queue.  When a client's queue is full,
+//! reading from the serial port pauses until it drains, so a slow client slows the port
+//! down rather than losing data; likewise clients are not read while the serial queue is
+//! full.  Writable interest is only registered while a queue has something in it.  Data
+//! arriving from a serial port with no clients is discarded.
+//!
+//! A [`UdpPort`] instead sends what the serial port receives as UDP datagrams, to any
+//! number of unicast, broadcast or multicast destinations.  Each datagram carries either
+//! whatever one read returned or one delimited record, such as an NMEA sentence.  Datagrams
+//! arriving on its socket can optionally be written to the serial port.  UDP has no
+//! backpressure: a datagram that cannot be sent right away, or that does not fit in the
+//! serial write queue, is dropped.
+//!
+//! The bridge owns its [`Poll`], so all ports run in one thread and one event loop.
+//! [`config`] reads the configuration files of the `mio-serial-bridge` binary.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::net::TcpListener;
+//! use mio_serial::bridge::{Bridge, BridgePort, ConnectionPolicy};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Duration;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let listener = TcpListener::bind("0.0.0.0:7000".parse().unwrap()).unwrap();
+//! let monitor = TcpListener::bind("0.0.0.0:7001".parse().unwrap()).unwrap();
+//!
+//! let mut bridge = Bridge::new().unwrap();
+//! bridge
+//!     .add(
+//!         BridgePort::new(stream, listener)
+//!             .with_monitor(monitor)
+//!             .with_policy(ConnectionPolicy::KickOld)
+//!             .with_idle_timeout(Some(Duration::from_secs(600))),
+//!     )
+//!     .unwrap();
+//! bridge.run().unwrap();
+//! ```
+use crate::buffered::WriteQueue;
+use crate::delimited::{DelimitedReader, Delimiter};
+use crate::SerialStream;
+use mio::event::Source;
+use mio::net::{TcpListener, TcpStream, UdpSocket};
+use mio::{Events, Interest, Poll, Registry, Token};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+pub mod config;
+
+/// Default limit on read-write clients per port
+pub const DEFAULT_MAX_CLIENTS: usize = 1;
+
+/// Default limit on monitor clients per port
+pub const DEFAULT_MAX_MONITORS: usize = 4;
+
+/// Default size of each client's and each serial port's write queue
+pub const DEFAULT_QUEUE_SIZE: usize = 64 * 1024;
+
+/// Default largest datagram sent by a [`UdpPort`], which fits an Ethernet frame
+pub const DEFAULT_MAX_DATAGRAM: usize = 1472;
+
+/// Bytes moved per read
+const CHUNK: usize = 4096;
+
+/// Largest UDP payload
+const MAX_UDP_PAYLOAD: usize = 65_507;
+
+/// What to do with a read-write client beyond a port's limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConnectionPolicy {
+    /// Close the new connection
+    #[default]
+    RejectNew,
+    /// Close the longest-connected client to make room for the new one
+    KickOld,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Serial,
+    Listener,
+    Monitor,
+    Client,
+    UdpSerial,
+    UdpSocket,
+}
+
+#[derive(Debug)]
+struct Client {
+    token: Token,
+    stream: TcpStream,
+    addr: SocketAddr,
+    monitor: bool,
+    queue: WriteQueue,
+    writable: bool,
+    last_activity: Instant,
+    closed: bool,
+}
+
+/// One serial port and its listeners, to be added to a [`Bridge`]
+pub struct BridgePort<S = SerialStream> {
+    serial: S,
+    listener: TcpListener,
+    monitor: Option<TcpListener>,
+    max_clients: usize,
+    max_monitors: usize,
+    policy: ConnectionPolicy,
+    idle_timeout: Option<Duration>,
+    serial_queue: WriteQueue,
+    serial_writable: bool,
+    serial_token: Token,
+    clients: Vec<Client>,
+}
+
+impl<S> std::fmt::Debug for BridgePort<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BridgePort")
+            .field("listener", &self.listener)
+            .field("monitor", &self.monitor)
+            .field("clients", &self.clients.len())
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> BridgePort<S> {
+    /// Bridge `serial` to read-write clients connecting to `listener`
+    pub fn new(serial: S, listener: TcpListener) -> Self {
+        Self {
+            serial,
+            listener,
+            monitor: None,
+            max_clients: DEFAULT_MAX_CLIENTS,
+            max_monitors: DEFAULT_MAX_MONITORS,
+            policy: ConnectionPolicy::default(),
+            idle_timeout: None,
+            serial_queue: WriteQueue::new(),
+            serial_writable: false,
+            serial_token: Token(0),
+            clients: Vec::new(),
+        }
+    }
+
+    /// Accept read-only monitor clients on `listener`
+    #[must_use]
+    pub fn with_monitor(mut self, listener: TcpListener) -> Self {
+        self.monitor = Some(listener);
+        self
+    }
+
+    /// Set the limit on read-write clients
+    #[must_use]
+    pub fn with_max_clients(mut self, max_clients: usize) -> Self {
+        self.max_clients = max_clients;
+        self
+    }
+
+    /// Set the limit on monitor clients; monitors beyond it are always turned away
+    #[must_use]
+    pub fn with_max_monitors(mut self, max_monitors: usize) -> Self {
+        self.max_monitors = max_monitors;
+        self
+    }
+
+    /// Set what happens to a read-write client beyond the limit
+    #[must_use]
+    pub fn with_policy(mut self, policy: ConnectionPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Close clients after `timeout` without traffic in either direction, or never
+    #[must_use]
+    pub fn with_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// The address read-write clients connect to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The address monitor clients connect to, if there is a monitor listener
+    pub fn monitor_addr(&self) -> Option<io::Result<SocketAddr>> {
+        self.monitor.as_ref().map(|l| l.local_addr())
+    }
+
+    /// Number of connected read-write clients
+    pub fn clients(&self) -> usize {
+        self.clients.iter().filter(|c| !c.monitor).count()
+    }
+
+    /// Number of connected monitor clients
+    pub fn monitors(&self) -> usize {
+        self.clients.iter().filter(|c| c.monitor).count()
+    }
+
+    /// Get a reference to the serial port
+    pub fn get_ref(&self) -> &S {
+        &self.serial
+    }
+
+    /// Get a mutable reference to the serial port
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    fn next_idle_deadline(&self) -> Option<Instant> {
+        let timeout = self.idle_timeout?;
+        self.clients.iter().map(|c| c.last_activity + timeout).min()
+    }
+}
+
+/// How a [`UdpPort`] splits serial data into datagrams
+#[derive(Debug)]
+enum Input<S> {
+    Chunks(S),
+    Records(DelimitedReader<S>),
+}
+
+impl<S: Read> Input<S> {
+    fn get_ref(&self) -> &S {
+        match self {
+            Input::Chunks(serial) => serial,
+            Input::Records(reader) => reader.get_ref(),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut S {
+        match self {
+            Input::Chunks(serial) => serial,
+            Input::Records(reader) => reader.get_mut(),
+        }
+    }
+}
+
+/// One serial port sending datagrams, to be added to a [`Bridge`]
+pub struct UdpPort<S = SerialStream> {
+    input: Input<S>,
+    socket: UdpSocket,
+    destinations: Vec<SocketAddr>,
+    max_datagram: usize,
+    receive: bool,
+    serial_queue: WriteQueue,
+    serial_writable: bool,
+    serial_token: Token,
+    stats: UdpStats,
+}
+
+/// Counters kept by a [`UdpPort`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpStats {
+    /// Datagrams sent, counting each destination
+    pub sent: u64,
+    /// Datagrams received and queued for the serial port
+    pub received: u64,
+    /// Datagrams not sent or not queued, and records that were too long
+    pub dropped: u64,
+}
+
+impl<S> std::fmt::Debug for UdpPort<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UdpPort")
+            .field("socket", &self.socket)
+            .field("destinations", &self.destinations)
+            .field("receive", &self.receive)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Read> UdpPort<S> {
+    /// Send what `serial` receives from `socket`, one datagram per read
+    pub fn new(serial: S, socket: UdpSocket) -> Self {
+        Self {
+            input: Input::Chunks(serial),
+            socket,
+            destinations: Vec::new(),
+            max_datagram: DEFAULT_MAX_DATAGRAM,
+            receive: false,
+            serial_queue: WriteQueue::new(),
+            serial_writable: false,
+            serial_token: Token(0),
+            stats: UdpStats::default(),
+        }
+    }
+
+    /// Add a destination; broadcast and multicast addresses work as well as unicast ones
+    #[must_use]
+    pub fn with_destination(mut self, destination: SocketAddr) -> Self {
+        self.destinations.push(destination);
+        self
+    }
+
+    /// Send one datagram per record ending in `delimiter`, without the delimiter
+    ///
+    /// Records longer than the maximum datagram are dropped.
+    #[must_use]
+    pub fn with_records(mut self, delimiter: Delimiter) -> Self {
+        let serial = match self.input {
+            Input::Chunks(serial) => serial,
+            Input::Records(reader) => reader.into_inner(),
+        };
+        self.input = Input::Records(DelimitedReader::new(serial, delimiter));
+        self
+    }
+
+    /// Set the largest datagram to send; longer reads are split
+    #[must_use]
+    pub fn with_max_datagram(mut self, max_datagram: usize) -> Self {
+        self.max_datagram = max_datagram.clamp(1, MAX_UDP_PAYLOAD);
+        self
+    }
+
+    /// Write datagrams arriving on the socket, from anyone, to the serial port
+    #[must_use]
+    pub fn with_receive(mut self, receive: bool) -> Self {
+        self.receive = receive;
+        self
+    }
+
+    /// The address the socket is bound to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// The destinations datagrams are sent to
+    pub fn destinations(&self) -> &[SocketAddr] {
+        &self.destinations
+    }
+
+    /// Datagram counters
+    pub fn stats(&self) -> UdpStats {
+        self.stats
+    }
+
+    /// Get a reference to the UDP socket, e.g. to join a multicast group
+    pub fn socket(&self) -> &UdpSocket {
+        &self.socket
+    }
+
+    /// Get a reference to the serial port
+    pub fn get_ref(&self) -> &S {
+        self.input.get_ref()
+    }
+
+    /// Get a mutable reference to the serial port
+    pub fn get_mut(&mut self) -> &mut S {
+        self.input.get_mut()
+    }
+
+    fn send(&mut self, datagram: &[u8]) {
+        for destination in &self.destinations {
+            match self.socket.send_to(datagram, *destination) {
+                Ok(_) => self.stats.sent += 1,
+                Err(e) => {
+                    log::debug!("dropping datagram to {}: {}", destination, e);
+                    self.stats.dropped += 1;
+                }
+            }
+        }
+    }
+}
+
+/// Relays between serial ports and TCP clients or UDP destinations
+pub struct Bridge<S = SerialStream> {
+    poll: Poll,
+    events: Events,
+    ports: Vec<BridgePort<S>>,
+    udp_ports: Vec<UdpPort<S>>,
+    /// Datagram buffer shared by the UDP ports, allocated with the first one
+    udp_buf: Vec<u8>,
+    tokens: HashMap<Token, (usize, Role)>,
+    next_token: usize,
+}
+
+impl<S> std::fmt::Debug for Bridge<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Bridge")
+            .field("ports", &self.ports)
+            .field("udp_ports", &self.udp_ports)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Bridge<S>
+where
+    S: Read + Write + Source,
+{
+    /// Create a bridge with no ports
+    pub fn new() -> io::Result<Self> {
+        Ok(Self {
+            poll: Poll::new()?,
+            events: Events::with_capacity(256),
+            ports: Vec::new(),
+            udp_ports: Vec::new(),
+            udp_buf: Vec::new(),
+            tokens: HashMap::new(),
+            next_token: 0,
+        })
+    }
+
+    /// Add a port and start listening for its clients
+    ///
+    /// Returns the port's index for [`port`](Self::port).
+    pub fn add(&mut self, mut port: BridgePort<S>) -> io::Result<usize> {
+        let index = self.ports.len();
+
+        let token = self.allocate(index, Role::Serial);
+        port.serial_token = token;
+        self.poll
+            .registry()
+            .register(&mut port.serial, token, Interest::READABLE)?;
+        let token = self.allocate(index, Role::Listener);
+        self.poll
+            .registry()
+            .register(&mut port.listener, token, Interest::READABLE)?;
+        if let Some(monitor) = port.monitor.as_mut() {
+            let token = self.allocate(index, Role::Monitor);
+            self.poll
+                .registry()
+                .register(monitor, token, Interest::READABLE)?;
+        }
+        self.ports.push(port);
+        Ok(index)
+    }
+
+    /// Add a UDP port
+    ///
+    /// The socket is allowed to send broadcasts.  Returns the port's index for
+    /// [`udp_port`](Self::udp_port).
+    pub fn add_udp(&mut self, mut port: UdpPort<S>) -> io::Result<usize> {
+        let index = self.udp_ports.len();
+        port.socket.set_broadcast(true)?;
+
+        let token = self.allocate(index, Role::UdpSerial);
+        port.serial_token = token;
+        self.poll
+            .registry()
+            .register(port.get_mut(), token, Interest::READABLE)?;
+        if port.receive {
+            let token = self.allocate(index, Role::UdpSocket);
+            self.poll
+                .registry()
+                .register(&mut port.socket, token, Interest::READABLE)?;
+        }
+        self.udp_ports.push(port);
+        Ok(index)
+    }
+
+    /// Get a reference to the port at `index`
+    pub fn port(&self, index: usize) -> Option<&BridgePort<S>> {
+        self.ports.get(index)
+    }
+
+    /// Get a reference to the UDP port at `index`
+    pub fn udp_port(&self, index: usize) -> Option<&UdpPort<S>> {
+        self.udp_ports.get(index)
+    }
+
+    /// Number of ports, TCP and UDP
+    pub fn len(&self) -> usize {
+        self.ports.len() + self.udp_ports.len()
+    }
+
+    /// Whether no port has been added
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Run the event loop until an error occurs on a listener or a serial port
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.run_once(None)?;
+        }
+    }
+
+    /// Wait for events for at most `timeout` and handle them
+    ///
+    /// The wait is cut short when a client is due to time out.
+    pub fn run_once(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let now = Instant::now();
+        let deadline = self
+            .ports
+            .iter()
+            .filter_map(BridgePort::next_idle_deadline)
+            .min()
+            .map(|d| d.saturating_duration_since(now));
+        let wait = match (timeout, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        match self.poll.poll(&mut self.events, wait) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let mut ready = vec![false; self.ports.len()];
+        let mut udp_ready = vec![false; self.udp_ports.len()];
+        let events: Vec<Token> = self.events.iter().map(|e| e.token()).collect();
+        for token in events {
+            let (index, role) = match self.tokens.get(&token) {
+                Some(&found) => found,
+                None => continue,
+            };
+            match role {
+                Role::Listener => self.accept(index, false)?,
+                Role::Monitor => self.accept(index, true)?,
+                Role::Serial | Role::Client => {}
+                Role::UdpSerial | Role::UdpSocket => {
+                    udp_ready[index] = true;
+                    continue;
+                }
+            }
+            ready[index] = true;
+        }
+        for (index, ready) in udp_ready.into_iter().enumerate() {
+            if ready {
+                self.pump_udp(index)?;
+            }
+        }
+
+        let now = Instant::now();
+        for (index, ready) in ready.into_iter().enumerate() {
+            let port = &mut self.ports[index];
+            if let Some(timeout) = port.idle_timeout {
+                for client in port.clients.iter_mut() {
+                    if now.duration_since(client.last_activity) >= timeout {
+                        log::debug!("closing idle connection from {}", client.addr);
+                        client.closed = true;
+                    }
+                }
+            }
+            if ready {
+                self.pump(index)?;
+            }
+            self.update(index)?;
+        }
+        Ok(())
+    }
+
+    fn allocate(&mut self, index: usize, role: Role) -> Token {
+        let token = Token(self.next_token);
+        self.next_token += 1;
+        self.tokens.insert(token, (index, role));
+        token
+    }
+
+    fn accept(&mut self, index: usize, monitor: bool) -> io::Result<()> {
+        loop {
+            let port = &mut self.ports[index];
+            let listener = match (monitor, port.monitor.as_ref()) {
+                (true, Some(listener)) => listener,
+                _ => &port.listener,
+            };
+            let (mut stream, addr) = match listener.accept() {
+                Ok(accepted) => accepted,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            let connected = port
+                .clients
+                .iter()
+                .filter(|c| c.monitor == monitor && !c.closed)
+                .count();
+            let limit = if monitor {
+                port.max_monitors
+            } else {
+                port.max_clients
+            };
+            if connected >= limit {
+                let oldest = port.clients.iter_mut().find(|c| !c.monitor && !c.closed);
+                match (monitor, port.policy, oldest) {
+                    (false, ConnectionPolicy::KickOld, Some(oldest)) => {
+                        log::info!("closing {} to make room for {}", oldest.addr, addr);
+                        oldest.closed = true;
+                    }
+                    _ => {
+                        log::warn!("rejecting connection from {}: too many clients", addr);
+                        continue;
+                    }
+                }
+            }
+
+            let token = Token(self.next_token);
+            if let Err(e) = self
+                .poll
+                .registry()
+                .register(&mut stream, token, Interest::READABLE)
+            {
+                log::warn!("unable to register connection from {}: {}", addr, e);
+                continue;
+            }
+            self.allocate(index, Role::Client);
+            log::debug!(
+                "accepted {} connection from {}",
+                if monitor { "monitor" } else { "client" },
+                addr
+            );
+            self.ports[index].clients.push(Client {
+                token,
+                stream,
+                addr,
+                monitor,
+                queue: WriteQueue::with_high_water_mark(DEFAULT_QUEUE_SIZE),
+                writable: false,
+                last_activity: Instant::now(),
+                closed: false,
+            });
+        }
+    }
+
+    /// Move data on one port until nothing makes progress
+    fn pump(&mut self, index: usize) -> io::Result<()> {
+        let port = &mut self.ports[index];
+        let mut buf = [0u8; CHUNK];
+        loop {
+            let mut moved = 0;
+
+            // Clients to the serial port
+            for client in port.clients.iter_mut().filter(|c| !c.closed) {
+                loop {
+                    let room = if client.monitor {
+                        CHUNK
+                    } else {
+                        port.serial_queue.remaining().min(CHUNK)
+                    };
+                    if room == 0 {
+                        break;
+                    }
+                    match client.stream.read(&mut buf[..room]) {
+                        Ok(0) => {
+                            log::debug!("connection from {} closed", client.addr);
+                            client.closed = true;
+                            break;
+                        }
+                        Ok(n) => {
+                            client.last_activity = Instant::now();
+                            moved += n;
+                            // Monitors are read only to notice when they go away
+                            if !client.monitor {
+                                port.serial_queue.push(&buf[..n]);
+                            }
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                        Err(e) => {
+                            log::debug!("closing connection from {}: {}", client.addr, e);
+                            client.closed = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            moved += port.serial_queue.write_to(&mut port.serial)?;
+
+            // The serial port to every client
+            loop {
+                let room = port
+                    .clients
+                    .iter()
+                    .filter(|c| !c.closed)
+                    .map(|c| c.queue.remaining())
+                    .min()
+                    .unwrap_or(CHUNK)
+                    .min(CHUNK);
+                if room == 0 {
+                    break;
+                }
+                let n = match port.serial.read(&mut buf[..room]) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                moved += n;
+                for client in port.clients.iter_mut().filter(|c| !c.closed) {
+                    client.queue.push(&buf[..n]);
+                }
+            }
+
+            for client in port.clients.iter_mut().filter(|c| !c.closed) {
+                match client.queue.write_to(&mut client.stream) {
+                    Ok(0) => {}
+                    Ok(n) => {
+                        client.last_activity = Instant::now();
+                        moved += n;
+                    }
+                    Err(e) => {
+                        log::debug!("closing connection from {}: {}", client.addr, e);
+                        client.closed = true;
+                    }
+                }
+            }
+
+            if moved == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Move datagrams on one UDP port until nothing makes progress
+    fn pump_udp(&mut self, index: usize) -> io::Result<()> {
+        let port = &mut self.udp_ports[index];
+        self.udp_buf.resize(MAX_UDP_PAYLOAD, 0);
+        let buf = &mut self.udp_buf;
+        loop {
+            let mut moved = 0;
+
+            while port.receive {
+                let (n, from) = match port.socket.recv_from(buf) {
+                    Ok(received) => received,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                moved += n.max(1);
+                if port.serial_queue.remaining() >= n {
+                    port.serial_queue.push(&buf[..n]);
+                    port.stats.received += 1;
+                } else {
+                    log::warn!("dropping datagram from {}: serial port is behind", from);
+                    port.stats.dropped += 1;
+                }
+            }
+            moved += port.serial_queue.write_to(port.input.get_mut())?;
+
+            loop {
+                let datagram = match &mut port.input {
+                    Input::Chunks(serial) => match serial.read(&mut buf[..port.max_datagram]) {
+                        Ok(0) => break,
+                        Ok(n) => buf[..n].to_vec(),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(e) => return Err(e),
+                    },
+                    Input::Records(reader) => match reader.read_record() {
+                        Ok(Some(record)) if record.len() > port.max_datagram => {
+                            log::warn!("dropping {} byte record", record.len());
+                            port.stats.dropped += 1;
+                            continue;
+                        }
+                        Ok(Some(record)) => record,
+                        Ok(None) => break,
+                        Err(ref e) if e.kind() == io::ErrorKind::InvalidData => {
+                            log::warn!("dropping record: {}", e);
+                            port.stats.dropped += 1;
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    },
+                };
+                moved += datagram.len().max(1);
+                port.send(&datagram);
+            }
+
+            if moved == 0 {
+                break;
+            }
+        }
+
+        let writable = !port.serial_queue.is_empty();
+        if writable != port.serial_writable {
+            let interest = if writable {
+                Interest::READABLE | Interest::WRITABLE
+            } else {
+                Interest::READABLE
+            };
+            let token = port.serial_token;
+            self.poll
+                .registry()
+                .reregister(port.get_mut(), token, interest)?;
+            port.serial_writable = writable;
+        }
+        Ok(())
+    }
+
+    /// Drop closed clients and ask for writable events only where data is waiting
+    fn update(&mut self, index: usize) -> io::Result<()> {
+        let registry: &Registry = self.poll.registry();
+        let port = &mut self.ports[index];
+
+        let tokens = &mut self.tokens;
+        port.clients.retain_mut(|client| {
+            if client.closed {
+                let _ = registry.deregister(&mut client.stream);
+                tokens.remove(&client.token);
+                return false;
+            }
+            let writable = !client.queue.is_empty();
+            if writable != client.writable {
+                let interest = if writable {
+                    Interest::READABLE | Interest::WRITABLE
+                } else {
+                    Interest::READABLE
+                };
+                if let Err(e) = registry.reregister(&mut client.stream, client.token, interest) {
+                    log::debug!("closing connection from {}: {}", client.addr, e);
+                    let _ = registry.deregister(&mut client.stream);
+                    tokens.remove(&client.token);
+                    return false;
+                }
+                client.writable = writable;
+            }
+            true
+        });
+
+        let writable = !port.serial_queue.is_empty();
+        if writable != port.serial_writable {
+            let interest = if writable {
+                Interest::READABLE | Interest::WRITABLE
+            } else {
+                Interest::READABLE
+            };
+            registry.reregister(&mut port.serial, port.serial_token, interest)?;
+            port.serial_writable = writable;
+        }
+        Ok(())
+    }
+}
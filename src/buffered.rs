@@ -0,0 +1,369 @@
+//! Buffered, non-blocking writes for [`SerialStream`]
+//!
+//! Writing to a non-blocking serial port means dealing with short writes and `WouldBlock`
+//! errors, then waiting for a writable event before trying again.  [`BufferedSerialStream`]
+//! keeps the unwritten bytes in a [`WriteQueue`] and toggles [`Interest::WRITABLE`] on its
+//! own registration so the event loop only wakes up for writes while there is something
+//! left to send.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::{BufferedSerialStream, SerialPortBuilderExt};
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 9600).open_native_async().unwrap();
+//! let mut port = BufferedSerialStream::new(stream);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut port, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! port.queue_write(b"hello world").unwrap();
+//!
+//! while port.is_write_pending() {
+//!     poll.poll(&mut events, None).unwrap();
+//!     for event in events.iter() {
+//!         if event.is_writable() {
+//!             port.on_writable().unwrap();
+//!         }
+//!     }
+//! }
+//! ```
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+/// Default high-water mark used by [`WriteQueue::new`] and [`BufferedSerialStream::new`]
+pub const DEFAULT_HIGH_WATER_MARK: usize = 64 * 1024;
+
+/// A queue of outbound bytes with an upper bound on its size
+///
+/// The high-water mark is the number of pending bytes at which the queue stops accepting
+/// new data.  Callers see that as backpressure: [`WriteQueue::push`] accepts fewer bytes
+/// than offered and [`BufferedSerialStream::queue_write`] returns `WouldBlock`.
+#[derive(Debug, Clone)]
+pub struct WriteQueue {
+    buf: VecDeque<u8>,
+    high_water_mark: usize,
+}
+
+impl WriteQueue {
+    /// Create an empty queue using [`DEFAULT_HIGH_WATER_MARK`]
+    pub fn new() -> Self {
+        Self::with_high_water_mark(DEFAULT_HIGH_WATER_MARK)
+    }
+
+    /// Create an empty queue that holds at most `high_water_mark` bytes
+    pub fn with_high_water_mark(high_water_mark: usize) -> Self {
+        Self {
+            buf: VecDeque::new(),
+            high_water_mark,
+        }
+    }
+
+    /// The configured high-water mark
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water_mark
+    }
+
+    /// Number of bytes waiting to be written
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns `true` if there are no bytes waiting to be written
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Returns `true` if the queue has reached its high-water mark
+    pub fn is_full(&self) -> bool {
+        self.buf.len() >= self.high_water_mark
+    }
+
+    /// Number of bytes the queue will accept before reaching its high-water mark
+    pub fn remaining(&self) -> usize {
+        self.high_water_mark.saturating_sub(self.buf.len())
+    }
+
+    /// Append as much of `data` as fits below the high-water mark
+    ///
+    /// Returns the number of bytes accepted.
+    pub fn push(&mut self, data: &[u8]) -> usize {
+        let n = data.len().min(self.remaining());
+        self.buf.extend(&data[..n]);
+        n
+    }
+
+    /// Discard all pending bytes
+    pub fn clear(&mut self) {
+        self.buf.clear();
+    }
+
+    /// Write pending bytes to `writer` until the queue is empty or the writer would block
+    ///
+    /// Returns the number of bytes written.  A `WouldBlock` from the writer is not an error,
+    /// it just ends the attempt early.
+    pub fn write_to<W: Write + ?Sized>(&mut self, writer: &mut W) -> io::Result<usize> {
+        let mut written = 0;
+        while !self.buf.is_empty() {
+            let (front, _) = self.buf.as_slices();
+            match writer.write(front) {
+                Ok(0) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::WriteZero,
+                        "failed to write queued data",
+                    ))
+                }
+                Ok(n) => {
+                    self.buf.drain(..n);
+                    written += n;
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(written)
+    }
+}
+
+impl Default for WriteQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug)]
+struct Registration {
+    registry: Registry,
+    token: Token,
+    interests: Interest,
+    writable: bool,
+}
+
+/// A [`SerialStream`] (or any other mio [`Source`]) with an outbound [`WriteQueue`]
+///
+/// Writes go straight to the stream while nothing is queued.  Whatever the stream does not
+/// accept is queued and [`Interest::WRITABLE`] is added to the registration; call
+/// [`on_writable`](Self::on_writable) for each writable event and the interest is dropped
+/// again once the queue drains.
+///
+/// The [`Write`] impl is a thin wrapper around [`queue_write`](Self::queue_write), so the
+/// buffered stream can be handed to code expecting a plain writer.
+#[derive(Debug)]
+pub struct BufferedSerialStream<S = SerialStream> {
+    inner: S,
+    queue: WriteQueue,
+    registration: Option<Registration>,
+}
+
+impl<S> BufferedSerialStream<S>
+where
+    S: Read + Write + Source,
+{
+    /// Wrap `inner` using [`DEFAULT_HIGH_WATER_MARK`]
+    pub fn new(inner: S) -> Self {
+        Self::with_high_water_mark(inner, DEFAULT_HIGH_WATER_MARK)
+    }
+
+    /// Wrap `inner`, queueing at most `high_water_mark` bytes
+    pub fn with_high_water_mark(inner: S, high_water_mark: usize) -> Self {
+        Self {
+            inner,
+            queue: WriteQueue::with_high_water_mark(high_water_mark),
+            registration: None,
+        }
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    ///
+    /// Writing to the stream directly will interleave with any queued data.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume the wrapper, returning the underlying stream
+    ///
+    /// Any queued bytes are dropped.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Get a reference to the outbound queue
+    pub fn write_queue(&self) -> &WriteQueue {
+        &self.queue
+    }
+
+    /// Returns `true` if there are queued bytes waiting for a writable event
+    pub fn is_write_pending(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    /// Returns `true` if the queue is at its high-water mark
+    pub fn is_full(&self) -> bool {
+        self.queue.is_full()
+    }
+
+    /// Write `data` to the stream, queueing whatever cannot be written immediately
+    ///
+    /// Returns the number of bytes accepted, which is less than `data.len()` only when the
+    /// queue reaches its high-water mark.
+    ///
+    /// ## Errors
+    ///
+    /// * `WouldBlock` if the queue is already full and no bytes were accepted.
+    /// * Any error returned by the stream other than `WouldBlock`.
+    pub fn queue_write(&mut self, data: &[u8]) -> io::Result<usize> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+
+        let mut accepted = 0;
+        if self.queue.is_empty() {
+            loop {
+                match self.inner.write(&data[accepted..]) {
+                    Ok(n) => {
+                        accepted += n;
+                        if n == 0 || accepted == data.len() {
+                            break;
+                        }
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+
+        accepted += self.queue.push(&data[accepted..]);
+        self.update_interest()?;
+
+        if accepted == 0 {
+            Err(io::ErrorKind::WouldBlock.into())
+        } else {
+            Ok(accepted)
+        }
+    }
+
+    /// Flush queued data after a writable event
+    ///
+    /// Returns the number of bytes written.  Writable interest is removed from the
+    /// registration once the queue is empty.
+    pub fn on_writable(&mut self) -> io::Result<usize> {
+        let written = self.queue.write_to(&mut self.inner)?;
+        self.update_interest()?;
+        Ok(written)
+    }
+
+    /// Discard any queued bytes and drop writable interest
+    pub fn clear_write_queue(&mut self) -> io::Result<()> {
+        self.queue.clear();
+        self.update_interest()
+    }
+
+    fn update_interest(&mut self) -> io::Result<()> {
+        let want_writable = !self.queue.is_empty();
+        if let Some(ref mut reg) = self.registration {
+            if reg.writable != want_writable {
+                let interests = effective_interests(reg.interests, want_writable);
+                self.inner.reregister(&reg.registry, reg.token, interests)?;
+                reg.writable = want_writable;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn effective_interests(interests: Interest, writable: bool) -> Interest {
+    if writable {
+        interests | Interest::WRITABLE
+    } else {
+        interests
+    }
+}
+
+impl<S> Read for BufferedSerialStream<S>
+where
+    S: Read + Write + Source,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<S> Write for BufferedSerialStream<S>
+where
+    S: Read + Write + Source,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.queue_write(buf)
+    }
+
+    /// Attempt to write out the queue, then flush the underlying stream
+    ///
+    /// Returns `WouldBlock` if queued bytes remain.
+    fn flush(&mut self) -> io::Result<()> {
+        self.on_writable()?;
+        if self.queue.is_empty() {
+            self.inner.flush()
+        } else {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+    }
+}
+
+impl<S> Source for BufferedSerialStream<S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        let writable = !self.queue.is_empty();
+        self.inner
+            .register(registry, token, effective_interests(interests, writable))?;
+        self.registration = Some(Registration {
+            registry: registry.try_clone()?,
+            token,
+            interests,
+            writable,
+        });
+        Ok(())
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        let writable = !self.queue.is_empty();
+        self.inner
+            .reregister(registry, token, effective_interests(interests, writable))?;
+        self.registration = Some(Registration {
+            registry: registry.try_clone()?,
+            token,
+            interests,
+            writable,
+        });
+        Ok(())
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.registration = None;
+        self.inner.deregister(registry)
+    }
+}
@@ -0,0 +1,707 @@
+//! Traffic capture files and time-accurate replay
+//!
+//! A capture records a serial session: the data in each direction, changes to the line
+//! settings and changes to the control lines, each with the time it happened.
+//! [`CaptureWriter`] writes captures, [`CaptureReader`] reads them back, and [`Replayer`]
+//! plays one into a [`SerialStream`] with the original timing, optionally sped up or
+//! slowed down, so that parsers can be regression-tested against captures taken in the
+//! field.  [`SerialTee`](crate::tee::SerialTee) can record captures as it relays.
+//!
+//! ## File format
+//!
+//! All integers are little-endian.  A capture starts with a 16 byte header:
+//!
+//! | Offset | Size | Contents                                              |
+//! |--------|------|-------------------------------------------------------|
+//! | 0      | 8    | Magic and version, `MIOSCAP1`                         |
+//! | 8      | 8    | Start of the capture, microseconds since the Unix epoch |
+//!
+//! Records follow, each with a 13 byte header and a body:
+//!
+//! | Offset | Size   | Contents                                            |
+//! |--------|--------|-----------------------------------------------------|
+//! | 0      | 8      | Time since the start of the capture, in microseconds |
+//! | 8      | 1      | Kind                                                |
+//! | 9      | 4      | Body length                                         |
+//! | 13     | length | Body                                                |
+//!
+//! | Kind | Body                                                              |
+//! |------|-------------------------------------------------------------------|
+//! | 1    | Data from the application to the device                           |
+//! | 2    | Data from the device to the application                           |
+//! | 3    | Settings, 8 bytes: baud rate (4 bytes), data bits, parity, stop bits and flow control |
+//! | 4    | Control lines, 1 byte                                             |
+//!
+//! Settings use data bits 5 to 8, parity 0 for none, 1 for odd and 2 for even, stop bits 1
+//! or 2, and flow control 0 for none, 1 for software and 2 for hardware.  The control line
+//! byte has RTS in bit 0, then DTR, CTS, DSR, RI, CD, and the break condition in bit 6.
+//!
+//! Readers skip records of kinds they do not know, so new kinds can be added without a
+//! new version.  Records are in time order.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::capture::{replay, CaptureReader, Direction};
+//! use std::fs::File;
+//! use std::io::{self, BufReader, Read};
+//!
+//! let file = BufReader::new(File::open("field.cap").unwrap());
+//! let capture = CaptureReader::new(file).unwrap();
+//! // Play what the device sent at twice the original speed
+//! let (mut port, player) = replay(capture, Direction::FromDevice, 2.0).unwrap();
+//!
+//! let mut received = Vec::new();
+//! let mut buf = [0u8; 256];
+//! loop {
+//!     match port.read(&mut buf) {
+//!         Ok(n) => received.extend_from_slice(&buf[..n]),
+//!         Err(e) if e.kind() == io::ErrorKind::WouldBlock && player.is_finished() => break,
+//!         Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+//!             std::thread::sleep(std::time::Duration::from_millis(1))
+//!         }
+//!         Err(e) => panic!("{e}"),
+//!     }
+//! }
+//! ```
+use crate::buffered::WriteQueue;
+use crate::{DataBits, FlowControl, Parity, SerialPort, SerialStream, StopBits};
+use mio::event::Source;
+use mio::{Interest, Registry, Token};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Magic and version at the start of every capture
+pub const MAGIC: &[u8; 8] = b"MIOSCAP1";
+
+const KIND_TO_DEVICE: u8 = 1;
+const KIND_FROM_DEVICE: u8 = 2;
+const KIND_SETTINGS: u8 = 3;
+const KIND_CONTROL_LINES: u8 = 4;
+
+/// Largest record body a reader accepts
+const MAX_BODY: u32 = 16 * 1024 * 1024;
+
+/// Which way data was travelling
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// From the application to the device
+    ToDevice,
+    /// From the device to the application
+    FromDevice,
+}
+
+impl Direction {
+    /// The marker used in logs, `>` or `<`
+    pub fn marker(self) -> char {
+        match self {
+            Direction::ToDevice => '>',
+            Direction::FromDevice => '<',
+        }
+    }
+}
+
+/// Line settings of a serial port
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// Baud rate
+    pub baud_rate: u32,
+    /// Character size
+    pub data_bits: DataBits,
+    /// Parity
+    pub parity: Parity,
+    /// Stop bits
+    pub stop_bits: StopBits,
+    /// Flow control
+    pub flow_control: FlowControl,
+}
+
+impl Settings {
+    /// Read the current settings of `port`
+    pub fn read_from<P: SerialPort + ?Sized>(port: &P) -> crate::Result<Self> {
+        Ok(Self {
+            baud_rate: port.baud_rate()?,
+            data_bits: port.data_bits()?,
+            parity: port.parity()?,
+            stop_bits: port.stop_bits()?,
+            flow_control: port.flow_control()?,
+        })
+    }
+
+    /// Change `port` to these settings
+    pub fn apply_to<P: SerialPort + ?Sized>(&self, port: &mut P) -> crate::Result<()> {
+        port.set_baud_rate(self.baud_rate)?;
+        port.set_data_bits(self.data_bits)?;
+        port.set_parity(self.parity)?;
+        port.set_stop_bits(self.stop_bits)?;
+        port.set_flow_control(self.flow_control)
+    }
+
+    fn encode(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&self.baud_rate.to_le_bytes());
+        dst.push(match self.data_bits {
+            DataBits::Five => 5,
+            DataBits::Six => 6,
+            DataBits::Seven => 7,
+            DataBits::Eight => 8,
+        });
+        dst.push(match self.parity {
+            Parity::None => 0,
+            Parity::Odd => 1,
+            Parity::Even => 2,
+        });
+        dst.push(match self.stop_bits {
+            StopBits::One => 1,
+            StopBits::Two => 2,
+        });
+        dst.push(match self.flow_control {
+            FlowControl::None => 0,
+            FlowControl::Software => 1,
+            FlowControl::Hardware => 2,
+        });
+    }
+
+    fn decode(body: &[u8]) -> Option<Self> {
+        let body: &[u8; 8] = body.try_into().ok()?;
+        Some(Self {
+            baud_rate: u32::from_le_bytes([body[0], body[1], body[2], body[3]]),
+            data_bits: match body[4] {
+                5 => DataBits::Five,
+                6 => DataBits::Six,
+                7 => DataBits::Seven,
+                8 => DataBits::Eight,
+                _ => return None,
+            },
+            parity: match body[5] {
+                0 => Parity::None,
+                1 => Parity::Odd,
+                2 => Parity::Even,
+                _ => return None,
+            },
+            stop_bits: match body[6] {
+                1 => StopBits::One,
+                2 => StopBits::Two,
+                _ => return None,
+            },
+            flow_control: match body[7] {
+                0 => FlowControl::None,
+                1 => FlowControl::Software,
+                2 => FlowControl::Hardware,
+                _ => return None,
+            },
+        })
+    }
+}
+
+/// States of the control lines and of the break condition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlLines {
+    /// Request To Send, an output
+    pub rts: bool,
+    /// Data Terminal Ready, an output
+    pub dtr: bool,
+    /// Clear To Send, an input
+    pub cts: bool,
+    /// Data Set Ready, an input
+    pub dsr: bool,
+    /// Ring Indicator, an input
+    pub ri: bool,
+    /// Carrier Detect, an input
+    pub cd: bool,
+    /// Whether a break is being sent
+    pub break_state: bool,
+}
+
+impl ControlLines {
+    fn to_byte(self) -> u8 {
+        [
+            self.rts,
+            self.dtr,
+            self.cts,
+            self.dsr,
+            self.ri,
+            self.cd,
+            self.break_state,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |byte, (bit, &set)| byte | (u8::from(set) << bit))
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        let bit = |n: u8| byte & (1 << n) != 0;
+        Self {
+            rts: bit(0),
+            dtr: bit(1),
+            cts: bit(2),
+            dsr: bit(3),
+            ri: bit(4),
+            cd: bit(5),
+            break_state: bit(6),
+        }
+    }
+}
+
+/// Something that happened on the line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Bytes sent one way
+    Data {
+        /// Which way they went
+        direction: Direction,
+        /// The bytes
+        data: Vec<u8>,
+    },
+    /// The line settings changed
+    Settings(Settings),
+    /// A control line or the break condition changed
+    ControlLines(ControlLines),
+}
+
+/// An event and when it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Time since the start of the capture
+    pub offset: Duration,
+    /// What happened
+    pub event: Event,
+}
+
+/// Writes a capture
+///
+/// The header is written with the first record, so creating a writer never fails.
+/// Timestamps are taken from when the writer was created.
+#[derive(Debug)]
+pub struct CaptureWriter<W> {
+    inner: W,
+    start: SystemTime,
+    started: Instant,
+    header_written: bool,
+    buf: Vec<u8>,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Start a capture now
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            start: SystemTime::now(),
+            started: Instant::now(),
+            header_written: false,
+            buf: Vec::new(),
+        }
+    }
+
+    /// When the capture started
+    pub fn start_time(&self) -> SystemTime {
+        self.start
+    }
+
+    /// Record `data` travelling in `direction` now
+    pub fn write_data(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let kind = match direction {
+            Direction::ToDevice => KIND_TO_DEVICE,
+            Direction::FromDevice => KIND_FROM_DEVICE,
+        };
+        self.write_record(self.started.elapsed(), kind, data)
+    }
+
+    /// Record a change of line settings now
+    pub fn write_settings(&mut self, settings: &Settings) -> io::Result<()> {
+        let mut body = Vec::with_capacity(8);
+        settings.encode(&mut body);
+        self.write_record(self.started.elapsed(), KIND_SETTINGS, &body)
+    }
+
+    /// Record a change of control lines now
+    pub fn write_control_lines(&mut self, lines: ControlLines) -> io::Result<()> {
+        self.write_record(
+            self.started.elapsed(),
+            KIND_CONTROL_LINES,
+            &[lines.to_byte()],
+        )
+    }
+
+    /// Record `entry` with its own offset, e.g. to build a capture by hand
+    ///
+    /// Entries should be written in time order.
+    pub fn write_entry(&mut self, entry: &Entry) -> io::Result<()> {
+        match &entry.event {
+            Event::Data { direction, data } => {
+                let kind = match direction {
+                    Direction::ToDevice => KIND_TO_DEVICE,
+                    Direction::FromDevice => KIND_FROM_DEVICE,
+                };
+                self.write_record(entry.offset, kind, data)
+            }
+            Event::Settings(settings) => {
+                let mut body = Vec::with_capacity(8);
+                settings.encode(&mut body);
+                self.write_record(entry.offset, KIND_SETTINGS, &body)
+            }
+            Event::ControlLines(lines) => {
+                self.write_record(entry.offset, KIND_CONTROL_LINES, &[lines.to_byte()])
+            }
+        }
+    }
+
+    /// Flush the underlying writer
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_header()?;
+        self.inner.flush()
+    }
+
+    /// Get a reference to the underlying writer
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Return the underlying writer, without writing the header if nothing was recorded
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if !self.header_written {
+            let micros = self
+                .start
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_micros() as u64);
+            let mut header = [0u8; 16];
+            header[..8].copy_from_slice(MAGIC);
+            header[8..].copy_from_slice(&micros.to_le_bytes());
+            self.inner.write_all(&header)?;
+            self.header_written = true;
+        }
+        Ok(())
+    }
+
+    fn write_record(&mut self, offset: Duration, kind: u8, body: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(body.len())
+            .ok()
+            .filter(|&len| len <= MAX_BODY)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "record too long"))?;
+        self.write_header()?;
+        self.buf.clear();
+        self.buf
+            .extend_from_slice(&(offset.as_micros() as u64).to_le_bytes());
+        self.buf.push(kind);
+        self.buf.extend_from_slice(&len.to_le_bytes());
+        self.buf.extend_from_slice(body);
+        self.inner.write_all(&self.buf)
+    }
+}
+
+/// Reads a capture
+///
+/// Also an iterator over the entries.
+#[derive(Debug)]
+pub struct CaptureReader<R> {
+    inner: R,
+    start: SystemTime,
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Read the header of a capture
+    ///
+    /// Fails with `InvalidData` if `inner` does not start with a capture header.
+    pub fn new(mut inner: R) -> io::Result<Self> {
+        let mut header = [0u8; 16];
+        inner.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a capture file",
+            ));
+        }
+        let micros = u64::from_le_bytes(header[8..].try_into().unwrap());
+        Ok(Self {
+            inner,
+            start: UNIX_EPOCH + Duration::from_micros(micros),
+        })
+    }
+
+    /// When the capture started
+    pub fn start_time(&self) -> SystemTime {
+        self.start
+    }
+
+    /// Read the next entry, or `None` at the end of the capture
+    ///
+    /// A capture that ends part way through a record fails with `UnexpectedEof`.
+    pub fn read_entry(&mut self) -> io::Result<Option<Entry>> {
+        loop {
+            let mut header = [0u8; 13];
+            match self.inner.read(&mut header[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => {}
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+            self.inner.read_exact(&mut header[1..])?;
+            let offset = Duration::from_micros(u64::from_le_bytes(header[..8].try_into().unwrap()));
+            let kind = header[8];
+            let len = u32::from_le_bytes(header[9..].try_into().unwrap());
+            if len > MAX_BODY {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("{len} byte record"),
+                ));
+            }
+            let mut body = vec![0u8; len as usize];
+            self.inner.read_exact(&mut body)?;
+
+            let invalid = || io::Error::new(io::ErrorKind::InvalidData, "invalid record");
+            let event = match kind {
+                KIND_TO_DEVICE => Event::Data {
+                    direction: Direction::ToDevice,
+                    data: body,
+                },
+                KIND_FROM_DEVICE => Event::Data {
+                    direction: Direction::FromDevice,
+                    data: body,
+                },
+                KIND_SETTINGS => Event::Settings(Settings::decode(&body).ok_or_else(invalid)?),
+                KIND_CONTROL_LINES => match body[..] {
+                    [byte] => Event::ControlLines(ControlLines::from_byte(byte)),
+                    _ => return Err(invalid()),
+                },
+                _ => {
+                    log::debug!("skipping capture record of kind {}", kind);
+                    continue;
+                }
+            };
+            return Ok(Some(Entry { offset, event }));
+        }
+    }
+
+    /// Return the underlying reader
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Entry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_entry().transpose()
+    }
+}
+
+/// Plays a capture into a serial stream with the original timing
+///
+/// Data in the chosen direction is written to the stream when it is due, and settings
+/// records are applied to it; everything else is skipped.  The replayer is a mio
+/// [`Source`] for the stream: call [`handle_io`](Self::handle_io) on writable events and
+/// whenever the deadline from [`next_deadline`](Self::next_deadline) expires, or call
+/// [`run`](Self::run) to do all of it in the current thread.
+#[derive(Debug)]
+pub struct Replayer<R> {
+    reader: CaptureReader<R>,
+    stream: SerialStream,
+    direction: Direction,
+    speed: f64,
+    started: Option<Instant>,
+    pending: Option<Entry>,
+    queue: WriteQueue,
+    finished: bool,
+}
+
+impl<R: Read> Replayer<R> {
+    /// Play what was sent from the device in `reader` into `stream`, at the original speed
+    ///
+    /// The clock starts at the first call to [`handle_io`](Self::handle_io).
+    pub fn new(reader: CaptureReader<R>, stream: SerialStream) -> Self {
+        Self {
+            reader,
+            stream,
+            direction: Direction::FromDevice,
+            speed: 1.0,
+            started: None,
+            pending: None,
+            queue: WriteQueue::new(),
+            finished: false,
+        }
+    }
+
+    /// Play the data sent in `direction` instead
+    #[must_use]
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Scale time by `speed`: 2.0 plays twice as fast, 0.5 half as fast, and
+    /// `f64::INFINITY` without any delays
+    ///
+    /// ## Panics
+    ///
+    /// If `speed` is not greater than zero.
+    #[must_use]
+    pub fn with_speed(mut self, speed: f64) -> Self {
+        assert!(speed > 0.0, "replay speed must be greater than zero");
+        self.speed = speed;
+        self
+    }
+
+    /// Whether everything has been read from the capture and written to the stream
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// When the next entry is due, if one is waiting
+    pub fn next_deadline(&self) -> Option<Instant> {
+        let started = self.started?;
+        self.pending
+            .as_ref()
+            .map(|entry| started + self.scale(entry.offset))
+    }
+
+    /// Write whatever is due at `now` to the stream
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        let started = *self.started.get_or_insert(now);
+        loop {
+            self.queue.write_to(&mut self.stream)?;
+            if !self.queue.is_empty() {
+                return Ok(());
+            }
+            if self.pending.is_none() {
+                self.pending = self.next_entry()?;
+            }
+            let entry = match self.pending.take() {
+                Some(entry) => entry,
+                None => {
+                    self.finished = true;
+                    return Ok(());
+                }
+            };
+            if started + self.scale(entry.offset) > now {
+                self.pending = Some(entry);
+                return Ok(());
+            }
+            match entry.event {
+                Event::Data { direction, data } => {
+                    // What does not fit waits for the queue to drain
+                    let n = self.queue.push(&data);
+                    if n < data.len() {
+                        self.pending = Some(Entry {
+                            offset: entry.offset,
+                            event: Event::Data {
+                                direction,
+                                data: data[n..].to_vec(),
+                            },
+                        });
+                    }
+                }
+                Event::Settings(settings) => {
+                    if let Err(e) = settings.apply_to(&mut self.stream) {
+                        log::debug!("unable to apply {:?}: {}", settings, e);
+                    }
+                }
+                Event::ControlLines(_) => {}
+            }
+        }
+    }
+
+    /// Play the whole capture, sleeping until each entry is due
+    pub fn run(&mut self) -> io::Result<()> {
+        while !self.finished {
+            self.handle_io(Instant::now())?;
+            let wait = if !self.queue.is_empty() {
+                Some(Duration::from_millis(1))
+            } else {
+                self.next_deadline()
+                    .map(|d| d.saturating_duration_since(Instant::now()))
+            };
+            if let Some(wait) = wait {
+                std::thread::sleep(wait);
+            }
+        }
+        Ok(())
+    }
+
+    /// Get a reference to the stream
+    pub fn get_ref(&self) -> &SerialStream {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the stream
+    pub fn get_mut(&mut self) -> &mut SerialStream {
+        &mut self.stream
+    }
+
+    /// Return the stream
+    pub fn into_inner(self) -> SerialStream {
+        self.stream
+    }
+
+    fn scale(&self, offset: Duration) -> Duration {
+        if self.speed.is_infinite() {
+            Duration::ZERO
+        } else {
+            offset.div_f64(self.speed)
+        }
+    }
+
+    fn next_entry(&mut self) -> io::Result<Option<Entry>> {
+        while let Some(entry) = self.reader.read_entry()? {
+            match &entry.event {
+                Event::Data { direction, .. } if *direction != self.direction => continue,
+                Event::ControlLines(_) => continue,
+                _ => return Ok(Some(entry)),
+            }
+        }
+        Ok(None)
+    }
+}
+
+impl<R> Source for Replayer<R> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.stream.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.stream.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.stream.deregister(registry)
+    }
+}
+
+/// Play `reader` into one end of a new pseudo-terminal pair on its own thread
+///
+/// Returns the other end, for the code under test to read, and the thread, which finishes
+/// once the whole capture has been written.  The thread hands back its end of the pair
+/// rather than closing it, so whatever has not been read yet stays readable for as long
+/// as the handle or its result is kept.  See [`Replayer::with_speed`] for `speed`.
+#[cfg(unix)]
+pub fn replay<R: Read + Send + 'static>(
+    reader: CaptureReader<R>,
+    direction: Direction,
+    speed: f64,
+) -> io::Result<(
+    SerialStream,
+    std::thread::JoinHandle<io::Result<SerialStream>>,
+)> {
+    let (master, slave) = SerialStream::pair()?;
+    let mut replayer = Replayer::new(reader, master)
+        .with_direction(direction)
+        .with_speed(speed);
+    let thread = std::thread::spawn(move || {
+        replayer.run()?;
+        Ok(replayer.into_inner())
+    });
+    Ok((slave, thread))
+}
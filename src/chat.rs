@@ -0,0 +1,791 @@
+//! pppd-style chat scripts
+//!
+//! [`Script::parse`] reads the script syntax of the `chat` program that ships with pppd,
+//! and [`Chat`] runs it over an [`Expect`] engine without blocking the event loop.  A
+//! script is a list of expect–send pairs interleaved with keywords:
+//!
+//! ```text
+//! ABORT BUSY ABORT 'NO CARRIER'
+//! TIMEOUT 5
+//! '' ATZ
+//! OK-AT-OK ATD*99#
+//! CONNECT \d\c
+//! ```
+//!
+//! Supported are:
+//!
+//! * `ABORT`, `CLR_ABORT`, `REPORT`, `CLR_REPORT`, `TIMEOUT` and `SAY`; `ECHO` and `HANGUP`
+//!   are accepted and ignored.
+//! * Sub-expects (`OK-AT-OK`): if the expect string does not arrive in time, send the
+//!   string after the hyphen and expect the next one.
+//! * The escapes `\b \c \d \n \N \p \q \r \s \t \\ \ddd` and `^C`, and the special send
+//!   string `EOT`.  `\K` and `BREAK` are rejected as there is no way to send a break
+//!   through a generic stream.
+//! * Send strings are followed by a carriage return unless they end in `\c`.
+//!
+//! A script ends with the [`Outcome`], or with the [`ChatError`] that stopped it; its
+//! [`exit_code`](ChatError::exit_code) is the one `chat` would have exited with.
+//!
+//! Like [`expect`](crate::expect), this module needs the `expect` feature.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::chat::{Chat, Script};
+//! use mio_serial::expect::Expect;
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let script = Script::parse(&std::fs::read_to_string("/etc/chatscripts/gprs").unwrap())
+//!     .expect("invalid chat script");
+//! let stream = mio_serial::new("/dev/ttyUSB2", 115200).open_native_async().unwrap();
+//! match Chat::new(Expect::new(stream), script).run_blocking() {
+//!     Ok(outcome) => println!("connected; reports: {:?}", outcome.reports),
+//!     Err(e) => std::process::exit(e.exit_code()),
+//! }
+//! ```
+use crate::expect::{Expect, ExpectError, Match, Pattern};
+use crate::SerialStream;
+use mio::{event::Source, Events, Interest, Poll, Registry, Token};
+use regex::bytes::Regex;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Expect timeout in effect until the script sets one
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(45);
+
+/// Delay for the `\d` escape
+pub const DELAY: Duration = Duration::from_secs(1);
+
+/// Pause for the `\p` escape
+pub const PAUSE: Duration = Duration::from_millis(100);
+
+/// Part of a send string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendPart {
+    /// Bytes to send
+    Bytes(Vec<u8>),
+    /// Wait before sending the rest
+    Delay(Duration),
+}
+
+/// A send string with its escapes resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SendString {
+    /// What to send, in order
+    pub parts: Vec<SendPart>,
+    /// `true` unless the string ended in `\c`
+    pub carriage_return: bool,
+}
+
+/// One step of a script
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    /// Fail the script when this string is received
+    Abort(Vec<u8>),
+    /// Stop aborting on this string
+    ClearAbort(Vec<u8>),
+    /// Record lines starting with this string
+    Report(Vec<u8>),
+    /// Stop recording lines starting with this string
+    ClearReport(Vec<u8>),
+    /// Set the timeout for the following expect strings
+    Timeout(Duration),
+    /// Add a message to the outcome
+    Say(String),
+    /// Wait for a string; if it times out, send each fallback string in turn and wait for
+    /// the string after it
+    Expect {
+        /// The string to wait for; an empty string matches at once
+        expect: Vec<u8>,
+        /// Sub-expects: what to send and what to wait for next
+        fallbacks: Vec<(SendString, Vec<u8>)>,
+    },
+    /// Send a string
+    Send(SendString),
+}
+
+/// A parsed chat script
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Script {
+    /// The steps, in order
+    pub steps: Vec<Step>,
+}
+
+impl Script {
+    /// Parse chat script syntax
+    ///
+    /// Tokens are separated by whitespace and may be quoted with `'` or `"`; lines starting
+    /// with `#` are comments.
+    ///
+    /// ## Errors
+    ///
+    /// [`ChatError::Parse`] for unterminated quotes, keywords without an argument and
+    /// unsupported escapes.
+    pub fn parse(text: &str) -> Result<Self, ChatError> {
+        let mut tokens = tokenize(text)?.into_iter();
+        let mut steps = Vec::new();
+        let mut expecting = true;
+        while let Some(token) = tokens.next() {
+            let keyword = match token.as_str() {
+                "ABORT" | "CLR_ABORT" | "REPORT" | "CLR_REPORT" | "TIMEOUT" | "SAY" | "ECHO"
+                | "HANGUP" => token,
+                _ => {
+                    steps.push(if expecting {
+                        parse_expect(&token)?
+                    } else {
+                        Step::Send(parse_send(&token)?)
+                    });
+                    expecting = !expecting;
+                    continue;
+                }
+            };
+            let argument = tokens
+                .next()
+                .ok_or_else(|| ChatError::Parse(format!("{keyword} needs an argument")))?;
+            let step = match keyword.as_str() {
+                "ABORT" => Step::Abort(unescape(&argument)?),
+                "CLR_ABORT" => Step::ClearAbort(unescape(&argument)?),
+                "REPORT" => Step::Report(unescape(&argument)?),
+                "CLR_REPORT" => Step::ClearReport(unescape(&argument)?),
+                "TIMEOUT" => {
+                    let seconds: u64 = argument
+                        .parse()
+                        .map_err(|_| ChatError::Parse(format!("invalid TIMEOUT {argument:?}")))?;
+                    Step::Timeout(Duration::from_secs(seconds))
+                }
+                "SAY" => Step::Say(String::from_utf8_lossy(&unescape(&argument)?).into_owned()),
+                _ => continue,
+            };
+            steps.push(step);
+        }
+        Ok(Self { steps })
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, ChatError> {
+    let mut tokens = Vec::new();
+    for line in text.lines() {
+        if line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut chars = line.chars().peekable();
+        loop {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            let first = match chars.next() {
+                Some(first) => first,
+                None => break,
+            };
+            let mut token = String::new();
+            let quote = if first == '\'' || first == '"' {
+                Some(first)
+            } else {
+                token.push(first);
+                if first == '\\' {
+                    token.extend(chars.next());
+                }
+                None
+            };
+            loop {
+                match (chars.next(), quote) {
+                    (None, Some(_)) => {
+                        return Err(ChatError::Parse(format!("unterminated quote in {line:?}")))
+                    }
+                    (None, None) => break,
+                    (Some(c), Some(q)) if c == q => break,
+                    (Some(c), None) if c.is_whitespace() => break,
+                    (Some('\\'), _) => {
+                        token.push('\\');
+                        token.extend(chars.next());
+                    }
+                    (Some(c), _) => token.push(c),
+                }
+            }
+            tokens.push(token);
+        }
+    }
+    Ok(tokens)
+}
+
+/// Split `OK-AT-OK` into the expect string and its fallbacks
+fn parse_expect(token: &str) -> Result<Step, ChatError> {
+    let mut pieces = Vec::new();
+    let mut piece = String::new();
+    let mut chars = token.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                piece.push('\\');
+                piece.extend(chars.next());
+            }
+            '-' => pieces.push(std::mem::take(&mut piece)),
+            _ => piece.push(c),
+        }
+    }
+    pieces.push(piece);
+    if pieces.len() % 2 == 0 {
+        return Err(ChatError::Parse(format!(
+            "sub-expect {token:?} ends with a send string"
+        )));
+    }
+    let mut pieces = pieces.into_iter();
+    let expect = unescape(&pieces.next().unwrap_or_default())?;
+    let mut fallbacks = Vec::new();
+    while let (Some(send), Some(expect)) = (pieces.next(), pieces.next()) {
+        fallbacks.push((parse_send(&send)?, unescape(&expect)?));
+    }
+    Ok(Step::Expect { expect, fallbacks })
+}
+
+fn parse_send(token: &str) -> Result<SendString, ChatError> {
+    match token {
+        "EOT" => {
+            return Ok(SendString {
+                parts: vec![SendPart::Bytes(vec![0x04])],
+                carriage_return: false,
+            })
+        }
+        "BREAK" => return Err(ChatError::Parse("BREAK is not supported".to_string())),
+        _ => {}
+    }
+    let mut parts = Vec::new();
+    let mut bytes = Vec::new();
+    let mut carriage_return = true;
+    let mut chars = token.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&'c') && chars.clone().nth(1).is_none() => {
+                chars.next();
+                carriage_return = false;
+            }
+            '\\' => match chars.peek() {
+                Some('d') | Some('p') => {
+                    let delay = if chars.next() == Some('d') {
+                        DELAY
+                    } else {
+                        PAUSE
+                    };
+                    if !bytes.is_empty() {
+                        parts.push(SendPart::Bytes(std::mem::take(&mut bytes)));
+                    }
+                    parts.push(SendPart::Delay(delay));
+                }
+                Some('N') => {
+                    chars.next();
+                    bytes.push(0);
+                }
+                _ => escape_sequence(&mut chars, &mut bytes)?,
+            },
+            '^' => control_character(&mut chars, &mut bytes)?,
+            _ => push_char(c, &mut bytes),
+        }
+    }
+    if !bytes.is_empty() {
+        parts.push(SendPart::Bytes(bytes));
+    }
+    Ok(SendString {
+        parts,
+        carriage_return,
+    })
+}
+
+/// Resolve the escapes valid in expect, abort and report strings
+fn unescape(token: &str) -> Result<Vec<u8>, ChatError> {
+    let mut bytes = Vec::new();
+    let mut chars = token.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => escape_sequence(&mut chars, &mut bytes)?,
+            '^' => control_character(&mut chars, &mut bytes)?,
+            _ => push_char(c, &mut bytes),
+        }
+    }
+    Ok(bytes)
+}
+
+/// Resolve the escape after a backslash
+fn escape_sequence(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    bytes: &mut Vec<u8>,
+) -> Result<(), ChatError> {
+    let c = chars
+        .next()
+        .ok_or_else(|| ChatError::Parse("trailing backslash".to_string()))?;
+    match c {
+        'b' => bytes.push(0x08),
+        'n' => bytes.push(b'\n'),
+        'r' => bytes.push(b'\r'),
+        's' => bytes.push(b' '),
+        't' => bytes.push(b'\t'),
+        'q' => {}
+        '0'..='7' => {
+            let mut value = c.to_digit(8).unwrap_or(0);
+            for _ in 0..2 {
+                match chars.peek().and_then(|d| d.to_digit(8)) {
+                    Some(digit) => {
+                        value = value * 8 + digit;
+                        chars.next();
+                    }
+                    None => break,
+                }
+            }
+            bytes.push(value as u8);
+        }
+        'c' | 'd' | 'p' | 'N' | 'K' => {
+            return Err(ChatError::Parse(format!("\\{c} is not supported here")))
+        }
+        _ => push_char(c, bytes),
+    }
+    Ok(())
+}
+
+fn control_character(
+    chars: &mut std::iter::Peekable<std::str::Chars<'_>>,
+    bytes: &mut Vec<u8>,
+) -> Result<(), ChatError> {
+    match chars.next() {
+        Some('?') => bytes.push(0x7F),
+        Some(c @ '@'..='_') => bytes.push(c as u8 & 0x1F),
+        Some(c @ 'a'..='z') => bytes.push(c as u8 & 0x1F),
+        Some(c) => return Err(ChatError::Parse(format!("^{c} is not a control character"))),
+        None => bytes.push(b'^'),
+    }
+    Ok(())
+}
+
+fn push_char(c: char, bytes: &mut Vec<u8>) {
+    let mut buf = [0u8; 4];
+    bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+}
+
+/// What a successful script produced
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// Lines that matched a `REPORT` string, without their line ending
+    pub reports: Vec<String>,
+    /// `SAY` messages, in order
+    pub messages: Vec<String>,
+}
+
+/// Why a script failed
+#[derive(Debug)]
+pub enum ChatError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The script could not be parsed
+    Parse(String),
+    /// An `ABORT` string was received; `index` counts the abort strings in the order they
+    /// were defined
+    Aborted {
+        /// Position of the abort string in the script's `ABORT` list
+        index: usize,
+        /// The abort string
+        text: String,
+    },
+    /// An expect string, and all its sub-expects, timed out
+    Timeout(String),
+    /// The stream reached end-of-file while waiting
+    Eof,
+}
+
+impl ChatError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            ChatError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+
+    /// The exit status `chat` reports for this error
+    ///
+    /// 1 for a bad script, 2 for I/O errors, 3 for a timeout and 4 plus the index for an
+    /// `ABORT` string.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ChatError::Parse(_) => 1,
+            ChatError::Io(_) | ChatError::Eof => 2,
+            ChatError::Timeout(_) => 3,
+            ChatError::Aborted { index, .. } => 4 + *index as i32,
+        }
+    }
+}
+
+impl fmt::Display for ChatError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChatError::Io(e) => write!(f, "{e}"),
+            ChatError::Parse(why) => write!(f, "invalid chat script: {why}"),
+            ChatError::Aborted { text, .. } => write!(f, "aborted on {text:?}"),
+            ChatError::Timeout(expect) => write!(f, "timed out waiting for {expect:?}"),
+            ChatError::Eof => write!(f, "end of file"),
+        }
+    }
+}
+
+impl std::error::Error for ChatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ChatError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ChatError {
+    fn from(e: io::Error) -> Self {
+        ChatError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    /// Ready for the next step
+    Next,
+    /// Waiting for the expect string of attempt `attempt` (0 is the main string)
+    Expecting {
+        attempt: usize,
+        deadline: Instant,
+    },
+    /// Sending; afterwards wait for the sub-expect `then`, or go on with the next step
+    Sending {
+        parts: VecDeque<SendPart>,
+        resume_at: Option<Instant>,
+        then: Option<usize>,
+    },
+    Done,
+}
+
+/// Non-blocking chat script runner
+#[derive(Debug)]
+pub struct Chat<S = SerialStream> {
+    console: Expect<S>,
+    steps: Vec<Step>,
+    index: usize,
+    state: State,
+    timeout: Duration,
+    aborts: Vec<Vec<u8>>,
+    reports: Vec<Vec<u8>>,
+    outcome: Outcome,
+    result: Option<Result<Outcome, ChatError>>,
+}
+
+impl<S> Chat<S>
+where
+    S: Read + Write + Source,
+{
+    /// Run `script` on `console`
+    pub fn new(console: Expect<S>, script: Script) -> Self {
+        Self {
+            console,
+            steps: script.steps,
+            index: 0,
+            state: State::Next,
+            timeout: DEFAULT_TIMEOUT,
+            aborts: Vec::new(),
+            reports: Vec::new(),
+            outcome: Outcome::default(),
+            result: None,
+        }
+    }
+
+    /// Get a reference to the expect engine
+    pub fn get_ref(&self) -> &Expect<S> {
+        &self.console
+    }
+
+    /// Get a mutable reference to the expect engine
+    pub fn get_mut(&mut self) -> &mut Expect<S> {
+        &mut self.console
+    }
+
+    /// Consume the runner, returning the expect engine
+    pub fn into_inner(self) -> Expect<S> {
+        self.console
+    }
+
+    /// Returns `true` once the script has succeeded or failed
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Sending {
+                resume_at: Some(resume_at),
+                ..
+            } => Some(resume_at),
+            _ => self.console.next_deadline(),
+        }
+    }
+
+    /// Take the outcome of the script once it has finished
+    pub fn poll_result(&mut self) -> Option<Result<Outcome, ChatError>> {
+        self.result.take()
+    }
+
+    /// Drive the script
+    ///
+    /// Send strings that do not fit in the write queue wait for it to drain, so register
+    /// the runner for writable as well as readable events.
+    ///
+    /// ## Errors
+    ///
+    /// I/O errors from the stream; they also end the script.
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        loop {
+            if let Err(e) = self.console.handle_io(now) {
+                self.fail(ChatError::Io(io::Error::new(e.kind(), e.to_string())));
+                return Err(e);
+            }
+            if !self.advance(now) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run the script to the end on a private poll instance
+    ///
+    /// ## Errors
+    ///
+    /// The error that ended the script, or errors from creating the poll instance.
+    pub fn run_blocking(mut self) -> Result<Outcome, ChatError> {
+        let mut poll = Poll::new()?;
+        let mut events = Events::with_capacity(4);
+        poll.registry().register(
+            &mut self.console,
+            Token(0),
+            Interest::READABLE | Interest::WRITABLE,
+        )?;
+        loop {
+            // I/O errors also end the script with a result
+            let _ = self.handle_io(Instant::now());
+            if let Some(result) = self.poll_result() {
+                return result;
+            }
+            let timeout = self
+                .next_deadline()
+                .map(|d| d.saturating_duration_since(Instant::now()));
+            match poll.poll(&mut events, timeout) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => result?,
+            }
+        }
+    }
+
+    /// Make progress without I/O; returns `true` if anything changed
+    fn advance(&mut self, now: Instant) -> bool {
+        let mut progressed = false;
+        loop {
+            match self.state {
+                State::Done => return progressed,
+                State::Next => {
+                    let step = match self.steps.get(self.index) {
+                        Some(step) => step.clone(),
+                        None => {
+                            self.state = State::Done;
+                            self.result = Some(Ok(std::mem::take(&mut self.outcome)));
+                            return true;
+                        }
+                    };
+                    self.start_step(step, now);
+                }
+                State::Expecting { attempt, deadline } => match self.console.poll_match() {
+                    None => return progressed,
+                    Some(Ok(found)) => self.on_match(found, attempt, deadline, now),
+                    Some(Err(ExpectError::Timeout(_))) => self.on_timeout(attempt),
+                    Some(Err(ExpectError::Eof(_))) => self.fail(ChatError::Eof),
+                    Some(Err(ExpectError::Io(e))) => self.fail(ChatError::Io(e)),
+                },
+                State::Sending {
+                    ref mut parts,
+                    ref mut resume_at,
+                    then,
+                } => {
+                    if resume_at.is_some_and(|at| now < at) {
+                        return progressed;
+                    }
+                    *resume_at = None;
+                    match parts.pop_front() {
+                        Some(SendPart::Delay(delay)) => *resume_at = Some(now + delay),
+                        Some(SendPart::Bytes(mut bytes)) => {
+                            // Send what fits; the rest waits for the queue to drain
+                            let n = bytes.len().min(self.console.send_capacity());
+                            if n < bytes.len() {
+                                parts.push_front(SendPart::Bytes(bytes.split_off(n)));
+                                if n == 0 {
+                                    return progressed;
+                                }
+                            }
+                            if let Err(e) = self.console.send(bytes) {
+                                self.fail(ChatError::Io(e));
+                            }
+                        }
+                        None => match then {
+                            Some(attempt) => self.start_expect(attempt, now + self.timeout, now),
+                            None => {
+                                self.index += 1;
+                                self.state = State::Next;
+                            }
+                        },
+                    }
+                }
+            }
+            progressed = true;
+        }
+    }
+
+    fn start_step(&mut self, step: Step, now: Instant) {
+        match step {
+            Step::Abort(text) => self.aborts.push(text),
+            Step::ClearAbort(text) => self.aborts.retain(|a| *a != text),
+            Step::Report(text) => self.reports.push(text),
+            Step::ClearReport(text) => self.reports.retain(|r| *r != text),
+            Step::Timeout(timeout) => self.timeout = timeout,
+            Step::Say(message) => self.outcome.messages.push(message),
+            Step::Expect { .. } => {
+                self.start_expect(0, now + self.timeout, now);
+                return;
+            }
+            Step::Send(send) => {
+                self.send(send, None);
+                return;
+            }
+        }
+        self.index += 1;
+    }
+
+    fn expect_string(&self, attempt: usize) -> &[u8] {
+        match self.steps[self.index] {
+            Step::Expect {
+                ref expect,
+                ref fallbacks,
+            } => match attempt {
+                0 => expect,
+                _ => &fallbacks[attempt - 1].1,
+            },
+            _ => unreachable!("not an expect step"),
+        }
+    }
+
+    fn start_expect(&mut self, attempt: usize, deadline: Instant, now: Instant) {
+        let expect = self.expect_string(attempt).to_vec();
+        if expect.is_empty() {
+            self.index += 1;
+            self.state = State::Next;
+            return;
+        }
+        let mut patterns = vec![Pattern::Exact(expect)];
+        patterns.extend(self.aborts.iter().map(|a| Pattern::Exact(a.clone())));
+        patterns.extend(self.reports.iter().map(|r| {
+            let text = regex::escape(&String::from_utf8_lossy(r));
+            Pattern::Regex(Regex::new(&format!(r"{text}(?-u:[^\r\n])*\r?\n")).expect("valid regex"))
+        }));
+        self.console
+            .expect_any(patterns, deadline.saturating_duration_since(now));
+        self.state = State::Expecting { attempt, deadline };
+    }
+
+    fn on_match(&mut self, found: Match, attempt: usize, deadline: Instant, now: Instant) {
+        let index = found.index;
+        if index <= self.aborts.len() {
+            // A `REPORT` string starting where the expect or abort string does loses the
+            // tie, so look for it in the matched text as well
+            self.report_within(&found);
+        }
+        if index == 0 {
+            self.index += 1;
+            self.state = State::Next;
+        } else if index <= self.aborts.len() {
+            let text = String::from_utf8_lossy(&self.aborts[index - 1]).into_owned();
+            self.fail(ChatError::Aborted {
+                index: index - 1,
+                text,
+            });
+        } else {
+            let line = found.matched.trim_end_matches(['\r', '\n']).to_string();
+            self.outcome.reports.push(line);
+            if now < deadline {
+                self.start_expect(attempt, deadline, now);
+            } else {
+                self.on_timeout(attempt);
+            }
+        }
+    }
+
+    /// Record the `REPORT` strings that start within the text consumed by `found`, up to
+    /// the end of their line or of the text received so far
+    fn report_within(&mut self, found: &Match) {
+        let text = format!("{}{}{}", found.before, found.matched, found.after);
+        let consumed = found.before.len() + found.matched.len();
+        for report in &self.reports {
+            let report = String::from_utf8_lossy(report);
+            if let Some(start) = text.find(&*report).filter(|&start| start < consumed) {
+                let line = text[start..].split(['\r', '\n']).next().unwrap_or_default();
+                self.outcome.reports.push(line.to_string());
+            }
+        }
+    }
+
+    fn on_timeout(&mut self, attempt: usize) {
+        let fallback = match self.steps[self.index] {
+            Step::Expect { ref fallbacks, .. } => {
+                fallbacks.get(attempt).map(|(send, _)| send.clone())
+            }
+            _ => None,
+        };
+        match fallback {
+            Some(send) => self.send(send, Some(attempt + 1)),
+            None => {
+                let expect = String::from_utf8_lossy(self.expect_string(attempt)).into_owned();
+                self.fail(ChatError::Timeout(expect));
+            }
+        }
+    }
+
+    fn send(&mut self, send: SendString, then: Option<usize>) {
+        let mut parts: VecDeque<SendPart> = send.parts.into();
+        if send.carriage_return {
+            parts.push_back(SendPart::Bytes(vec![b'\r']));
+        }
+        self.state = State::Sending {
+            parts,
+            resume_at: None,
+            then,
+        };
+    }
+
+    fn fail(&mut self, e: ChatError) {
+        self.state = State::Done;
+        self.result = Some(Err(e));
+    }
+}
+
+impl<S> Source for Chat<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.console.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.console.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.console.deregister(registry)
+    }
+}
@@ -0,0 +1,1324 @@
+//! GSM 07.10 / 3GPP TS 27.010 multiplexer (CMUX)
+//!
+//! Cellular modems can carry several virtual serial channels over one UART once switched
+//! into multiplexer mode with `AT+CMUX`.  Each channel is a Data Link Connection (DLC)
+//! identified by its DLCI; DLCI 0 is the control channel.  [`Mux`] implements the
+//! multiplexer on top of a [`SerialStream`] and hands out each data channel as a
+//! [`Channel`], a mio [`Source`] with [`Read`] and [`Write`], so code written for a serial
+//! port can run on a channel unchanged.
+//!
+//! Both framing options are supported ([`Mode`]): *basic*, with `0xF9` flags and a length
+//! field, and *advanced*, with `0x7E` flags and HDLC-style transparency.  Channels are
+//! opened with SABM/UA, closed with DISC/UA, carry data in UIH frames, and exchange
+//! emulated V.24 control lines with the MSC control message ([`ModemSignals`]).
+//!
+//! Channels are backed by Unix domain socket pairs: the [`Mux`] keeps one end of each pair
+//! and forwards between it and the serial line.  The mux registers its own ends under the
+//! token it is registered with, so call [`Mux::handle_io`] on every event for that token
+//! and whenever the deadline from [`Mux::next_deadline`] expires.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::cmux::{Mode, Mux, MuxEvent};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! // The modem has already been switched into multiplexer mode with AT+CMUX=0
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let mut mux = Mux::new(stream, Mode::Basic);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(16);
+//! poll.registry()
+//!     .register(&mut mux, Token(0), Interest::READABLE | Interest::WRITABLE)
+//!     .unwrap();
+//! mux.start().unwrap();
+//!
+//! let mut channels = Vec::new();
+//! loop {
+//!     let timeout = mux
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//!     mux.handle_io(Instant::now()).unwrap();
+//!     while let Some(event) = mux.poll_event() {
+//!         if let MuxEvent::Started = event {
+//!             // One channel for AT commands, one for GNSS NMEA output
+//!             channels.push(mux.open_channel(1).unwrap());
+//!             channels.push(mux.open_channel(2).unwrap());
+//!         }
+//!     }
+//! }
+//! ```
+use crate::codec::hdlc::{ESCAPE, ESCAPE_XOR, FLAG as ADVANCED_FLAG};
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::crc::CRC_8_ROHC;
+use crate::SerialStream;
+use mio::net::UnixStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Frame delimiter of the basic option
+pub const BASIC_FLAG: u8 = 0xF9;
+
+/// The control channel
+pub const CONTROL_DLCI: u8 = 0;
+
+/// Highest valid DLCI
+pub const MAX_DLCI: u8 = 63;
+
+/// Default maximum information field length (N1) of the basic option
+pub const DEFAULT_BASIC_FRAME_SIZE: usize = 31;
+
+/// Largest information field the two length octets of the basic option can describe
+pub const MAX_BASIC_FRAME_SIZE: usize = 0x7FFF;
+
+/// Default maximum information field length (N1) of the advanced option
+pub const DEFAULT_ADVANCED_FRAME_SIZE: usize = 64;
+
+/// Default acknowledgement timer (T1)
+pub const DEFAULT_ACK_TIMER: Duration = Duration::from_millis(100);
+
+/// Default number of retransmissions (N2)
+pub const DEFAULT_RETRIES: u8 = 3;
+
+/// Received bytes buffered for a channel before asking the peer to stop sending
+const RX_HIGH_WATER_MARK: usize = 16 * 1024;
+
+const POLL_FINAL: u8 = 0x10;
+const EA: u8 = 0x01;
+const CR: u8 = 0x02;
+
+const MSG_CLD: u8 = 0x30;
+const MSG_TEST: u8 = 0x08;
+const MSG_FCON: u8 = 0x28;
+const MSG_FCOFF: u8 = 0x18;
+const MSG_MSC: u8 = 0x38;
+const MSG_NSC: u8 = 0x04;
+
+/// Framing option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `0xF9` flags and a length field
+    Basic,
+    /// `0x7E` flags with byte stuffing
+    Advanced,
+}
+
+impl Mode {
+    fn default_frame_size(self) -> usize {
+        match self {
+            Mode::Basic => DEFAULT_BASIC_FRAME_SIZE,
+            Mode::Advanced => DEFAULT_ADVANCED_FRAME_SIZE,
+        }
+    }
+}
+
+/// Frame type, from the control field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// Set Asynchronous Balanced Mode: open a DLC
+    Sabm,
+    /// Unnumbered Acknowledgement
+    Ua,
+    /// Disconnected Mode: refuse a DLC
+    Dm,
+    /// Disconnect: close a DLC
+    Disc,
+    /// Unnumbered Information with Header check, the usual data frame
+    Uih,
+    /// Unnumbered Information
+    Ui,
+}
+
+impl FrameType {
+    fn control(self) -> u8 {
+        match self {
+            FrameType::Sabm => 0x2F,
+            FrameType::Ua => 0x63,
+            FrameType::Dm => 0x0F,
+            FrameType::Disc => 0x43,
+            FrameType::Uih => 0xEF,
+            FrameType::Ui => 0x03,
+        }
+    }
+
+    fn from_control(control: u8) -> Option<Self> {
+        Some(match control & !POLL_FINAL {
+            0x2F => FrameType::Sabm,
+            0x63 => FrameType::Ua,
+            0x0F => FrameType::Dm,
+            0x43 => FrameType::Disc,
+            0xEF => FrameType::Uih,
+            0x03 => FrameType::Ui,
+            _ => return None,
+        })
+    }
+}
+
+/// A multiplexer frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    /// Data link connection identifier, 0 to 63
+    pub dlci: u8,
+    /// The C/R bit of the address field
+    pub cr: bool,
+    /// Frame type
+    pub frame_type: FrameType,
+    /// The poll/final bit
+    pub poll_final: bool,
+    /// Information field
+    pub info: Vec<u8>,
+}
+
+impl Frame {
+    fn address(&self) -> u8 {
+        (self.dlci << 2) | if self.cr { CR } else { 0 } | EA
+    }
+
+    fn control(&self) -> u8 {
+        self.frame_type.control() | if self.poll_final { POLL_FINAL } else { 0 }
+    }
+
+    /// Compute the FCS; UIH frames only cover the header
+    fn fcs(&self, header: &[u8]) -> u8 {
+        let crc = CRC_8_ROHC.update(CRC_8_ROHC.init_value(), header);
+        let crc = match self.frame_type {
+            FrameType::Uih => crc,
+            _ => CRC_8_ROHC.update(crc, &self.info),
+        };
+        !CRC_8_ROHC.finalize(crc)
+    }
+}
+
+/// Errors reported by the multiplexer
+#[derive(Debug)]
+pub enum CmuxError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// A frame failed its frame check sequence
+    BadFcs,
+    /// A frame's information field is longer than the maximum frame size
+    FrameTooLong(usize),
+    /// A frame is malformed
+    InvalidFrame(&'static str),
+    /// The DLCI is out of range or already in use
+    InvalidDlci(u8),
+    /// The control channel is not open
+    NotStarted,
+}
+
+impl CmuxError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            CmuxError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CmuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CmuxError::Io(e) => write!(f, "{e}"),
+            CmuxError::BadFcs => write!(f, "frame check sequence mismatch"),
+            CmuxError::FrameTooLong(len) => write!(f, "frame of {len} bytes is too long"),
+            CmuxError::InvalidFrame(why) => write!(f, "invalid frame: {why}"),
+            CmuxError::InvalidDlci(dlci) => write!(f, "DLCI {dlci} is invalid or in use"),
+            CmuxError::NotStarted => write!(f, "multiplexer control channel is not open"),
+        }
+    }
+}
+
+impl std::error::Error for CmuxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CmuxError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CmuxError {
+    fn from(e: io::Error) -> Self {
+        CmuxError::Io(e)
+    }
+}
+
+/// Encodes and decodes multiplexer frames
+#[derive(Debug, Clone)]
+pub struct CmuxCodec {
+    mode: Mode,
+    max_frame_size: usize,
+}
+
+impl CmuxCodec {
+    /// Create a codec using the default maximum frame size of `mode`
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            max_frame_size: mode.default_frame_size(),
+        }
+    }
+
+    /// Set the maximum information field length (N1)
+    ///
+    /// The basic option is limited to [`MAX_BASIC_FRAME_SIZE`]; larger sizes are clamped.
+    #[must_use]
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = match self.mode {
+            Mode::Basic => max_frame_size.min(MAX_BASIC_FRAME_SIZE),
+            Mode::Advanced => max_frame_size,
+        };
+        self
+    }
+
+    /// The framing option
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The maximum information field length (N1)
+    pub fn max_frame_size(&self) -> usize {
+        self.max_frame_size
+    }
+
+    fn decode_basic(&mut self, src: &mut Vec<u8>) -> Result<Option<Frame>, CmuxError> {
+        loop {
+            match src.iter().position(|&b| b != BASIC_FLAG) {
+                // Keep one flag, it may open the next frame
+                Some(start) if start > 0 => drop(src.drain(..start - 1)),
+                Some(_) => {
+                    src.drain(..1);
+                    continue;
+                }
+                None => {
+                    let keep = usize::from(!src.is_empty());
+                    src.drain(..src.len() - keep);
+                    return Ok(None);
+                }
+            }
+            if src.len() < 4 {
+                return Ok(None);
+            }
+            let (len, header_len) = if src[3] & EA != 0 {
+                (usize::from(src[3] >> 1), 3)
+            } else if src.len() < 5 {
+                return Ok(None);
+            } else {
+                (usize::from(src[3] >> 1) | (usize::from(src[4]) << 7), 4)
+            };
+            if len > self.max_frame_size {
+                src.drain(..1);
+                return Err(CmuxError::FrameTooLong(len));
+            }
+            let total = 1 + header_len + len + 2;
+            if src.len() < total {
+                return Ok(None);
+            }
+            if src[total - 1] != BASIC_FLAG {
+                src.drain(..1);
+                return Err(CmuxError::InvalidFrame("missing closing flag"));
+            }
+            let header = &src[1..1 + header_len];
+            let info = &src[1 + header_len..1 + header_len + len];
+            let frame = parse_frame(header[0], header[1], info);
+            let result = frame.and_then(|frame| {
+                if frame.fcs(header) == src[total - 2] {
+                    Ok(frame)
+                } else {
+                    Err(CmuxError::BadFcs)
+                }
+            });
+            // The closing flag stays in the buffer
+            src.drain(..total - 1);
+            return result.map(Some);
+        }
+    }
+
+    fn decode_advanced(&mut self, src: &mut Vec<u8>) -> Result<Option<Frame>, CmuxError> {
+        loop {
+            let start = match src.iter().position(|&b| b == ADVANCED_FLAG) {
+                Some(start) => start,
+                None => {
+                    src.clear();
+                    return Ok(None);
+                }
+            };
+            src.drain(..start);
+            let end = match src[1..].iter().position(|&b| b == ADVANCED_FLAG) {
+                Some(end) => end + 1,
+                None if src.len() > 2 * (self.max_frame_size + 3) + 1 => {
+                    let len = src.len();
+                    src.clear();
+                    return Err(CmuxError::FrameTooLong(len));
+                }
+                None => return Ok(None),
+            };
+            if end == 1 {
+                src.drain(..1);
+                continue;
+            }
+
+            let mut content = Vec::with_capacity(end);
+            let mut escaped = false;
+            for &b in &src[1..end] {
+                if escaped {
+                    content.push(b ^ ESCAPE_XOR);
+                    escaped = false;
+                } else if b == ESCAPE {
+                    escaped = true;
+                } else {
+                    content.push(b);
+                }
+            }
+            // The closing flag stays in the buffer
+            src.drain(..end);
+
+            if content.len() < 3 || escaped {
+                return Err(CmuxError::InvalidFrame("frame too short"));
+            }
+            let info = &content[2..content.len() - 1];
+            if info.len() > self.max_frame_size {
+                return Err(CmuxError::FrameTooLong(info.len()));
+            }
+            let frame = parse_frame(content[0], content[1], info)?;
+            if frame.fcs(&content[..2]) != content[content.len() - 1] {
+                return Err(CmuxError::BadFcs);
+            }
+            return Ok(Some(frame));
+        }
+    }
+}
+
+fn parse_frame(address: u8, control: u8, info: &[u8]) -> Result<Frame, CmuxError> {
+    if address & EA == 0 {
+        return Err(CmuxError::InvalidFrame("extended address"));
+    }
+    let frame_type =
+        FrameType::from_control(control).ok_or(CmuxError::InvalidFrame("unknown frame type"))?;
+    Ok(Frame {
+        dlci: address >> 2,
+        cr: address & CR != 0,
+        frame_type,
+        poll_final: control & POLL_FINAL != 0,
+        info: info.to_vec(),
+    })
+}
+
+impl Decoder for CmuxCodec {
+    type Item = Frame;
+    type Error = CmuxError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Frame>, CmuxError> {
+        match self.mode {
+            Mode::Basic => self.decode_basic(src),
+            Mode::Advanced => self.decode_advanced(src),
+        }
+    }
+}
+
+impl Encoder<&Frame> for CmuxCodec {
+    type Error = CmuxError;
+
+    fn encode(&mut self, frame: &Frame, dst: &mut Vec<u8>) -> Result<(), CmuxError> {
+        if frame.dlci > MAX_DLCI {
+            return Err(CmuxError::InvalidDlci(frame.dlci));
+        }
+        if frame.info.len() > self.max_frame_size {
+            return Err(CmuxError::FrameTooLong(frame.info.len()));
+        }
+        match self.mode {
+            Mode::Basic => {
+                let len = frame.info.len();
+                let mut header = vec![frame.address(), frame.control()];
+                if len <= 0x7F {
+                    header.push(((len as u8) << 1) | EA);
+                } else {
+                    header.push((len as u8) << 1);
+                    header.push((len >> 7) as u8);
+                }
+                dst.push(BASIC_FLAG);
+                dst.extend_from_slice(&header);
+                dst.extend_from_slice(&frame.info);
+                dst.push(frame.fcs(&header));
+                dst.push(BASIC_FLAG);
+            }
+            Mode::Advanced => {
+                let header = [frame.address(), frame.control()];
+                let fcs = frame.fcs(&header);
+                dst.push(ADVANCED_FLAG);
+                for &b in header.iter().chain(&frame.info).chain([&fcs]) {
+                    // XON and XOFF are escaped too, for links with software flow control
+                    if matches!(b, ADVANCED_FLAG | ESCAPE | 0x11 | 0x13) {
+                        dst.extend_from_slice(&[ESCAPE, b ^ ESCAPE_XOR]);
+                    } else {
+                        dst.push(b);
+                    }
+                }
+                dst.push(ADVANCED_FLAG);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Emulated V.24 control lines, carried by the MSC control message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModemSignals {
+    /// FC: the sender cannot accept frames on this channel
+    pub flow_control: bool,
+    /// RTC: ready to communicate (DTR from the host, DSR from the modem)
+    pub ready_to_communicate: bool,
+    /// RTR: ready to receive (RTS from the host, CTS from the modem)
+    pub ready_to_receive: bool,
+    /// IC: incoming call (RI)
+    pub incoming_call: bool,
+    /// DV: data valid (DCD)
+    pub data_valid: bool,
+}
+
+impl ModemSignals {
+    fn to_byte(self) -> u8 {
+        let mut byte = EA;
+        for (set, bit) in [
+            (self.flow_control, 0x02),
+            (self.ready_to_communicate, 0x04),
+            (self.ready_to_receive, 0x08),
+            (self.incoming_call, 0x40),
+            (self.data_valid, 0x80),
+        ] {
+            if set {
+                byte |= bit;
+            }
+        }
+        byte
+    }
+
+    fn from_byte(byte: u8) -> Self {
+        Self {
+            flow_control: byte & 0x02 != 0,
+            ready_to_communicate: byte & 0x04 != 0,
+            ready_to_receive: byte & 0x08 != 0,
+            incoming_call: byte & 0x40 != 0,
+            data_valid: byte & 0x80 != 0,
+        }
+    }
+}
+
+impl Default for ModemSignals {
+    /// Ready to communicate and to receive
+    fn default() -> Self {
+        Self {
+            flow_control: false,
+            ready_to_communicate: true,
+            ready_to_receive: true,
+            incoming_call: false,
+            data_valid: false,
+        }
+    }
+}
+
+/// State of an open or opening channel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelState {
+    /// SABM sent, waiting for UA
+    Opening,
+    /// Open for data
+    Open,
+    /// DISC sent, waiting for UA
+    Closing,
+}
+
+/// Something that happened on the multiplexer
+#[derive(Debug)]
+pub enum MuxEvent {
+    /// The control channel is open; data channels can now be opened
+    Started,
+    /// The peer accepted a channel opened with [`Mux::open_channel`]
+    Opened(u8),
+    /// The peer refused a channel or did not answer
+    Rejected(u8),
+    /// The peer opened a channel (only if accepting incoming channels)
+    Incoming(Channel),
+    /// A channel was closed by either side
+    Closed(u8),
+    /// The peer changed the control lines of a channel
+    SignalsChanged {
+        /// The channel
+        dlci: u8,
+        /// The peer's control lines
+        signals: ModemSignals,
+    },
+    /// The multiplexer was closed down; all channels are closed
+    CloseDown,
+}
+
+/// Multiplexer statistics
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MuxStats {
+    /// Frames received
+    pub rx_frames: u64,
+    /// Frames sent
+    pub tx_frames: u64,
+    /// Frames discarded because of a bad FCS or malformed header
+    pub bad_frames: u64,
+    /// SABM and DISC frames sent again after the acknowledgement timer expired
+    pub retransmissions: u64,
+}
+
+/// One multiplexer channel
+///
+/// Reading returns data the peer sent on the channel, writing sends data on it, and
+/// reading returns end-of-file once the channel is closed.  Dropping the channel closes it.
+#[derive(Debug)]
+pub struct Channel {
+    dlci: u8,
+    stream: UnixStream,
+}
+
+impl Channel {
+    /// The channel's DLCI
+    pub fn dlci(&self) -> u8 {
+        self.dlci
+    }
+}
+
+impl Read for Channel {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.read(buf)
+    }
+}
+
+impl Write for Channel {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Source for Channel {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.stream.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.stream.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.stream.deregister(registry)
+    }
+}
+
+#[derive(Debug)]
+struct Dlc {
+    state: ChannelState,
+    peer: Option<UnixStream>,
+    deadline: Option<Instant>,
+    retries_left: u8,
+    remote_signals: Option<ModemSignals>,
+    local_signals: ModemSignals,
+    rx_backlog: Vec<u8>,
+    tx_chunk: Option<Vec<u8>>,
+    throttled: bool,
+}
+
+impl Dlc {
+    fn new(state: ChannelState, peer: Option<UnixStream>) -> Self {
+        Self {
+            state,
+            peer,
+            deadline: None,
+            retries_left: 0,
+            remote_signals: None,
+            local_signals: ModemSignals::default(),
+            rx_backlog: Vec::new(),
+            tx_chunk: None,
+            throttled: false,
+        }
+    }
+}
+
+/// A CMUX multiplexer over a serial stream
+#[derive(Debug)]
+pub struct Mux<S = SerialStream> {
+    framed: FramedSerial<CmuxCodec, S>,
+    initiator: bool,
+    accept_incoming: bool,
+    ack_timer: Duration,
+    retries: u8,
+    dlcs: BTreeMap<u8, Dlc>,
+    events: VecDeque<MuxEvent>,
+    /// Frames other than channel data waiting for room in the write queue
+    tx_backlog: VecDeque<Frame>,
+    registration: Option<(Registry, Token)>,
+    flow_stopped: bool,
+    stats: MuxStats,
+}
+
+impl<S> Mux<S>
+where
+    S: Read + Write + Source,
+{
+    /// Create the initiating side, normally the host talking to a modem
+    pub fn new(stream: S, mode: Mode) -> Self {
+        Self::with_role(stream, mode, true)
+    }
+
+    /// Create the responding side, which accepts channels opened by the initiator
+    pub fn responder(stream: S, mode: Mode) -> Self {
+        Self::with_role(stream, mode, false)
+    }
+
+    fn with_role(stream: S, mode: Mode, initiator: bool) -> Self {
+        Self {
+            framed: FramedSerial::new(stream, CmuxCodec::new(mode)),
+            initiator,
+            accept_incoming: !initiator,
+            ack_timer: DEFAULT_ACK_TIMER,
+            retries: DEFAULT_RETRIES,
+            dlcs: BTreeMap::new(),
+            events: VecDeque::new(),
+            tx_backlog: VecDeque::new(),
+            registration: None,
+            flow_stopped: false,
+            stats: MuxStats::default(),
+        }
+    }
+
+    /// Set the maximum information field length (N1); both sides must agree on it
+    ///
+    /// The basic option is limited to [`MAX_BASIC_FRAME_SIZE`]; larger sizes are clamped.
+    #[must_use]
+    pub fn with_max_frame_size(mut self, max_frame_size: usize) -> Self {
+        let codec = self.framed.codec_mut();
+        *codec = codec.clone().with_max_frame_size(max_frame_size);
+        self
+    }
+
+    /// Set the acknowledgement timer (T1)
+    #[must_use]
+    pub fn with_ack_timer(mut self, ack_timer: Duration) -> Self {
+        self.ack_timer = ack_timer;
+        self
+    }
+
+    /// Set the number of retransmissions (N2)
+    #[must_use]
+    pub fn with_retries(mut self, retries: u8) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Accept or refuse channels opened by the peer
+    ///
+    /// The responder accepts incoming channels by default, the initiator refuses them.
+    #[must_use]
+    pub fn with_accept_incoming(mut self, accept_incoming: bool) -> Self {
+        self.accept_incoming = accept_incoming;
+        self
+    }
+
+    /// The framing option
+    pub fn mode(&self) -> Mode {
+        self.framed.codec().mode()
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.framed.get_mut()
+    }
+
+    /// Consume the multiplexer, returning the underlying stream
+    ///
+    /// Channels still open see end-of-file.
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+
+    /// Frame counters
+    pub fn stats(&self) -> MuxStats {
+        self.stats
+    }
+
+    /// Reset the frame counters
+    pub fn reset_stats(&mut self) {
+        self.stats = MuxStats::default();
+    }
+
+    /// Returns `true` once the control channel is open
+    pub fn is_started(&self) -> bool {
+        matches!(self.dlcs.get(&CONTROL_DLCI), Some(dlc) if dlc.state == ChannelState::Open)
+    }
+
+    /// The state of channel `dlci`, or `None` if it is closed
+    pub fn channel_state(&self, dlci: u8) -> Option<ChannelState> {
+        self.dlcs.get(&dlci).map(|dlc| dlc.state)
+    }
+
+    /// The control lines last reported by the peer for channel `dlci`
+    pub fn remote_signals(&self, dlci: u8) -> Option<ModemSignals> {
+        self.dlcs.get(&dlci).and_then(|dlc| dlc.remote_signals)
+    }
+
+    /// Take the next event
+    pub fn poll_event(&mut self) -> Option<MuxEvent> {
+        self.events.pop_front()
+    }
+
+    /// The next time [`handle_io`](Self::handle_io) must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.dlcs.values().filter_map(|dlc| dlc.deadline).min()
+    }
+
+    /// Open the control channel; [`MuxEvent::Started`] follows when the peer answers
+    pub fn start(&mut self) -> Result<(), CmuxError> {
+        if self.dlcs.contains_key(&CONTROL_DLCI) {
+            return Ok(());
+        }
+        self.dlcs
+            .insert(CONTROL_DLCI, Dlc::new(ChannelState::Opening, None));
+        self.send_command(CONTROL_DLCI, FrameType::Sabm, Instant::now())
+    }
+
+    /// Open channel `dlci`
+    ///
+    /// The channel can be used straight away: data written before the peer accepts it is
+    /// held until [`MuxEvent::Opened`], and a refused channel reads end-of-file.
+    pub fn open_channel(&mut self, dlci: u8) -> Result<Channel, CmuxError> {
+        if !self.is_started() {
+            return Err(CmuxError::NotStarted);
+        }
+        if dlci == CONTROL_DLCI || dlci > MAX_DLCI || self.dlcs.contains_key(&dlci) {
+            return Err(CmuxError::InvalidDlci(dlci));
+        }
+        let channel = self.create_channel(dlci, ChannelState::Opening)?;
+        self.send_command(dlci, FrameType::Sabm, Instant::now())?;
+        Ok(channel)
+    }
+
+    /// Close channel `dlci`; [`MuxEvent::Closed`] follows when the peer answers
+    pub fn close_channel(&mut self, dlci: u8) -> Result<(), CmuxError> {
+        self.close_channel_at(dlci, Instant::now())
+    }
+
+    fn close_channel_at(&mut self, dlci: u8, now: Instant) -> Result<(), CmuxError> {
+        match self.dlcs.get_mut(&dlci) {
+            Some(dlc) if dlci != CONTROL_DLCI && dlc.state != ChannelState::Closing => {
+                dlc.state = ChannelState::Closing;
+                self.send_command(dlci, FrameType::Disc, now)
+            }
+            Some(_) => Ok(()),
+            None => Err(CmuxError::InvalidDlci(dlci)),
+        }
+    }
+
+    /// Send the local control lines of channel `dlci` to the peer
+    pub fn set_signals(&mut self, dlci: u8, signals: ModemSignals) -> Result<(), CmuxError> {
+        let dlc = match self.dlcs.get_mut(&dlci) {
+            Some(dlc) if dlci != CONTROL_DLCI => dlc,
+            _ => return Err(CmuxError::InvalidDlci(dlci)),
+        };
+        dlc.local_signals = signals;
+        let signals = ModemSignals {
+            flow_control: signals.flow_control || dlc.throttled,
+            ..signals
+        };
+        self.send_msc(dlci, signals)
+    }
+
+    /// Close down the multiplexer; [`MuxEvent::CloseDown`] follows when the peer answers
+    ///
+    /// Afterwards the modem is back in AT command mode on the serial line.
+    pub fn close(&mut self) -> Result<(), CmuxError> {
+        if !self.is_started() {
+            return Err(CmuxError::NotStarted);
+        }
+        self.send_control(MSG_CLD, true, &[])
+    }
+
+    /// Drive the multiplexer: write queued frames, read and dispatch received frames,
+    /// forward channel data and handle retransmissions
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the serial stream are returned; bad frames are counted in
+    /// [`MuxStats`] and otherwise ignored.
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.framed.on_writable()?;
+        self.flush_tx_backlog()?;
+        loop {
+            match self.framed.read_frame() {
+                Ok(Some(frame)) => {
+                    self.stats.rx_frames += 1;
+                    self.on_frame(frame)?;
+                }
+                Ok(None) => break,
+                Err(CmuxError::Io(e)) => return Err(e),
+                Err(e) => {
+                    log::debug!("discarding frame: {}", e);
+                    self.stats.bad_frames += 1;
+                }
+            }
+        }
+        self.check_timers(now)?;
+        self.flush_backlogs()?;
+        self.forward_channels(now)?;
+        self.framed.on_writable()?;
+        self.flush_tx_backlog()
+    }
+
+    fn create_channel(&mut self, dlci: u8, state: ChannelState) -> io::Result<Channel> {
+        let (stream, mut peer) = UnixStream::pair()?;
+        if let Some((ref registry, token)) = self.registration {
+            registry.register(&mut peer, token, Interest::READABLE | Interest::WRITABLE)?;
+        }
+        self.dlcs.insert(dlci, Dlc::new(state, Some(peer)));
+        Ok(Channel { dlci, stream })
+    }
+
+    fn remove(&mut self, dlci: u8) -> Option<Dlc> {
+        let mut dlc = self.dlcs.remove(&dlci)?;
+        if let (Some((ref registry, _)), Some(ref mut peer)) = (&self.registration, &mut dlc.peer) {
+            let _ = registry.deregister(peer);
+        }
+        Some(dlc)
+    }
+
+    /// Queue `frame`, holding it back in order while the write queue is full
+    fn send(&mut self, frame: Frame) -> io::Result<()> {
+        self.tx_backlog.push_back(frame);
+        self.flush_tx_backlog()
+    }
+
+    /// Move held back frames into the write queue until it is full
+    fn flush_tx_backlog(&mut self) -> io::Result<()> {
+        while let Some(frame) = self.tx_backlog.pop_front() {
+            match self.framed.send(&frame) {
+                Ok(_) => self.stats.tx_frames += 1,
+                Err(CmuxError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.tx_backlog.push_front(frame);
+                    break;
+                }
+                Err(CmuxError::Io(e)) => return Err(e),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn send_command(
+        &mut self,
+        dlci: u8,
+        frame_type: FrameType,
+        now: Instant,
+    ) -> Result<(), CmuxError> {
+        if let Some(dlc) = self.dlcs.get_mut(&dlci) {
+            dlc.deadline = Some(now + self.ack_timer);
+            dlc.retries_left = self.retries;
+        }
+        self.send(Frame {
+            dlci,
+            cr: self.initiator,
+            frame_type,
+            poll_final: true,
+            info: Vec::new(),
+        })?;
+        Ok(())
+    }
+
+    fn send_response(&mut self, dlci: u8, frame_type: FrameType) -> io::Result<()> {
+        self.send(Frame {
+            dlci,
+            cr: !self.initiator,
+            frame_type,
+            poll_final: true,
+            info: Vec::new(),
+        })
+    }
+
+    fn send_control(&mut self, message: u8, command: bool, values: &[u8]) -> Result<(), CmuxError> {
+        let mut info = vec![
+            (message << 2) | if command { CR } else { 0 } | EA,
+            ((values.len() as u8) << 1) | EA,
+        ];
+        info.extend_from_slice(values);
+        self.send(Frame {
+            dlci: CONTROL_DLCI,
+            cr: self.initiator,
+            frame_type: FrameType::Uih,
+            poll_final: false,
+            info,
+        })?;
+        Ok(())
+    }
+
+    fn send_msc(&mut self, dlci: u8, signals: ModemSignals) -> Result<(), CmuxError> {
+        let address = (dlci << 2) | CR | EA;
+        self.send_control(MSG_MSC, true, &[address, signals.to_byte()])
+    }
+
+    fn on_frame(&mut self, frame: Frame) -> io::Result<()> {
+        let dlci = frame.dlci;
+        match frame.frame_type {
+            FrameType::Sabm => self.on_sabm(dlci),
+            FrameType::Disc => {
+                self.send_response(
+                    dlci,
+                    if self.dlcs.contains_key(&dlci) {
+                        FrameType::Ua
+                    } else {
+                        FrameType::Dm
+                    },
+                )?;
+                if dlci == CONTROL_DLCI {
+                    self.close_down();
+                } else if self.remove(dlci).is_some() {
+                    self.events.push_back(MuxEvent::Closed(dlci));
+                }
+                Ok(())
+            }
+            FrameType::Ua | FrameType::Dm => {
+                let accepted = frame.frame_type == FrameType::Ua;
+                let state = match self.dlcs.get_mut(&dlci) {
+                    Some(dlc) => dlc.state,
+                    None => return Ok(()),
+                };
+                match state {
+                    ChannelState::Opening if accepted => {
+                        let dlc = self.dlcs.get_mut(&dlci).unwrap();
+                        dlc.state = ChannelState::Open;
+                        dlc.deadline = None;
+                        self.events.push_back(if dlci == CONTROL_DLCI {
+                            MuxEvent::Started
+                        } else {
+                            MuxEvent::Opened(dlci)
+                        });
+                    }
+                    ChannelState::Opening => {
+                        self.remove(dlci);
+                        self.events.push_back(MuxEvent::Rejected(dlci));
+                    }
+                    ChannelState::Closing => {
+                        self.remove(dlci);
+                        self.events.push_back(MuxEvent::Closed(dlci));
+                    }
+                    ChannelState::Open => {}
+                }
+                Ok(())
+            }
+            FrameType::Uih | FrameType::Ui if dlci == CONTROL_DLCI => self.on_control(&frame.info),
+            FrameType::Uih | FrameType::Ui => {
+                match self.dlcs.get_mut(&dlci) {
+                    Some(dlc) if dlc.state == ChannelState::Open => {
+                        dlc.rx_backlog.extend_from_slice(&frame.info)
+                    }
+                    _ => log::debug!("discarding data for closed DLCI {}", dlci),
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn on_sabm(&mut self, dlci: u8) -> io::Result<()> {
+        if dlci == CONTROL_DLCI {
+            let dlc = self
+                .dlcs
+                .entry(CONTROL_DLCI)
+                .or_insert_with(|| Dlc::new(ChannelState::Opening, None));
+            if dlc.state != ChannelState::Open {
+                dlc.state = ChannelState::Open;
+                dlc.deadline = None;
+                self.events.push_back(MuxEvent::Started);
+            }
+            return self.send_response(dlci, FrameType::Ua);
+        }
+
+        if self.dlcs.contains_key(&dlci) {
+            // A retransmitted SABM for a channel that is already open
+            return self.send_response(dlci, FrameType::Ua);
+        }
+        if !self.accept_incoming || !self.is_started() {
+            return self.send_response(dlci, FrameType::Dm);
+        }
+        let channel = self.create_channel(dlci, ChannelState::Open)?;
+        self.events.push_back(MuxEvent::Incoming(channel));
+        self.send_response(dlci, FrameType::Ua)
+    }
+
+    fn on_control(&mut self, info: &[u8]) -> io::Result<()> {
+        if info.len() < 2 || info[1] & EA == 0 {
+            self.stats.bad_frames += 1;
+            return Ok(());
+        }
+        let message = info[0] >> 2;
+        let command = info[0] & CR != 0;
+        let len = usize::from(info[1] >> 1);
+        let values = match info.get(2..2 + len) {
+            Some(values) => values,
+            None => {
+                self.stats.bad_frames += 1;
+                return Ok(());
+            }
+        };
+
+        if !command {
+            if message == MSG_CLD {
+                self.close_down();
+            }
+            return Ok(());
+        }
+
+        let result = match message {
+            MSG_MSC if values.len() >= 2 => {
+                let dlci = values[0] >> 2;
+                let signals = ModemSignals::from_byte(values[1]);
+                if let Some(dlc) = self.dlcs.get_mut(&dlci) {
+                    dlc.remote_signals = Some(signals);
+                    self.events
+                        .push_back(MuxEvent::SignalsChanged { dlci, signals });
+                }
+                self.send_control(MSG_MSC, false, values)
+            }
+            MSG_CLD => {
+                let result = self.send_control(MSG_CLD, false, &[]);
+                self.close_down();
+                result
+            }
+            MSG_TEST => self.send_control(MSG_TEST, false, values),
+            MSG_FCON | MSG_FCOFF => {
+                self.flow_stopped = message == MSG_FCOFF;
+                self.send_control(message, false, &[])
+            }
+            _ => self.send_control(MSG_NSC, false, &info[..1]),
+        };
+        match result {
+            Err(CmuxError::Io(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn close_down(&mut self) {
+        let dlcis: Vec<u8> = self.dlcs.keys().copied().collect();
+        for dlci in dlcis {
+            self.remove(dlci);
+        }
+        self.events.push_back(MuxEvent::CloseDown);
+    }
+
+    fn check_timers(&mut self, now: Instant) -> io::Result<()> {
+        let expired: Vec<u8> = self
+            .dlcs
+            .iter()
+            .filter(|(_, dlc)| dlc.deadline.is_some_and(|d| now >= d))
+            .map(|(&dlci, _)| dlci)
+            .collect();
+        for dlci in expired {
+            let dlc = self.dlcs.get_mut(&dlci).unwrap();
+            let frame_type = match dlc.state {
+                ChannelState::Opening => FrameType::Sabm,
+                ChannelState::Closing => FrameType::Disc,
+                ChannelState::Open => {
+                    dlc.deadline = None;
+                    continue;
+                }
+            };
+            if dlc.retries_left == 0 {
+                let state = dlc.state;
+                self.remove(dlci);
+                self.events.push_back(match state {
+                    ChannelState::Opening => MuxEvent::Rejected(dlci),
+                    _ => MuxEvent::Closed(dlci),
+                });
+                continue;
+            }
+            dlc.retries_left -= 1;
+            dlc.deadline = Some(now + self.ack_timer);
+            self.stats.retransmissions += 1;
+            self.send(Frame {
+                dlci,
+                cr: self.initiator,
+                frame_type,
+                poll_final: true,
+                info: Vec::new(),
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Deliver received data to the channels, throttling the peer while a channel's
+    /// reader falls behind
+    fn flush_backlogs(&mut self) -> io::Result<()> {
+        let mut throttle = Vec::new();
+        for (&dlci, dlc) in self.dlcs.iter_mut() {
+            let peer = match dlc.peer {
+                Some(ref mut peer) => peer,
+                None => continue,
+            };
+            while !dlc.rx_backlog.is_empty() {
+                match peer.write(&dlc.rx_backlog) {
+                    Ok(n) => drop(dlc.rx_backlog.drain(..n)),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => {
+                        log::debug!("DLCI {} channel write failed: {}", dlci, e);
+                        dlc.rx_backlog.clear();
+                    }
+                }
+            }
+            let throttled = dlc.rx_backlog.len() >= RX_HIGH_WATER_MARK;
+            if throttled != dlc.throttled {
+                dlc.throttled = throttled;
+                throttle.push((dlci, dlc.local_signals, throttled));
+            }
+        }
+        for (dlci, signals, throttled) in throttle {
+            let signals = ModemSignals {
+                flow_control: signals.flow_control || throttled,
+                ..signals
+            };
+            if let Err(CmuxError::Io(e)) = self.send_msc(dlci, signals) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Send data written to the channels, while the serial write queue has room
+    ///
+    /// Data waits until held back frames are out, so that it never overtakes the UA that
+    /// opened its channel.
+    fn forward_channels(&mut self, now: Instant) -> io::Result<()> {
+        if self.flow_stopped || !self.tx_backlog.is_empty() {
+            return Ok(());
+        }
+        let max_frame_size = self.framed.codec().max_frame_size();
+        let mut buf = vec![0u8; max_frame_size];
+        let mut closed = Vec::new();
+        let dlcis: Vec<u8> = self.dlcs.keys().copied().collect();
+        'channels: for dlci in dlcis {
+            loop {
+                let dlc = self.dlcs.get_mut(&dlci).unwrap();
+                let peer_blocked = dlc.remote_signals.is_some_and(|s| s.flow_control);
+                if dlc.state != ChannelState::Open || peer_blocked {
+                    continue 'channels;
+                }
+                let info = match dlc.tx_chunk.take() {
+                    Some(chunk) => chunk,
+                    None => {
+                        let peer = match dlc.peer {
+                            Some(ref mut peer) => peer,
+                            None => continue 'channels,
+                        };
+                        match peer.read(&mut buf) {
+                            Ok(0) => {
+                                closed.push(dlci);
+                                continue 'channels;
+                            }
+                            Ok(n) => buf[..n].to_vec(),
+                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                                continue 'channels
+                            }
+                            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                            Err(e) => {
+                                log::debug!("DLCI {} channel read failed: {}", dlci, e);
+                                closed.push(dlci);
+                                continue 'channels;
+                            }
+                        }
+                    }
+                };
+                let frame = Frame {
+                    dlci,
+                    cr: self.initiator,
+                    frame_type: FrameType::Uih,
+                    poll_final: false,
+                    info,
+                };
+                match self.framed.send(&frame) {
+                    Ok(_) => self.stats.tx_frames += 1,
+                    Err(CmuxError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                        // The serial line is busy; try again after the next writable event
+                        self.dlcs.get_mut(&dlci).unwrap().tx_chunk = Some(frame.info);
+                        break 'channels;
+                    }
+                    Err(CmuxError::Io(e)) => return Err(e),
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidInput, e)),
+                }
+            }
+        }
+        for dlci in closed {
+            if let Err(CmuxError::Io(e)) = self.close_channel_at(dlci, now) {
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<S> Source for Mux<S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.register(registry, token, interests)?;
+        for peer in self.dlcs.values_mut().filter_map(|dlc| dlc.peer.as_mut()) {
+            registry.register(peer, token, Interest::READABLE | Interest::WRITABLE)?;
+        }
+        self.registration = Some((registry.try_clone()?, token));
+        Ok(())
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.reregister(registry, token, interests)?;
+        for peer in self.dlcs.values_mut().filter_map(|dlc| dlc.peer.as_mut()) {
+            registry.reregister(peer, token, Interest::READABLE | Interest::WRITABLE)?;
+        }
+        self.registration = Some((registry.try_clone()?, token));
+        Ok(())
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.framed.deregister(registry)?;
+        for peer in self.dlcs.values_mut().filter_map(|dlc| dlc.peer.as_mut()) {
+            registry.deregister(peer)?;
+        }
+        self.registration = None;
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! COBS and COBS/R framing
+//!
+//! [Consistent Overhead Byte Stuffing](https://en.wikipedia.org/wiki/Consistent_Overhead_Byte_Stuffing)
+//! removes every `0x00` from a frame at a cost of at most one byte per 254, so `0x00` can
+//! be used as an unambiguous frame delimiter.  This is the framing used by `postcard` and
+//! many other embedded Rust projects.
+//!
+//! COBS/R ("reduced") is a variant that often saves the final overhead byte: if the last
+//! data byte is larger than the final length code, it replaces the code.
+//!
+//! Corrupted frames are reported as [`CobsError::InvalidEncoding`] and the decoder picks up
+//! again after the next `0x00`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::codec::{cobs::CobsCodec, FramedSerial};
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let mut framed = FramedSerial::new(stream, CobsCodec::new().with_max_frame_length(256));
+//!
+//! // Decode straight into a fixed buffer instead of allocating for each frame
+//! let mut buf = [0u8; 256];
+//! if let Some(n) = framed
+//!     .read_with(|codec, src| codec.decode_into(src, &mut buf))
+//!     .unwrap()
+//! {
+//!     println!("{:?}", &buf[..n]);
+//! }
+//! ```
+use super::{Decoder, Encoder};
+use std::fmt;
+use std::io;
+
+/// Default maximum decoded frame length used by [`CobsCodec::new`]
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 4096;
+
+/// Which flavour of byte stuffing to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// Standard COBS
+    Cobs,
+    /// COBS/R, which may drop the final overhead byte
+    CobsR,
+}
+
+/// Errors reported by COBS encoding and decoding
+#[derive(Debug)]
+pub enum CobsError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The frame is not a valid COBS encoding
+    InvalidEncoding,
+    /// A frame grew past the maximum frame length
+    ///
+    /// Everything up to the next `0x00` is discarded.
+    FrameTooLong(usize),
+    /// The destination buffer cannot hold the decoded frame
+    ///
+    /// The frame has been consumed from the receive buffer.
+    BufferTooSmall,
+}
+
+impl fmt::Display for CobsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CobsError::Io(e) => write!(f, "{e}"),
+            CobsError::InvalidEncoding => write!(f, "invalid COBS encoding"),
+            CobsError::FrameTooLong(max) => {
+                write!(f, "COBS frame exceeds maximum length of {max} bytes")
+            }
+            CobsError::BufferTooSmall => write!(f, "buffer too small for decoded COBS frame"),
+        }
+    }
+}
+
+impl std::error::Error for CobsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CobsError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CobsError {
+    fn from(e: io::Error) -> Self {
+        CobsError::Io(e)
+    }
+}
+
+impl CobsError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            CobsError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// Maximum encoded length of `len` bytes of data, not counting the delimiter
+pub fn max_encoded_len(len: usize) -> usize {
+    len + len / 254 + 1
+}
+
+/// Append the COBS encoding of `src` to `dst`
+///
+/// No `0x00` delimiter is added.
+pub fn encode(src: &[u8], dst: &mut Vec<u8>) {
+    dst.reserve(max_encoded_len(src.len()));
+    let mut code_index = dst.len();
+    let mut code = 1u8;
+    dst.push(0);
+
+    for (i, &byte) in src.iter().enumerate() {
+        if byte == 0 {
+            dst[code_index] = code;
+            code_index = dst.len();
+            code = 1;
+            dst.push(0);
+        } else {
+            dst.push(byte);
+            code += 1;
+            if code == 0xFF && i + 1 < src.len() {
+                dst[code_index] = code;
+                code_index = dst.len();
+                code = 1;
+                dst.push(0);
+            }
+        }
+    }
+    dst[code_index] = code;
+}
+
+/// Append the COBS/R encoding of `src` to `dst`
+///
+/// No `0x00` delimiter is added.
+pub fn encode_cobsr(src: &[u8], dst: &mut Vec<u8>) {
+    let start = dst.len();
+    encode(src, dst);
+
+    // The final block starts at the last code byte; find it by walking the codes.
+    let mut code_index = start;
+    loop {
+        let next = code_index + dst[code_index] as usize;
+        if next >= dst.len() {
+            break;
+        }
+        code_index = next;
+    }
+    if let Some(&last) = src.last() {
+        if last > dst[code_index] {
+            dst[code_index] = last;
+            dst.pop();
+        }
+    }
+}
+
+/// Decode a COBS (or COBS/R) encoded frame into `dst`
+///
+/// `src` must not include the `0x00` delimiter.  Returns the decoded length.
+pub fn decode_into(src: &[u8], dst: &mut [u8], variant: Variant) -> Result<usize, CobsError> {
+    let mut i = 0;
+    let mut o = 0;
+
+    while i < src.len() {
+        let code = src[i];
+        if code == 0 {
+            return Err(CobsError::InvalidEncoding);
+        }
+        i += 1;
+        let n = code as usize - 1;
+
+        if i + n > src.len() {
+            // Only valid for COBS/R, where the final code byte is really the last data byte
+            if variant != Variant::CobsR {
+                return Err(CobsError::InvalidEncoding);
+            }
+            let rest = &src[i..];
+            let out = dst
+                .get_mut(o..o + rest.len() + 1)
+                .ok_or(CobsError::BufferTooSmall)?;
+            out[..rest.len()].copy_from_slice(rest);
+            out[rest.len()] = code;
+            return Ok(o + rest.len() + 1);
+        }
+
+        dst.get_mut(o..o + n)
+            .ok_or(CobsError::BufferTooSmall)?
+            .copy_from_slice(&src[i..i + n]);
+        o += n;
+        i += n;
+
+        if code != 0xFF && i < src.len() {
+            *dst.get_mut(o).ok_or(CobsError::BufferTooSmall)? = 0;
+            o += 1;
+        }
+    }
+    Ok(o)
+}
+
+/// COBS or COBS/R encoder and decoder using `0x00` frame delimiters
+///
+/// Empty frames (consecutive delimiters) are skipped, so a sender may also send a leading
+/// `0x00` to flush a receiver that came up mid-frame.
+#[derive(Debug, Clone)]
+pub struct CobsCodec {
+    variant: Variant,
+    max_frame_length: usize,
+    discarding: bool,
+}
+
+impl CobsCodec {
+    /// Create a standard COBS codec with [`DEFAULT_MAX_FRAME_LENGTH`]
+    pub fn new() -> Self {
+        Self {
+            variant: Variant::Cobs,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            discarding: false,
+        }
+    }
+
+    /// Create a COBS/R codec with [`DEFAULT_MAX_FRAME_LENGTH`]
+    pub fn new_cobsr() -> Self {
+        Self {
+            variant: Variant::CobsR,
+            ..Self::new()
+        }
+    }
+
+    /// Set the maximum decoded frame length
+    #[must_use]
+    pub fn with_max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// The stuffing variant in use
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    /// The configured maximum decoded frame length
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+
+    /// Decode the next frame in `src` into `dst` without allocating
+    ///
+    /// Follows the [`Decoder::decode`] contract and returns the decoded length.  Use it with
+    /// [`FramedSerial::read_with`](super::FramedSerial::read_with).
+    pub fn decode_into(
+        &mut self,
+        src: &mut Vec<u8>,
+        dst: &mut [u8],
+    ) -> Result<Option<usize>, CobsError> {
+        let variant = self.variant;
+        self.with_next_frame(src, |frame| decode_into(frame, dst, variant))
+    }
+
+    /// Call `f` with the next delimited, non-empty frame in `src` and remove it
+    ///
+    /// Enforces the maximum frame length and skips the remains of an overlong frame.
+    fn with_next_frame<T, F>(&mut self, src: &mut Vec<u8>, f: F) -> Result<Option<T>, CobsError>
+    where
+        F: FnOnce(&[u8]) -> Result<T, CobsError>,
+    {
+        let max_encoded = max_encoded_len(self.max_frame_length);
+        loop {
+            let pos = match src.iter().position(|&b| b == 0) {
+                Some(pos) => pos,
+                None => {
+                    if self.discarding {
+                        src.clear();
+                    } else if src.len() > max_encoded {
+                        src.clear();
+                        self.discarding = true;
+                        return Err(CobsError::FrameTooLong(self.max_frame_length));
+                    }
+                    return Ok(None);
+                }
+            };
+
+            if self.discarding || pos == 0 {
+                self.discarding = false;
+                src.drain(..=pos);
+                continue;
+            }
+
+            let result = if pos > max_encoded {
+                Err(CobsError::FrameTooLong(self.max_frame_length))
+            } else {
+                f(&src[..pos]).map(Some)
+            };
+            src.drain(..=pos);
+            return result;
+        }
+    }
+}
+
+impl Default for CobsCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for CobsCodec {
+    type Item = Vec<u8>;
+    type Error = CobsError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, CobsError> {
+        let variant = self.variant;
+        let max_frame_length = self.max_frame_length;
+        self.with_next_frame(src, |frame| {
+            // The decoded frame is never longer than the encoded one
+            let mut decoded = vec![0u8; frame.len()];
+            let n = decode_into(frame, &mut decoded, variant)?;
+            if n > max_frame_length {
+                return Err(CobsError::FrameTooLong(max_frame_length));
+            }
+            decoded.truncate(n);
+            Ok(decoded)
+        })
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for CobsCodec {
+    type Error = CobsError;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> Result<(), CobsError> {
+        let item = item.as_ref();
+        if item.len() > self.max_frame_length {
+            return Err(CobsError::FrameTooLong(self.max_frame_length));
+        }
+        match self.variant {
+            Variant::Cobs => encode(item, dst),
+            Variant::CobsR => encode_cobsr(item, dst),
+        }
+        dst.push(0);
+        Ok(())
+    }
+}
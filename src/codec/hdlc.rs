@@ -0,0 +1,392 @@
+//! HDLC-like asynchronous framing ([RFC 1662](https://www.rfc-editor.org/rfc/rfc1662))
+//!
+//! This is the octet-stuffed framing used by PPP and by many proprietary radio links:
+//!
+//! - Frames are separated by [`FLAG`] (`0x7E`) bytes.
+//! - [`FLAG`], [`ESCAPE`] (`0x7D`) and any control character selected by the
+//!   Async-Control-Character-Map (ACCM) are sent as [`ESCAPE`] followed by the byte XOR `0x20`.
+//! - Each frame ends with a 16 or 32-bit Frame Check Sequence (FCS), sent least significant
+//!   byte first.
+//!
+//! Decoded frames contain everything between the flags except the FCS, i.e. the address,
+//! control and information fields.  A frame with a bad FCS is reported as
+//! [`HdlcError::BadFcs`] and a frame cut short by the `0x7D 0x7E` abort sequence as
+//! [`HdlcError::Aborted`], and both are counted in [`HdlcStats`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::codec::hdlc::{Fcs, HdlcCodec};
+//! use mio_serial::codec::FramedSerial;
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let codec = HdlcCodec::new().with_fcs(Fcs::Fcs32).with_accm(0x000A_0000);
+//! let mut framed = FramedSerial::new(stream, codec);
+//!
+//! // PPP LCP Configure-Request with an empty option list
+//! framed.send(&[0xFF, 0x03, 0xC0, 0x21, 0x01, 0x01, 0x00, 0x04][..]).unwrap();
+//! ```
+use super::{Decoder, Encoder};
+use crate::crc::{CRC_16_IBM_SDLC, CRC_32_ISO_HDLC};
+use std::fmt;
+use std::io;
+
+/// Frame delimiter
+pub const FLAG: u8 = 0x7E;
+/// Control escape
+pub const ESCAPE: u8 = 0x7D;
+/// Value XORed into an escaped byte
+pub const ESCAPE_XOR: u8 = 0x20;
+
+/// Default Async-Control-Character-Map: escape every control character
+pub const DEFAULT_ACCM: u32 = 0xFFFF_FFFF;
+
+/// Default maximum frame length (including the FCS) used by [`HdlcCodec::new`]
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 1500 + 8;
+
+/// Frame Check Sequence appended to each frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fcs {
+    /// No FCS
+    None,
+    /// 16-bit FCS (CRC-16/IBM-SDLC)
+    Fcs16,
+    /// 32-bit FCS (CRC-32/ISO-HDLC)
+    Fcs32,
+}
+
+impl Fcs {
+    /// Length of the FCS in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Fcs::None => 0,
+            Fcs::Fcs16 => 2,
+            Fcs::Fcs32 => 4,
+        }
+    }
+
+    /// Returns `true` for [`Fcs::None`]
+    pub fn is_empty(&self) -> bool {
+        *self == Fcs::None
+    }
+
+    /// Append the FCS of `data` to `dst`, least significant byte first
+    fn append(&self, data: &[u8], dst: &mut Vec<u8>) {
+        match self {
+            Fcs::None => {}
+            Fcs::Fcs16 => dst.extend_from_slice(&CRC_16_IBM_SDLC.checksum(data).to_le_bytes()),
+            Fcs::Fcs32 => dst.extend_from_slice(&CRC_32_ISO_HDLC.checksum(data).to_le_bytes()),
+        }
+    }
+
+    /// Check the FCS at the end of `frame`
+    fn verify(&self, frame: &[u8]) -> bool {
+        let (data, fcs) = frame.split_at(frame.len() - self.len());
+        match self {
+            Fcs::None => true,
+            Fcs::Fcs16 => CRC_16_IBM_SDLC.checksum(data).to_le_bytes() == fcs,
+            Fcs::Fcs32 => CRC_32_ISO_HDLC.checksum(data).to_le_bytes() == fcs,
+        }
+    }
+}
+
+/// Errors reported by [`HdlcCodec`]
+#[derive(Debug)]
+pub enum HdlcError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The frame check sequence did not match
+    BadFcs,
+    /// The frame was ended by the `0x7D 0x7E` abort sequence
+    Aborted,
+    /// The frame is too short to contain an FCS
+    TooShort,
+    /// The frame grew past the maximum frame length; the rest of it is discarded
+    FrameTooLong(usize),
+}
+
+impl fmt::Display for HdlcError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HdlcError::Io(e) => write!(f, "{e}"),
+            HdlcError::BadFcs => write!(f, "bad frame check sequence"),
+            HdlcError::Aborted => write!(f, "frame aborted by sender"),
+            HdlcError::TooShort => write!(f, "frame too short"),
+            HdlcError::FrameTooLong(max) => {
+                write!(f, "frame exceeds maximum length of {max} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HdlcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HdlcError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for HdlcError {
+    fn from(e: io::Error) -> Self {
+        HdlcError::Io(e)
+    }
+}
+
+impl HdlcError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            HdlcError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// Receive statistics kept by [`HdlcCodec`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HdlcStats {
+    /// Frames received with a good FCS
+    pub frames: u64,
+    /// Frames dropped because of a bad FCS
+    pub bad_fcs: u64,
+    /// Frames ended by an abort sequence
+    pub aborted: u64,
+    /// Frames too short to hold an FCS
+    pub too_short: u64,
+    /// Frames longer than the maximum frame length
+    pub too_long: u64,
+}
+
+/// HDLC-like framing encoder and decoder
+#[derive(Debug, Clone)]
+pub struct HdlcCodec {
+    fcs: Fcs,
+    tx_accm: u32,
+    rx_accm: u32,
+    leading_flag: bool,
+    max_frame_length: usize,
+    frame: Vec<u8>,
+    escaped: bool,
+    discarding: bool,
+    stats: HdlcStats,
+}
+
+impl HdlcCodec {
+    /// Create a codec with FCS-16, [`DEFAULT_ACCM`] in both directions and a leading flag on
+    /// every frame
+    pub fn new() -> Self {
+        Self {
+            fcs: Fcs::Fcs16,
+            tx_accm: DEFAULT_ACCM,
+            rx_accm: DEFAULT_ACCM,
+            leading_flag: true,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            frame: Vec::new(),
+            escaped: false,
+            discarding: false,
+            stats: HdlcStats::default(),
+        }
+    }
+
+    /// Select the frame check sequence
+    #[must_use]
+    pub fn with_fcs(mut self, fcs: Fcs) -> Self {
+        self.fcs = fcs;
+        self
+    }
+
+    /// Set the ACCM for both directions
+    ///
+    /// Bit `n` set means control character `n` (`0x00` to `0x1F`) is escaped when sent, and
+    /// dropped if received unescaped (it was inserted by a device on the link).
+    #[must_use]
+    pub fn with_accm(mut self, accm: u32) -> Self {
+        self.tx_accm = accm;
+        self.rx_accm = accm;
+        self
+    }
+
+    /// Set the ACCM used for sending only
+    #[must_use]
+    pub fn with_tx_accm(mut self, accm: u32) -> Self {
+        self.tx_accm = accm;
+        self
+    }
+
+    /// Set the ACCM used for receiving only
+    #[must_use]
+    pub fn with_rx_accm(mut self, accm: u32) -> Self {
+        self.rx_accm = accm;
+        self
+    }
+
+    /// Send an opening flag before every frame
+    ///
+    /// RFC 1662 allows a single flag between back-to-back frames; the opening flag is only
+    /// needed after idle time.  Enabled by default.
+    #[must_use]
+    pub fn with_leading_flag(mut self, leading_flag: bool) -> Self {
+        self.leading_flag = leading_flag;
+        self
+    }
+
+    /// Set the maximum frame length, including the FCS
+    #[must_use]
+    pub fn with_max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// The selected frame check sequence
+    pub fn fcs(&self) -> Fcs {
+        self.fcs
+    }
+
+    /// Change the ACCM used for sending, e.g. after LCP negotiation
+    pub fn set_tx_accm(&mut self, accm: u32) {
+        self.tx_accm = accm;
+    }
+
+    /// Change the ACCM used for receiving, e.g. after LCP negotiation
+    pub fn set_rx_accm(&mut self, accm: u32) {
+        self.rx_accm = accm;
+    }
+
+    /// Receive statistics
+    pub fn stats(&self) -> &HdlcStats {
+        &self.stats
+    }
+
+    /// Reset the receive statistics
+    pub fn reset_stats(&mut self) {
+        self.stats = HdlcStats::default();
+    }
+
+    fn must_escape(&self, byte: u8) -> bool {
+        byte == FLAG || byte == ESCAPE || (byte < 0x20 && self.tx_accm & (1 << byte) != 0)
+    }
+
+    fn end_frame(&mut self) -> Result<Option<Vec<u8>>, HdlcError> {
+        let mut frame = std::mem::take(&mut self.frame);
+        if frame.len() <= self.fcs.len() {
+            self.stats.too_short += 1;
+            return Err(HdlcError::TooShort);
+        }
+        if !self.fcs.verify(&frame) {
+            self.stats.bad_fcs += 1;
+            return Err(HdlcError::BadFcs);
+        }
+        frame.truncate(frame.len() - self.fcs.len());
+        self.stats.frames += 1;
+        Ok(Some(frame))
+    }
+}
+
+impl Default for HdlcCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for HdlcCodec {
+    type Item = Vec<u8>;
+    type Error = HdlcError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, HdlcError> {
+        let mut result = Ok(None);
+        let mut consumed = 0;
+
+        for &byte in src.iter() {
+            consumed += 1;
+
+            if byte == FLAG {
+                if self.discarding {
+                    self.discarding = false;
+                    self.frame.clear();
+                    continue;
+                }
+                if self.escaped {
+                    self.escaped = false;
+                    self.frame.clear();
+                    self.stats.aborted += 1;
+                    result = Err(HdlcError::Aborted);
+                    break;
+                }
+                if self.frame.is_empty() {
+                    continue;
+                }
+                result = self.end_frame();
+                break;
+            }
+
+            if self.discarding || (byte < 0x20 && self.rx_accm & (1 << byte) != 0) {
+                continue;
+            }
+
+            if self.escaped {
+                self.escaped = false;
+                self.frame.push(byte ^ ESCAPE_XOR);
+            } else if byte == ESCAPE {
+                self.escaped = true;
+            } else {
+                self.frame.push(byte);
+            }
+
+            if self.frame.len() > self.max_frame_length {
+                self.frame.clear();
+                self.escaped = false;
+                self.discarding = true;
+                self.stats.too_long += 1;
+                result = Err(HdlcError::FrameTooLong(self.max_frame_length));
+                break;
+            }
+        }
+
+        src.drain(..consumed);
+        result
+    }
+
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, HdlcError> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if self.frame.is_empty() && !self.escaped => Ok(None),
+            None => {
+                self.frame.clear();
+                self.escaped = false;
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "partial HDLC frame").into())
+            }
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for HdlcCodec {
+    type Error = HdlcError;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> Result<(), HdlcError> {
+        let item = item.as_ref();
+        if item.len() + self.fcs.len() > self.max_frame_length {
+            return Err(HdlcError::FrameTooLong(self.max_frame_length));
+        }
+
+        let mut frame = Vec::with_capacity(item.len() + self.fcs.len());
+        frame.extend_from_slice(item);
+        self.fcs.append(item, &mut frame);
+
+        dst.reserve(frame.len() * 2 + 2);
+        if self.leading_flag {
+            dst.push(FLAG);
+        }
+        for byte in frame {
+            if self.must_escape(byte) {
+                dst.extend_from_slice(&[ESCAPE, byte ^ ESCAPE_XOR]);
+            } else {
+                dst.push(byte);
+            }
+        }
+        dst.push(FLAG);
+        Ok(())
+    }
+}
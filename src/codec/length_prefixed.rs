@@ -0,0 +1,389 @@
+//! Length-prefixed binary framing with a sync word and CRC
+//!
+//! Covers the common "sync, length, payload, CRC" frame layout:
+//!
+//! ```text
+//! +-----------+--------+-------------------+-------+
+//! | sync word | length | payload           | CRC   |
+//! +-----------+--------+-------------------+-------+
+//! ```
+//!
+//! The sync pattern, the width and byte order of the length field, an adjustment between
+//! the length field and the actual payload length, and the CRC algorithm and coverage are
+//! all configurable.
+//!
+//! When a frame fails its CRC (or carries an impossible length) the decoder reports the
+//! error and then hunts for the sync pattern starting one byte after the start of the bad
+//! frame, so a single corrupt frame does not take any following frames with it.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::codec::length_prefixed::{Checksum, CrcCoverage, LengthPrefixedCodec};
+//! use mio_serial::codec::FramedSerial;
+//! use mio_serial::crc::CRC_16_IBM_3740;
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! // 0xAA 0x55, u16 little-endian length, payload, CRC-16/CCITT over length and payload
+//! let codec = LengthPrefixedCodec::new(&[0xAA, 0x55])
+//!     .with_length_width(2)
+//!     .with_checksum(Checksum::Crc16(CRC_16_IBM_3740))
+//!     .with_crc_coverage(CrcCoverage::LengthAndPayload);
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let mut framed = FramedSerial::new(stream, codec);
+//! framed.send(&b"ping"[..]).unwrap();
+//! ```
+use super::{Decoder, Encoder};
+use crate::crc::{Crc16, Crc32};
+use std::fmt;
+use std::io;
+
+/// Default maximum payload length used by [`LengthPrefixedCodec::new`]
+pub const DEFAULT_MAX_PAYLOAD_LENGTH: usize = 4096;
+
+/// Byte order of multi-byte fields
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Least significant byte first
+    LittleEndian,
+    /// Most significant byte first
+    BigEndian,
+}
+
+impl ByteOrder {
+    fn read(&self, bytes: &[u8]) -> u64 {
+        match self {
+            ByteOrder::LittleEndian => bytes
+                .iter()
+                .rev()
+                .fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+            ByteOrder::BigEndian => bytes.iter().fold(0, |acc, &b| (acc << 8) | u64::from(b)),
+        }
+    }
+
+    fn write(&self, value: u64, width: usize, dst: &mut Vec<u8>) {
+        let bytes = value.to_le_bytes();
+        match self {
+            ByteOrder::LittleEndian => dst.extend_from_slice(&bytes[..width]),
+            ByteOrder::BigEndian => dst.extend(bytes[..width].iter().rev()),
+        }
+    }
+}
+
+/// Checksum appended after the payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// No checksum
+    None,
+    /// A 16-bit CRC, e.g. [`CRC_16_IBM_3740`](crate::crc::CRC_16_IBM_3740)
+    Crc16(Crc16),
+    /// A 32-bit CRC, e.g. [`CRC_32_ISO_HDLC`](crate::crc::CRC_32_ISO_HDLC)
+    Crc32(Crc32),
+}
+
+impl Checksum {
+    /// Length of the checksum in bytes
+    pub fn len(&self) -> usize {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc16(_) => 2,
+            Checksum::Crc32(_) => 4,
+        }
+    }
+
+    /// Returns `true` for [`Checksum::None`]
+    pub fn is_empty(&self) -> bool {
+        *self == Checksum::None
+    }
+
+    fn compute(&self, data: &[u8]) -> u64 {
+        match self {
+            Checksum::None => 0,
+            Checksum::Crc16(crc) => u64::from(crc.checksum(data)),
+            Checksum::Crc32(crc) => u64::from(crc.checksum(data)),
+        }
+    }
+}
+
+/// Which part of the frame the CRC is computed over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcCoverage {
+    /// The payload only
+    Payload,
+    /// The length field and the payload
+    LengthAndPayload,
+    /// The sync word, length field and payload
+    Frame,
+}
+
+/// Errors reported by [`LengthPrefixedCodec`]
+#[derive(Debug)]
+pub enum LengthPrefixedError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The CRC did not match; the decoder resumes hunting for the sync pattern
+    CrcMismatch {
+        /// CRC computed over the received frame
+        computed: u64,
+        /// CRC carried in the frame
+        received: u64,
+    },
+    /// The length field gave a payload length that is negative or over the maximum
+    InvalidLength(i64),
+    /// An outgoing payload does not fit the length field or the maximum payload length
+    PayloadTooLong(usize),
+}
+
+impl fmt::Display for LengthPrefixedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LengthPrefixedError::Io(e) => write!(f, "{e}"),
+            LengthPrefixedError::CrcMismatch { computed, received } => write!(
+                f,
+                "CRC mismatch: computed 0x{computed:X}, received 0x{received:X}"
+            ),
+            LengthPrefixedError::InvalidLength(len) => write!(f, "invalid payload length {len}"),
+            LengthPrefixedError::PayloadTooLong(len) => {
+                write!(f, "payload of {len} bytes is too long to encode")
+            }
+        }
+    }
+}
+
+impl std::error::Error for LengthPrefixedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LengthPrefixedError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for LengthPrefixedError {
+    fn from(e: io::Error) -> Self {
+        LengthPrefixedError::Io(e)
+    }
+}
+
+impl LengthPrefixedError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            LengthPrefixedError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// Configurable length-prefixed frame encoder and decoder
+#[derive(Debug, Clone)]
+pub struct LengthPrefixedCodec {
+    sync: Vec<u8>,
+    length_width: usize,
+    length_order: ByteOrder,
+    length_adjustment: i64,
+    checksum: Checksum,
+    crc_coverage: CrcCoverage,
+    crc_order: ByteOrder,
+    max_payload_length: usize,
+}
+
+impl LengthPrefixedCodec {
+    /// Create a codec with the given sync pattern
+    ///
+    /// Defaults to a one byte length field holding the payload length, no checksum, and
+    /// little-endian multi-byte fields.
+    pub fn new(sync: &[u8]) -> Self {
+        Self {
+            sync: sync.to_vec(),
+            length_width: 1,
+            length_order: ByteOrder::LittleEndian,
+            length_adjustment: 0,
+            checksum: Checksum::None,
+            crc_coverage: CrcCoverage::Payload,
+            crc_order: ByteOrder::LittleEndian,
+            max_payload_length: DEFAULT_MAX_PAYLOAD_LENGTH,
+        }
+    }
+
+    /// Set the width of the length field in bytes
+    ///
+    /// ## Panics
+    ///
+    /// If `width` is not 1, 2, 3 or 4.
+    #[must_use]
+    pub fn with_length_width(mut self, width: usize) -> Self {
+        assert!(
+            (1..=4).contains(&width),
+            "length field must be 1 to 4 bytes"
+        );
+        self.length_width = width;
+        self
+    }
+
+    /// Set the byte order of the length field
+    #[must_use]
+    pub fn with_length_order(mut self, order: ByteOrder) -> Self {
+        self.length_order = order;
+        self
+    }
+
+    /// Set the value added to the length field to get the payload length
+    ///
+    /// For example, use `-2` if the length field also counts a 16-bit CRC, or `1` if it is
+    /// one less than the payload length.
+    #[must_use]
+    pub fn with_length_adjustment(mut self, adjustment: i64) -> Self {
+        self.length_adjustment = adjustment;
+        self
+    }
+
+    /// Set the checksum appended after the payload
+    #[must_use]
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        self.checksum = checksum;
+        self
+    }
+
+    /// Set which part of the frame the checksum covers
+    #[must_use]
+    pub fn with_crc_coverage(mut self, coverage: CrcCoverage) -> Self {
+        self.crc_coverage = coverage;
+        self
+    }
+
+    /// Set the byte order of the checksum
+    #[must_use]
+    pub fn with_crc_order(mut self, order: ByteOrder) -> Self {
+        self.crc_order = order;
+        self
+    }
+
+    /// Set the maximum payload length
+    #[must_use]
+    pub fn with_max_payload_length(mut self, max_payload_length: usize) -> Self {
+        self.max_payload_length = max_payload_length;
+        self
+    }
+
+    /// The sync pattern
+    pub fn sync(&self) -> &[u8] {
+        &self.sync
+    }
+
+    fn header_len(&self) -> usize {
+        self.sync.len() + self.length_width
+    }
+
+    fn crc_range(&self, payload_end: usize) -> std::ops::Range<usize> {
+        let start = match self.crc_coverage {
+            CrcCoverage::Payload => self.header_len(),
+            CrcCoverage::LengthAndPayload => self.sync.len(),
+            CrcCoverage::Frame => 0,
+        };
+        start..payload_end
+    }
+
+    /// Drop bytes up to the next possible start of the sync pattern
+    ///
+    /// Returns `true` if `src` now starts with the full sync pattern.
+    fn hunt(&self, src: &mut Vec<u8>) -> bool {
+        if self.sync.is_empty() {
+            return true;
+        }
+        if let Some(pos) = src
+            .windows(self.sync.len())
+            .position(|w| w == self.sync.as_slice())
+        {
+            src.drain(..pos);
+            return true;
+        }
+        // Keep a tail that could be the start of a sync pattern split across reads
+        let keep = (self.sync.len() - 1).min(src.len());
+        let tail = (1..=keep)
+            .rev()
+            .find(|&n| src[src.len() - n..] == self.sync[..n])
+            .unwrap_or(0);
+        let drop = src.len() - tail;
+        src.drain(..drop);
+        false
+    }
+
+    /// Skip the first byte of a bad frame so hunting resumes inside it
+    fn resync(&self, src: &mut Vec<u8>) {
+        if !src.is_empty() {
+            src.drain(..1);
+        }
+    }
+}
+
+impl Decoder for LengthPrefixedCodec {
+    type Item = Vec<u8>;
+    type Error = LengthPrefixedError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, LengthPrefixedError> {
+        if !self.hunt(src) {
+            return Ok(None);
+        }
+
+        let header_len = self.header_len();
+        if src.len() < header_len {
+            return Ok(None);
+        }
+
+        let field = self.length_order.read(&src[self.sync.len()..header_len]);
+        let payload_len = field as i64 + self.length_adjustment;
+        if payload_len < 0 || payload_len as usize > self.max_payload_length {
+            self.resync(src);
+            return Err(LengthPrefixedError::InvalidLength(payload_len));
+        }
+
+        let payload_end = header_len + payload_len as usize;
+        let frame_len = payload_end + self.checksum.len();
+        if src.len() < frame_len {
+            return Ok(None);
+        }
+
+        if !self.checksum.is_empty() {
+            let computed = self.checksum.compute(&src[self.crc_range(payload_end)]);
+            let received = self.crc_order.read(&src[payload_end..frame_len]);
+            if computed != received {
+                self.resync(src);
+                return Err(LengthPrefixedError::CrcMismatch { computed, received });
+            }
+        }
+
+        let payload = src[header_len..payload_end].to_vec();
+        src.drain(..frame_len);
+        Ok(Some(payload))
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for LengthPrefixedCodec {
+    type Error = LengthPrefixedError;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> Result<(), LengthPrefixedError> {
+        let payload = item.as_ref();
+        let field = payload.len() as i64 - self.length_adjustment;
+        let max_field = (1i64 << (8 * self.length_width)) - 1;
+        if payload.len() > self.max_payload_length || field < 0 || field > max_field {
+            return Err(LengthPrefixedError::PayloadTooLong(payload.len()));
+        }
+
+        let start = dst.len();
+        dst.extend_from_slice(&self.sync);
+        self.length_order
+            .write(field as u64, self.length_width, dst);
+        dst.extend_from_slice(payload);
+
+        if !self.checksum.is_empty() {
+            let range = self.crc_range(dst.len() - start);
+            let crc = self
+                .checksum
+                .compute(&dst[start + range.start..start + range.end]);
+            self.crc_order.write(crc, self.checksum.len(), dst);
+        }
+        Ok(())
+    }
+}
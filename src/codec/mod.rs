@@ -0,0 +1,334 @@
+//! Frame-oriented I/O over a [`SerialStream`]
+//!
+//! The [`Decoder`] and [`Encoder`] traits split the protocol logic (turning bytes into
+//! frames and back) from the non-blocking I/O, which [`FramedSerial`] takes care of:
+//!
+//! - On a readable event, call [`FramedSerial::read_frame`] until it returns `Ok(None)`.
+//! - Queue outgoing frames with [`FramedSerial::send`].
+//! - On a writable event, call [`FramedSerial::on_writable`].
+//!
+//! Writable interest is added and removed automatically as in [`BufferedSerialStream`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::codec::{Decoder, Encoder, FramedSerial};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::io;
+//!
+//! /// Frames are single bytes
+//! struct ByteCodec;
+//!
+//! impl Decoder for ByteCodec {
+//!     type Item = u8;
+//!     type Error = io::Error;
+//!
+//!     fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<u8>> {
+//!         Ok(if src.is_empty() { None } else { Some(src.remove(0)) })
+//!     }
+//! }
+//!
+//! impl Encoder<u8> for ByteCodec {
+//!     type Error = io::Error;
+//!
+//!     fn encode(&mut self, item: u8, dst: &mut Vec<u8>) -> io::Result<()> {
+//!         dst.push(item);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 9600).open_native_async().unwrap();
+//! let mut framed = FramedSerial::new(stream, ByteCodec);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(1);
+//! poll.registry()
+//!     .register(&mut framed, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! loop {
+//!     poll.poll(&mut events, None).unwrap();
+//!     for event in events.iter() {
+//!         if event.is_writable() {
+//!             framed.on_writable().unwrap();
+//!         }
+//!         while let Some(byte) = framed.read_frame().unwrap() {
+//!             // echo it back
+//!             framed.send(byte).unwrap();
+//!         }
+//!     }
+//! }
+//! ```
+use crate::buffered::{BufferedSerialStream, DEFAULT_HIGH_WATER_MARK};
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::io::{self, Read, Write};
+
+pub mod cobs;
+pub mod hdlc;
+pub mod length_prefixed;
+pub mod slip;
+
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// Decodes frames from a buffer of received bytes
+pub trait Decoder {
+    /// The type of decoded frames
+    type Item;
+
+    /// The type of decoding errors
+    ///
+    /// I/O errors from the stream are converted into this type by [`FramedSerial`].
+    type Error: From<io::Error>;
+
+    /// Attempt to decode a frame from the front of `src`
+    ///
+    /// Implementations remove the bytes of a decoded frame (and any bytes they choose to
+    /// discard) from `src`.  Return `Ok(None)` if more data is needed; the remaining bytes
+    /// are kept and more are appended before the next call.
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error>;
+
+    /// Called once the stream reaches end of file
+    ///
+    /// The default implementation decodes as usual and reports leftover bytes as an
+    /// `UnexpectedEof` error.
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(
+                io::Error::new(io::ErrorKind::UnexpectedEof, "bytes remaining on stream").into(),
+            ),
+        }
+    }
+}
+
+/// Encodes frames into bytes for transmission
+pub trait Encoder<Item> {
+    /// The type of encoding errors
+    ///
+    /// I/O errors from the stream are converted into this type by [`FramedSerial`].
+    type Error: From<io::Error>;
+
+    /// Append the encoded form of `item` to `dst`
+    fn encode(&mut self, item: Item, dst: &mut Vec<u8>) -> Result<(), Self::Error>;
+}
+
+/// A [`SerialStream`] combined with a codec
+///
+/// Received bytes are buffered until the codec can produce a whole frame.  Sent frames are
+/// encoded and pushed through an internal [`BufferedSerialStream`], so a frame is either
+/// queued whole or not at all.
+#[derive(Debug)]
+pub struct FramedSerial<C, S = SerialStream> {
+    inner: BufferedSerialStream<S>,
+    codec: C,
+    read_buf: Vec<u8>,
+    write_buf: Vec<u8>,
+    eof: bool,
+}
+
+impl<C, S> FramedSerial<C, S>
+where
+    S: Read + Write + Source,
+{
+    /// Wrap `inner` with `codec`, queueing up to [`DEFAULT_HIGH_WATER_MARK`] bytes of
+    /// outgoing frames
+    pub fn new(inner: S, codec: C) -> Self {
+        Self::with_high_water_mark(inner, codec, DEFAULT_HIGH_WATER_MARK)
+    }
+
+    /// Wrap `inner` with `codec`, queueing up to `high_water_mark` bytes of outgoing frames
+    pub fn with_high_water_mark(inner: S, codec: C, high_water_mark: usize) -> Self {
+        Self {
+            inner: BufferedSerialStream::with_high_water_mark(inner, high_water_mark),
+            codec,
+            read_buf: Vec::new(),
+            write_buf: Vec::new(),
+            eof: false,
+        }
+    }
+
+    /// Get a reference to the codec
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+
+    /// Get a mutable reference to the codec
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.inner.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    ///
+    /// Reading or writing the stream directly will interfere with framing.
+    pub fn get_mut(&mut self) -> &mut S {
+        self.inner.get_mut()
+    }
+
+    /// Consume the framed stream, returning the underlying stream
+    ///
+    /// Buffered input and queued output are lost.
+    pub fn into_inner(self) -> S {
+        self.inner.into_inner()
+    }
+
+    /// Bytes received but not yet decoded into a frame
+    pub fn read_buffer(&self) -> &[u8] {
+        &self.read_buf
+    }
+
+    /// Mutable access to the received bytes not yet decoded into a frame
+    pub fn read_buffer_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.read_buf
+    }
+
+    /// Returns `true` once the stream has reported end of file
+    pub fn is_eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Returns `true` if encoded frames are waiting for a writable event
+    pub fn is_write_pending(&self) -> bool {
+        self.inner.is_write_pending()
+    }
+
+    /// Number of queued bytes still waiting to be written
+    pub fn write_pending_len(&self) -> usize {
+        self.inner.write_queue().len()
+    }
+
+    /// Return the next complete frame
+    ///
+    /// Reads from the stream until the codec yields a frame or the stream would block.
+    /// Returns `Ok(None)` when the caller should wait for the next readable event.
+    pub fn read_frame(&mut self) -> Result<Option<C::Item>, C::Error>
+    where
+        C: Decoder,
+    {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            if self.eof {
+                return self.codec.decode_eof(&mut self.read_buf);
+            }
+            if let Some(frame) = self.codec.decode(&mut self.read_buf)? {
+                return Ok(Some(frame));
+            }
+            match self.inner.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.read_buf.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Return the next frame using a custom decode function
+    ///
+    /// `decode` is called with the codec and the receive buffer and follows the same
+    /// contract as [`Decoder::decode`].  This allows decoding straight into a caller-supplied
+    /// buffer instead of allocating a new item for each frame, for example with
+    /// [`CobsCodec::decode_into`](cobs::CobsCodec::decode_into).
+    pub fn read_with<T, E, F>(&mut self, mut decode: F) -> Result<Option<T>, E>
+    where
+        F: FnMut(&mut C, &mut Vec<u8>) -> Result<Option<T>, E>,
+        E: From<io::Error>,
+    {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            if let Some(frame) = decode(&mut self.codec, &mut self.read_buf)? {
+                return Ok(Some(frame));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.inner.read(&mut chunk) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.read_buf.extend_from_slice(&chunk[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Encode `item` and queue it for transmission
+    ///
+    /// Returns the encoded length of the frame.
+    ///
+    /// ## Errors
+    ///
+    /// * `WouldBlock` (converted into the codec's error type) if the encoded frame does not
+    ///   fit in the write queue.  Nothing is queued; retry after a writable event.
+    /// * `InvalidInput` if the encoded frame is larger than the write queue's high-water
+    ///   mark, so it could never be queued.  Nothing is queued.
+    /// * Any error from the codec or from the stream other than `WouldBlock`.
+    pub fn send<I>(&mut self, item: I) -> Result<usize, <C as Encoder<I>>::Error>
+    where
+        C: Encoder<I>,
+    {
+        self.write_buf.clear();
+        self.codec.encode(item, &mut self.write_buf)?;
+        self.send_encoded()
+    }
+
+    fn send_encoded<E: From<io::Error>>(&mut self) -> Result<usize, E> {
+        let queue = self.inner.write_queue();
+        if self.write_buf.len() > queue.high_water_mark() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "frame is larger than the write queue",
+            )
+            .into());
+        }
+        if self.write_buf.len() > queue.remaining() {
+            return Err(io::Error::from(io::ErrorKind::WouldBlock).into());
+        }
+        let mut sent = 0;
+        while sent < self.write_buf.len() {
+            sent += self.inner.queue_write(&self.write_buf[sent..])?;
+        }
+        Ok(sent)
+    }
+
+    /// Write out queued frames after a writable event
+    ///
+    /// Returns the number of bytes written.
+    pub fn on_writable(&mut self) -> io::Result<usize> {
+        self.inner.on_writable()
+    }
+}
+
+impl<C, S> Source for FramedSerial<C, S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
@@ -0,0 +1,231 @@
+//! SLIP framing ([RFC 1055](https://www.rfc-editor.org/rfc/rfc1055))
+//!
+//! Frames end with an `END` byte (`0xC0`).  `END` and `ESC` (`0xDB`) bytes inside a frame are
+//! replaced with `ESC ESC_END` and `ESC ESC_ESC`.  Many implementations also send an `END`
+//! before each frame to flush any line noise the receiver may have collected; enable this
+//! with [`SlipCodec::with_leading_end`].  Empty frames are never returned, so the decoder
+//! accepts either convention.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::codec::{slip::SlipCodec, FramedSerial};
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let mut framed = FramedSerial::new(stream, SlipCodec::new().with_leading_end(true));
+//! framed.send(&b"\x01\x02\xc0\x03"[..]).unwrap();
+//! ```
+use super::{Decoder, Encoder};
+use std::fmt;
+use std::io;
+
+/// Frame delimiter
+pub const END: u8 = 0xC0;
+/// Escape byte
+pub const ESC: u8 = 0xDB;
+/// Escaped `END` following an `ESC`
+pub const ESC_END: u8 = 0xDC;
+/// Escaped `ESC` following an `ESC`
+pub const ESC_ESC: u8 = 0xDD;
+
+/// Default maximum decoded frame length used by [`SlipCodec::new`]
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 4096;
+
+/// Errors reported by [`SlipCodec`]
+#[derive(Debug)]
+pub enum SlipError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// `ESC` was followed by a byte other than `ESC_END` or `ESC_ESC`
+    ///
+    /// The rest of the frame is discarded.
+    InvalidEscape(u8),
+    /// A decoded frame grew past the maximum frame length
+    ///
+    /// The rest of the frame is discarded.
+    FrameTooLong(usize),
+}
+
+impl fmt::Display for SlipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SlipError::Io(e) => write!(f, "{e}"),
+            SlipError::InvalidEscape(b) => write!(f, "invalid SLIP escape sequence 0xDB 0x{b:02X}"),
+            SlipError::FrameTooLong(max) => {
+                write!(f, "SLIP frame exceeds maximum length of {max} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SlipError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SlipError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SlipError {
+    fn from(e: io::Error) -> Self {
+        SlipError::Io(e)
+    }
+}
+
+impl SlipError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            SlipError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// SLIP encoder and decoder
+///
+/// Decoding state is kept in the codec, so escape sequences split across reads are handled
+/// transparently.
+#[derive(Debug, Clone)]
+pub struct SlipCodec {
+    leading_end: bool,
+    max_frame_length: usize,
+    frame: Vec<u8>,
+    escaped: bool,
+    discarding: bool,
+}
+
+impl SlipCodec {
+    /// Create a codec without a leading `END` and [`DEFAULT_MAX_FRAME_LENGTH`]
+    pub fn new() -> Self {
+        Self {
+            leading_end: false,
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+            frame: Vec::new(),
+            escaped: false,
+            discarding: false,
+        }
+    }
+
+    /// Send an `END` byte before every encoded frame
+    #[must_use]
+    pub fn with_leading_end(mut self, leading_end: bool) -> Self {
+        self.leading_end = leading_end;
+        self
+    }
+
+    /// Set the maximum decoded frame length
+    #[must_use]
+    pub fn with_max_frame_length(mut self, max_frame_length: usize) -> Self {
+        self.max_frame_length = max_frame_length;
+        self
+    }
+
+    /// Returns `true` if a leading `END` is sent before every frame
+    pub fn leading_end(&self) -> bool {
+        self.leading_end
+    }
+
+    /// The configured maximum decoded frame length
+    pub fn max_frame_length(&self) -> usize {
+        self.max_frame_length
+    }
+}
+
+impl Default for SlipCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for SlipCodec {
+    type Item = Vec<u8>;
+    type Error = SlipError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, SlipError> {
+        let mut result = Ok(None);
+        let mut consumed = 0;
+
+        for &byte in src.iter() {
+            consumed += 1;
+
+            if self.discarding {
+                if byte == END {
+                    self.discarding = false;
+                }
+                continue;
+            }
+
+            if self.escaped {
+                self.escaped = false;
+                match byte {
+                    ESC_END => self.frame.push(END),
+                    ESC_ESC => self.frame.push(ESC),
+                    _ => {
+                        self.frame.clear();
+                        // An END still terminates the (now broken) frame
+                        self.discarding = byte != END;
+                        result = Err(SlipError::InvalidEscape(byte));
+                        break;
+                    }
+                }
+            } else {
+                match byte {
+                    END if self.frame.is_empty() => {}
+                    END => {
+                        result = Ok(Some(std::mem::take(&mut self.frame)));
+                        break;
+                    }
+                    ESC => self.escaped = true,
+                    _ => self.frame.push(byte),
+                }
+            }
+
+            if self.frame.len() > self.max_frame_length {
+                self.frame.clear();
+                self.escaped = false;
+                self.discarding = true;
+                result = Err(SlipError::FrameTooLong(self.max_frame_length));
+                break;
+            }
+        }
+
+        src.drain(..consumed);
+        result
+    }
+
+    fn decode_eof(&mut self, src: &mut Vec<u8>) -> Result<Option<Vec<u8>>, SlipError> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            None if self.frame.is_empty() && !self.escaped => Ok(None),
+            None => {
+                self.frame.clear();
+                self.escaped = false;
+                Err(io::Error::new(io::ErrorKind::UnexpectedEof, "partial SLIP frame").into())
+            }
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Encoder<T> for SlipCodec {
+    type Error = SlipError;
+
+    fn encode(&mut self, item: T, dst: &mut Vec<u8>) -> Result<(), SlipError> {
+        let item = item.as_ref();
+        dst.reserve(item.len() + 2);
+        if self.leading_end {
+            dst.push(END);
+        }
+        for &byte in item {
+            match byte {
+                END => dst.extend_from_slice(&[ESC, ESC_END]),
+                ESC => dst.extend_from_slice(&[ESC, ESC_ESC]),
+                _ => dst.push(byte),
+            }
+        }
+        dst.push(END);
+        Ok(())
+    }
+}
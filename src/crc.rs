@@ -0,0 +1,256 @@
+//! Cyclic redundancy checks used by the framing and protocol modules
+//!
+//! The algorithms are described with the parameters of the
+//! [CRC RevEng catalogue](https://reveng.sourceforge.io/crc-catalogue/) and computed bit by
+//! bit, which is plenty fast for serial line rates.  Only algorithms whose input and output
+//! reflection match are supported; that covers every CRC in common use on serial links.
+//!
+//! ## Example
+//!
+//! ```
+//! use mio_serial::crc::{CRC_16_IBM_SDLC, CRC_32_ISO_HDLC};
+//!
+//! assert_eq!(CRC_16_IBM_SDLC.checksum(b"123456789"), 0x906E);
+//!
+//! // Checksums can also be computed incrementally
+//! let crc = CRC_32_ISO_HDLC.update(CRC_32_ISO_HDLC.init_value(), b"12345");
+//! let crc = CRC_32_ISO_HDLC.update(crc, b"6789");
+//! assert_eq!(CRC_32_ISO_HDLC.finalize(crc), 0xCBF4_3926);
+//! ```
+
+/// Parameters of an 8-bit CRC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc8 {
+    /// Generator polynomial, normal (not reflected) form
+    pub poly: u8,
+    /// Initial register value
+    pub init: u8,
+    /// `true` if bytes are processed least significant bit first
+    pub reflected: bool,
+    /// Value XORed into the final register
+    pub xorout: u8,
+}
+
+/// CRC-8/ROHC; the 3GPP TS 27.010 (CMUX) FCS is its ones' complement
+pub const CRC_8_ROHC: Crc8 = Crc8 {
+    poly: 0x07,
+    init: 0xFF,
+    reflected: true,
+    xorout: 0x00,
+};
+
+impl Crc8 {
+    /// Register value before any data has been processed
+    pub fn init_value(&self) -> u8 {
+        if self.reflected {
+            self.init.reverse_bits()
+        } else {
+            self.init
+        }
+    }
+
+    /// Process `data`, starting from register value `crc`
+    pub fn update(&self, mut crc: u8, data: &[u8]) -> u8 {
+        if self.reflected {
+            let poly = self.poly.reverse_bits();
+            for &byte in data {
+                crc ^= byte;
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        } else {
+            for &byte in data {
+                crc ^= byte;
+                for _ in 0..8 {
+                    crc = if crc & 0x80 != 0 {
+                        (crc << 1) ^ self.poly
+                    } else {
+                        crc << 1
+                    };
+                }
+            }
+        }
+        crc
+    }
+
+    /// Turn a register value into the checksum
+    pub fn finalize(&self, crc: u8) -> u8 {
+        crc ^ self.xorout
+    }
+
+    /// Checksum of `data`
+    pub fn checksum(&self, data: &[u8]) -> u8 {
+        self.finalize(self.update(self.init_value(), data))
+    }
+}
+
+/// Parameters of a 16-bit CRC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc16 {
+    /// Generator polynomial, normal (not reflected) form
+    pub poly: u16,
+    /// Initial register value
+    pub init: u16,
+    /// `true` if bytes are processed least significant bit first
+    pub reflected: bool,
+    /// Value XORed into the final register
+    pub xorout: u16,
+}
+
+/// CRC-16/IBM-SDLC, also known as CRC-16/X-25; the PPP and HDLC FCS-16
+pub const CRC_16_IBM_SDLC: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xFFFF,
+    reflected: true,
+    xorout: 0xFFFF,
+};
+
+/// CRC-16/MODBUS
+pub const CRC_16_MODBUS: Crc16 = Crc16 {
+    poly: 0x8005,
+    init: 0xFFFF,
+    reflected: true,
+    xorout: 0x0000,
+};
+
+/// CRC-16/XMODEM, also used by YMODEM and ZMODEM
+pub const CRC_16_XMODEM: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0x0000,
+    reflected: false,
+    xorout: 0x0000,
+};
+
+/// CRC-16/IBM-3740, often called CRC-16/CCITT-FALSE
+pub const CRC_16_IBM_3740: Crc16 = Crc16 {
+    poly: 0x1021,
+    init: 0xFFFF,
+    reflected: false,
+    xorout: 0x0000,
+};
+
+impl Crc16 {
+    /// Register value before any data has been processed
+    pub fn init_value(&self) -> u16 {
+        if self.reflected {
+            self.init.reverse_bits()
+        } else {
+            self.init
+        }
+    }
+
+    /// Process `data`, starting from register value `crc`
+    pub fn update(&self, mut crc: u16, data: &[u8]) -> u16 {
+        if self.reflected {
+            let poly = self.poly.reverse_bits();
+            for &byte in data {
+                crc ^= u16::from(byte);
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        } else {
+            for &byte in data {
+                crc ^= u16::from(byte) << 8;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 {
+                        (crc << 1) ^ self.poly
+                    } else {
+                        crc << 1
+                    };
+                }
+            }
+        }
+        crc
+    }
+
+    /// Turn a register value into the checksum
+    pub fn finalize(&self, crc: u16) -> u16 {
+        crc ^ self.xorout
+    }
+
+    /// Checksum of `data`
+    pub fn checksum(&self, data: &[u8]) -> u16 {
+        self.finalize(self.update(self.init_value(), data))
+    }
+}
+
+/// Parameters of a 32-bit CRC
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crc32 {
+    /// Generator polynomial, normal (not reflected) form
+    pub poly: u32,
+    /// Initial register value
+    pub init: u32,
+    /// `true` if bytes are processed least significant bit first
+    pub reflected: bool,
+    /// Value XORed into the final register
+    pub xorout: u32,
+}
+
+/// CRC-32/ISO-HDLC, the common "CRC-32" used by Ethernet, zlib, PPP FCS-32 and ZMODEM
+pub const CRC_32_ISO_HDLC: Crc32 = Crc32 {
+    poly: 0x04C1_1DB7,
+    init: 0xFFFF_FFFF,
+    reflected: true,
+    xorout: 0xFFFF_FFFF,
+};
+
+impl Crc32 {
+    /// Register value before any data has been processed
+    pub fn init_value(&self) -> u32 {
+        if self.reflected {
+            self.init.reverse_bits()
+        } else {
+            self.init
+        }
+    }
+
+    /// Process `data`, starting from register value `crc`
+    pub fn update(&self, mut crc: u32, data: &[u8]) -> u32 {
+        if self.reflected {
+            let poly = self.poly.reverse_bits();
+            for &byte in data {
+                crc ^= u32::from(byte);
+                for _ in 0..8 {
+                    crc = if crc & 1 != 0 {
+                        (crc >> 1) ^ poly
+                    } else {
+                        crc >> 1
+                    };
+                }
+            }
+        } else {
+            for &byte in data {
+                crc ^= u32::from(byte) << 24;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000_0000 != 0 {
+                        (crc << 1) ^ self.poly
+                    } else {
+                        crc << 1
+                    };
+                }
+            }
+        }
+        crc
+    }
+
+    /// Turn a register value into the checksum
+    pub fn finalize(&self, crc: u32) -> u32 {
+        crc ^ self.xorout
+    }
+
+    /// Checksum of `data`
+    pub fn checksum(&self, data: &[u8]) -> u32 {
+        self.finalize(self.update(self.init_value(), data))
+    }
+}
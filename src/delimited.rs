@@ -0,0 +1,310 @@
+//! Delimited record reader for [`SerialStream`]
+//!
+//! A single readable event may carry half a line, several lines, or the tail of one line
+//! and the start of the next.  [`DelimitedReader`] keeps the partial data between events
+//! and hands back complete records as they become available.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::delimited::{Delimiter, DelimitedReader};
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 9600).open_native_async().unwrap();
+//! let mut lines = DelimitedReader::new(stream, Delimiter::CrLf).with_max_length(256);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(1);
+//! poll.registry()
+//!     .register(&mut lines, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! loop {
+//!     poll.poll(&mut events, None).unwrap();
+//!     while let Some(line) = lines.read_record().unwrap() {
+//!         println!("{}", String::from_utf8_lossy(&line));
+//!     }
+//! }
+//! ```
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+/// Default maximum record length used by [`DelimitedReader::new`]
+pub const DEFAULT_MAX_LENGTH: usize = 8 * 1024;
+
+const READ_CHUNK_SIZE: usize = 1024;
+
+/// Marks the end of a record
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Delimiter {
+    /// A single terminating byte, e.g. `b'\n'`
+    Byte(u8),
+    /// A carriage return followed by a line feed
+    CrLf,
+    /// Any one of the given bytes
+    AnyOf(Vec<u8>),
+}
+
+impl Delimiter {
+    /// Find the first delimiter in `buf` at or after `from`
+    ///
+    /// Returns the index of the delimiter and its length in bytes.
+    fn find(&self, buf: &[u8], from: usize) -> Option<(usize, usize)> {
+        let tail = &buf[from..];
+        match self {
+            Delimiter::Byte(b) => tail.iter().position(|c| c == b).map(|i| (from + i, 1)),
+            Delimiter::CrLf => tail
+                .windows(2)
+                .position(|w| w == b"\r\n")
+                .map(|i| (from + i, 2)),
+            Delimiter::AnyOf(set) => tail
+                .iter()
+                .position(|c| set.contains(c))
+                .map(|i| (from + i, 1)),
+        }
+    }
+
+    /// Number of trailing bytes that could be the start of a delimiter split across reads
+    fn overlap(&self) -> usize {
+        match self {
+            Delimiter::CrLf => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// Reads delimiter-terminated records from a non-blocking stream
+///
+/// Records are returned without their delimiter.  A record that grows past the maximum
+/// length is reported once as an `InvalidData` error and the rest of it, up to the next
+/// delimiter, is discarded so the reader picks up cleanly with the following record.
+#[derive(Debug)]
+pub struct DelimitedReader<S = SerialStream> {
+    inner: S,
+    delimiter: Delimiter,
+    max_length: usize,
+    skip_empty: bool,
+    buf: Vec<u8>,
+    scan_from: usize,
+    discarding: bool,
+    last_read: Option<Instant>,
+    eof: bool,
+}
+
+impl<S: Read> DelimitedReader<S> {
+    /// Wrap `inner`, splitting its input on `delimiter`
+    pub fn new(inner: S, delimiter: Delimiter) -> Self {
+        Self {
+            inner,
+            delimiter,
+            max_length: DEFAULT_MAX_LENGTH,
+            skip_empty: false,
+            buf: Vec::new(),
+            scan_from: 0,
+            discarding: false,
+            last_read: None,
+            eof: false,
+        }
+    }
+
+    /// Set the maximum record length, not counting the delimiter
+    #[must_use]
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Skip empty records instead of returning them
+    ///
+    /// Useful with [`Delimiter::AnyOf`] when a peer ends lines with `\r\n` but `\n` alone
+    /// must also be accepted.
+    #[must_use]
+    pub fn with_skip_empty(mut self, skip_empty: bool) -> Self {
+        self.skip_empty = skip_empty;
+        self
+    }
+
+    /// The configured delimiter
+    pub fn delimiter(&self) -> &Delimiter {
+        &self.delimiter
+    }
+
+    /// The configured maximum record length
+    pub fn max_length(&self) -> usize {
+        self.max_length
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    ///
+    /// Reading from the stream directly will bypass any partially assembled record.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume the reader, returning the underlying stream
+    ///
+    /// Any buffered data is lost.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns `true` once the stream has reported end of file
+    pub fn is_eof(&self) -> bool {
+        self.eof
+    }
+
+    /// Number of buffered bytes not yet returned as part of a record
+    pub fn partial_len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Time the last bytes were read from the stream
+    pub fn last_read(&self) -> Option<Instant> {
+        self.last_read
+    }
+
+    /// Return the next complete record
+    ///
+    /// Reads from the stream until a record is complete or the stream would block.  Returns
+    /// `Ok(None)` when no complete record is available, in which case the caller should wait
+    /// for the next readable event.  With edge-triggered events call this in a loop until it
+    /// returns `Ok(None)`.
+    ///
+    /// ## Errors
+    ///
+    /// * `InvalidData` if a record exceeds the maximum length.
+    /// * Any error returned by the stream other than `WouldBlock`.
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut chunk = [0u8; READ_CHUNK_SIZE];
+        loop {
+            if let Some(record) = self.next_buffered()? {
+                return Ok(Some(record));
+            }
+            if self.eof {
+                return Ok(None);
+            }
+            match self.inner.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(None);
+                }
+                Ok(n) => {
+                    self.buf.extend_from_slice(&chunk[..n]);
+                    self.last_read = Some(Instant::now());
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Take whatever partial record is buffered, if any
+    pub fn take_partial(&mut self) -> Option<Vec<u8>> {
+        self.scan_from = 0;
+        if self.discarding || self.buf.is_empty() {
+            self.discarding = false;
+            self.buf.clear();
+            None
+        } else {
+            Some(std::mem::take(&mut self.buf))
+        }
+    }
+
+    /// Deadline at which a buffered partial record is considered stale
+    ///
+    /// Returns `None` if nothing is buffered.  Use it to compute the timeout passed to
+    /// [`mio::Poll::poll`], then call [`flush_stale`](Self::flush_stale).
+    pub fn partial_deadline(&self, timeout: Duration) -> Option<Instant> {
+        if self.buf.is_empty() {
+            None
+        } else {
+            self.last_read.map(|t| t + timeout)
+        }
+    }
+
+    /// Take the partial record if no bytes have arrived for at least `timeout`
+    pub fn flush_stale(&mut self, timeout: Duration) -> Option<Vec<u8>> {
+        match self.partial_deadline(timeout) {
+            Some(deadline) if Instant::now() >= deadline => self.take_partial(),
+            _ => None,
+        }
+    }
+
+    fn next_buffered(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.delimiter.find(&self.buf, self.scan_from) {
+                Some((pos, len)) => {
+                    let record: Vec<u8> = self.buf.drain(..pos + len).take(pos).collect();
+                    self.scan_from = 0;
+                    if self.discarding {
+                        self.discarding = false;
+                        continue;
+                    }
+                    if record.len() > self.max_length {
+                        return Err(too_long(self.max_length));
+                    }
+                    if self.skip_empty && record.is_empty() {
+                        continue;
+                    }
+                    return Ok(Some(record));
+                }
+                None => {
+                    let overlap = self.delimiter.overlap();
+                    if self.buf.len() > self.max_length + overlap {
+                        // Keep a possible partial delimiter so it still matches next time
+                        let keep = self.buf.len() - overlap;
+                        self.buf.drain(..keep);
+                        self.scan_from = 0;
+                        if !self.discarding {
+                            self.discarding = true;
+                            return Err(too_long(self.max_length));
+                        }
+                    } else {
+                        self.scan_from = self.buf.len().saturating_sub(overlap);
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+}
+
+fn too_long(max_length: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("record exceeds maximum length of {max_length} bytes"),
+    )
+}
+
+impl<S: Source> Source for DelimitedReader<S> {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
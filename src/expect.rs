@@ -0,0 +1,587 @@
+//! Expect-style scripting for interactive consoles
+//!
+//! [`Expect`] waits for patterns in the output of a boot loader, shell or any other
+//! interactive console, and sends text in between: "send this, wait for that, then send
+//! this".  Patterns are literal strings or regular expressions ([`Pattern`]); each
+//! [`Match`] carries the text before the match, the match itself with its capture groups,
+//! and whatever arrived after it.  Everything received and sent can be copied to a
+//! transcript.
+//!
+//! [`Expect`] never blocks: start waiting with [`Expect::expect`] or
+//! [`Expect::expect_any`], then call [`Expect::handle_io`] on every event for its token and
+//! whenever the deadline from [`Expect::next_deadline`] expires, until
+//! [`Expect::poll_match`] returns the outcome.  For straight-line scripts,
+//! [`BlockingExpect`] runs its own poll loop instead.
+//!
+//! This module is only available with the `expect` feature, which pulls in `regex`.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::expect::{BlockingExpect, Expect, Pattern};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Duration;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let console = Expect::new(stream).with_transcript(std::io::stderr());
+//! let mut console = BlockingExpect::new(console).unwrap();
+//!
+//! console.expect("Hit any key to stop autoboot", Duration::from_secs(30)).unwrap();
+//! console.send(" ").unwrap();
+//! console.expect("=> ", Duration::from_secs(2)).unwrap();
+//! console.send_line("version").unwrap();
+//! let version = Pattern::regex(r"U-Boot (\S+)").unwrap();
+//! let found = console.expect(version, Duration::from_secs(2)).unwrap();
+//! println!("U-Boot version {}", found.captures[1].as_deref().unwrap_or("?"));
+//! ```
+use crate::buffered::WriteQueue;
+use crate::SerialStream;
+use mio::{event::Source, Events, Interest, Poll, Registry, Token};
+use regex::bytes::Regex;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Default number of unmatched bytes kept for matching
+pub const DEFAULT_MAX_BUFFER: usize = 64 * 1024;
+
+/// Default line ending appended by `send_line`
+pub const DEFAULT_LINE_ENDING: &str = "\r";
+
+/// Something to wait for
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Literal bytes
+    Exact(Vec<u8>),
+    /// A regular expression, matched against the raw bytes
+    Regex(Regex),
+}
+
+impl Pattern {
+    /// Compile a regular expression pattern
+    ///
+    /// ## Errors
+    ///
+    /// Returns the error from [`Regex::new`] if `pattern` is not a valid expression.
+    pub fn regex(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Pattern::Regex)
+    }
+
+    /// Find the first match in `haystack`: its range and capture groups
+    fn find(&self, haystack: &[u8]) -> Option<(usize, usize, Vec<Option<String>>)> {
+        match self {
+            Pattern::Exact(needle) if needle.is_empty() => Some((0, 0, Vec::new())),
+            Pattern::Exact(needle) => haystack
+                .windows(needle.len())
+                .position(|w| w == &needle[..])
+                .map(|start| (start, start + needle.len(), Vec::new())),
+            Pattern::Regex(regex) => regex.captures(haystack).map(|captures| {
+                let whole = captures.get(0).expect("group 0 always matches");
+                let groups = captures
+                    .iter()
+                    .map(|group| group.map(|g| String::from_utf8_lossy(g.as_bytes()).into_owned()))
+                    .collect();
+                (whole.start(), whole.end(), groups)
+            }),
+        }
+    }
+}
+
+impl From<&str> for Pattern {
+    fn from(text: &str) -> Self {
+        Pattern::Exact(text.as_bytes().to_vec())
+    }
+}
+
+impl From<String> for Pattern {
+    fn from(text: String) -> Self {
+        Pattern::Exact(text.into_bytes())
+    }
+}
+
+impl From<&[u8]> for Pattern {
+    fn from(bytes: &[u8]) -> Self {
+        Pattern::Exact(bytes.to_vec())
+    }
+}
+
+impl From<Regex> for Pattern {
+    fn from(regex: Regex) -> Self {
+        Pattern::Regex(regex)
+    }
+}
+
+/// A successful match
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match {
+    /// Index of the pattern that matched, in the order given to `expect_any`
+    pub index: usize,
+    /// Text received before the match
+    pub before: String,
+    /// The matched text
+    pub matched: String,
+    /// Capture groups of a regular expression, starting with the whole match; empty for
+    /// literal patterns
+    pub captures: Vec<Option<String>>,
+    /// Text received after the match so far; it stays buffered for the next expectation
+    pub after: String,
+}
+
+/// Why an expectation failed
+#[derive(Debug)]
+pub enum ExpectError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// No pattern matched in time; holds the unmatched text received so far
+    Timeout(String),
+    /// The stream reached end-of-file before a pattern matched
+    Eof(String),
+}
+
+impl ExpectError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            ExpectError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExpectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpectError::Io(e) => write!(f, "{e}"),
+            ExpectError::Timeout(text) => write!(f, "timed out waiting for a match in {text:?}"),
+            ExpectError::Eof(text) => write!(f, "end of file before a match in {text:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ExpectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExpectError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ExpectError {
+    fn from(e: io::Error) -> Self {
+        ExpectError::Io(e)
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    patterns: Vec<Pattern>,
+    deadline: Instant,
+}
+
+/// Non-blocking expect engine
+pub struct Expect<S = SerialStream> {
+    inner: S,
+    write_queue: WriteQueue,
+    buffer: Vec<u8>,
+    max_buffer: usize,
+    line_ending: String,
+    transcript: Option<Box<dyn Write + Send>>,
+    pending: Option<Pending>,
+    result: Option<Result<Match, ExpectError>>,
+    eof: bool,
+}
+
+impl<S> Expect<S>
+where
+    S: Read + Write + Source,
+{
+    /// Create an engine on `inner`
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            write_queue: WriteQueue::new(),
+            buffer: Vec::new(),
+            max_buffer: DEFAULT_MAX_BUFFER,
+            line_ending: DEFAULT_LINE_ENDING.to_string(),
+            transcript: None,
+            pending: None,
+            result: None,
+            eof: false,
+        }
+    }
+
+    /// Copy everything received and sent to `transcript`
+    #[must_use]
+    pub fn with_transcript(mut self, transcript: impl Write + Send + 'static) -> Self {
+        self.transcript = Some(Box::new(transcript));
+        self
+    }
+
+    /// Keep at most `max_buffer` unmatched bytes, discarding the oldest
+    #[must_use]
+    pub fn with_max_buffer(mut self, max_buffer: usize) -> Self {
+        self.max_buffer = max_buffer;
+        self
+    }
+
+    /// Set the line ending appended by [`send_line`](Self::send_line)
+    #[must_use]
+    pub fn with_line_ending(mut self, line_ending: impl Into<String>) -> Self {
+        self.line_ending = line_ending.into();
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consume the engine, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Queue `data` for sending and write as much as the stream accepts
+    ///
+    /// ## Errors
+    ///
+    /// I/O errors from the stream, or `WouldBlock` if the write queue is full.
+    pub fn send(&mut self, data: impl AsRef<[u8]>) -> io::Result<()> {
+        let data = data.as_ref();
+        if self.write_queue.remaining() < data.len() {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        self.write_queue.push(data);
+        self.log(data);
+        self.write_queue.write_to(&mut self.inner).map(|_| ())
+    }
+
+    /// The most [`send`](Self::send) accepts without `WouldBlock`
+    pub fn send_capacity(&self) -> usize {
+        self.write_queue.remaining()
+    }
+
+    /// Send `line` followed by the line ending
+    ///
+    /// ## Errors
+    ///
+    /// As for [`send`](Self::send).
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let mut data = line.as_bytes().to_vec();
+        data.extend_from_slice(self.line_ending.as_bytes());
+        self.send(data)
+    }
+
+    /// Start waiting up to `timeout` for `pattern`
+    ///
+    /// The timeout starts now, so [`next_deadline`](Self::next_deadline) is set as soon as
+    /// this returns.  An expectation still pending is replaced.
+    pub fn expect(&mut self, pattern: impl Into<Pattern>, timeout: Duration) {
+        self.expect_any([pattern.into()], timeout);
+    }
+
+    /// Start waiting up to `timeout` for whichever of `patterns` matches first
+    ///
+    /// If several patterns match, the one starting earliest in the received text wins, and
+    /// among those the first given.
+    pub fn expect_any(&mut self, patterns: impl IntoIterator<Item = Pattern>, timeout: Duration) {
+        self.result = None;
+        self.pending = Some(Pending {
+            patterns: patterns.into_iter().collect(),
+            deadline: Instant::now() + timeout,
+        });
+    }
+
+    /// Returns `true` while an expectation is waiting for a match
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Received text not consumed by a match yet
+    pub fn buffer(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    /// Discard the received text not consumed by a match
+    pub fn clear_buffer(&mut self) {
+        self.buffer.clear();
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|p| p.deadline)
+    }
+
+    /// Take the outcome of the last expectation once it has finished
+    pub fn poll_match(&mut self) -> Option<Result<Match, ExpectError>> {
+        self.result.take()
+    }
+
+    /// Write queued data, read the stream and check the pending expectation
+    ///
+    /// ## Errors
+    ///
+    /// I/O errors from the stream; they also fail the pending expectation.
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        let io_result = match self.write_queue.write_to(&mut self.inner) {
+            Ok(_) => self.fill(),
+            Err(e) => Err(e),
+        };
+        if let Err(e) = io_result {
+            if self.pending.take().is_some() {
+                let error = io::Error::new(e.kind(), e.to_string());
+                self.result = Some(Err(ExpectError::Io(error)));
+            }
+            return Err(e);
+        }
+        let pending = match self.pending {
+            Some(ref mut pending) => pending,
+            None => return Ok(()),
+        };
+        let deadline = pending.deadline;
+
+        let found = pending
+            .patterns
+            .iter()
+            .enumerate()
+            .filter_map(|(index, pattern)| {
+                pattern
+                    .find(&self.buffer)
+                    .map(|(start, end, captures)| (start, index, end, captures))
+            })
+            .min_by_key(|&(start, index, _, _)| (start, index));
+        if let Some((start, index, end, captures)) = found {
+            let consumed: Vec<u8> = self.buffer.drain(..end).collect();
+            self.pending = None;
+            self.result = Some(Ok(Match {
+                index,
+                before: String::from_utf8_lossy(&consumed[..start]).into_owned(),
+                matched: String::from_utf8_lossy(&consumed[start..]).into_owned(),
+                captures,
+                after: String::from_utf8_lossy(&self.buffer).into_owned(),
+            }));
+        } else if self.eof {
+            self.pending = None;
+            let text = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.result = Some(Err(ExpectError::Eof(text)));
+        } else if now >= deadline {
+            self.pending = None;
+            let text = String::from_utf8_lossy(&self.buffer).into_owned();
+            self.result = Some(Err(ExpectError::Timeout(text)));
+        }
+        Ok(())
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 1024];
+        loop {
+            match self.inner.read(&mut buf) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(());
+                }
+                Ok(n) => {
+                    self.log(&buf[..n]);
+                    self.buffer.extend_from_slice(&buf[..n]);
+                    if self.buffer.len() > self.max_buffer {
+                        let excess = self.buffer.len() - self.max_buffer;
+                        self.buffer.drain(..excess);
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn log(&mut self, data: &[u8]) {
+        if let Some(ref mut transcript) = self.transcript {
+            if let Err(e) = transcript.write_all(data) {
+                log::warn!("unable to write expect transcript: {e}");
+                self.transcript = None;
+            }
+        }
+    }
+}
+
+impl<S> fmt::Debug for Expect<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Expect")
+            .field("buffer", &String::from_utf8_lossy(&self.buffer))
+            .field("write_queue", &self.write_queue.len())
+            .field("max_buffer", &self.max_buffer)
+            .field("line_ending", &self.line_ending)
+            .field("transcript", &self.transcript.is_some())
+            .field("pending", &self.pending)
+            .field("result", &self.result)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Source for Expect<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.inner.deregister(registry)
+    }
+}
+
+const TOKEN: Token = Token(0);
+
+/// An [`Expect`] engine driven by its own poll loop, for scripts that may block
+#[derive(Debug)]
+pub struct BlockingExpect<S = SerialStream>
+where
+    S: Read + Write + Source,
+{
+    expect: Expect<S>,
+    poll: Poll,
+    events: Events,
+}
+
+impl<S> BlockingExpect<S>
+where
+    S: Read + Write + Source,
+{
+    /// Take over `expect`, registering its stream with a private [`Poll`]
+    ///
+    /// ## Errors
+    ///
+    /// Errors from creating the poll instance or registering the stream.
+    pub fn new(mut expect: Expect<S>) -> io::Result<Self> {
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut expect, TOKEN, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self {
+            expect,
+            poll,
+            events: Events::with_capacity(4),
+        })
+    }
+
+    /// Get a reference to the engine
+    pub fn get_ref(&self) -> &Expect<S> {
+        &self.expect
+    }
+
+    /// Get a mutable reference to the engine
+    pub fn get_mut(&mut self) -> &mut Expect<S> {
+        &mut self.expect
+    }
+
+    /// Deregister the stream and return the engine
+    ///
+    /// ## Errors
+    ///
+    /// Errors from deregistering the stream.
+    pub fn into_inner(mut self) -> io::Result<Expect<S>> {
+        self.poll.registry().deregister(&mut self.expect)?;
+        Ok(self.expect)
+    }
+
+    /// Send `data`, waiting until it has been written
+    ///
+    /// ## Errors
+    ///
+    /// I/O errors from the stream.
+    pub fn send(&mut self, data: impl AsRef<[u8]>) -> io::Result<()> {
+        let mut data = data.as_ref();
+        while !data.is_empty() {
+            let n = data.len().min(self.expect.write_queue.remaining());
+            if n > 0 {
+                self.expect.send(&data[..n])?;
+                data = &data[n..];
+            } else {
+                self.wait(None)?;
+                self.expect.handle_io(Instant::now())?;
+            }
+        }
+        while !self.expect.write_queue.is_empty() {
+            self.wait(None)?;
+            self.expect.handle_io(Instant::now())?;
+        }
+        Ok(())
+    }
+
+    /// Send `line` followed by the line ending, waiting until it has been written
+    ///
+    /// ## Errors
+    ///
+    /// I/O errors from the stream.
+    pub fn send_line(&mut self, line: &str) -> io::Result<()> {
+        let mut data = line.as_bytes().to_vec();
+        data.extend_from_slice(self.expect.line_ending.as_bytes());
+        self.send(data)
+    }
+
+    /// Wait up to `timeout` for `pattern`
+    ///
+    /// ## Errors
+    ///
+    /// [`ExpectError::Timeout`] if the pattern did not appear in time, or the errors from
+    /// [`Expect::poll_match`].
+    pub fn expect(
+        &mut self,
+        pattern: impl Into<Pattern>,
+        timeout: Duration,
+    ) -> Result<Match, ExpectError> {
+        self.expect_any([pattern.into()], timeout)
+    }
+
+    /// Wait up to `timeout` for whichever of `patterns` matches first
+    ///
+    /// ## Errors
+    ///
+    /// As for [`expect`](Self::expect).
+    pub fn expect_any(
+        &mut self,
+        patterns: impl IntoIterator<Item = Pattern>,
+        timeout: Duration,
+    ) -> Result<Match, ExpectError> {
+        self.expect.expect_any(patterns, timeout);
+        loop {
+            self.expect.handle_io(Instant::now())?;
+            if let Some(result) = self.expect.poll_match() {
+                return result;
+            }
+            let timeout = self
+                .expect
+                .next_deadline()
+                .map(|d| d.saturating_duration_since(Instant::now()));
+            self.wait(timeout)?;
+        }
+    }
+
+    fn wait(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        match self.poll.poll(&mut self.events, timeout) {
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => Ok(()),
+            result => result,
+        }
+    }
+}
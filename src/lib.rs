@@ -37,6 +37,32 @@ pub use serialport::available_ports;
 // Re-export creation of SerialPortBuilder objects
 pub use serialport::new;
 
+pub mod at;
+pub mod bridge;
+pub mod buffered;
+pub mod capture;
+#[cfg(feature = "expect")]
+pub mod chat;
+#[cfg(unix)]
+pub mod cmux;
+pub use buffered::{BufferedSerialStream, WriteQueue};
+pub mod codec;
+pub use codec::FramedSerial;
+pub mod crc;
+pub mod delimited;
+pub use delimited::{DelimitedReader, Delimiter};
+#[cfg(feature = "expect")]
+pub mod expect;
+pub mod modbus;
+pub mod nmea;
+pub mod rfc2217;
+#[cfg(unix)]
+pub mod tee;
+mod transfer;
+pub mod ubx;
+pub mod xmodem;
+pub mod zmodem;
+
 use mio::{event::Source, Interest, Registry, Token};
 use std::io::{Error as StdIoError, ErrorKind as StdIoErrorKind, Result as StdIoResult};
 use std::time::Duration;
@@ -668,7 +694,7 @@ mod io {
         }
     }
 
-    impl<'a> Read for &'a SerialStream {
+    impl Read for &SerialStream {
         fn read(&mut self, bytes: &mut [u8]) -> StdIoResult<usize> {
             uninterruptibly!(match unsafe {
                 libc::read(
@@ -683,7 +709,7 @@ mod io {
         }
     }
 
-    impl<'a> Write for &'a SerialStream {
+    impl Write for &SerialStream {
         fn write(&mut self, bytes: &[u8]) -> StdIoResult<usize> {
             uninterruptibly!(match unsafe {
                 libc::write(
@@ -0,0 +1,132 @@
+//! Modbus ASCII framing
+//!
+//! An ASCII frame starts with `:`, carries the unit address, the PDU and an LRC as pairs of
+//! hexadecimal digits, and ends with CR LF.  A `:` in the middle of a frame starts a new
+//! frame.  Gaps of up to one second between characters are allowed; pass the timeout to
+//! [`FrameTiming::ascii`](super::FrameTiming::ascii) so that [`Client`](super::Client) and [`Server`](super::Server)
+//! drop incomplete frames after it.
+//!
+//! [`AsciiCodec`] produces the same [`Adu`] as [`RtuCodec`](super::RtuCodec), so clients and
+//! servers work the same in both modes.
+use super::{Adu, ModbusError, MAX_PDU_LENGTH};
+use crate::codec::{Decoder, Encoder};
+use std::time::Duration;
+
+/// Marks the start of a frame
+pub const START: u8 = b':';
+
+/// Longest inter-character timeout allowed by the specification
+pub const MAX_INTER_CHAR_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Largest possible ASCII frame: start, 255 bytes as hex digits, LRC and CR LF
+pub const MAX_FRAME_LENGTH: usize = 1 + 2 * (1 + MAX_PDU_LENGTH + 1) + 2;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// The longitudinal redundancy check of `data`: the two's complement of its byte sum
+pub fn lrc(data: &[u8]) -> u8 {
+    data.iter()
+        .fold(0u8, |acc, b| acc.wrapping_add(*b))
+        .wrapping_neg()
+}
+
+fn hex_value(digit: u8) -> Option<u8> {
+    match digit {
+        b'0'..=b'9' => Some(digit - b'0'),
+        b'A'..=b'F' => Some(digit - b'A' + 10),
+        b'a'..=b'f' => Some(digit - b'a' + 10),
+        _ => None,
+    }
+}
+
+/// Modbus ASCII frame encoder and decoder
+///
+/// The same codec serves clients and servers, since ASCII frames are delimited rather than
+/// sized by their content.
+#[derive(Debug, Clone, Default)]
+pub struct AsciiCodec;
+
+impl AsciiCodec {
+    /// Create a new codec
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for AsciiCodec {
+    type Item = Adu;
+    type Error = ModbusError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Adu>, ModbusError> {
+        let mut start = match src.iter().position(|&b| b == START) {
+            Some(start) => start,
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        };
+        let end = loop {
+            match src[start + 1..]
+                .iter()
+                .position(|&b| b == START || b == b'\n')
+            {
+                // A new start character abandons the frame before it
+                Some(i) if src[start + 1 + i] == START => start += 1 + i,
+                Some(i) => break start + 1 + i,
+                None if src.len() - start >= MAX_FRAME_LENGTH => {
+                    src.clear();
+                    return Err(ModbusError::InvalidFrame("frame too long"));
+                }
+                None => {
+                    src.drain(..start);
+                    return Ok(None);
+                }
+            }
+        };
+
+        let frame: Vec<u8> = src.drain(..=end).skip(start).collect();
+        let digits = match frame[1..].strip_suffix(b"\r\n") {
+            Some(digits) => digits,
+            None => return Err(ModbusError::InvalidFrame("missing CR before LF")),
+        };
+        if digits.len() % 2 != 0 || digits.len() < 6 {
+            return Err(ModbusError::InvalidFrame("bad number of hex digits"));
+        }
+
+        let bytes = digits
+            .chunks(2)
+            .map(|pair| Some(hex_value(pair[0])? << 4 | hex_value(pair[1])?))
+            .collect::<Option<Vec<u8>>>()
+            .ok_or(ModbusError::InvalidFrame("invalid hex digit"))?;
+        let (data, checksum) = bytes.split_at(bytes.len() - 1);
+        if lrc(data) != checksum[0] {
+            return Err(ModbusError::ChecksumMismatch);
+        }
+
+        Ok(Some(Adu {
+            unit: data[0],
+            pdu: data[1..].to_vec(),
+        }))
+    }
+}
+
+impl Encoder<&Adu> for AsciiCodec {
+    type Error = ModbusError;
+
+    fn encode(&mut self, adu: &Adu, dst: &mut Vec<u8>) -> Result<(), ModbusError> {
+        let mut sum = adu.unit;
+        let push_hex = |dst: &mut Vec<u8>, b: u8| {
+            dst.push(HEX_DIGITS[usize::from(b >> 4)]);
+            dst.push(HEX_DIGITS[usize::from(b & 0x0F)]);
+        };
+        dst.push(START);
+        push_hex(dst, adu.unit);
+        for &b in &adu.pdu {
+            sum = sum.wrapping_add(b);
+            push_hex(dst, b);
+        }
+        push_hex(dst, sum.wrapping_neg());
+        dst.extend_from_slice(b"\r\n");
+        Ok(())
+    }
+}
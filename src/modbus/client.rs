@@ -0,0 +1,405 @@
+//! Non-blocking Modbus serial line client (master)
+//!
+//! [`Client`] keeps a queue of requests and sends them one at a time, waiting for each
+//! response (or the response timeout) before sending the next.  It never blocks: call
+//! [`Client::handle_io`] whenever the event loop wakes up, either for an event on the
+//! client's token or because the timeout from [`Client::next_deadline`] expired, and
+//! collect finished requests with [`Client::poll_completion`].
+//!
+//! The client waits for the bus to be quiet for the inter-frame delay (t3.5) after the last
+//! byte received or sent before starting a new request.  Broadcast writes (unit
+//! [`BROADCAST`]) get no response; they complete after the turnaround delay.
+use super::ascii::AsciiCodec;
+use super::rtu::{FrameTiming, RtuCodec};
+use super::{Adu, ModbusError, Request, Response, BROADCAST};
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::{SerialPort, SerialStream};
+use mio::{event::Source, Interest, Registry, Token};
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for a response, measured from the end of the request
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Default delay after a broadcast before the next request is sent
+pub const DEFAULT_TURNAROUND_DELAY: Duration = Duration::from_millis(100);
+
+/// Identifies a submitted request
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequestId(u64);
+
+/// The outcome of a submitted request
+#[derive(Debug)]
+pub struct Completion {
+    /// The id returned by [`Client::submit`]
+    pub id: RequestId,
+    /// The unit the request was addressed to
+    pub unit: u8,
+    /// The request as submitted
+    pub request: Request,
+    /// The response, or why there is none
+    pub result: Result<Response, ModbusError>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    id: RequestId,
+    unit: u8,
+    request: Request,
+    timeout: Duration,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    pending: Pending,
+    deadline: Instant,
+}
+
+/// A Modbus client using RTU framing
+pub type RtuClient<S = SerialStream> = Client<RtuCodec, S>;
+
+/// A Modbus client using ASCII framing
+pub type AsciiClient<S = SerialStream> = Client<AsciiCodec, S>;
+
+/// Non-blocking Modbus client over a serial stream
+///
+/// The codec `C` supplies the serial line framing, e.g. [`RtuCodec`].
+#[derive(Debug)]
+pub struct Client<C, S = SerialStream> {
+    framed: FramedSerial<C, S>,
+    timing: FrameTiming,
+    response_timeout: Duration,
+    turnaround_delay: Duration,
+    queue: VecDeque<Pending>,
+    in_flight: Option<InFlight>,
+    completions: VecDeque<Completion>,
+    next_id: u64,
+    quiet_from: Option<Instant>,
+    last_rx: Option<Instant>,
+    rx_len: usize,
+}
+
+impl<S> Client<RtuCodec, S>
+where
+    S: SerialPort + Read + Write + Source,
+{
+    /// Create an RTU client with timing derived from the current port settings
+    pub fn new_rtu(stream: S) -> crate::Result<Self> {
+        let timing = FrameTiming::from_port(&stream)?;
+        Ok(Self::new(stream, RtuCodec::client(), timing))
+    }
+}
+
+impl<S> Client<AsciiCodec, S>
+where
+    S: SerialPort + Read + Write + Source,
+{
+    /// Create an ASCII client allowing gaps of up to `inter_char_timeout` within a frame
+    pub fn new_ascii(stream: S, inter_char_timeout: Duration) -> crate::Result<Self> {
+        let timing = FrameTiming::ascii_from_port(&stream, inter_char_timeout)?;
+        Ok(Self::new(stream, AsciiCodec::new(), timing))
+    }
+}
+
+impl<C, S> Client<C, S>
+where
+    S: Read + Write + Source,
+    C: Decoder<Item = Adu, Error = ModbusError> + for<'a> Encoder<&'a Adu, Error = ModbusError>,
+{
+    /// Create a client using `codec` for framing and `timing` for bus timing
+    pub fn new(stream: S, codec: C, timing: FrameTiming) -> Self {
+        Self {
+            framed: FramedSerial::new(stream, codec),
+            timing,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+            turnaround_delay: DEFAULT_TURNAROUND_DELAY,
+            queue: VecDeque::new(),
+            in_flight: None,
+            completions: VecDeque::new(),
+            next_id: 0,
+            quiet_from: None,
+            last_rx: None,
+            rx_len: 0,
+        }
+    }
+
+    /// Set the default response timeout
+    #[must_use]
+    pub fn with_response_timeout(mut self, timeout: Duration) -> Self {
+        self.response_timeout = timeout;
+        self
+    }
+
+    /// Set the delay after a broadcast before the next request
+    #[must_use]
+    pub fn with_turnaround_delay(mut self, delay: Duration) -> Self {
+        self.turnaround_delay = delay;
+        self
+    }
+
+    /// The bus timing in use
+    pub fn timing(&self) -> FrameTiming {
+        self.timing
+    }
+
+    /// Change the bus timing, e.g. after changing the baud rate
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.framed.get_mut()
+    }
+
+    /// Consume the client, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+
+    /// Queue `request` for `unit` using the default response timeout
+    pub fn submit(&mut self, unit: u8, request: Request) -> RequestId {
+        let timeout = self.response_timeout;
+        self.submit_with_timeout(unit, request, timeout)
+    }
+
+    /// Queue `request` for `unit` with its own response timeout
+    ///
+    /// Invalid requests, including broadcast reads, complete immediately with
+    /// [`ModbusError::InvalidRequest`].
+    pub fn submit_with_timeout(
+        &mut self,
+        unit: u8,
+        request: Request,
+        timeout: Duration,
+    ) -> RequestId {
+        let id = RequestId(self.next_id);
+        self.next_id += 1;
+        let pending = Pending {
+            id,
+            unit,
+            request,
+            timeout,
+        };
+
+        let invalid = pending.request.validate().err().or_else(|| {
+            (unit == BROADCAST && !pending.request.is_write())
+                .then_some(ModbusError::InvalidRequest("only writes can be broadcast"))
+        });
+        match invalid {
+            Some(e) => self.complete(pending, Err(e)),
+            None => self.queue.push_back(pending),
+        }
+        id
+    }
+
+    /// Number of requests queued or waiting for a response
+    pub fn pending(&self) -> usize {
+        self.queue.len() + usize::from(self.in_flight.is_some())
+    }
+
+    /// Returns `true` if no requests are queued or in flight
+    pub fn is_idle(&self) -> bool {
+        self.pending() == 0
+    }
+
+    /// Take the next finished request, in completion order
+    pub fn poll_completion(&mut self) -> Option<Completion> {
+        self.completions.pop_front()
+    }
+
+    /// The next time [`handle_io`](Self::handle_io) must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.in_flight {
+            Some(ref f) => Some(f.deadline),
+            None if !self.queue.is_empty() => Some(self.quiet_from.unwrap_or_else(Instant::now)),
+            None if self.rx_len > 0 => self.last_rx.map(|t| t + self.timing.frame_timeout()),
+            None => None,
+        }
+    }
+
+    /// Drive the client: write queued bytes, read responses, handle timeouts and start the
+    /// next request
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; protocol errors are reported through
+    /// the affected request's [`Completion`].
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.framed.on_writable()?;
+        self.read_responses(now)?;
+        self.check_timers(now);
+        self.start_next(now)
+    }
+
+    fn read_responses(&mut self, now: Instant) -> io::Result<()> {
+        let mut activity = false;
+        loop {
+            match self.framed.read_frame() {
+                Ok(Some(adu)) => {
+                    activity = true;
+                    self.on_frame(Ok(adu));
+                }
+                Ok(None) => break,
+                Err(ModbusError::Io(e)) => return Err(e),
+                Err(e) => {
+                    activity = true;
+                    self.on_frame(Err(e));
+                }
+            }
+        }
+
+        let rx_len = self.framed.read_buffer().len();
+        if activity || rx_len != self.rx_len {
+            self.last_rx = Some(now);
+            self.extend_quiet(now + self.timing.inter_frame);
+        }
+        self.rx_len = rx_len;
+        Ok(())
+    }
+
+    fn on_frame(&mut self, frame: Result<Adu, ModbusError>) {
+        let expected_unit = match self.in_flight {
+            Some(ref f) if f.pending.unit != BROADCAST => f.pending.unit,
+            _ => {
+                log::debug!("discarding unexpected frame: {:?}", frame);
+                return;
+            }
+        };
+
+        let result = match frame {
+            Ok(ref adu) if adu.unit != expected_unit => {
+                log::debug!("discarding response from unit {}", adu.unit);
+                return;
+            }
+            Ok(adu) => {
+                let request = &self.in_flight.as_ref().unwrap().pending.request;
+                Response::decode(request, &adu.pdu)
+            }
+            Err(e) => Err(e),
+        };
+
+        let in_flight = self.in_flight.take().unwrap();
+        self.complete(in_flight.pending, result);
+    }
+
+    fn check_timers(&mut self, now: Instant) {
+        if let Some(ref f) = self.in_flight {
+            if now >= f.deadline {
+                let in_flight = self.in_flight.take().unwrap();
+                let result = if in_flight.pending.unit == BROADCAST {
+                    Response::write_echo(&in_flight.pending.request)
+                        .ok_or(ModbusError::InvalidRequest("only writes can be broadcast"))
+                } else {
+                    // Whatever arrived so far belongs to the abandoned response
+                    self.framed.read_buffer_mut().clear();
+                    self.rx_len = 0;
+                    Err(ModbusError::Timeout)
+                };
+                self.complete(in_flight.pending, result);
+            }
+        }
+
+        if self.in_flight.is_none() && self.rx_len > 0 {
+            if let Some(last_rx) = self.last_rx {
+                if now >= last_rx + self.timing.frame_timeout() {
+                    log::debug!("discarding {} bytes of line noise", self.rx_len);
+                    self.framed.read_buffer_mut().clear();
+                    self.rx_len = 0;
+                }
+            }
+        }
+    }
+
+    fn start_next(&mut self, now: Instant) -> io::Result<()> {
+        if self.in_flight.is_some() || self.quiet_from.is_some_and(|t| now < t) {
+            return Ok(());
+        }
+        let pending = match self.queue.pop_front() {
+            Some(pending) => pending,
+            None => return Ok(()),
+        };
+
+        let mut pdu = Vec::new();
+        pending.request.encode(&mut pdu);
+        let adu = Adu {
+            unit: pending.unit,
+            pdu,
+        };
+
+        match self.framed.send(&adu) {
+            Ok(len) => {
+                let sent_at = now + self.timing.transmit_time(len);
+                let wait = if pending.unit == BROADCAST {
+                    self.turnaround_delay
+                } else {
+                    pending.timeout
+                };
+                self.extend_quiet(sent_at + self.timing.inter_frame);
+                self.in_flight = Some(InFlight {
+                    pending,
+                    deadline: sent_at + wait,
+                });
+                Ok(())
+            }
+            Err(ModbusError::Io(e)) if e.kind() == io::ErrorKind::WouldBlock => {
+                self.queue.push_front(pending);
+                Ok(())
+            }
+            Err(ModbusError::Io(e)) => {
+                self.queue.push_front(pending);
+                Err(e)
+            }
+            Err(e) => {
+                self.complete(pending, Err(e));
+                Ok(())
+            }
+        }
+    }
+
+    fn extend_quiet(&mut self, until: Instant) {
+        self.quiet_from = Some(self.quiet_from.map_or(until, |t| t.max(until)));
+    }
+
+    fn complete(&mut self, pending: Pending, result: Result<Response, ModbusError>) {
+        self.completions.push_back(Completion {
+            id: pending.id,
+            unit: pending.unit,
+            request: pending.request,
+            result,
+        });
+    }
+}
+
+impl<C, S> Source for Client<C, S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.framed.deregister(registry)
+    }
+}
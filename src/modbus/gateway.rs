@@ -0,0 +1,348 @@
+//! Modbus TCP to serial line gateway
+//!
+//! [`Gateway`] accepts Modbus TCP connections and forwards each request to a serial line
+//! [`Client`].  Requests from all connections share the bus and are sent one at a time in
+//! the order they arrive.  Responses are returned to the connection that asked, with the
+//! request's MBAP transaction id.  A device that does not answer in time gets exception
+//! 0x0B (gateway target device failed to respond), as does a garbled response.  Broadcast
+//! requests (unit [`BROADCAST`]) are carried out on the bus but get no reply, just as on the
+//! serial line.
+//!
+//! The gateway owns its [`Poll`], so everything runs in one thread and one event loop.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::net::TcpListener;
+//! use mio_serial::modbus::{Gateway, RtuClient};
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 19200).open_native_async().unwrap();
+//! let client = RtuClient::new_rtu(stream).unwrap();
+//! let listener = TcpListener::bind("0.0.0.0:502".parse().unwrap()).unwrap();
+//!
+//! let mut gateway = Gateway::new(listener, client).unwrap();
+//! gateway.run().unwrap();
+//! ```
+use super::client::{Client, RequestId};
+use super::rtu::RtuCodec;
+use super::tcp::{MbapCodec, MbapFrame};
+use super::{Adu, Exception, ModbusError, Request, BROADCAST};
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::SerialStream;
+use mio::event::Source;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token};
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Default limit on simultaneous TCP connections
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+const LISTENER: Token = Token(0);
+const SERIAL: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+
+#[derive(Debug)]
+struct Origin {
+    token: Token,
+    transaction: u16,
+    function: u8,
+}
+
+/// Counters kept by a [`Gateway`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GatewayStats {
+    /// TCP connections accepted
+    pub connections: u64,
+    /// Requests forwarded to the serial line
+    pub requests: u64,
+    /// Requests the device did not answer in time, answered with exception 0x0B
+    pub timeouts: u64,
+    /// Requests whose response was garbled or that failed on the serial line, answered with
+    /// exception 0x0B
+    pub errors: u64,
+    /// Requests rejected by the gateway without reaching the serial line
+    pub rejected: u64,
+}
+
+/// Forwards Modbus TCP requests to a serial line client
+pub struct Gateway<C = RtuCodec, S = SerialStream> {
+    poll: Poll,
+    events: Events,
+    ready: Vec<(Token, bool, bool)>,
+    listener: TcpListener,
+    client: Client<C, S>,
+    connections: HashMap<Token, FramedSerial<MbapCodec, TcpStream>>,
+    origins: HashMap<RequestId, Origin>,
+    next_token: usize,
+    max_connections: usize,
+    stats: GatewayStats,
+}
+
+impl<C, S> std::fmt::Debug for Gateway<C, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Gateway")
+            .field("listener", &self.listener)
+            .field("connections", &self.connections.len())
+            .field("in_flight", &self.origins.len())
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<C, S> Gateway<C, S>
+where
+    S: Read + Write + Source,
+    C: Decoder<Item = Adu, Error = ModbusError> + for<'a> Encoder<&'a Adu, Error = ModbusError>,
+{
+    /// Create a gateway accepting connections on `listener` and forwarding to `client`
+    pub fn new(mut listener: TcpListener, mut client: Client<C, S>) -> io::Result<Self> {
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        poll.registry()
+            .register(&mut client, SERIAL, Interest::READABLE)?;
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(64),
+            ready: Vec::new(),
+            listener,
+            client,
+            connections: HashMap::new(),
+            origins: HashMap::new(),
+            next_token: FIRST_CONNECTION,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            stats: GatewayStats::default(),
+        })
+    }
+
+    /// Set the limit on simultaneous TCP connections
+    ///
+    /// Connections beyond the limit are closed as soon as they are accepted.
+    #[must_use]
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// The address the gateway is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Number of open TCP connections
+    pub fn connections(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// Request and error counters
+    pub fn stats(&self) -> GatewayStats {
+        self.stats
+    }
+
+    /// Get a reference to the serial line client
+    pub fn client(&self) -> &Client<C, S> {
+        &self.client
+    }
+
+    /// Run the event loop until an error occurs on the listener or the serial line
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.run_once(None)?;
+        }
+    }
+
+    /// Wait for events for at most `timeout` and handle them
+    ///
+    /// The wait is cut short when the serial line client has a deadline.
+    pub fn run_once(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let now = Instant::now();
+        let deadline = self
+            .client
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(now));
+        let wait = match (timeout, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        match self.poll.poll(&mut self.events, wait) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        let mut ready = std::mem::take(&mut self.ready);
+        ready.clear();
+        ready.extend(
+            self.events
+                .iter()
+                .map(|e| (e.token(), e.is_readable(), e.is_writable())),
+        );
+        for &(token, readable, writable) in &ready {
+            match token {
+                LISTENER => self.accept()?,
+                SERIAL => {}
+                token => self.connection_ready(token, readable, writable),
+            }
+        }
+        self.ready = ready;
+
+        self.client.handle_io(Instant::now())?;
+        while let Some(completion) = self.client.poll_completion() {
+            let origin = match self.origins.remove(&completion.id) {
+                Some(origin) => origin,
+                None => continue,
+            };
+            if completion.unit == BROADCAST {
+                if let Err(e) = completion.result {
+                    log::debug!("broadcast: {}", e);
+                }
+                continue;
+            }
+            let mut pdu = Vec::new();
+            match completion.result {
+                Ok(response) => response.encode(&mut pdu),
+                Err(ModbusError::Exception(exception)) => {
+                    exception.encode(origin.function, &mut pdu)
+                }
+                Err(ModbusError::InvalidRequest(_)) => {
+                    Exception::IllegalFunction.encode(origin.function, &mut pdu)
+                }
+                Err(ModbusError::Timeout) => {
+                    log::debug!("unit {}: response timed out", completion.unit);
+                    self.stats.timeouts += 1;
+                    Exception::GatewayTargetDeviceFailedToRespond.encode(origin.function, &mut pdu)
+                }
+                Err(e) => {
+                    log::debug!("unit {}: {}", completion.unit, e);
+                    self.stats.errors += 1;
+                    Exception::GatewayTargetDeviceFailedToRespond.encode(origin.function, &mut pdu)
+                }
+            }
+            let frame = MbapFrame {
+                transaction: origin.transaction,
+                unit: completion.unit,
+                pdu,
+            };
+            self.respond(origin.token, &frame);
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let (stream, addr) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if self.connections.len() >= self.max_connections {
+                log::warn!("rejecting connection from {}: too many connections", addr);
+                continue;
+            }
+
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            let mut framed = FramedSerial::new(stream, MbapCodec::new());
+            if let Err(e) = self
+                .poll
+                .registry()
+                .register(&mut framed, token, Interest::READABLE)
+            {
+                log::warn!("unable to register connection from {}: {}", addr, e);
+                continue;
+            }
+            log::debug!("accepted connection from {}", addr);
+            self.connections.insert(token, framed);
+            self.stats.connections += 1;
+        }
+    }
+
+    fn connection_ready(&mut self, token: Token, readable: bool, writable: bool) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        if writable {
+            if let Err(e) = conn.on_writable() {
+                log::debug!("closing connection: {}", e);
+                self.close(token);
+                return;
+            }
+        }
+        if !readable {
+            return;
+        }
+
+        let mut requests = Vec::new();
+        let close = loop {
+            match conn.read_frame() {
+                Ok(Some(frame)) => requests.push(frame),
+                Ok(None) => break conn.is_eof(),
+                Err(e) => {
+                    log::debug!("closing connection: {}", e);
+                    break true;
+                }
+            }
+        };
+
+        for frame in requests {
+            self.forward(token, frame);
+        }
+        if close {
+            self.close(token);
+        }
+    }
+
+    fn forward(&mut self, token: Token, frame: MbapFrame) {
+        let function = frame.pdu.first().copied().unwrap_or(0);
+        match Request::decode(&frame.pdu) {
+            Ok(request) => {
+                let id = self.client.submit(frame.unit, request);
+                self.origins.insert(
+                    id,
+                    Origin {
+                        token,
+                        transaction: frame.transaction,
+                        function,
+                    },
+                );
+                self.stats.requests += 1;
+            }
+            Err(exception) => {
+                self.stats.rejected += 1;
+                if frame.unit == BROADCAST {
+                    return;
+                }
+                let mut pdu = Vec::new();
+                exception.encode(function, &mut pdu);
+                let response = MbapFrame { pdu, ..frame };
+                self.respond(token, &response);
+            }
+        }
+    }
+
+    fn respond(&mut self, token: Token, frame: &MbapFrame) {
+        let conn = match self.connections.get_mut(&token) {
+            Some(conn) => conn,
+            // The client went away while its request was on the bus
+            None => return,
+        };
+        if let Err(e) = conn.send(frame) {
+            log::debug!("closing connection: {}", e);
+            self.close(token);
+        }
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut conn) = self.connections.remove(&token) {
+            let _ = self.poll.registry().deregister(&mut conn);
+        }
+    }
+}
@@ -0,0 +1,742 @@
+//! Modbus over serial lines
+//!
+//! This module provides the Modbus application protocol (the PDU, see [`Request`] and
+//! [`Response`]) and its serial line framing:
+//!
+//! - [`rtu`]: binary RTU framing with CRC-16 and inter-frame timing.
+//! - [`ascii`]: hex-encoded ASCII framing with LRC.
+//! - [`client`]: a non-blocking master that queues requests and matches responses.
+//! - [`server`]: a non-blocking slave serving a [`RegisterMap`].
+//! - [`tcp`]: Modbus TCP (MBAP) framing.
+//! - [`gateway`]: forwards Modbus TCP requests to a serial line.
+//!
+//! Supported function codes are 1 to 6, 15, 16 and 23.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::modbus::{Request, RtuClient};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 19200).open_native_async().unwrap();
+//! let mut client = RtuClient::new_rtu(stream).unwrap();
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut client, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! client.submit(17, Request::ReadHoldingRegisters { address: 0x6B, quantity: 3 });
+//!
+//! loop {
+//!     let timeout = client
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//!     client.handle_io(Instant::now()).unwrap();
+//!     while let Some(completion) = client.poll_completion() {
+//!         println!("{:?}", completion.result);
+//!     }
+//! }
+//! ```
+use std::fmt;
+use std::io;
+
+pub mod ascii;
+pub mod client;
+pub mod gateway;
+pub mod rtu;
+pub mod server;
+pub mod tcp;
+
+pub use ascii::AsciiCodec;
+pub use client::{AsciiClient, Client, Completion, RequestId, RtuClient};
+pub use gateway::{Gateway, GatewayStats};
+pub use rtu::{FrameTiming, RtuCodec};
+pub use server::{AsciiServer, MemoryMap, RegisterMap, RtuServer, Server, ServerStats};
+pub use tcp::{MbapCodec, MbapFrame};
+
+/// Function code: Read Coils
+pub const READ_COILS: u8 = 0x01;
+/// Function code: Read Discrete Inputs
+pub const READ_DISCRETE_INPUTS: u8 = 0x02;
+/// Function code: Read Holding Registers
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+/// Function code: Read Input Registers
+pub const READ_INPUT_REGISTERS: u8 = 0x04;
+/// Function code: Write Single Coil
+pub const WRITE_SINGLE_COIL: u8 = 0x05;
+/// Function code: Write Single Register
+pub const WRITE_SINGLE_REGISTER: u8 = 0x06;
+/// Function code: Write Multiple Coils
+pub const WRITE_MULTIPLE_COILS: u8 = 0x0F;
+/// Function code: Write Multiple Registers
+pub const WRITE_MULTIPLE_REGISTERS: u8 = 0x10;
+/// Function code: Read/Write Multiple Registers
+pub const READ_WRITE_MULTIPLE_REGISTERS: u8 = 0x17;
+
+/// Unit address used for broadcast requests
+pub const BROADCAST: u8 = 0;
+
+/// Maximum length of a PDU
+pub const MAX_PDU_LENGTH: usize = 253;
+
+/// A Modbus application data unit: the addressed unit plus the PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Adu {
+    /// Unit (slave) address
+    pub unit: u8,
+    /// Protocol data unit, starting with the function code
+    pub pdu: Vec<u8>,
+}
+
+/// A Modbus exception code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exception {
+    /// 0x01: the function code is not supported
+    IllegalFunction,
+    /// 0x02: the data address is not valid
+    IllegalDataAddress,
+    /// 0x03: a value in the request is not valid
+    IllegalDataValue,
+    /// 0x04: an unrecoverable error occurred in the server
+    ServerDeviceFailure,
+    /// 0x05: the request was accepted but will take a long time
+    Acknowledge,
+    /// 0x06: the server is busy with a long-running command
+    ServerDeviceBusy,
+    /// 0x08: memory parity error
+    MemoryParityError,
+    /// 0x0A: a gateway could not allocate a path to the target
+    GatewayPathUnavailable,
+    /// 0x0B: the target device behind a gateway did not respond
+    GatewayTargetDeviceFailedToRespond,
+    /// Any other exception code
+    Other(u8),
+}
+
+impl Exception {
+    /// The exception code as sent on the wire
+    pub fn code(&self) -> u8 {
+        match self {
+            Exception::IllegalFunction => 0x01,
+            Exception::IllegalDataAddress => 0x02,
+            Exception::IllegalDataValue => 0x03,
+            Exception::ServerDeviceFailure => 0x04,
+            Exception::Acknowledge => 0x05,
+            Exception::ServerDeviceBusy => 0x06,
+            Exception::MemoryParityError => 0x08,
+            Exception::GatewayPathUnavailable => 0x0A,
+            Exception::GatewayTargetDeviceFailedToRespond => 0x0B,
+            Exception::Other(code) => *code,
+        }
+    }
+
+    /// Append an exception response for `function` to `dst`
+    pub fn encode(&self, function: u8, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&[function | 0x80, self.code()]);
+    }
+}
+
+impl From<u8> for Exception {
+    fn from(code: u8) -> Self {
+        match code {
+            0x01 => Exception::IllegalFunction,
+            0x02 => Exception::IllegalDataAddress,
+            0x03 => Exception::IllegalDataValue,
+            0x04 => Exception::ServerDeviceFailure,
+            0x05 => Exception::Acknowledge,
+            0x06 => Exception::ServerDeviceBusy,
+            0x08 => Exception::MemoryParityError,
+            0x0A => Exception::GatewayPathUnavailable,
+            0x0B => Exception::GatewayTargetDeviceFailedToRespond,
+            code => Exception::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for Exception {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Exception::IllegalFunction => "illegal function",
+            Exception::IllegalDataAddress => "illegal data address",
+            Exception::IllegalDataValue => "illegal data value",
+            Exception::ServerDeviceFailure => "server device failure",
+            Exception::Acknowledge => "acknowledge",
+            Exception::ServerDeviceBusy => "server device busy",
+            Exception::MemoryParityError => "memory parity error",
+            Exception::GatewayPathUnavailable => "gateway path unavailable",
+            Exception::GatewayTargetDeviceFailedToRespond => {
+                "gateway target device failed to respond"
+            }
+            Exception::Other(code) => return write!(f, "exception code 0x{code:02X}"),
+        };
+        f.write_str(text)
+    }
+}
+
+/// Errors reported by the Modbus client, server and codecs
+#[derive(Debug)]
+pub enum ModbusError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The server answered with an exception response
+    Exception(Exception),
+    /// No response arrived before the response timeout
+    Timeout,
+    /// A received frame failed its CRC or LRC check
+    ChecksumMismatch,
+    /// A received frame or response is malformed or does not match the request
+    InvalidFrame(&'static str),
+    /// A request cannot be sent as given
+    InvalidRequest(&'static str),
+}
+
+impl fmt::Display for ModbusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ModbusError::Io(e) => write!(f, "{e}"),
+            ModbusError::Exception(e) => write!(f, "modbus exception: {e}"),
+            ModbusError::Timeout => write!(f, "response timed out"),
+            ModbusError::ChecksumMismatch => write!(f, "frame checksum mismatch"),
+            ModbusError::InvalidFrame(why) => write!(f, "invalid frame: {why}"),
+            ModbusError::InvalidRequest(why) => write!(f, "invalid request: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for ModbusError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ModbusError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ModbusError {
+    fn from(e: io::Error) -> Self {
+        ModbusError::Io(e)
+    }
+}
+
+impl ModbusError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            ModbusError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+/// A Modbus request PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// 0x01: read `quantity` coils starting at `address`
+    ReadCoils {
+        /// First coil address
+        address: u16,
+        /// Number of coils, 1 to 2000
+        quantity: u16,
+    },
+    /// 0x02: read `quantity` discrete inputs starting at `address`
+    ReadDiscreteInputs {
+        /// First input address
+        address: u16,
+        /// Number of inputs, 1 to 2000
+        quantity: u16,
+    },
+    /// 0x03: read `quantity` holding registers starting at `address`
+    ReadHoldingRegisters {
+        /// First register address
+        address: u16,
+        /// Number of registers, 1 to 125
+        quantity: u16,
+    },
+    /// 0x04: read `quantity` input registers starting at `address`
+    ReadInputRegisters {
+        /// First register address
+        address: u16,
+        /// Number of registers, 1 to 125
+        quantity: u16,
+    },
+    /// 0x05: set or clear one coil
+    WriteSingleCoil {
+        /// Coil address
+        address: u16,
+        /// New coil state
+        value: bool,
+    },
+    /// 0x06: write one holding register
+    WriteSingleRegister {
+        /// Register address
+        address: u16,
+        /// New register value
+        value: u16,
+    },
+    /// 0x0F: write consecutive coils starting at `address`
+    WriteMultipleCoils {
+        /// First coil address
+        address: u16,
+        /// New coil states, 1 to 1968
+        values: Vec<bool>,
+    },
+    /// 0x10: write consecutive holding registers starting at `address`
+    WriteMultipleRegisters {
+        /// First register address
+        address: u16,
+        /// New register values, 1 to 123
+        values: Vec<u16>,
+    },
+    /// 0x17: write registers, then read registers, in one transaction
+    ReadWriteMultipleRegisters {
+        /// First register to read
+        read_address: u16,
+        /// Number of registers to read, 1 to 125
+        read_quantity: u16,
+        /// First register to write
+        write_address: u16,
+        /// Values to write, 1 to 121
+        values: Vec<u16>,
+    },
+}
+
+impl Request {
+    /// The function code of this request
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Request::ReadCoils { .. } => READ_COILS,
+            Request::ReadDiscreteInputs { .. } => READ_DISCRETE_INPUTS,
+            Request::ReadHoldingRegisters { .. } => READ_HOLDING_REGISTERS,
+            Request::ReadInputRegisters { .. } => READ_INPUT_REGISTERS,
+            Request::WriteSingleCoil { .. } => WRITE_SINGLE_COIL,
+            Request::WriteSingleRegister { .. } => WRITE_SINGLE_REGISTER,
+            Request::WriteMultipleCoils { .. } => WRITE_MULTIPLE_COILS,
+            Request::WriteMultipleRegisters { .. } => WRITE_MULTIPLE_REGISTERS,
+            Request::ReadWriteMultipleRegisters { .. } => READ_WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    /// Returns `true` if the request only writes data, so it may be broadcast
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Request::WriteSingleCoil { .. }
+                | Request::WriteSingleRegister { .. }
+                | Request::WriteMultipleCoils { .. }
+                | Request::WriteMultipleRegisters { .. }
+        )
+    }
+
+    /// Check quantities against the limits set by the Modbus specification
+    pub fn validate(&self) -> Result<(), ModbusError> {
+        let ok = match self {
+            Request::ReadCoils { quantity, .. } | Request::ReadDiscreteInputs { quantity, .. } => {
+                (1..=2000).contains(quantity)
+            }
+            Request::ReadHoldingRegisters { quantity, .. }
+            | Request::ReadInputRegisters { quantity, .. } => (1..=125).contains(quantity),
+            Request::WriteSingleCoil { .. } | Request::WriteSingleRegister { .. } => true,
+            Request::WriteMultipleCoils { values, .. } => (1..=0x7B0).contains(&values.len()),
+            Request::WriteMultipleRegisters { values, .. } => (1..=123).contains(&values.len()),
+            Request::ReadWriteMultipleRegisters {
+                read_quantity,
+                values,
+                ..
+            } => (1..=125).contains(read_quantity) && (1..=121).contains(&values.len()),
+        };
+        if ok {
+            Ok(())
+        } else {
+            Err(ModbusError::InvalidRequest("quantity out of range"))
+        }
+    }
+
+    /// Append the encoded PDU to `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        dst.push(self.function_code());
+        match self {
+            Request::ReadCoils { address, quantity }
+            | Request::ReadDiscreteInputs { address, quantity }
+            | Request::ReadHoldingRegisters { address, quantity }
+            | Request::ReadInputRegisters { address, quantity } => {
+                put_u16(dst, *address);
+                put_u16(dst, *quantity);
+            }
+            Request::WriteSingleCoil { address, value } => {
+                put_u16(dst, *address);
+                put_u16(dst, coil_value(*value));
+            }
+            Request::WriteSingleRegister { address, value } => {
+                put_u16(dst, *address);
+                put_u16(dst, *value);
+            }
+            Request::WriteMultipleCoils { address, values } => {
+                put_u16(dst, *address);
+                put_u16(dst, values.len() as u16);
+                dst.push(values.len().div_ceil(8) as u8);
+                pack_bits(values, dst);
+            }
+            Request::WriteMultipleRegisters { address, values } => {
+                put_u16(dst, *address);
+                put_u16(dst, values.len() as u16);
+                dst.push((values.len() * 2) as u8);
+                values.iter().for_each(|v| put_u16(dst, *v));
+            }
+            Request::ReadWriteMultipleRegisters {
+                read_address,
+                read_quantity,
+                write_address,
+                values,
+            } => {
+                put_u16(dst, *read_address);
+                put_u16(dst, *read_quantity);
+                put_u16(dst, *write_address);
+                put_u16(dst, values.len() as u16);
+                dst.push((values.len() * 2) as u8);
+                values.iter().for_each(|v| put_u16(dst, *v));
+            }
+        }
+    }
+
+    /// Decode a request PDU
+    ///
+    /// Errors are the exception a server should answer with.
+    pub fn decode(pdu: &[u8]) -> Result<Request, Exception> {
+        let function = *pdu.first().ok_or(Exception::IllegalFunction)?;
+        let u16_at = |i: usize| -> Result<u16, Exception> {
+            pdu.get(i..i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(Exception::IllegalDataValue)
+        };
+
+        let request = match function {
+            READ_COILS | READ_DISCRETE_INPUTS | READ_HOLDING_REGISTERS | READ_INPUT_REGISTERS => {
+                expect_len(pdu, 5)?;
+                let (address, quantity) = (u16_at(1)?, u16_at(3)?);
+                match function {
+                    READ_COILS => Request::ReadCoils { address, quantity },
+                    READ_DISCRETE_INPUTS => Request::ReadDiscreteInputs { address, quantity },
+                    READ_HOLDING_REGISTERS => Request::ReadHoldingRegisters { address, quantity },
+                    _ => Request::ReadInputRegisters { address, quantity },
+                }
+            }
+            WRITE_SINGLE_COIL => {
+                expect_len(pdu, 5)?;
+                let value = match u16_at(3)? {
+                    0xFF00 => true,
+                    0x0000 => false,
+                    _ => return Err(Exception::IllegalDataValue),
+                };
+                Request::WriteSingleCoil {
+                    address: u16_at(1)?,
+                    value,
+                }
+            }
+            WRITE_SINGLE_REGISTER => {
+                expect_len(pdu, 5)?;
+                Request::WriteSingleRegister {
+                    address: u16_at(1)?,
+                    value: u16_at(3)?,
+                }
+            }
+            WRITE_MULTIPLE_COILS => {
+                let quantity = u16_at(3)? as usize;
+                let byte_count = *pdu.get(5).ok_or(Exception::IllegalDataValue)? as usize;
+                if byte_count != quantity.div_ceil(8) {
+                    return Err(Exception::IllegalDataValue);
+                }
+                expect_len(pdu, 6 + byte_count)?;
+                Request::WriteMultipleCoils {
+                    address: u16_at(1)?,
+                    values: unpack_bits(&pdu[6..], quantity),
+                }
+            }
+            WRITE_MULTIPLE_REGISTERS => {
+                let quantity = u16_at(3)? as usize;
+                let byte_count = *pdu.get(5).ok_or(Exception::IllegalDataValue)? as usize;
+                if byte_count != quantity * 2 {
+                    return Err(Exception::IllegalDataValue);
+                }
+                expect_len(pdu, 6 + byte_count)?;
+                Request::WriteMultipleRegisters {
+                    address: u16_at(1)?,
+                    values: unpack_registers(&pdu[6..]),
+                }
+            }
+            READ_WRITE_MULTIPLE_REGISTERS => {
+                let quantity = u16_at(7)? as usize;
+                let byte_count = *pdu.get(9).ok_or(Exception::IllegalDataValue)? as usize;
+                if byte_count != quantity * 2 {
+                    return Err(Exception::IllegalDataValue);
+                }
+                expect_len(pdu, 10 + byte_count)?;
+                Request::ReadWriteMultipleRegisters {
+                    read_address: u16_at(1)?,
+                    read_quantity: u16_at(3)?,
+                    write_address: u16_at(5)?,
+                    values: unpack_registers(&pdu[10..]),
+                }
+            }
+            _ => return Err(Exception::IllegalFunction),
+        };
+
+        request
+            .validate()
+            .map_err(|_| Exception::IllegalDataValue)?;
+        Ok(request)
+    }
+}
+
+/// A Modbus response PDU
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Response {
+    /// 0x01: coil states
+    ReadCoils(Vec<bool>),
+    /// 0x02: discrete input states
+    ReadDiscreteInputs(Vec<bool>),
+    /// 0x03: holding register values
+    ReadHoldingRegisters(Vec<u16>),
+    /// 0x04: input register values
+    ReadInputRegisters(Vec<u16>),
+    /// 0x05: echo of the written coil
+    WriteSingleCoil {
+        /// Coil address
+        address: u16,
+        /// Coil state
+        value: bool,
+    },
+    /// 0x06: echo of the written register
+    WriteSingleRegister {
+        /// Register address
+        address: u16,
+        /// Register value
+        value: u16,
+    },
+    /// 0x0F: confirmation of a multiple coil write
+    WriteMultipleCoils {
+        /// First coil address
+        address: u16,
+        /// Number of coils written
+        quantity: u16,
+    },
+    /// 0x10: confirmation of a multiple register write
+    WriteMultipleRegisters {
+        /// First register address
+        address: u16,
+        /// Number of registers written
+        quantity: u16,
+    },
+    /// 0x17: values of the registers read
+    ReadWriteMultipleRegisters(Vec<u16>),
+}
+
+impl Response {
+    /// The function code of this response
+    pub fn function_code(&self) -> u8 {
+        match self {
+            Response::ReadCoils(_) => READ_COILS,
+            Response::ReadDiscreteInputs(_) => READ_DISCRETE_INPUTS,
+            Response::ReadHoldingRegisters(_) => READ_HOLDING_REGISTERS,
+            Response::ReadInputRegisters(_) => READ_INPUT_REGISTERS,
+            Response::WriteSingleCoil { .. } => WRITE_SINGLE_COIL,
+            Response::WriteSingleRegister { .. } => WRITE_SINGLE_REGISTER,
+            Response::WriteMultipleCoils { .. } => WRITE_MULTIPLE_COILS,
+            Response::WriteMultipleRegisters { .. } => WRITE_MULTIPLE_REGISTERS,
+            Response::ReadWriteMultipleRegisters(_) => READ_WRITE_MULTIPLE_REGISTERS,
+        }
+    }
+
+    /// The response a server sends after carrying out a write `request`
+    ///
+    /// Returns `None` for read requests.  Also used to complete broadcast writes, which
+    /// get no response on the wire.
+    pub fn write_echo(request: &Request) -> Option<Response> {
+        match request {
+            Request::WriteSingleCoil { address, value } => Some(Response::WriteSingleCoil {
+                address: *address,
+                value: *value,
+            }),
+            Request::WriteSingleRegister { address, value } => {
+                Some(Response::WriteSingleRegister {
+                    address: *address,
+                    value: *value,
+                })
+            }
+            Request::WriteMultipleCoils { address, values } => Some(Response::WriteMultipleCoils {
+                address: *address,
+                quantity: values.len() as u16,
+            }),
+            Request::WriteMultipleRegisters { address, values } => {
+                Some(Response::WriteMultipleRegisters {
+                    address: *address,
+                    quantity: values.len() as u16,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Append the encoded PDU to `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        dst.push(self.function_code());
+        match self {
+            Response::ReadCoils(bits) | Response::ReadDiscreteInputs(bits) => {
+                dst.push(bits.len().div_ceil(8) as u8);
+                pack_bits(bits, dst);
+            }
+            Response::ReadHoldingRegisters(values)
+            | Response::ReadInputRegisters(values)
+            | Response::ReadWriteMultipleRegisters(values) => {
+                dst.push((values.len() * 2) as u8);
+                values.iter().for_each(|v| put_u16(dst, *v));
+            }
+            Response::WriteSingleCoil { address, value } => {
+                put_u16(dst, *address);
+                put_u16(dst, coil_value(*value));
+            }
+            Response::WriteSingleRegister { address, value } => {
+                put_u16(dst, *address);
+                put_u16(dst, *value);
+            }
+            Response::WriteMultipleCoils { address, quantity }
+            | Response::WriteMultipleRegisters { address, quantity } => {
+                put_u16(dst, *address);
+                put_u16(dst, *quantity);
+            }
+        }
+    }
+
+    /// Decode the response PDU to `request`
+    ///
+    /// Exception responses are returned as [`ModbusError::Exception`].
+    pub fn decode(request: &Request, pdu: &[u8]) -> Result<Response, ModbusError> {
+        let function = *pdu.first().ok_or(ModbusError::InvalidFrame("empty PDU"))?;
+        if function == request.function_code() | 0x80 {
+            let code = *pdu
+                .get(1)
+                .ok_or(ModbusError::InvalidFrame("missing exception code"))?;
+            return Err(ModbusError::Exception(Exception::from(code)));
+        }
+        if function != request.function_code() {
+            return Err(ModbusError::InvalidFrame("function code mismatch"));
+        }
+
+        let u16_at = |i: usize| -> Result<u16, ModbusError> {
+            pdu.get(i..i + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+                .ok_or(ModbusError::InvalidFrame("response too short"))
+        };
+        let data = || -> Result<&[u8], ModbusError> {
+            let byte_count = *pdu
+                .get(1)
+                .ok_or(ModbusError::InvalidFrame("missing byte count"))?
+                as usize;
+            if pdu.len() != 2 + byte_count {
+                return Err(ModbusError::InvalidFrame("byte count mismatch"));
+            }
+            Ok(&pdu[2..])
+        };
+        let registers = |quantity: u16| -> Result<Vec<u16>, ModbusError> {
+            let data = data()?;
+            if data.len() != quantity as usize * 2 {
+                return Err(ModbusError::InvalidFrame("unexpected register count"));
+            }
+            Ok(unpack_registers(data))
+        };
+        let bits = |quantity: u16| -> Result<Vec<bool>, ModbusError> {
+            let data = data()?;
+            if data.len() != (quantity as usize).div_ceil(8) {
+                return Err(ModbusError::InvalidFrame("unexpected coil count"));
+            }
+            Ok(unpack_bits(data, quantity as usize))
+        };
+        let echo = |response: Response| -> Result<Response, ModbusError> {
+            if pdu.len() != 5 {
+                return Err(ModbusError::InvalidFrame("unexpected response length"));
+            }
+            if Response::write_echo(request).as_ref() != Some(&response) {
+                return Err(ModbusError::InvalidFrame("write echo mismatch"));
+            }
+            Ok(response)
+        };
+
+        match request {
+            Request::ReadCoils { quantity, .. } => bits(*quantity).map(Response::ReadCoils),
+            Request::ReadDiscreteInputs { quantity, .. } => {
+                bits(*quantity).map(Response::ReadDiscreteInputs)
+            }
+            Request::ReadHoldingRegisters { quantity, .. } => {
+                registers(*quantity).map(Response::ReadHoldingRegisters)
+            }
+            Request::ReadInputRegisters { quantity, .. } => {
+                registers(*quantity).map(Response::ReadInputRegisters)
+            }
+            Request::ReadWriteMultipleRegisters { read_quantity, .. } => {
+                registers(*read_quantity).map(Response::ReadWriteMultipleRegisters)
+            }
+            Request::WriteSingleCoil { .. } => echo(Response::WriteSingleCoil {
+                address: u16_at(1)?,
+                value: u16_at(3)? == 0xFF00,
+            }),
+            Request::WriteSingleRegister { .. } => echo(Response::WriteSingleRegister {
+                address: u16_at(1)?,
+                value: u16_at(3)?,
+            }),
+            Request::WriteMultipleCoils { .. } => echo(Response::WriteMultipleCoils {
+                address: u16_at(1)?,
+                quantity: u16_at(3)?,
+            }),
+            Request::WriteMultipleRegisters { .. } => echo(Response::WriteMultipleRegisters {
+                address: u16_at(1)?,
+                quantity: u16_at(3)?,
+            }),
+        }
+    }
+}
+
+fn expect_len(pdu: &[u8], len: usize) -> Result<(), Exception> {
+    if pdu.len() == len {
+        Ok(())
+    } else {
+        Err(Exception::IllegalDataValue)
+    }
+}
+
+fn coil_value(value: bool) -> u16 {
+    if value {
+        0xFF00
+    } else {
+        0x0000
+    }
+}
+
+fn put_u16(dst: &mut Vec<u8>, value: u16) {
+    dst.extend_from_slice(&value.to_be_bytes());
+}
+
+fn pack_bits(bits: &[bool], dst: &mut Vec<u8>) {
+    for chunk in bits.chunks(8) {
+        let byte = chunk
+            .iter()
+            .enumerate()
+            .fold(0u8, |acc, (i, &bit)| acc | (u8::from(bit) << i));
+        dst.push(byte);
+    }
+}
+
+fn unpack_bits(data: &[u8], quantity: usize) -> Vec<bool> {
+    (0..quantity)
+        .map(|i| data[i / 8] & (1 << (i % 8)) != 0)
+        .collect()
+}
+
+fn unpack_registers(data: &[u8]) -> Vec<u16> {
+    data.chunks_exact(2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .collect()
+}
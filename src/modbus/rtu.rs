@@ -0,0 +1,260 @@
+//! Modbus RTU framing
+//!
+//! An RTU frame is the unit address, the PDU and a CRC-16/MODBUS sent least significant byte
+//! first.  Frames are separated by at least 3.5 character times of silence; see
+//! [`FrameTiming`].
+//!
+//! [`RtuCodec`] delimits frames by their content rather than by timing, which works reliably
+//! over USB adapters and pseudo terminals where gaps between bytes are not preserved.  The
+//! length of every supported request and response can be worked out from its first few
+//! bytes.  For other function codes the codec looks for the shortest prefix with a valid CRC.
+use super::ascii::MAX_INTER_CHAR_TIMEOUT;
+use super::{Adu, ModbusError};
+use crate::codec::{Decoder, Encoder};
+use crate::crc::CRC_16_MODBUS;
+use crate::{DataBits, Parity, SerialPort, StopBits};
+use std::time::Duration;
+
+/// Largest possible RTU frame: address, 253 byte PDU and CRC
+pub const MAX_FRAME_LENGTH: usize = 256;
+
+/// Character and frame timing for a serial line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameTiming {
+    /// Time to send one character, including start, parity and stop bits
+    pub char_time: Duration,
+    /// Longest allowed gap between characters of a frame (t1.5)
+    pub inter_char: Duration,
+    /// Shortest silence between frames (t3.5)
+    pub inter_frame: Duration,
+}
+
+impl FrameTiming {
+    /// Timing for the given line settings
+    ///
+    /// Above 19200 baud the Modbus serial line specification fixes t1.5 at 750µs and t3.5 at
+    /// 1.75ms.
+    pub fn new(baud_rate: u32, data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Self {
+        let char_time = char_time(baud_rate, data_bits, parity, stop_bits);
+        let (inter_char, inter_frame) = if baud_rate > 19200 {
+            (Duration::from_micros(750), Duration::from_micros(1750))
+        } else {
+            (char_time * 3 / 2, char_time * 7 / 2)
+        };
+
+        Self {
+            char_time,
+            inter_char,
+            inter_frame,
+        }
+    }
+
+    /// Timing for ASCII mode with the given line settings
+    ///
+    /// ASCII frames are delimited by their start and end characters, so no inter-frame
+    /// silence is needed beyond one character.  `inter_char_timeout` is the longest gap
+    /// allowed inside a frame; it is capped at [`MAX_INTER_CHAR_TIMEOUT`].
+    pub fn ascii(
+        baud_rate: u32,
+        data_bits: DataBits,
+        parity: Parity,
+        stop_bits: StopBits,
+        inter_char_timeout: Duration,
+    ) -> Self {
+        let char_time = char_time(baud_rate, data_bits, parity, stop_bits);
+        Self {
+            char_time,
+            inter_char: inter_char_timeout.min(MAX_INTER_CHAR_TIMEOUT),
+            inter_frame: char_time,
+        }
+    }
+
+    /// Timing for the current settings of `port`
+    pub fn from_port<P: SerialPort + ?Sized>(port: &P) -> crate::Result<Self> {
+        Ok(Self::new(
+            port.baud_rate()?,
+            port.data_bits()?,
+            port.parity()?,
+            port.stop_bits()?,
+        ))
+    }
+
+    /// ASCII mode timing for the current settings of `port`
+    pub fn ascii_from_port<P: SerialPort + ?Sized>(
+        port: &P,
+        inter_char_timeout: Duration,
+    ) -> crate::Result<Self> {
+        Ok(Self::ascii(
+            port.baud_rate()?,
+            port.data_bits()?,
+            port.parity()?,
+            port.stop_bits()?,
+            inter_char_timeout,
+        ))
+    }
+
+    /// How long an incomplete frame is kept while no more bytes arrive
+    ///
+    /// This is the longer of the inter-character and inter-frame gaps.
+    pub fn frame_timeout(&self) -> Duration {
+        self.inter_char.max(self.inter_frame)
+    }
+
+    /// Time to send `bytes` characters
+    pub fn transmit_time(&self, bytes: usize) -> Duration {
+        self.char_time * bytes as u32
+    }
+}
+
+fn char_time(baud_rate: u32, data_bits: DataBits, parity: Parity, stop_bits: StopBits) -> Duration {
+    let data_bits = match data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    };
+    let parity_bits = match parity {
+        Parity::None => 0,
+        Parity::Odd | Parity::Even => 1,
+    };
+    let stop_bits = match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    };
+    let bits = 1 + data_bits + parity_bits + stop_bits;
+    Duration::from_nanos(bits * 1_000_000_000 / u64::from(baud_rate.max(1)))
+}
+
+/// Which side of the conversation a codec decodes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// Decode responses (used by a client/master)
+    Client,
+    /// Decode requests (used by a server/slave)
+    Server,
+}
+
+enum PduLength {
+    Known(usize),
+    NeedMore,
+    Unknown,
+}
+
+fn request_pdu_len(pdu: &[u8]) -> PduLength {
+    let byte_count_at = |i: usize, fixed: usize| match pdu.get(i) {
+        Some(&n) => PduLength::Known(fixed + n as usize),
+        None => PduLength::NeedMore,
+    };
+    match pdu[0] {
+        0x01..=0x06 => PduLength::Known(5),
+        0x0F | 0x10 => byte_count_at(5, 6),
+        0x17 => byte_count_at(9, 10),
+        _ => PduLength::Unknown,
+    }
+}
+
+fn response_pdu_len(pdu: &[u8]) -> PduLength {
+    match pdu[0] {
+        f if f & 0x80 != 0 => PduLength::Known(2),
+        0x01..=0x04 | 0x17 => match pdu.get(1) {
+            Some(&n) => PduLength::Known(2 + n as usize),
+            None => PduLength::NeedMore,
+        },
+        0x05 | 0x06 | 0x0F | 0x10 => PduLength::Known(5),
+        _ => PduLength::Unknown,
+    }
+}
+
+fn crc_ok(frame: &[u8]) -> bool {
+    let (data, crc) = frame.split_at(frame.len() - 2);
+    CRC_16_MODBUS.checksum(data).to_le_bytes() == crc
+}
+
+/// Modbus RTU frame encoder and decoder
+#[derive(Debug, Clone)]
+pub struct RtuCodec {
+    role: Role,
+}
+
+impl RtuCodec {
+    /// Create a codec that decodes responses, for use by a client
+    pub fn client() -> Self {
+        Self { role: Role::Client }
+    }
+
+    /// Create a codec that decodes requests, for use by a server
+    pub fn server() -> Self {
+        Self { role: Role::Server }
+    }
+
+    /// The role this codec decodes for
+    pub fn role(&self) -> Role {
+        self.role
+    }
+}
+
+impl Decoder for RtuCodec {
+    type Item = Adu;
+    type Error = ModbusError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Adu>, ModbusError> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+
+        let pdu_len = match self.role {
+            Role::Client => response_pdu_len(&src[1..]),
+            Role::Server => request_pdu_len(&src[1..]),
+        };
+
+        let frame_len = match pdu_len {
+            PduLength::NeedMore => return Ok(None),
+            PduLength::Known(n) => {
+                let frame_len = 1 + n + 2;
+                if frame_len > MAX_FRAME_LENGTH {
+                    src.clear();
+                    return Err(ModbusError::InvalidFrame("frame too long"));
+                }
+                if src.len() < frame_len {
+                    return Ok(None);
+                }
+                if !crc_ok(&src[..frame_len]) {
+                    src.drain(..frame_len);
+                    return Err(ModbusError::ChecksumMismatch);
+                }
+                frame_len
+            }
+            PduLength::Unknown => {
+                let limit = src.len().min(MAX_FRAME_LENGTH);
+                match (4..=limit).find(|&n| crc_ok(&src[..n])) {
+                    Some(n) => n,
+                    None if src.len() >= MAX_FRAME_LENGTH => {
+                        src.clear();
+                        return Err(ModbusError::InvalidFrame("unknown function code"));
+                    }
+                    None => return Ok(None),
+                }
+            }
+        };
+
+        let adu = Adu {
+            unit: src[0],
+            pdu: src[1..frame_len - 2].to_vec(),
+        };
+        src.drain(..frame_len);
+        Ok(Some(adu))
+    }
+}
+
+impl Encoder<&Adu> for RtuCodec {
+    type Error = ModbusError;
+
+    fn encode(&mut self, adu: &Adu, dst: &mut Vec<u8>) -> Result<(), ModbusError> {
+        let start = dst.len();
+        dst.push(adu.unit);
+        dst.extend_from_slice(&adu.pdu);
+        let crc = CRC_16_MODBUS.checksum(&dst[start..]);
+        dst.extend_from_slice(&crc.to_le_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,514 @@
+//! Non-blocking Modbus serial line server (slave)
+//!
+//! [`Server`] answers requests addressed to its unit ids from a [`RegisterMap`] supplied by
+//! the application.  Requests the map rejects, or that fail to decode, are answered with an
+//! exception response.  Broadcast writes (unit [`BROADCAST`]) are carried out without a
+//! response; broadcast reads are ignored.
+//!
+//! Like [`Client`](super::Client), the server never blocks.  Call [`Server::handle_io`] on
+//! every event for its token and when the deadline from [`Server::next_deadline`] expires,
+//! which is used to drop incomplete frames after the inter-frame silence.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::modbus::{MemoryMap, RtuServer};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 19200).open_native_async().unwrap();
+//! let mut server = RtuServer::new_rtu(stream, 17, Box::new(MemoryMap::new(100))).unwrap();
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut server, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! loop {
+//!     let timeout = server
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//!     server.handle_io(Instant::now()).unwrap();
+//! }
+//! ```
+use super::ascii::AsciiCodec;
+use super::rtu::{FrameTiming, RtuCodec};
+use super::{Adu, Exception, ModbusError, Request, Response, BROADCAST};
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::{SerialPort, SerialStream};
+use mio::{event::Source, Interest, Registry, Token};
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// The data model served by a [`Server`]
+///
+/// Every method defaults to [`Exception::IllegalFunction`], so a map only implements the
+/// tables it has.  Return [`Exception::IllegalDataAddress`] for addresses outside a table.
+pub trait RegisterMap {
+    /// Read `quantity` coils starting at `address`
+    fn read_coils(&mut self, address: u16, quantity: u16) -> Result<Vec<bool>, Exception> {
+        let _ = (address, quantity);
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `quantity` discrete inputs starting at `address`
+    fn read_discrete_inputs(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<bool>, Exception> {
+        let _ = (address, quantity);
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `quantity` holding registers starting at `address`
+    fn read_holding_registers(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>, Exception> {
+        let _ = (address, quantity);
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Read `quantity` input registers starting at `address`
+    fn read_input_registers(&mut self, address: u16, quantity: u16) -> Result<Vec<u16>, Exception> {
+        let _ = (address, quantity);
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Write `values` to the coils starting at `address`
+    fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), Exception> {
+        let _ = (address, values);
+        Err(Exception::IllegalFunction)
+    }
+
+    /// Write `values` to the holding registers starting at `address`
+    fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Exception> {
+        let _ = (address, values);
+        Err(Exception::IllegalFunction)
+    }
+}
+
+/// Carry out `request` against `map`
+///
+/// Read/Write Multiple Registers performs the write before the read, as required by the
+/// specification.
+pub fn execute(map: &mut dyn RegisterMap, request: &Request) -> Result<Response, Exception> {
+    match *request {
+        Request::ReadCoils { address, quantity } => {
+            return map.read_coils(address, quantity).map(Response::ReadCoils)
+        }
+        Request::ReadDiscreteInputs { address, quantity } => {
+            return map
+                .read_discrete_inputs(address, quantity)
+                .map(Response::ReadDiscreteInputs)
+        }
+        Request::ReadHoldingRegisters { address, quantity } => {
+            return map
+                .read_holding_registers(address, quantity)
+                .map(Response::ReadHoldingRegisters)
+        }
+        Request::ReadInputRegisters { address, quantity } => {
+            return map
+                .read_input_registers(address, quantity)
+                .map(Response::ReadInputRegisters)
+        }
+        Request::ReadWriteMultipleRegisters {
+            read_address,
+            read_quantity,
+            write_address,
+            ref values,
+        } => {
+            map.write_registers(write_address, values)?;
+            return map
+                .read_holding_registers(read_address, read_quantity)
+                .map(Response::ReadWriteMultipleRegisters);
+        }
+        Request::WriteSingleCoil { address, value } => map.write_coils(address, &[value])?,
+        Request::WriteSingleRegister { address, value } => {
+            map.write_registers(address, &[value])?
+        }
+        Request::WriteMultipleCoils {
+            address,
+            ref values,
+        } => map.write_coils(address, values)?,
+        Request::WriteMultipleRegisters {
+            address,
+            ref values,
+        } => map.write_registers(address, values)?,
+    }
+    Ok(Response::write_echo(request).expect("write requests have an echo"))
+}
+
+/// A [`RegisterMap`] backed by plain vectors
+///
+/// Each table covers addresses `0..len`; anything beyond is an illegal data address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryMap {
+    /// Coils, read/write single bits
+    pub coils: Vec<bool>,
+    /// Discrete inputs, read-only single bits
+    pub discrete_inputs: Vec<bool>,
+    /// Holding registers, read/write 16 bit words
+    pub holding_registers: Vec<u16>,
+    /// Input registers, read-only 16 bit words
+    pub input_registers: Vec<u16>,
+}
+
+impl MemoryMap {
+    /// Create a map with `len` zeroed entries in each table
+    pub fn new(len: usize) -> Self {
+        Self {
+            coils: vec![false; len],
+            discrete_inputs: vec![false; len],
+            holding_registers: vec![0; len],
+            input_registers: vec![0; len],
+        }
+    }
+}
+
+fn table_range(
+    address: u16,
+    quantity: usize,
+    len: usize,
+) -> Result<std::ops::Range<usize>, Exception> {
+    let start = usize::from(address);
+    let end = start + quantity;
+    if end > len {
+        Err(Exception::IllegalDataAddress)
+    } else {
+        Ok(start..end)
+    }
+}
+
+impl RegisterMap for MemoryMap {
+    fn read_coils(&mut self, address: u16, quantity: u16) -> Result<Vec<bool>, Exception> {
+        let range = table_range(address, quantity.into(), self.coils.len())?;
+        Ok(self.coils[range].to_vec())
+    }
+
+    fn read_discrete_inputs(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<bool>, Exception> {
+        let range = table_range(address, quantity.into(), self.discrete_inputs.len())?;
+        Ok(self.discrete_inputs[range].to_vec())
+    }
+
+    fn read_holding_registers(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>, Exception> {
+        let range = table_range(address, quantity.into(), self.holding_registers.len())?;
+        Ok(self.holding_registers[range].to_vec())
+    }
+
+    fn read_input_registers(&mut self, address: u16, quantity: u16) -> Result<Vec<u16>, Exception> {
+        let range = table_range(address, quantity.into(), self.input_registers.len())?;
+        Ok(self.input_registers[range].to_vec())
+    }
+
+    fn write_coils(&mut self, address: u16, values: &[bool]) -> Result<(), Exception> {
+        let range = table_range(address, values.len(), self.coils.len())?;
+        self.coils[range].copy_from_slice(values);
+        Ok(())
+    }
+
+    fn write_registers(&mut self, address: u16, values: &[u16]) -> Result<(), Exception> {
+        let range = table_range(address, values.len(), self.holding_registers.len())?;
+        self.holding_registers[range].copy_from_slice(values);
+        Ok(())
+    }
+}
+
+/// Counters kept by a [`Server`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Requests answered with a normal response
+    pub responses: u64,
+    /// Requests answered with an exception response
+    pub exceptions: u64,
+    /// Broadcast requests carried out
+    pub broadcasts: u64,
+    /// Frames addressed to other units
+    pub other_units: u64,
+    /// Frames dropped because of a bad checksum or framing
+    pub bad_frames: u64,
+}
+
+/// A Modbus server using RTU framing
+pub type RtuServer<S = SerialStream> = Server<RtuCodec, S>;
+
+/// A Modbus server using ASCII framing
+pub type AsciiServer<S = SerialStream> = Server<AsciiCodec, S>;
+
+/// Non-blocking Modbus server over a serial stream
+///
+/// The codec `C` supplies the serial line framing, e.g. [`RtuCodec`].
+pub struct Server<C, S = SerialStream> {
+    framed: FramedSerial<C, S>,
+    timing: FrameTiming,
+    units: Vec<u8>,
+    map: Box<dyn RegisterMap>,
+    stats: ServerStats,
+    last_rx: Option<Instant>,
+    rx_len: usize,
+}
+
+impl<C: std::fmt::Debug, S: std::fmt::Debug> std::fmt::Debug for Server<C, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Server")
+            .field("framed", &self.framed)
+            .field("timing", &self.timing)
+            .field("units", &self.units)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Server<RtuCodec, S>
+where
+    S: SerialPort + Read + Write + Source,
+{
+    /// Create an RTU server for `unit` with timing derived from the current port settings
+    pub fn new_rtu(stream: S, unit: u8, map: Box<dyn RegisterMap>) -> crate::Result<Self> {
+        let timing = FrameTiming::from_port(&stream)?;
+        Ok(Self::new(stream, RtuCodec::server(), timing, unit, map))
+    }
+}
+
+impl<S> Server<AsciiCodec, S>
+where
+    S: SerialPort + Read + Write + Source,
+{
+    /// Create an ASCII server for `unit` allowing gaps of up to `inter_char_timeout` within a
+    /// frame
+    pub fn new_ascii(
+        stream: S,
+        unit: u8,
+        map: Box<dyn RegisterMap>,
+        inter_char_timeout: Duration,
+    ) -> crate::Result<Self> {
+        let timing = FrameTiming::ascii_from_port(&stream, inter_char_timeout)?;
+        Ok(Self::new(stream, AsciiCodec::new(), timing, unit, map))
+    }
+}
+
+impl<C, S> Server<C, S>
+where
+    S: Read + Write + Source,
+    C: Decoder<Item = Adu, Error = ModbusError> + for<'a> Encoder<&'a Adu, Error = ModbusError>,
+{
+    /// Create a server answering requests for `unit` from `map`
+    ///
+    /// # Panics
+    ///
+    /// If `unit` is the broadcast address.
+    pub fn new(
+        stream: S,
+        codec: C,
+        timing: FrameTiming,
+        unit: u8,
+        map: Box<dyn RegisterMap>,
+    ) -> Self {
+        assert_ne!(unit, BROADCAST, "the broadcast address is not a unit id");
+        Self {
+            framed: FramedSerial::new(stream, codec),
+            timing,
+            units: vec![unit],
+            map,
+            stats: ServerStats::default(),
+            last_rx: None,
+            rx_len: 0,
+        }
+    }
+
+    /// Also answer requests for `unit`
+    ///
+    /// # Panics
+    ///
+    /// If `unit` is the broadcast address.
+    #[must_use]
+    pub fn with_unit(mut self, unit: u8) -> Self {
+        assert_ne!(unit, BROADCAST, "the broadcast address is not a unit id");
+        if !self.units.contains(&unit) {
+            self.units.push(unit);
+        }
+        self
+    }
+
+    /// The unit ids this server answers for
+    pub fn units(&self) -> &[u8] {
+        &self.units
+    }
+
+    /// The bus timing in use
+    pub fn timing(&self) -> FrameTiming {
+        self.timing
+    }
+
+    /// Change the bus timing, e.g. after changing the baud rate
+    pub fn set_timing(&mut self, timing: FrameTiming) {
+        self.timing = timing;
+    }
+
+    /// Get a reference to the register map
+    pub fn map(&self) -> &dyn RegisterMap {
+        self.map.as_ref()
+    }
+
+    /// Get a mutable reference to the register map
+    pub fn map_mut(&mut self) -> &mut dyn RegisterMap {
+        self.map.as_mut()
+    }
+
+    /// Request and error counters
+    pub fn stats(&self) -> ServerStats {
+        self.stats
+    }
+
+    /// Reset all counters to zero
+    pub fn reset_stats(&mut self) {
+        self.stats = ServerStats::default();
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.framed.get_mut()
+    }
+
+    /// Consume the server, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+
+    /// The next time [`handle_io`](Self::handle_io) must be called even without an event
+    ///
+    /// Returns `None` unless part of a frame is buffered.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        if self.rx_len > 0 {
+            self.last_rx.map(|t| t + self.timing.frame_timeout())
+        } else {
+            None
+        }
+    }
+
+    /// Drive the server: write queued responses, then read and answer requests
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned.  Malformed frames are dropped and
+    /// counted in [`ServerStats::bad_frames`].
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.framed.on_writable()?;
+        loop {
+            match self.framed.read_frame() {
+                Ok(Some(adu)) => self.on_request(adu)?,
+                Ok(None) => break,
+                Err(ModbusError::Io(e)) => return Err(e),
+                Err(e) => {
+                    log::debug!("dropping bad frame: {}", e);
+                    self.stats.bad_frames += 1;
+                }
+            }
+        }
+
+        let rx_len = self.framed.read_buffer().len();
+        if rx_len != self.rx_len {
+            self.last_rx = Some(now);
+        } else if rx_len > 0 && self.next_deadline().is_some_and(|d| now >= d) {
+            log::debug!("dropping {} bytes of incomplete frame", rx_len);
+            self.framed.read_buffer_mut().clear();
+            self.stats.bad_frames += 1;
+        }
+        self.rx_len = self.framed.read_buffer().len();
+        Ok(())
+    }
+
+    fn on_request(&mut self, adu: Adu) -> io::Result<()> {
+        let broadcast = adu.unit == BROADCAST;
+        if !broadcast && !self.units.contains(&adu.unit) {
+            self.stats.other_units += 1;
+            return Ok(());
+        }
+
+        let function = adu.pdu.first().copied().unwrap_or(0);
+        let result = Request::decode(&adu.pdu).and_then(|request| {
+            if broadcast && !request.is_write() {
+                // Nobody may answer a broadcast; reads make no sense there
+                return Err(Exception::IllegalFunction);
+            }
+            execute(self.map.as_mut(), &request)
+        });
+
+        if broadcast {
+            match result {
+                Ok(_) => self.stats.broadcasts += 1,
+                Err(e) => log::debug!("broadcast request failed: {}", e),
+            }
+            return Ok(());
+        }
+
+        let mut pdu = Vec::new();
+        match result {
+            Ok(response) => {
+                self.stats.responses += 1;
+                response.encode(&mut pdu);
+            }
+            Err(exception) => {
+                self.stats.exceptions += 1;
+                exception.encode(function, &mut pdu);
+            }
+        }
+        let response = Adu {
+            unit: adu.unit,
+            pdu,
+        };
+        match self.framed.send(&response) {
+            Ok(_) => Ok(()),
+            Err(ModbusError::Io(e)) => Err(e),
+            Err(e) => {
+                log::warn!("unable to encode response: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<C, S> Source for Server<C, S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.framed.deregister(registry)
+    }
+}
@@ -0,0 +1,84 @@
+//! Modbus TCP framing
+//!
+//! Modbus TCP prefixes the unit address and PDU with the MBAP header: a transaction id
+//! chosen by the client and echoed by the server, a protocol id of zero and the length of
+//! the rest of the frame.  There is no checksum, TCP takes care of that.
+use super::{ModbusError, MAX_PDU_LENGTH};
+use crate::codec::{Decoder, Encoder};
+
+/// Length of the MBAP header, including the unit address
+pub const MBAP_HEADER_LENGTH: usize = 7;
+
+/// Protocol id for Modbus
+pub const PROTOCOL_ID: u16 = 0;
+
+/// A Modbus TCP application data unit
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbapFrame {
+    /// Transaction id, echoed in the response
+    pub transaction: u16,
+    /// Unit address, used by gateways to select a serial line device
+    pub unit: u8,
+    /// Protocol data unit, starting with the function code
+    pub pdu: Vec<u8>,
+}
+
+/// Modbus TCP frame encoder and decoder
+///
+/// Frames with a protocol id other than zero or an impossible length are reported as
+/// [`ModbusError::InvalidFrame`].  The stream cannot be resynchronised after that, so the
+/// connection should be closed.
+#[derive(Debug, Clone, Default)]
+pub struct MbapCodec;
+
+impl MbapCodec {
+    /// Create a new codec
+    pub fn new() -> Self {
+        Self
+    }
+}
+
+impl Decoder for MbapCodec {
+    type Item = MbapFrame;
+    type Error = ModbusError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<MbapFrame>, ModbusError> {
+        if src.len() < MBAP_HEADER_LENGTH {
+            return Ok(None);
+        }
+        let transaction = u16::from_be_bytes([src[0], src[1]]);
+        let protocol = u16::from_be_bytes([src[2], src[3]]);
+        let length = usize::from(u16::from_be_bytes([src[4], src[5]]));
+        if protocol != PROTOCOL_ID {
+            return Err(ModbusError::InvalidFrame("unknown protocol id"));
+        }
+        if !(2..=1 + MAX_PDU_LENGTH).contains(&length) {
+            return Err(ModbusError::InvalidFrame("invalid MBAP length"));
+        }
+
+        let frame_len = 6 + length;
+        if src.len() < frame_len {
+            return Ok(None);
+        }
+        let frame = MbapFrame {
+            transaction,
+            unit: src[6],
+            pdu: src[MBAP_HEADER_LENGTH..frame_len].to_vec(),
+        };
+        src.drain(..frame_len);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<&MbapFrame> for MbapCodec {
+    type Error = ModbusError;
+
+    fn encode(&mut self, frame: &MbapFrame, dst: &mut Vec<u8>) -> Result<(), ModbusError> {
+        dst.extend_from_slice(&frame.transaction.to_be_bytes());
+        dst.extend_from_slice(&PROTOCOL_ID.to_be_bytes());
+        dst.extend_from_slice(&(1 + frame.pdu.len() as u16).to_be_bytes());
+        dst.push(frame.unit);
+        dst.extend_from_slice(&frame.pdu);
+        Ok(())
+    }
+}
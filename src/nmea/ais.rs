@@ -0,0 +1,185 @@
+//! AIS message reassembly
+//!
+//! AIS receivers wrap radio messages in `!AIVDM` (other vessels) and `!AIVDO` (own vessel)
+//! sentences.  A message too long for one sentence is split over several, numbered within
+//! a sequential message id:
+//!
+//! ```text
+//! !AIVDM,2,1,3,B,55P5TL01VIaAL@7WKO@mBplU@<PDhh000000001S;AJ::4A80?4i@E53,0*3E
+//! !AIVDM,2,2,3,B,1@0000000000000,2*55
+//! ```
+//!
+//! [`AisAssembler`] collects the fragments and returns the complete [`AisMessage`].  The
+//! payload is left in its 6-bit ASCII armoring; [`AisMessage::bits`] unpacks it.
+use super::{NmeaError, RawSentence};
+
+/// Number of sequential message ids, and so of messages being assembled at once
+const MAX_PENDING: usize = 10;
+
+/// Returns `true` if `raw` is an AIS VDM or VDO sentence
+pub fn is_ais(raw: &RawSentence) -> bool {
+    raw.start == b'!' && matches!(raw.sentence_type(), "VDM" | "VDO")
+}
+
+/// A complete AIS message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AisMessage {
+    /// Talker id, e.g. `"AI"`
+    pub talker: String,
+    /// `true` for VDO (own vessel) messages
+    pub own_vessel: bool,
+    /// Radio channel, `'A'` or `'B'`
+    pub channel: Option<char>,
+    /// The armored payload of all fragments joined together
+    pub payload: String,
+    /// Number of padding bits at the end of the payload
+    pub fill_bits: u8,
+}
+
+impl AisMessage {
+    /// Unpack the payload into one `bool` per bit, without the fill bits
+    ///
+    /// Returns `None` if the payload contains characters outside the 6-bit armoring.
+    pub fn bits(&self) -> Option<Vec<bool>> {
+        let mut bits = Vec::with_capacity(self.payload.len() * 6);
+        for c in self.payload.bytes() {
+            let value = match c {
+                b'0'..=b'W' => c - b'0',
+                b'`'..=b'w' => c - b'0' - 8,
+                _ => return None,
+            };
+            bits.extend((0..6).rev().map(|i| value & (1 << i) != 0));
+        }
+        bits.truncate(bits.len().saturating_sub(usize::from(self.fill_bits)));
+        Some(bits)
+    }
+
+    /// The message type, from the first six bits
+    pub fn message_type(&self) -> Option<u8> {
+        self.field(0, 6).map(|v| v as u8)
+    }
+
+    /// The MMSI of the sending station
+    pub fn mmsi(&self) -> Option<u32> {
+        self.field(8, 30)
+    }
+
+    /// An unsigned field of `len` bits starting at bit `start`
+    pub fn field(&self, start: usize, len: usize) -> Option<u32> {
+        let bits = self.bits()?;
+        let field = bits.get(start..start + len)?;
+        Some(field.iter().fold(0, |acc, &b| acc << 1 | u32::from(b)))
+    }
+}
+
+#[derive(Debug)]
+struct Partial {
+    key: (String, String),
+    total: u8,
+    received: u8,
+    channel: Option<char>,
+    payload: String,
+}
+
+/// Combines multi-sentence AIS messages
+#[derive(Debug, Default)]
+pub struct AisAssembler {
+    pending: Vec<Partial>,
+    dropped: u64,
+}
+
+impl AisAssembler {
+    /// Create an empty assembler
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of incomplete messages discarded because a fragment was missing or out of
+    /// order
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Discard all partially assembled messages
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Add a VDM or VDO sentence
+    ///
+    /// Returns the message once its last fragment has arrived.
+    pub fn push(&mut self, raw: &RawSentence) -> Result<Option<AisMessage>, NmeaError> {
+        let invalid = |field: usize| NmeaError::InvalidField {
+            sentence: raw.sentence_type().to_string(),
+            field,
+        };
+        let number = |index: usize| -> Result<u8, NmeaError> {
+            raw.field(index).parse().map_err(|_| invalid(index + 1))
+        };
+        let total = number(0)?;
+        let fragment = number(1)?;
+        if total == 0 || fragment == 0 || fragment > total {
+            return Err(invalid(2));
+        }
+        let channel = raw.field(3).chars().next();
+        let payload = raw.field(4);
+        let fill_bits = match raw.field(5) {
+            "" => 0,
+            _ => number(5)?,
+        };
+
+        let message = |channel, payload| AisMessage {
+            talker: raw.talker().to_string(),
+            own_vessel: raw.sentence_type() == "VDO",
+            channel,
+            payload,
+            fill_bits,
+        };
+        if total == 1 {
+            return Ok(Some(message(channel, payload.to_string())));
+        }
+
+        let key = (raw.address.clone(), raw.field(2).to_string());
+        let index = self.pending.iter().position(|p| p.key == key);
+        if fragment == 1 {
+            if let Some(index) = index {
+                self.pending.remove(index);
+                self.dropped += 1;
+            }
+            if self.pending.len() >= MAX_PENDING {
+                self.pending.remove(0);
+                self.dropped += 1;
+            }
+            self.pending.push(Partial {
+                key,
+                total,
+                received: 1,
+                channel,
+                payload: payload.to_string(),
+            });
+            return Ok(None);
+        }
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                self.dropped += 1;
+                return Ok(None);
+            }
+        };
+        let partial = &mut self.pending[index];
+        if partial.total != total || partial.received + 1 != fragment {
+            self.pending.remove(index);
+            self.dropped += 1;
+            return Ok(None);
+        }
+        partial.received = fragment;
+        partial.payload.push_str(payload);
+        if fragment < total {
+            return Ok(None);
+        }
+
+        let partial = self.pending.remove(index);
+        Ok(Some(message(partial.channel.or(channel), partial.payload)))
+    }
+}
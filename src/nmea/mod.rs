@@ -0,0 +1,467 @@
+//! NMEA 0183 sentences over a [`SerialStream`]
+//!
+//! [`NmeaCodec`] splits the input into `$` and `!` sentences and checks the `*hh`
+//! checksum where there is one.  [`NmeaReader`] adds parsing into [`Sentence`]: GGA, RMC,
+//! VTG, GSA, GSV and HDT become typed structs, multi-sentence AIS messages (VDM/VDO) are
+//! reassembled by [`AisAssembler`], and everything else is passed through as a
+//! [`RawSentence`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::nmea::{NmeaReader, Sentence};
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 4800).open_native_async().unwrap();
+//! let mut reader = NmeaReader::new(stream);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(1);
+//! poll.registry()
+//!     .register(&mut reader, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! loop {
+//!     poll.poll(&mut events, None).unwrap();
+//!     loop {
+//!         match reader.read_sentence() {
+//!             Ok(Some(Sentence::Gga(gga))) => println!("{:?} {:?}", gga.latitude, gga.longitude),
+//!             Ok(Some(other)) => println!("{:?}", other),
+//!             Ok(None) => break,
+//!             Err(e) if e.is_recoverable() => eprintln!("{e}"),
+//!             Err(e) => panic!("{e}"),
+//!         }
+//!     }
+//! }
+//! ```
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::fmt;
+use std::io::{self, Read, Write};
+
+pub mod ais;
+pub mod sentences;
+
+pub use ais::{AisAssembler, AisMessage};
+pub use sentences::{Date, Gga, Gsa, Gsv, Hdt, Rmc, SatelliteInfo, Sentence, Time, Vtg};
+
+/// Longest sentence allowed by the standard, including the start character and CR LF
+pub const MAX_SENTENCE_LENGTH: usize = 82;
+
+/// Default longest sentence accepted by [`NmeaCodec`]
+///
+/// Many receivers exceed the standard's 82 characters, so the default is more lenient.
+pub const DEFAULT_MAX_SENTENCE_LENGTH: usize = 256;
+
+/// The NMEA checksum of `data`: all bytes XORed together
+pub fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, b| acc ^ b)
+}
+
+/// Errors reported by the NMEA codec and reader
+#[derive(Debug)]
+pub enum NmeaError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The `*hh` checksum does not match the sentence
+    BadChecksum {
+        /// Checksum computed over the received sentence
+        computed: u8,
+        /// Checksum sent with the sentence
+        received: u8,
+    },
+    /// The sentence has no checksum but one is required
+    MissingChecksum,
+    /// The sentence is longer than the configured maximum
+    SentenceTooLong(usize),
+    /// The sentence is not well formed
+    InvalidSentence(&'static str),
+    /// A field of a known sentence type could not be parsed
+    InvalidField {
+        /// Sentence type, e.g. `"GGA"`
+        sentence: String,
+        /// Index of the field, counting from 1 after the address
+        field: usize,
+    },
+}
+
+impl NmeaError {
+    /// Returns `true` if the reader can carry on with the next sentence
+    ///
+    /// Only I/O errors are not recoverable.
+    pub fn is_recoverable(&self) -> bool {
+        !matches!(self, NmeaError::Io(_))
+    }
+
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            NmeaError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NmeaError::Io(e) => write!(f, "{e}"),
+            NmeaError::BadChecksum { computed, received } => write!(
+                f,
+                "checksum mismatch: computed {computed:02X}, received {received:02X}"
+            ),
+            NmeaError::MissingChecksum => write!(f, "sentence has no checksum"),
+            NmeaError::SentenceTooLong(max) => {
+                write!(f, "sentence exceeds maximum length of {max} bytes")
+            }
+            NmeaError::InvalidSentence(why) => write!(f, "invalid sentence: {why}"),
+            NmeaError::InvalidField { sentence, field } => {
+                write!(f, "invalid field {field} in {sentence} sentence")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NmeaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            NmeaError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for NmeaError {
+    fn from(e: io::Error) -> Self {
+        NmeaError::Io(e)
+    }
+}
+
+/// A sentence split into its address and fields
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawSentence {
+    /// `b'$'` for parametric sentences, `b'!'` for encapsulated (AIS) sentences
+    pub start: u8,
+    /// The address field, e.g. `"GPGGA"` or `"PUBX"`
+    pub address: String,
+    /// The fields after the address, without the checksum
+    pub fields: Vec<String>,
+}
+
+impl RawSentence {
+    /// Create a sentence from its parts
+    pub fn new<A, I, F>(start: u8, address: A, fields: I) -> Self
+    where
+        A: Into<String>,
+        I: IntoIterator<Item = F>,
+        F: Into<String>,
+    {
+        Self {
+            start,
+            address: address.into(),
+            fields: fields.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Parse a sentence without its line ending
+    ///
+    /// The checksum is verified if present; when `require_checksum` is set a missing
+    /// checksum is an error.
+    pub fn parse(line: &[u8], require_checksum: bool) -> Result<Self, NmeaError> {
+        let (&start, rest) = line
+            .split_first()
+            .ok_or(NmeaError::InvalidSentence("empty sentence"))?;
+        if start != b'$' && start != b'!' {
+            return Err(NmeaError::InvalidSentence("missing start character"));
+        }
+
+        let body = match rest.iter().rposition(|&b| b == b'*') {
+            Some(star) => {
+                let received = parse_hex_byte(&rest[star + 1..])
+                    .ok_or(NmeaError::InvalidSentence("malformed checksum"))?;
+                let computed = checksum(&rest[..star]);
+                if computed != received {
+                    return Err(NmeaError::BadChecksum { computed, received });
+                }
+                &rest[..star]
+            }
+            None if require_checksum => return Err(NmeaError::MissingChecksum),
+            None => rest,
+        };
+
+        let body = std::str::from_utf8(body)
+            .ok()
+            .filter(|body| body.is_ascii())
+            .ok_or(NmeaError::InvalidSentence("not valid ASCII"))?;
+        let mut parts = body.split(',');
+        let address = parts.next().unwrap_or_default();
+        if address.is_empty() || !address.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Err(NmeaError::InvalidSentence("invalid address field"));
+        }
+        Ok(Self {
+            start,
+            address: address.to_string(),
+            fields: parts.map(str::to_string).collect(),
+        })
+    }
+
+    /// The talker id, e.g. `"GP"`, or `"P"` for proprietary sentences
+    pub fn talker(&self) -> &str {
+        if self.address.starts_with('P') {
+            "P"
+        } else {
+            self.address.get(..2).unwrap_or(&self.address)
+        }
+    }
+
+    /// The sentence type, e.g. `"GGA"`
+    ///
+    /// For proprietary sentences this is everything after the `P`.
+    pub fn sentence_type(&self) -> &str {
+        if self.address.starts_with('P') {
+            &self.address[1..]
+        } else {
+            self.address.get(2..).unwrap_or_default()
+        }
+    }
+
+    /// Field `index`, or an empty string if the sentence is shorter
+    pub fn field(&self, index: usize) -> &str {
+        self.fields.get(index).map_or("", String::as_str)
+    }
+
+    /// Append the sentence with its checksum and CR LF to `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        let start = dst.len();
+        dst.push(self.start);
+        dst.extend_from_slice(self.address.as_bytes());
+        for field in &self.fields {
+            dst.push(b',');
+            dst.extend_from_slice(field.as_bytes());
+        }
+        let sum = checksum(&dst[start + 1..]);
+        dst.extend_from_slice(format!("*{sum:02X}\r\n").as_bytes());
+    }
+}
+
+impl fmt::Display for RawSentence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut line = Vec::new();
+        self.encode(&mut line);
+        f.write_str(String::from_utf8_lossy(&line).trim_end())
+    }
+}
+
+fn parse_hex_byte(digits: &[u8]) -> Option<u8> {
+    let digits = std::str::from_utf8(digits).ok()?;
+    if digits.len() != 2 {
+        return None;
+    }
+    u8::from_str_radix(digits, 16).ok()
+}
+
+/// Splits a byte stream into NMEA sentences
+///
+/// Bytes outside of sentences are skipped.  Lines may end in CR LF or a bare LF.
+#[derive(Debug, Clone)]
+pub struct NmeaCodec {
+    max_length: usize,
+    require_checksum: bool,
+}
+
+impl NmeaCodec {
+    /// Create a codec accepting sentences up to [`DEFAULT_MAX_SENTENCE_LENGTH`] bytes
+    pub fn new() -> Self {
+        Self {
+            max_length: DEFAULT_MAX_SENTENCE_LENGTH,
+            require_checksum: false,
+        }
+    }
+
+    /// Set the longest accepted sentence, including the start character and line ending
+    #[must_use]
+    pub fn with_max_length(mut self, max_length: usize) -> Self {
+        self.max_length = max_length;
+        self
+    }
+
+    /// Reject sentences without a checksum
+    #[must_use]
+    pub fn with_require_checksum(mut self, require_checksum: bool) -> Self {
+        self.require_checksum = require_checksum;
+        self
+    }
+}
+
+impl Default for NmeaCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for NmeaCodec {
+    type Item = RawSentence;
+    type Error = NmeaError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<RawSentence>, NmeaError> {
+        let start = match src.iter().position(|&b| b == b'$' || b == b'!') {
+            Some(start) => start,
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        };
+        src.drain(..start);
+
+        let end = match src.iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None if src.len() > self.max_length => {
+                src.clear();
+                return Err(NmeaError::SentenceTooLong(self.max_length));
+            }
+            None => return Ok(None),
+        };
+
+        // A start character inside the line means the previous sentence was cut short
+        if let Some(restart) = src[1..end].iter().position(|&b| b == b'$' || b == b'!') {
+            src.drain(..=restart);
+            return Err(NmeaError::InvalidSentence("truncated sentence"));
+        }
+
+        let line: Vec<u8> = src.drain(..=end).collect();
+        if line.len() > self.max_length {
+            return Err(NmeaError::SentenceTooLong(self.max_length));
+        }
+        let line = line
+            .strip_suffix(b"\r\n")
+            .or_else(|| line.strip_suffix(b"\n"))
+            .unwrap_or(&line);
+        RawSentence::parse(line, self.require_checksum).map(Some)
+    }
+}
+
+impl Encoder<&RawSentence> for NmeaCodec {
+    type Error = NmeaError;
+
+    fn encode(&mut self, sentence: &RawSentence, dst: &mut Vec<u8>) -> Result<(), NmeaError> {
+        sentence.encode(dst);
+        Ok(())
+    }
+}
+
+/// Reads parsed NMEA sentences from a non-blocking stream
+#[derive(Debug)]
+pub struct NmeaReader<S = SerialStream> {
+    framed: FramedSerial<NmeaCodec, S>,
+    ais: AisAssembler,
+}
+
+impl<S> NmeaReader<S>
+where
+    S: Read + Write + Source,
+{
+    /// Wrap `inner` using a default [`NmeaCodec`]
+    pub fn new(inner: S) -> Self {
+        Self::with_codec(inner, NmeaCodec::new())
+    }
+
+    /// Wrap `inner` using `codec`
+    pub fn with_codec(inner: S, codec: NmeaCodec) -> Self {
+        Self {
+            framed: FramedSerial::new(inner, codec),
+            ais: AisAssembler::new(),
+        }
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.framed.get_mut()
+    }
+
+    /// Consume the reader, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+
+    /// Get a mutable reference to the AIS reassembly state
+    pub fn ais_mut(&mut self) -> &mut AisAssembler {
+        &mut self.ais
+    }
+
+    /// Return the next sentence without parsing it
+    ///
+    /// Returns `Ok(None)` when the caller should wait for the next readable event.  AIS
+    /// fragments are not reassembled.
+    pub fn read_raw(&mut self) -> Result<Option<RawSentence>, NmeaError> {
+        self.framed.read_frame()
+    }
+
+    /// Return the next parsed sentence
+    ///
+    /// AIS fragments are held back until their message is complete.  Returns `Ok(None)`
+    /// when the caller should wait for the next readable event.
+    ///
+    /// ## Errors
+    ///
+    /// Checksum and parse errors affect one sentence only; see
+    /// [`NmeaError::is_recoverable`].
+    pub fn read_sentence(&mut self) -> Result<Option<Sentence>, NmeaError> {
+        loop {
+            let raw = match self.framed.read_frame()? {
+                Some(raw) => raw,
+                None => return Ok(None),
+            };
+            if ais::is_ais(&raw) {
+                match self.ais.push(&raw)? {
+                    Some(message) => return Ok(Some(Sentence::Ais(message))),
+                    None => continue,
+                }
+            }
+            return Sentence::parse(raw).map(Some);
+        }
+    }
+
+    /// Queue `sentence` for transmission
+    pub fn send(&mut self, sentence: &RawSentence) -> Result<(), NmeaError> {
+        self.framed.send(sentence).map(|_| ())
+    }
+
+    /// Write out queued sentences after a writable event
+    pub fn on_writable(&mut self) -> io::Result<usize> {
+        self.framed.on_writable()
+    }
+}
+
+impl<S> Source for NmeaReader<S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.framed.deregister(registry)
+    }
+}
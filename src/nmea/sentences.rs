@@ -0,0 +1,454 @@
+//! Typed NMEA sentences
+//!
+//! Empty fields are `None`.  Latitudes and longitudes are decimal degrees, negative to the
+//! south and west.
+use super::ais::AisMessage;
+use super::{NmeaError, RawSentence};
+
+/// A parsed sentence
+#[derive(Debug, Clone, PartialEq)]
+pub enum Sentence {
+    /// GGA: fix data
+    Gga(Gga),
+    /// RMC: recommended minimum data
+    Rmc(Rmc),
+    /// VTG: course and speed over ground
+    Vtg(Vtg),
+    /// GSA: DOP and active satellites
+    Gsa(Gsa),
+    /// GSV: satellites in view
+    Gsv(Gsv),
+    /// HDT: true heading
+    Hdt(Hdt),
+    /// A complete AIS message from one or more VDM/VDO sentences
+    Ais(AisMessage),
+    /// Any other sentence
+    Unknown(RawSentence),
+}
+
+impl Sentence {
+    /// Parse `raw` into a typed sentence, or return it as [`Sentence::Unknown`]
+    ///
+    /// AIS sentences are returned as `Unknown`; use an
+    /// [`AisAssembler`](super::AisAssembler) to combine them.
+    pub fn parse(raw: RawSentence) -> Result<Sentence, NmeaError> {
+        if raw.start != b'$' || raw.talker() == "P" {
+            return Ok(Sentence::Unknown(raw));
+        }
+        let sentence = match raw.sentence_type() {
+            "GGA" => Sentence::Gga(Gga::parse(&raw)?),
+            "RMC" => Sentence::Rmc(Rmc::parse(&raw)?),
+            "VTG" => Sentence::Vtg(Vtg::parse(&raw)?),
+            "GSA" => Sentence::Gsa(Gsa::parse(&raw)?),
+            "GSV" => Sentence::Gsv(Gsv::parse(&raw)?),
+            "HDT" => Sentence::Hdt(Hdt::parse(&raw)?),
+            _ => Sentence::Unknown(raw),
+        };
+        Ok(sentence)
+    }
+}
+
+/// UTC time of day
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Time {
+    /// Hour, 0 to 23
+    pub hour: u8,
+    /// Minute, 0 to 59
+    pub minute: u8,
+    /// Second, 0 to 60
+    pub second: u8,
+    /// Millisecond, 0 to 999
+    pub millisecond: u16,
+}
+
+/// UTC date
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    /// Year; two digit years are taken as 1980 to 2079
+    pub year: u16,
+    /// Month, 1 to 12
+    pub month: u8,
+    /// Day of the month, 1 to 31
+    pub day: u8,
+}
+
+/// GGA: time, position and fix related data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gga {
+    /// Talker id, e.g. `"GP"`
+    pub talker: String,
+    /// UTC time of the fix
+    pub time: Option<Time>,
+    /// Latitude in degrees
+    pub latitude: Option<f64>,
+    /// Longitude in degrees
+    pub longitude: Option<f64>,
+    /// Fix quality: 0 invalid, 1 GPS, 2 DGPS, 4 RTK fixed, 5 RTK float, ...
+    pub fix_quality: Option<u8>,
+    /// Number of satellites in use
+    pub satellites: Option<u8>,
+    /// Horizontal dilution of precision
+    pub hdop: Option<f32>,
+    /// Antenna altitude above mean sea level in metres
+    pub altitude: Option<f64>,
+    /// Geoid separation in metres
+    pub geoid_separation: Option<f64>,
+    /// Age of differential corrections in seconds
+    pub dgps_age: Option<f32>,
+    /// Differential reference station id
+    pub dgps_station: Option<u16>,
+}
+
+/// RMC: recommended minimum specific GNSS data
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rmc {
+    /// Talker id, e.g. `"GP"`
+    pub talker: String,
+    /// UTC time of the fix
+    pub time: Option<Time>,
+    /// `true` if the status field is `A` (valid)
+    pub valid: bool,
+    /// Latitude in degrees
+    pub latitude: Option<f64>,
+    /// Longitude in degrees
+    pub longitude: Option<f64>,
+    /// Speed over ground in knots
+    pub speed_knots: Option<f64>,
+    /// Course over ground in degrees true
+    pub course: Option<f64>,
+    /// UTC date of the fix
+    pub date: Option<Date>,
+    /// Magnetic variation in degrees, negative to the west
+    pub magnetic_variation: Option<f64>,
+    /// Mode indicator (NMEA 2.3 and later), e.g. `'A'` autonomous or `'D'` differential
+    pub mode: Option<char>,
+}
+
+/// VTG: course over ground and ground speed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Vtg {
+    /// Talker id, e.g. `"GP"`
+    pub talker: String,
+    /// Course over ground in degrees true
+    pub course_true: Option<f64>,
+    /// Course over ground in degrees magnetic
+    pub course_magnetic: Option<f64>,
+    /// Speed over ground in knots
+    pub speed_knots: Option<f64>,
+    /// Speed over ground in km/h
+    pub speed_kmh: Option<f64>,
+    /// Mode indicator (NMEA 2.3 and later)
+    pub mode: Option<char>,
+}
+
+/// GSA: DOP and active satellites
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsa {
+    /// Talker id, e.g. `"GN"`
+    pub talker: String,
+    /// `'M'` manual or `'A'` automatic 2D/3D selection
+    pub selection_mode: Option<char>,
+    /// 1 no fix, 2 2D fix, 3 3D fix
+    pub fix_type: Option<u8>,
+    /// Satellites used in the solution
+    pub satellites: Vec<u16>,
+    /// Position dilution of precision
+    pub pdop: Option<f32>,
+    /// Horizontal dilution of precision
+    pub hdop: Option<f32>,
+    /// Vertical dilution of precision
+    pub vdop: Option<f32>,
+    /// GNSS system id (NMEA 4.1 and later)
+    pub system_id: Option<u8>,
+}
+
+/// One satellite in a GSV sentence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SatelliteInfo {
+    /// Satellite id
+    pub prn: u16,
+    /// Elevation in degrees
+    pub elevation: Option<i16>,
+    /// Azimuth in degrees true
+    pub azimuth: Option<u16>,
+    /// Signal to noise ratio in dB-Hz, `None` when not tracking
+    pub snr: Option<u8>,
+}
+
+/// GSV: satellites in view
+///
+/// The satellite list is spread over several sentences; `message_number` and
+/// `total_messages` tell where this one fits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gsv {
+    /// Talker id, e.g. `"GP"`
+    pub talker: String,
+    /// Number of GSV sentences in this cycle
+    pub total_messages: u8,
+    /// Number of this sentence, starting at 1
+    pub message_number: u8,
+    /// Total number of satellites in view
+    pub satellites_in_view: u16,
+    /// Up to four satellites described by this sentence
+    pub satellites: Vec<SatelliteInfo>,
+    /// Signal id (NMEA 4.1 and later)
+    pub signal_id: Option<u8>,
+}
+
+/// HDT: true heading
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hdt {
+    /// Talker id, e.g. `"HE"`
+    pub talker: String,
+    /// Heading in degrees true
+    pub heading: Option<f64>,
+}
+
+/// Field accessors that report the failing field index
+struct Fields<'a> {
+    raw: &'a RawSentence,
+}
+
+impl<'a> Fields<'a> {
+    fn error(&self, index: usize) -> NmeaError {
+        NmeaError::InvalidField {
+            sentence: self.raw.sentence_type().to_string(),
+            field: index + 1,
+        }
+    }
+
+    fn str(&self, index: usize) -> Option<&'a str> {
+        Some(self.raw.field(index)).filter(|s| !s.is_empty())
+    }
+
+    fn number<T: std::str::FromStr>(&self, index: usize) -> Result<Option<T>, NmeaError> {
+        self.str(index)
+            .map(|s| s.parse().map_err(|_| self.error(index)))
+            .transpose()
+    }
+
+    fn char(&self, index: usize) -> Result<Option<char>, NmeaError> {
+        match self.str(index) {
+            None => Ok(None),
+            Some(s) if s.len() == 1 => Ok(s.chars().next()),
+            Some(_) => Err(self.error(index)),
+        }
+    }
+
+    /// A value in field `index` made negative when field `index + 1` is `negative`
+    fn signed(&self, index: usize, negative: char) -> Result<Option<f64>, NmeaError> {
+        let value = match self.number::<f64>(index)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        match self.char(index + 1)? {
+            Some(c) if c == negative => Ok(Some(-value)),
+            _ => Ok(Some(value)),
+        }
+    }
+
+    /// A `[d]ddmm.mmmm` angle followed by its hemisphere
+    fn coordinate(&self, index: usize, negative: char) -> Result<Option<f64>, NmeaError> {
+        let raw = match self.number::<f64>(index)? {
+            Some(raw) => raw,
+            None => return Ok(None),
+        };
+        let degrees = (raw / 100.0).trunc();
+        let minutes = raw - degrees * 100.0;
+        if minutes >= 60.0 {
+            return Err(self.error(index));
+        }
+        let value = degrees + minutes / 60.0;
+        match self.char(index + 1)? {
+            Some(c) if c == negative => Ok(Some(-value)),
+            _ => Ok(Some(value)),
+        }
+    }
+
+    fn time(&self, index: usize) -> Result<Option<Time>, NmeaError> {
+        let s = match self.str(index) {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        let digits = |range: std::ops::Range<usize>| -> Result<u8, NmeaError> {
+            s.get(range)
+                .and_then(|d| d.parse().ok())
+                .ok_or_else(|| self.error(index))
+        };
+        let (hour, minute, second) = (digits(0..2)?, digits(2..4)?, digits(4..6)?);
+        let millisecond = match s.get(6..) {
+            None | Some("") => 0,
+            Some(frac) if frac.starts_with('.') => {
+                let frac: f64 = format!("0{frac}").parse().map_err(|_| self.error(index))?;
+                (frac * 1000.0).round().min(999.0) as u16
+            }
+            Some(_) => return Err(self.error(index)),
+        };
+        if hour > 23 || minute > 59 || second > 60 {
+            return Err(self.error(index));
+        }
+        Ok(Some(Time {
+            hour,
+            minute,
+            second,
+            millisecond,
+        }))
+    }
+
+    fn date(&self, index: usize) -> Result<Option<Date>, NmeaError> {
+        let s = match self.str(index) {
+            Some(s) => s,
+            None => return Ok(None),
+        };
+        if s.len() != 6 {
+            return Err(self.error(index));
+        }
+        let digits = |range: std::ops::Range<usize>| -> Result<u8, NmeaError> {
+            s.get(range)
+                .and_then(|d| d.parse().ok())
+                .ok_or_else(|| self.error(index))
+        };
+        let (day, month, year) = (digits(0..2)?, digits(2..4)?, digits(4..6)?);
+        if !(1..=31).contains(&day) || !(1..=12).contains(&month) {
+            return Err(self.error(index));
+        }
+        let year = if year < 80 { 2000 } else { 1900 } + u16::from(year);
+        Ok(Some(Date { year, month, day }))
+    }
+}
+
+impl Gga {
+    /// Parse a GGA sentence
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            time: f.time(0)?,
+            latitude: f.coordinate(1, 'S')?,
+            longitude: f.coordinate(3, 'W')?,
+            fix_quality: f.number(5)?,
+            satellites: f.number(6)?,
+            hdop: f.number(7)?,
+            altitude: f.number(8)?,
+            geoid_separation: f.number(10)?,
+            dgps_age: f.number(12)?,
+            dgps_station: f.number(13)?,
+        })
+    }
+}
+
+impl Rmc {
+    /// Parse an RMC sentence
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            time: f.time(0)?,
+            valid: f.char(1)? == Some('A'),
+            latitude: f.coordinate(2, 'S')?,
+            longitude: f.coordinate(4, 'W')?,
+            speed_knots: f.number(6)?,
+            course: f.number(7)?,
+            date: f.date(8)?,
+            magnetic_variation: f.signed(9, 'W')?,
+            mode: f.char(11)?,
+        })
+    }
+}
+
+impl Vtg {
+    /// Parse a VTG sentence
+    ///
+    /// The pre-NMEA 2.0 form without unit fields is accepted too.
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        if raw.fields.len() == 4 {
+            return Ok(Self {
+                talker: raw.talker().to_string(),
+                course_true: f.number(0)?,
+                course_magnetic: f.number(1)?,
+                speed_knots: f.number(2)?,
+                speed_kmh: f.number(3)?,
+                mode: None,
+            });
+        }
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            course_true: f.number(0)?,
+            course_magnetic: f.number(2)?,
+            speed_knots: f.number(4)?,
+            speed_kmh: f.number(6)?,
+            mode: f.char(8)?,
+        })
+    }
+}
+
+impl Gsa {
+    /// Parse a GSA sentence
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        let satellites = (2..14)
+            .map(|i| f.number::<u16>(i))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            selection_mode: f.char(0)?,
+            fix_type: f.number(1)?,
+            satellites,
+            pdop: f.number(14)?,
+            hdop: f.number(15)?,
+            vdop: f.number(16)?,
+            system_id: f.number(17)?,
+        })
+    }
+}
+
+impl Gsv {
+    /// Parse a GSV sentence
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        let groups = raw.fields.len().saturating_sub(3);
+        let signal_id = if groups % 4 == 1 {
+            f.number(raw.fields.len() - 1)?
+        } else {
+            None
+        };
+
+        let mut satellites = Vec::new();
+        for i in (3..3 + groups - groups % 4).step_by(4) {
+            let prn = match f.number(i)? {
+                Some(prn) => prn,
+                None => continue,
+            };
+            satellites.push(SatelliteInfo {
+                prn,
+                elevation: f.number(i + 1)?,
+                azimuth: f.number(i + 2)?,
+                snr: f.number(i + 3)?,
+            });
+        }
+
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            total_messages: f.number(0)?.ok_or_else(|| f.error(0))?,
+            message_number: f.number(1)?.ok_or_else(|| f.error(1))?,
+            satellites_in_view: f.number(2)?.unwrap_or(0),
+            satellites,
+            signal_id,
+        })
+    }
+}
+
+impl Hdt {
+    /// Parse an HDT sentence
+    pub fn parse(raw: &RawSentence) -> Result<Self, NmeaError> {
+        let f = Fields { raw };
+        Ok(Self {
+            talker: raw.talker().to_string(),
+            heading: f.number(0)?,
+        })
+    }
+}
@@ -0,0 +1,595 @@
+//! RFC 2217 client
+//!
+//! [`Client`] connects to an RFC 2217 server and behaves like a local [`SerialStream`]: it
+//! implements [`Read`], [`Write`], [`SerialPort`] and mio's [`Source`], so code written for
+//! local ports works with remote ones.
+//!
+//! Data is non-blocking as usual.  Settings and control lines are changed with
+//! COM-PORT-OPTION commands; each [`SerialPort`] setter sends its command and waits, for at
+//! most [`timeout`](SerialPort::timeout), for the server to answer with the value the port
+//! really has.  A setter returns an `InvalidInput` error when the server could not do what
+//! was asked.  Getters return the values from the server's last answers, and the modem line
+//! getters return the state from its last NOTIFY-MODEMSTATE.
+//!
+//! Data arriving while a setter waits is kept and returned by the next read.  As mio's
+//! readiness events are edge-triggered, read until `WouldBlock` after changing a setting
+//! before waiting for the next event.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::rfc2217::Client;
+//! use mio_serial::SerialPort;
+//! use std::io::Read;
+//!
+//! let mut port = Client::open("rfc2217://lab-bench:2217").unwrap();
+//! port.set_baud_rate(115_200).unwrap();
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut port, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! let mut buf = [0u8; 1024];
+//! loop {
+//!     match port.read(&mut buf) {
+//!         Ok(0) => break,
+//!         Ok(n) => println!("{:?}", &buf[..n]),
+//!         Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+//!             poll.poll(&mut events, None).unwrap();
+//!         }
+//!         Err(e) => panic!("{}", e),
+//!     }
+//! }
+//! ```
+//!
+//! [`SerialStream`]: crate::SerialStream
+use super::{
+    data_bits_from_code, data_size_code, flow_control_code, parity_code, parity_from_code,
+    stop_bits_from_code, stop_size_code, Command, Negotiation, TelnetEvent, TelnetParser,
+};
+use super::{
+    BINARY, COM_PORT_OPTION, CONTROL_BREAK_OFF, CONTROL_BREAK_ON, CONTROL_DTR_OFF, CONTROL_DTR_ON,
+    CONTROL_FLOW_HARDWARE, CONTROL_FLOW_NONE, CONTROL_FLOW_REQUEST, CONTROL_FLOW_SOFTWARE,
+    CONTROL_RTS_OFF, CONTROL_RTS_ON, DO, MODEM_CD, MODEM_CTS, MODEM_DSR, MODEM_RI, PURGE_BOTH,
+    PURGE_RECEIVE, PURGE_TRANSMIT, SUPPRESS_GO_AHEAD, WILL,
+};
+use crate::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Registry, Token};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for the server to answer a command
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// URL scheme accepted by [`Client::open`]
+pub const SCHEME: &str = "rfc2217://";
+
+/// Options accepted in either direction
+const SUPPORTED_OPTIONS: &[u8] = &[BINARY, SUPPRESS_GO_AHEAD, COM_PORT_OPTION];
+
+/// Bytes accepted per write
+const CHUNK: usize = 4096;
+/// Unsent bytes above which writes return `WouldBlock`
+const HIGH_WATER_MARK: usize = 4 * CHUNK;
+
+/// Everything that changes while the connection is used, including from `&self` methods
+#[derive(Debug)]
+struct State {
+    parser: TelnetParser,
+    negotiation: Negotiation,
+    input: VecDeque<u8>,
+    output: Vec<u8>,
+    replies: Vec<Command>,
+    baud_rate: Option<u32>,
+    data_size: Option<u8>,
+    parity: Option<u8>,
+    stop_size: Option<u8>,
+    flow_control: Option<u8>,
+    modem_state: u8,
+    suspended: bool,
+    eof: bool,
+}
+
+impl State {
+    /// Sort bytes from the server into data, negotiations and commands
+    fn receive(&mut self, bytes: &[u8]) {
+        let mut data = Vec::new();
+        let mut events = Vec::new();
+        self.parser.feed(bytes, &mut data, &mut events);
+        self.input.extend(data);
+        for event in events {
+            match event {
+                TelnetEvent::Negotiate(verb, option) => {
+                    self.negotiation.receive(verb, option, &mut self.output)
+                }
+                TelnetEvent::Subnegotiation(COM_PORT_OPTION, body) => {
+                    match Command::decode(&body) {
+                        Some((command, true)) => self.handle_command(command),
+                        Some((command, false)) => {
+                            log::debug!("ignoring client command {:?}", command)
+                        }
+                        None => log::debug!("ignoring unknown COM-PORT-OPTION command {:?}", body),
+                    }
+                }
+                TelnetEvent::Subnegotiation(option, _) => {
+                    log::debug!("ignoring subnegotiation for option {}", option)
+                }
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        match command {
+            Command::NotifyModemState(state) => self.modem_state = state,
+            Command::NotifyLineState(state) => log::debug!("line state {:#04x}", state),
+            Command::FlowControlSuspend => self.suspended = true,
+            Command::FlowControlResume => self.suspended = false,
+            command => {
+                match command {
+                    Command::SetBaudRate(baud) => self.baud_rate = Some(baud),
+                    Command::SetDataSize(size) => self.data_size = Some(size),
+                    Command::SetParity(parity) => self.parity = Some(parity),
+                    Command::SetStopSize(size) => self.stop_size = Some(size),
+                    Command::SetControl(
+                        value @ (CONTROL_FLOW_NONE | CONTROL_FLOW_SOFTWARE | CONTROL_FLOW_HARDWARE),
+                    ) => self.flow_control = Some(value),
+                    _ => {}
+                }
+                self.replies.push(command);
+            }
+        }
+    }
+
+    /// Write queued bytes until done or the socket would block
+    fn flush(&mut self, mut socket: &mio::net::TcpStream) -> io::Result<()> {
+        while !self.output.is_empty() {
+            match socket.write(&self.output) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(n) => drop(self.output.drain(..n)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// Read whatever the socket has without blocking
+    fn fill(&mut self, mut socket: &mio::net::TcpStream) -> io::Result<()> {
+        let mut buf = [0u8; CHUNK];
+        while !self.eof {
+            match socket.read(&mut buf) {
+                Ok(0) => self.eof = true,
+                Ok(n) => self.receive(&buf[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A serial port on an RFC 2217 server
+#[derive(Debug)]
+pub struct Client {
+    name: String,
+    /// Registered with mio; shares its socket with `io`
+    socket: mio::net::TcpStream,
+    /// Registered with `poll`, where setters wait for the server's answers
+    io: mio::net::TcpStream,
+    poll: RefCell<(Poll, Events)>,
+    state: RefCell<State>,
+    timeout: Duration,
+}
+
+impl Client {
+    /// Connect to the server named by an `rfc2217://host:port` URL
+    ///
+    /// Anything after the port, such as a path or query, is ignored.
+    pub fn open(url: &str) -> io::Result<Self> {
+        let address = url
+            .strip_prefix(SCHEME)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("not an {}host:port URL: {}", SCHEME, url),
+                )
+            })?
+            .split(['/', '?'])
+            .next()
+            .unwrap_or_default();
+        let stream = TcpStream::connect(address)?;
+        Self::from_std(stream, url.to_string())
+    }
+
+    /// Connect to the server at `addr`
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        Self::from_std(stream, format!("{}{}", SCHEME, addr))
+    }
+
+    /// Negotiate COM-PORT-OPTION and fetch the current settings
+    fn from_std(stream: TcpStream, name: String) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(true)?;
+        let mut io = mio::net::TcpStream::from_std(stream.try_clone()?);
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut io, Token(0), Interest::READABLE | Interest::WRITABLE)?;
+        let mut state = State {
+            parser: TelnetParser::new(),
+            negotiation: Negotiation::new(SUPPORTED_OPTIONS),
+            input: VecDeque::new(),
+            output: Vec::new(),
+            replies: Vec::new(),
+            baud_rate: None,
+            data_size: None,
+            parity: None,
+            stop_size: None,
+            flow_control: None,
+            modem_state: 0,
+            suspended: false,
+            eof: false,
+        };
+        for (verb, option) in [
+            (WILL, COM_PORT_OPTION),
+            (WILL, BINARY),
+            (DO, BINARY),
+            (WILL, SUPPRESS_GO_AHEAD),
+            (DO, SUPPRESS_GO_AHEAD),
+        ] {
+            state.negotiation.request(verb, option, &mut state.output);
+        }
+
+        let client = Self {
+            name,
+            socket: mio::net::TcpStream::from_std(stream),
+            io,
+            poll: RefCell::new((poll, Events::with_capacity(4))),
+            state: RefCell::new(state),
+            timeout: DEFAULT_TIMEOUT,
+        };
+        client.wait(|state| !state.negotiation.local_pending(COM_PORT_OPTION))?;
+        if !client
+            .state
+            .borrow()
+            .negotiation
+            .local_enabled(COM_PORT_OPTION)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "server refused COM-PORT-OPTION",
+            ));
+        }
+        for command in [
+            Command::SetBaudRate(0),
+            Command::SetDataSize(0),
+            Command::SetParity(0),
+            Command::SetStopSize(0),
+            Command::SetControl(CONTROL_FLOW_REQUEST),
+        ] {
+            client.request(command)?;
+        }
+        Ok(client)
+    }
+
+    /// Exchange data with the server until `done`, for at most the timeout
+    fn wait(&self, mut done: impl FnMut(&mut State) -> bool) -> io::Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        let mut state = self.state.borrow_mut();
+        let mut poll = self.poll.borrow_mut();
+        let (poll, events) = &mut *poll;
+        loop {
+            state.flush(&self.io)?;
+            state.fill(&self.io)?;
+            if done(&mut state) {
+                return Ok(());
+            }
+            if state.eof {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "timed out waiting for the RFC 2217 server",
+                ));
+            }
+            match poll.poll(events, Some(remaining)) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                result => result?,
+            }
+        }
+    }
+
+    /// Send `command` and wait for the server's answer
+    fn request(&self, command: Command) -> io::Result<Command> {
+        let code = command.code();
+        {
+            let mut state = self.state.borrow_mut();
+            state.replies.retain(|c| c.code() != code);
+            command.encode(false, &mut state.output);
+        }
+        let mut reply = None;
+        self.wait(|state| {
+            if let Some(index) = state.replies.iter().position(|c| c.code() == code) {
+                reply = Some(state.replies.remove(index));
+            }
+            reply.is_some()
+        })?;
+        Ok(reply.expect("wait returned without a reply"))
+    }
+
+    /// Send `command` and check that the server answered with the same value
+    fn set(&self, command: Command, what: &str) -> crate::Result<()> {
+        let reply = self.request(command.clone())?;
+        if reply == command {
+            Ok(())
+        } else {
+            Err(crate::Error::new(
+                crate::ErrorKind::InvalidInput,
+                format!("server did not set {}: answered {:?}", what, reply),
+            ))
+        }
+    }
+
+    /// Read pending input, for getters that report what the server last said
+    fn refresh(&self) -> crate::Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.flush(&self.io)?;
+        state.fill(&self.io)?;
+        Ok(())
+    }
+
+    fn setting<T>(value: Option<T>, what: &str) -> crate::Result<T> {
+        value.ok_or_else(|| {
+            crate::Error::new(
+                crate::ErrorKind::Unknown,
+                format!("server did not report its {}", what),
+            )
+        })
+    }
+
+    fn modem_line(&mut self, line: u8) -> crate::Result<bool> {
+        self.refresh()?;
+        Ok(self.state.borrow().modem_state & line != 0)
+    }
+
+    /// The server's SIGNATURE
+    pub fn signature(&self) -> io::Result<String> {
+        match self.request(Command::Signature(Vec::new()))? {
+            Command::Signature(text) => Ok(String::from_utf8_lossy(&text).into_owned()),
+            _ => unreachable!("replies are matched by code"),
+        }
+    }
+
+    /// The address of the server
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.io.peer_addr()
+    }
+}
+
+impl Read for Client {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let state = self.state.get_mut();
+        if state.input.is_empty() {
+            state.flush(&self.io)?;
+            state.fill(&self.io)?;
+        }
+        if state.input.is_empty() {
+            return if state.eof {
+                Ok(0)
+            } else {
+                Err(io::ErrorKind::WouldBlock.into())
+            };
+        }
+        state.input.read(buf)
+    }
+}
+
+impl Write for Client {
+    /// Queue and send data, with `IAC` doubled
+    ///
+    /// Returns `WouldBlock` while too much is unsent or while the server has suspended the
+    /// flow with FLOWCONTROL-SUSPEND; in the latter case the matching FLOWCONTROL-RESUME
+    /// arrives as a readable event.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let state = self.state.get_mut();
+        state.flush(&self.io)?;
+        state.fill(&self.io)?;
+        if state.suspended || state.output.len() >= HIGH_WATER_MARK {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        let n = buf.len().min(CHUNK);
+        super::escape(&buf[..n], &mut state.output);
+        state.flush(&self.io)?;
+        Ok(n)
+    }
+
+    /// Send all queued data, waiting for at most [`timeout`](SerialPort::timeout)
+    ///
+    /// Data arriving meanwhile is kept for the next read.
+    fn flush(&mut self) -> io::Result<()> {
+        self.wait(|state| state.output.is_empty())
+    }
+}
+
+impl SerialPort for Client {
+    /// The URL the client was opened with
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn baud_rate(&self) -> crate::Result<u32> {
+        self.refresh()?;
+        Self::setting(self.state.borrow().baud_rate, "baud rate")
+    }
+
+    fn data_bits(&self) -> crate::Result<DataBits> {
+        self.refresh()?;
+        let code = self.state.borrow().data_size;
+        Self::setting(code.and_then(data_bits_from_code), "data size")
+    }
+
+    fn flow_control(&self) -> crate::Result<FlowControl> {
+        self.refresh()?;
+        let flow = match self.state.borrow().flow_control {
+            Some(CONTROL_FLOW_NONE) => Some(FlowControl::None),
+            Some(CONTROL_FLOW_SOFTWARE) => Some(FlowControl::Software),
+            Some(CONTROL_FLOW_HARDWARE) => Some(FlowControl::Hardware),
+            _ => None,
+        };
+        Self::setting(flow, "flow control")
+    }
+
+    fn parity(&self) -> crate::Result<Parity> {
+        self.refresh()?;
+        let code = self.state.borrow().parity;
+        Self::setting(code.and_then(parity_from_code), "parity")
+    }
+
+    fn stop_bits(&self) -> crate::Result<StopBits> {
+        self.refresh()?;
+        let code = self.state.borrow().stop_size;
+        Self::setting(code.and_then(stop_bits_from_code), "stop size")
+    }
+
+    /// How long setters wait for the server's answer
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> crate::Result<()> {
+        self.set(Command::SetBaudRate(baud_rate), "baud rate")
+    }
+
+    fn set_data_bits(&mut self, data_bits: DataBits) -> crate::Result<()> {
+        self.set(Command::SetDataSize(data_size_code(data_bits)), "data size")
+    }
+
+    fn set_flow_control(&mut self, flow_control: FlowControl) -> crate::Result<()> {
+        let value = flow_control_code(flow_control);
+        self.set(Command::SetControl(value), "flow control")
+    }
+
+    fn set_parity(&mut self, parity: Parity) -> crate::Result<()> {
+        self.set(Command::SetParity(parity_code(parity)), "parity")
+    }
+
+    fn set_stop_bits(&mut self, stop_bits: StopBits) -> crate::Result<()> {
+        self.set(Command::SetStopSize(stop_size_code(stop_bits)), "stop size")
+    }
+
+    /// Set how long setters wait for the server's answer
+    fn set_timeout(&mut self, timeout: Duration) -> crate::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, level: bool) -> crate::Result<()> {
+        let value = if level {
+            CONTROL_RTS_ON
+        } else {
+            CONTROL_RTS_OFF
+        };
+        self.set(Command::SetControl(value), "RTS")
+    }
+
+    fn write_data_terminal_ready(&mut self, level: bool) -> crate::Result<()> {
+        let value = if level {
+            CONTROL_DTR_ON
+        } else {
+            CONTROL_DTR_OFF
+        };
+        self.set(Command::SetControl(value), "DTR")
+    }
+
+    fn read_clear_to_send(&mut self) -> crate::Result<bool> {
+        self.modem_line(MODEM_CTS)
+    }
+
+    fn read_data_set_ready(&mut self) -> crate::Result<bool> {
+        self.modem_line(MODEM_DSR)
+    }
+
+    fn read_ring_indicator(&mut self) -> crate::Result<bool> {
+        self.modem_line(MODEM_RI)
+    }
+
+    fn read_carrier_detect(&mut self) -> crate::Result<bool> {
+        self.modem_line(MODEM_CD)
+    }
+
+    /// Bytes received from the server and not read yet
+    fn bytes_to_read(&self) -> crate::Result<u32> {
+        self.refresh()?;
+        Ok(self.state.borrow().input.len() as u32)
+    }
+
+    /// Bytes not yet handed to the network; the server's own buffers are not included
+    fn bytes_to_write(&self) -> crate::Result<u32> {
+        Ok(self.state.borrow().output.len() as u32)
+    }
+
+    /// Discard received data here and ask the server to purge its port
+    fn clear(&self, buffer_to_clear: ClearBuffer) -> crate::Result<()> {
+        let value = match buffer_to_clear {
+            ClearBuffer::Input => PURGE_RECEIVE,
+            ClearBuffer::Output => PURGE_TRANSMIT,
+            ClearBuffer::All => PURGE_BOTH,
+        };
+        self.set(Command::PurgeData(value), "purge")?;
+        if value != PURGE_TRANSMIT {
+            self.state.borrow_mut().input.clear();
+        }
+        Ok(())
+    }
+
+    /// Cloning is not supported, as for [`SerialStream`](crate::SerialStream)
+    fn try_clone(&self) -> crate::Result<Box<dyn SerialPort>> {
+        Err(crate::Error::new(
+            crate::ErrorKind::Io(io::ErrorKind::Other),
+            "cloning an RFC 2217 client is not supported",
+        ))
+    }
+
+    fn set_break(&self) -> crate::Result<()> {
+        self.set(Command::SetControl(CONTROL_BREAK_ON), "break")
+    }
+
+    fn clear_break(&self) -> crate::Result<()> {
+        self.set(Command::SetControl(CONTROL_BREAK_OFF), "break")
+    }
+}
+
+impl Source for Client {
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.socket.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.socket.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.socket.deregister(registry)
+    }
+}
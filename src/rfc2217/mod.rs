@@ -0,0 +1,448 @@
+//! RFC 2217 (Telnet Com Port Control Option)
+//!
+//! RFC 2217 carries a serial line over a Telnet connection.  Data bytes pass through as
+//! they are, with `IAC` (0xFF) doubled, and port settings travel as COM-PORT-OPTION
+//! subnegotiations: the client asks for a baud rate, data size, parity, stop size or
+//! control line state and the server answers with the setting the port really has.  The
+//! server also reports modem line changes with NOTIFY-MODEMSTATE.
+//!
+//! - [`server`]: shares a serial port with one remote client.
+//! - [`client`]: a remote port that can stand in for a [`SerialStream`](crate::SerialStream).
+//!
+//! [`Command`] is the decoded form of a COM-PORT-OPTION subnegotiation, for either
+//! direction.
+use crate::{DataBits, FlowControl, Parity, StopBits};
+
+pub mod client;
+pub mod server;
+
+pub use client::Client;
+pub use server::Server;
+
+/// Telnet: interpret as command
+pub const IAC: u8 = 255;
+/// Telnet: demand that the other side stop performing an option
+pub const DONT: u8 = 254;
+/// Telnet: request that the other side perform an option
+pub const DO: u8 = 253;
+/// Telnet: refuse to perform an option
+pub const WONT: u8 = 252;
+/// Telnet: offer to perform an option
+pub const WILL: u8 = 251;
+/// Telnet: start of subnegotiation
+pub const SB: u8 = 250;
+/// Telnet: end of subnegotiation
+pub const SE: u8 = 240;
+
+/// Telnet option: binary transmission (RFC 856)
+pub const BINARY: u8 = 0;
+/// Telnet option: suppress go ahead (RFC 858)
+pub const SUPPRESS_GO_AHEAD: u8 = 3;
+/// Telnet option: com port control (RFC 2217)
+pub const COM_PORT_OPTION: u8 = 44;
+
+/// Added to a subcommand code in messages from the server
+pub const SERVER_OFFSET: u8 = 100;
+
+/// Modem state: carrier detect
+pub const MODEM_CD: u8 = 0x80;
+/// Modem state: ring indicator
+pub const MODEM_RI: u8 = 0x40;
+/// Modem state: data set ready
+pub const MODEM_DSR: u8 = 0x20;
+/// Modem state: clear to send
+pub const MODEM_CTS: u8 = 0x10;
+/// Modem state: carrier detect changed
+pub const MODEM_DELTA_CD: u8 = 0x08;
+/// Modem state: ring indicator went low
+pub const MODEM_TRAILING_RI: u8 = 0x04;
+/// Modem state: data set ready changed
+pub const MODEM_DELTA_DSR: u8 = 0x02;
+/// Modem state: clear to send changed
+pub const MODEM_DELTA_CTS: u8 = 0x01;
+
+/// SET-CONTROL: request the outbound flow control setting
+pub const CONTROL_FLOW_REQUEST: u8 = 0;
+/// SET-CONTROL: no outbound flow control
+pub const CONTROL_FLOW_NONE: u8 = 1;
+/// SET-CONTROL: XON/XOFF outbound flow control
+pub const CONTROL_FLOW_SOFTWARE: u8 = 2;
+/// SET-CONTROL: RTS/CTS outbound flow control
+pub const CONTROL_FLOW_HARDWARE: u8 = 3;
+/// SET-CONTROL: request the break state
+pub const CONTROL_BREAK_REQUEST: u8 = 4;
+/// SET-CONTROL: break on
+pub const CONTROL_BREAK_ON: u8 = 5;
+/// SET-CONTROL: break off
+pub const CONTROL_BREAK_OFF: u8 = 6;
+/// SET-CONTROL: request the DTR state
+pub const CONTROL_DTR_REQUEST: u8 = 7;
+/// SET-CONTROL: DTR on
+pub const CONTROL_DTR_ON: u8 = 8;
+/// SET-CONTROL: DTR off
+pub const CONTROL_DTR_OFF: u8 = 9;
+/// SET-CONTROL: request the RTS state
+pub const CONTROL_RTS_REQUEST: u8 = 10;
+/// SET-CONTROL: RTS on
+pub const CONTROL_RTS_ON: u8 = 11;
+/// SET-CONTROL: RTS off
+pub const CONTROL_RTS_OFF: u8 = 12;
+/// SET-CONTROL: request the inbound flow control setting
+pub const CONTROL_INBOUND_REQUEST: u8 = 13;
+/// SET-CONTROL: no inbound flow control
+pub const CONTROL_INBOUND_NONE: u8 = 14;
+/// SET-CONTROL: XON/XOFF inbound flow control
+pub const CONTROL_INBOUND_SOFTWARE: u8 = 15;
+/// SET-CONTROL: RTS/CTS inbound flow control
+pub const CONTROL_INBOUND_HARDWARE: u8 = 16;
+
+/// PURGE-DATA: discard the receive buffer
+pub const PURGE_RECEIVE: u8 = 1;
+/// PURGE-DATA: discard the transmit buffer
+pub const PURGE_TRANSMIT: u8 = 2;
+/// PURGE-DATA: discard both buffers
+pub const PURGE_BOTH: u8 = 3;
+
+/// A COM-PORT-OPTION subnegotiation
+///
+/// The same commands go both ways; a value of 0 in a setting command from the client asks
+/// for the current setting without changing it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// SIGNATURE: free text identifying the sender, or a request for the peer's when empty
+    Signature(Vec<u8>),
+    /// SET-BAUDRATE
+    SetBaudRate(u32),
+    /// SET-DATASIZE: 5 to 8
+    SetDataSize(u8),
+    /// SET-PARITY: 1 none, 2 odd, 3 even, 4 mark, 5 space
+    SetParity(u8),
+    /// SET-STOPSIZE: 1 one, 2 two, 3 one and a half
+    SetStopSize(u8),
+    /// SET-CONTROL: one of the `CONTROL_*` values
+    SetControl(u8),
+    /// NOTIFY-LINESTATE
+    NotifyLineState(u8),
+    /// NOTIFY-MODEMSTATE: `MODEM_*` bits
+    NotifyModemState(u8),
+    /// FLOWCONTROL-SUSPEND: stop sending data
+    FlowControlSuspend,
+    /// FLOWCONTROL-RESUME: start sending data again
+    FlowControlResume,
+    /// SET-LINESTATE-MASK
+    SetLineStateMask(u8),
+    /// SET-MODEMSTATE-MASK
+    SetModemStateMask(u8),
+    /// PURGE-DATA: one of the `PURGE_*` values
+    PurgeData(u8),
+}
+
+impl Command {
+    /// The subcommand code as sent by a client
+    pub fn code(&self) -> u8 {
+        match self {
+            Command::Signature(_) => 0,
+            Command::SetBaudRate(_) => 1,
+            Command::SetDataSize(_) => 2,
+            Command::SetParity(_) => 3,
+            Command::SetStopSize(_) => 4,
+            Command::SetControl(_) => 5,
+            Command::NotifyLineState(_) => 6,
+            Command::NotifyModemState(_) => 7,
+            Command::FlowControlSuspend => 8,
+            Command::FlowControlResume => 9,
+            Command::SetLineStateMask(_) => 10,
+            Command::SetModemStateMask(_) => 11,
+            Command::PurgeData(_) => 12,
+        }
+    }
+
+    /// Decode the body of a COM-PORT-OPTION subnegotiation, without the option byte
+    ///
+    /// Returns the command and whether it came from a server, or `None` for an unknown
+    /// subcommand or a short body.
+    pub fn decode(body: &[u8]) -> Option<(Command, bool)> {
+        let (&code, value) = body.split_first()?;
+        let (code, from_server) = if code >= SERVER_OFFSET {
+            (code - SERVER_OFFSET, true)
+        } else {
+            (code, false)
+        };
+        let byte = || value.first().copied();
+        let command = match code {
+            0 => Command::Signature(value.to_vec()),
+            1 => Command::SetBaudRate(u32::from_be_bytes(value.get(..4)?.try_into().ok()?)),
+            2 => Command::SetDataSize(byte()?),
+            3 => Command::SetParity(byte()?),
+            4 => Command::SetStopSize(byte()?),
+            5 => Command::SetControl(byte()?),
+            6 => Command::NotifyLineState(byte()?),
+            7 => Command::NotifyModemState(byte()?),
+            8 => Command::FlowControlSuspend,
+            9 => Command::FlowControlResume,
+            10 => Command::SetLineStateMask(byte()?),
+            11 => Command::SetModemStateMask(byte()?),
+            12 => Command::PurgeData(byte()?),
+            _ => return None,
+        };
+        Some((command, from_server))
+    }
+
+    /// Append the complete subnegotiation, `IAC SB` to `IAC SE`, to `dst`
+    pub fn encode(&self, from_server: bool, dst: &mut Vec<u8>) {
+        let offset = if from_server { SERVER_OFFSET } else { 0 };
+        dst.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, self.code() + offset]);
+        match self {
+            Command::Signature(text) => escape(text, dst),
+            Command::SetBaudRate(baud) => escape(&baud.to_be_bytes(), dst),
+            Command::SetDataSize(v)
+            | Command::SetParity(v)
+            | Command::SetStopSize(v)
+            | Command::SetControl(v)
+            | Command::NotifyLineState(v)
+            | Command::NotifyModemState(v)
+            | Command::SetLineStateMask(v)
+            | Command::SetModemStateMask(v)
+            | Command::PurgeData(v) => escape(&[*v], dst),
+            Command::FlowControlSuspend | Command::FlowControlResume => {}
+        }
+        dst.extend_from_slice(&[IAC, SE]);
+    }
+}
+
+/// Append `data` to `dst`, doubling every `IAC`
+pub fn escape(data: &[u8], dst: &mut Vec<u8>) {
+    for &b in data {
+        if b == IAC {
+            dst.push(IAC);
+        }
+        dst.push(b);
+    }
+}
+
+/// SET-DATASIZE value for `data_bits`
+pub(crate) fn data_size_code(data_bits: DataBits) -> u8 {
+    match data_bits {
+        DataBits::Five => 5,
+        DataBits::Six => 6,
+        DataBits::Seven => 7,
+        DataBits::Eight => 8,
+    }
+}
+
+pub(crate) fn data_bits_from_code(code: u8) -> Option<DataBits> {
+    match code {
+        5 => Some(DataBits::Five),
+        6 => Some(DataBits::Six),
+        7 => Some(DataBits::Seven),
+        8 => Some(DataBits::Eight),
+        _ => None,
+    }
+}
+
+/// SET-PARITY value for `parity`
+pub(crate) fn parity_code(parity: Parity) -> u8 {
+    match parity {
+        Parity::None => 1,
+        Parity::Odd => 2,
+        Parity::Even => 3,
+    }
+}
+
+/// Mark and space parity have no [`Parity`] equivalent
+pub(crate) fn parity_from_code(code: u8) -> Option<Parity> {
+    match code {
+        1 => Some(Parity::None),
+        2 => Some(Parity::Odd),
+        3 => Some(Parity::Even),
+        _ => None,
+    }
+}
+
+/// SET-STOPSIZE value for `stop_bits`
+pub(crate) fn stop_size_code(stop_bits: StopBits) -> u8 {
+    match stop_bits {
+        StopBits::One => 1,
+        StopBits::Two => 2,
+    }
+}
+
+/// One and a half stop bits has no [`StopBits`] equivalent
+pub(crate) fn stop_bits_from_code(code: u8) -> Option<StopBits> {
+    match code {
+        1 => Some(StopBits::One),
+        2 => Some(StopBits::Two),
+        _ => None,
+    }
+}
+
+/// SET-CONTROL value for the outbound `flow_control` setting
+pub(crate) fn flow_control_code(flow_control: FlowControl) -> u8 {
+    match flow_control {
+        FlowControl::None => CONTROL_FLOW_NONE,
+        FlowControl::Software => CONTROL_FLOW_SOFTWARE,
+        FlowControl::Hardware => CONTROL_FLOW_HARDWARE,
+    }
+}
+
+/// Something other than data found by a [`TelnetParser`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum TelnetEvent {
+    /// WILL, WONT, DO or DONT and the option
+    Negotiate(u8, u8),
+    /// A subnegotiation: the option and its unescaped body
+    Subnegotiation(u8, Vec<u8>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ParseState {
+    Data,
+    Iac,
+    Negotiate(u8),
+    Sub,
+    SubIac,
+}
+
+/// Splits a Telnet byte stream into data and [`TelnetEvent`]s
+///
+/// Both sides negotiate binary transmission, so there is no CR NUL translation.  Other
+/// Telnet commands (NOP, AYT, ...) are dropped.
+#[derive(Debug)]
+pub(crate) struct TelnetParser {
+    state: ParseState,
+    sub: Vec<u8>,
+}
+
+impl TelnetParser {
+    pub(crate) fn new() -> Self {
+        Self {
+            state: ParseState::Data,
+            sub: Vec::new(),
+        }
+    }
+
+    /// Parse `src`, appending data bytes to `data` and everything else to `events`
+    pub(crate) fn feed(&mut self, src: &[u8], data: &mut Vec<u8>, events: &mut Vec<TelnetEvent>) {
+        for &b in src {
+            self.state = match (self.state, b) {
+                (ParseState::Data, IAC) => ParseState::Iac,
+                (ParseState::Data, b) => {
+                    data.push(b);
+                    ParseState::Data
+                }
+                (ParseState::Iac, IAC) => {
+                    data.push(IAC);
+                    ParseState::Data
+                }
+                (ParseState::Iac, WILL | WONT | DO | DONT) => ParseState::Negotiate(b),
+                (ParseState::Iac, SB) => {
+                    self.sub.clear();
+                    ParseState::Sub
+                }
+                (ParseState::Iac, _) => ParseState::Data,
+                (ParseState::Negotiate(verb), option) => {
+                    events.push(TelnetEvent::Negotiate(verb, option));
+                    ParseState::Data
+                }
+                (ParseState::Sub, IAC) => ParseState::SubIac,
+                (ParseState::Sub, b) => {
+                    self.sub.push(b);
+                    ParseState::Sub
+                }
+                (ParseState::SubIac, IAC) => {
+                    self.sub.push(IAC);
+                    ParseState::Sub
+                }
+                (ParseState::SubIac, SE) => {
+                    if let Some((&option, body)) = self.sub.split_first() {
+                        events.push(TelnetEvent::Subnegotiation(option, body.to_vec()));
+                    }
+                    ParseState::Data
+                }
+                // Anything else ends a malformed subnegotiation
+                (ParseState::SubIac, _) => ParseState::Data,
+            };
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionState {
+    No,
+    WantYes,
+    Yes,
+}
+
+/// Telnet option negotiation for one side of a connection
+///
+/// Options in the supported list are accepted in both directions and everything else is
+/// refused.  Requests we sent are remembered so the peer's answer is not answered again.
+#[derive(Debug)]
+pub(crate) struct Negotiation {
+    supported: &'static [u8],
+    local: [OptionState; 256],
+    remote: [OptionState; 256],
+}
+
+impl Negotiation {
+    pub(crate) fn new(supported: &'static [u8]) -> Self {
+        Self {
+            supported,
+            local: [OptionState::No; 256],
+            remote: [OptionState::No; 256],
+        }
+    }
+
+    /// Ask to enable `option`: WILL for our side, DO for the peer's
+    pub(crate) fn request(&mut self, verb: u8, option: u8, dst: &mut Vec<u8>) {
+        let state = match verb {
+            WILL => &mut self.local[option as usize],
+            _ => &mut self.remote[option as usize],
+        };
+        if *state == OptionState::No {
+            *state = OptionState::WantYes;
+            dst.extend_from_slice(&[IAC, verb, option]);
+        }
+    }
+
+    /// Handle a WILL, WONT, DO or DONT from the peer, appending any answer to `dst`
+    pub(crate) fn receive(&mut self, verb: u8, option: u8, dst: &mut Vec<u8>) {
+        let supported = self.supported.contains(&option);
+        let (state, accept, refuse) = match verb {
+            WILL | WONT => (&mut self.remote[option as usize], DO, DONT),
+            _ => (&mut self.local[option as usize], WILL, WONT),
+        };
+        match verb {
+            WILL | DO => match *state {
+                OptionState::Yes => {}
+                OptionState::WantYes => *state = OptionState::Yes,
+                OptionState::No if supported => {
+                    *state = OptionState::Yes;
+                    dst.extend_from_slice(&[IAC, accept, option]);
+                }
+                OptionState::No => dst.extend_from_slice(&[IAC, refuse, option]),
+            },
+            _ => {
+                if *state == OptionState::Yes {
+                    dst.extend_from_slice(&[IAC, refuse, option]);
+                }
+                *state = OptionState::No;
+            }
+        }
+    }
+
+    /// Whether the peer agreed to perform `option`
+    pub(crate) fn remote_enabled(&self, option: u8) -> bool {
+        self.remote[option as usize] == OptionState::Yes
+    }
+
+    /// Whether the peer agreed to let us perform `option`
+    pub(crate) fn local_enabled(&self, option: u8) -> bool {
+        self.local[option as usize] == OptionState::Yes
+    }
+
+    /// Whether our WILL for `option` is still waiting for an answer
+    pub(crate) fn local_pending(&self, option: u8) -> bool {
+        self.local[option as usize] == OptionState::WantYes
+    }
+}
@@ -0,0 +1,637 @@
+//! RFC 2217 server
+//!
+//! [`Server`] shares one serial port with a remote client over TCP.  Data is relayed in
+//! both directions, and the client's COM-PORT-OPTION commands are carried out on the port:
+//!
+//! - SET-BAUDRATE, SET-DATASIZE, SET-PARITY and SET-STOPSIZE change the line settings.
+//! - SET-CONTROL changes flow control and the break, DTR and RTS states.
+//! - PURGE-DATA discards the receive buffer, the transmit buffer, or both.
+//! - FLOWCONTROL-SUSPEND and FLOWCONTROL-RESUME pause data towards the client.
+//!
+//! Every command is answered with the value the port actually has afterwards, so a client
+//! asking for a setting the port cannot do learns what it got instead.  Modem lines are
+//! polled and changes are reported with NOTIFY-MODEMSTATE, filtered by the client's
+//! SET-MODEMSTATE-MASK.
+//!
+//! Only one client is served at a time; further connections are closed until it leaves.
+//! Data arriving from the port while nobody is connected is discarded.  The server owns
+//! its [`Poll`], so everything runs in one thread and one event loop.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::net::TcpListener;
+//! use mio_serial::rfc2217::Server;
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 9600).open_native_async().unwrap();
+//! let listener = TcpListener::bind("0.0.0.0:2217".parse().unwrap()).unwrap();
+//!
+//! let mut server = Server::new(listener, stream).unwrap();
+//! server.run().unwrap();
+//! ```
+use super::{
+    data_bits_from_code, data_size_code, flow_control_code, parity_code, parity_from_code,
+    stop_bits_from_code, stop_size_code, Command, Negotiation, TelnetEvent, TelnetParser,
+};
+use super::{
+    BINARY, COM_PORT_OPTION, CONTROL_BREAK_OFF, CONTROL_BREAK_ON, CONTROL_BREAK_REQUEST,
+    CONTROL_DTR_OFF, CONTROL_DTR_ON, CONTROL_DTR_REQUEST, CONTROL_FLOW_HARDWARE, CONTROL_FLOW_NONE,
+    CONTROL_FLOW_REQUEST, CONTROL_FLOW_SOFTWARE, CONTROL_INBOUND_HARDWARE, CONTROL_INBOUND_NONE,
+    CONTROL_INBOUND_REQUEST, CONTROL_INBOUND_SOFTWARE, CONTROL_RTS_OFF, CONTROL_RTS_ON,
+    CONTROL_RTS_REQUEST, DO, MODEM_CD, MODEM_CTS, MODEM_DELTA_CD, MODEM_DELTA_CTS, MODEM_DELTA_DSR,
+    MODEM_DSR, MODEM_RI, MODEM_TRAILING_RI, PURGE_BOTH, PURGE_RECEIVE, PURGE_TRANSMIT,
+    SUPPRESS_GO_AHEAD, WILL,
+};
+use crate::buffered::WriteQueue;
+use crate::{ClearBuffer, FlowControl, SerialPort, SerialStream};
+use mio::event::Source;
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token};
+use std::io::{self, Read};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+/// Default interval between modem line polls
+pub const DEFAULT_MODEM_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Default SIGNATURE sent to clients
+pub const DEFAULT_SIGNATURE: &str = concat!("mio-serial ", env!("CARGO_PKG_VERSION"));
+
+const LISTENER: Token = Token(0);
+const SERIAL: Token = Token(1);
+const FIRST_CONNECTION: usize = 2;
+
+/// Options accepted in either direction
+const SUPPORTED_OPTIONS: &[u8] = &[BINARY, SUPPRESS_GO_AHEAD, COM_PORT_OPTION];
+
+/// Bytes moved per read
+const CHUNK: usize = 4096;
+/// Room kept in the client queue for command replies
+const CONTROL_RESERVE: usize = 1024;
+
+/// The connected client
+#[derive(Debug)]
+struct Session {
+    stream: TcpStream,
+    addr: SocketAddr,
+    parser: TelnetParser,
+    negotiation: Negotiation,
+    queue: WriteQueue,
+    suspended: bool,
+    modem_mask: u8,
+    modem_state: Option<u8>,
+    overflow: bool,
+}
+
+impl Session {
+    fn send(&mut self, command: &Command) {
+        let mut frame = Vec::new();
+        command.encode(true, &mut frame);
+        self.send_raw(&frame);
+    }
+
+    fn send_raw(&mut self, bytes: &[u8]) {
+        // A client that does not read its replies loses the connection rather than
+        // receiving half a subnegotiation
+        if self.queue.push(bytes) < bytes.len() {
+            self.overflow = true;
+        }
+    }
+}
+
+/// Shares a serial port with an RFC 2217 client
+pub struct Server<S = SerialStream> {
+    poll: Poll,
+    events: Events,
+    listener: TcpListener,
+    serial: S,
+    serial_queue: WriteQueue,
+    session: Option<Session>,
+    next_token: usize,
+    modem_poll_interval: Duration,
+    next_modem_poll: Instant,
+    signature: String,
+    break_state: bool,
+    dtr: bool,
+    rts: bool,
+}
+
+impl<S> std::fmt::Debug for Server<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Server")
+            .field("listener", &self.listener)
+            .field("client", &self.session.as_ref().map(|s| s.addr))
+            .field("modem_poll_interval", &self.modem_poll_interval)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> Server<S>
+where
+    S: SerialPort + Source,
+{
+    /// Create a server accepting connections on `listener` and relaying them to `serial`
+    ///
+    /// DTR and RTS are assumed to be on, as they are after opening a port.
+    pub fn new(mut listener: TcpListener, mut serial: S) -> io::Result<Self> {
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut listener, LISTENER, Interest::READABLE)?;
+        poll.registry()
+            .register(&mut serial, SERIAL, Interest::READABLE | Interest::WRITABLE)?;
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(64),
+            listener,
+            serial,
+            serial_queue: WriteQueue::new(),
+            session: None,
+            next_token: FIRST_CONNECTION,
+            modem_poll_interval: DEFAULT_MODEM_POLL_INTERVAL,
+            next_modem_poll: Instant::now(),
+            signature: DEFAULT_SIGNATURE.to_string(),
+            break_state: false,
+            dtr: true,
+            rts: true,
+        })
+    }
+
+    /// Set how often the modem lines are polled for NOTIFY-MODEMSTATE
+    #[must_use]
+    pub fn with_modem_poll_interval(mut self, interval: Duration) -> Self {
+        self.modem_poll_interval = interval;
+        self
+    }
+
+    /// Set the text sent in reply to a SIGNATURE request
+    #[must_use]
+    pub fn with_signature(mut self, signature: impl Into<String>) -> Self {
+        self.signature = signature.into();
+        self
+    }
+
+    /// The address the server is listening on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// The address of the connected client, if there is one
+    pub fn client_addr(&self) -> Option<SocketAddr> {
+        self.session.as_ref().map(|s| s.addr)
+    }
+
+    /// Get a reference to the serial port
+    pub fn get_ref(&self) -> &S {
+        &self.serial
+    }
+
+    /// Get a mutable reference to the serial port
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.serial
+    }
+
+    /// Run the event loop until an error occurs on the listener or the serial port
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.run_once(None)?;
+        }
+    }
+
+    /// Wait for events for at most `timeout` and handle them
+    ///
+    /// The wait is cut short when the modem lines are due to be polled.
+    pub fn run_once(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let now = Instant::now();
+        let deadline = self
+            .session
+            .as_ref()
+            .map(|_| self.next_modem_poll.saturating_duration_since(now));
+        let wait = match (timeout, deadline) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+
+        match self.poll.poll(&mut self.events, wait) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+        if self.events.iter().any(|e| e.token() == LISTENER) {
+            self.accept()?;
+        }
+
+        // Both directions are pumped on every wakeup, which also covers readiness that
+        // was left unused while a queue was full
+        self.pump()?;
+
+        let now = Instant::now();
+        if now >= self.next_modem_poll {
+            self.next_modem_poll = now + self.modem_poll_interval;
+            self.poll_modem();
+            self.pump()?;
+        }
+        Ok(())
+    }
+
+    fn accept(&mut self) -> io::Result<()> {
+        loop {
+            let (mut stream, addr) = match self.listener.accept() {
+                Ok(accepted) => accepted,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if let Some(session) = &self.session {
+                log::warn!(
+                    "rejecting connection from {}: port in use by {}",
+                    addr,
+                    session.addr
+                );
+                continue;
+            }
+
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            if let Err(e) = self.poll.registry().register(
+                &mut stream,
+                token,
+                Interest::READABLE | Interest::WRITABLE,
+            ) {
+                log::warn!("unable to register connection from {}: {}", addr, e);
+                continue;
+            }
+            log::debug!("accepted connection from {}", addr);
+
+            let mut session = Session {
+                stream,
+                addr,
+                parser: TelnetParser::new(),
+                negotiation: Negotiation::new(SUPPORTED_OPTIONS),
+                queue: WriteQueue::with_high_water_mark(2 * CHUNK + CONTROL_RESERVE),
+                suspended: false,
+                modem_mask: 0xFF,
+                modem_state: None,
+                overflow: false,
+            };
+            let mut offer = Vec::new();
+            for (verb, option) in [
+                (WILL, BINARY),
+                (DO, BINARY),
+                (WILL, SUPPRESS_GO_AHEAD),
+                (DO, SUPPRESS_GO_AHEAD),
+                (DO, COM_PORT_OPTION),
+            ] {
+                session.negotiation.request(verb, option, &mut offer);
+            }
+            session.send_raw(&offer);
+            self.session = Some(session);
+            self.next_modem_poll = Instant::now();
+        }
+    }
+
+    /// Move data until neither direction makes progress
+    fn pump(&mut self) -> io::Result<()> {
+        loop {
+            let mut moved = self.read_client();
+            moved += self.serial_queue.write_to(&mut self.serial)?;
+            moved += self.read_serial()?;
+            moved += self.write_client();
+            if moved == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn read_client(&mut self) -> usize {
+        let mut buf = [0u8; CHUNK];
+        let mut moved = 0;
+        while self.serial_queue.remaining() >= CHUNK {
+            let session = match self.session.as_mut() {
+                Some(session) => session,
+                None => break,
+            };
+            let n = match session.stream.read(&mut buf) {
+                Ok(0) => {
+                    log::debug!("connection from {} closed", session.addr);
+                    self.close();
+                    break;
+                }
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    log::debug!("closing connection from {}: {}", session.addr, e);
+                    self.close();
+                    break;
+                }
+            };
+            moved += n;
+
+            let mut data = Vec::new();
+            let mut events = Vec::new();
+            session.parser.feed(&buf[..n], &mut data, &mut events);
+            // Parsed data is never longer than the bytes read, so it always fits
+            self.serial_queue.push(&data);
+            for event in events {
+                self.handle_event(event);
+            }
+        }
+        moved
+    }
+
+    fn read_serial(&mut self) -> io::Result<usize> {
+        let mut buf = [0u8; CHUNK];
+        let mut moved = 0;
+        loop {
+            // Escaping can double the data, so only read what is sure to fit
+            let limit = match &self.session {
+                Some(s) if s.suspended => return Ok(moved),
+                Some(s) if s.queue.remaining() < 2 * CHUNK => return Ok(moved),
+                Some(_) => CHUNK,
+                None => CHUNK,
+            };
+            let n = match self.serial.read(&mut buf[..limit]) {
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(moved),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if n == 0 {
+                return Ok(moved);
+            }
+            moved += n;
+            if let Some(session) = self.session.as_mut() {
+                let mut escaped = Vec::with_capacity(n);
+                super::escape(&buf[..n], &mut escaped);
+                session.queue.push(&escaped);
+            }
+        }
+    }
+
+    fn write_client(&mut self) -> usize {
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return 0,
+        };
+        if session.overflow {
+            log::warn!(
+                "closing connection from {}: not reading replies",
+                session.addr
+            );
+            self.close();
+            return 0;
+        }
+        match session.queue.write_to(&mut session.stream) {
+            Ok(n) => n,
+            Err(e) => {
+                log::debug!("closing connection from {}: {}", session.addr, e);
+                self.close();
+                0
+            }
+        }
+    }
+
+    fn close(&mut self) {
+        if let Some(mut session) = self.session.take() {
+            let _ = self.poll.registry().deregister(&mut session.stream);
+        }
+    }
+
+    fn handle_event(&mut self, event: TelnetEvent) {
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+        match event {
+            TelnetEvent::Negotiate(verb, option) => {
+                let mut reply = Vec::new();
+                session.negotiation.receive(verb, option, &mut reply);
+                session.send_raw(&reply);
+            }
+            TelnetEvent::Subnegotiation(COM_PORT_OPTION, body) => match Command::decode(&body) {
+                Some((command, false)) => self.handle_command(command),
+                Some((command, true)) => log::debug!("ignoring server command {:?}", command),
+                None => log::debug!("ignoring unknown COM-PORT-OPTION command {:?}", body),
+            },
+            TelnetEvent::Subnegotiation(option, _) => {
+                log::debug!("ignoring subnegotiation for option {}", option)
+            }
+        }
+    }
+
+    fn handle_command(&mut self, command: Command) {
+        log::debug!("{:?}", command);
+        let reply = match command {
+            Command::Signature(text) if text.is_empty() => {
+                Some(Command::Signature(self.signature.clone().into_bytes()))
+            }
+            Command::Signature(text) => {
+                log::info!("client signature: {}", String::from_utf8_lossy(&text));
+                None
+            }
+            Command::SetBaudRate(baud) => {
+                if baud != 0 {
+                    if let Err(e) = self.serial.set_baud_rate(baud) {
+                        log::warn!("unable to set baud rate {}: {}", baud, e);
+                    }
+                }
+                Some(Command::SetBaudRate(self.serial.baud_rate().unwrap_or(0)))
+            }
+            Command::SetDataSize(code) => {
+                if let Some(data_bits) = data_bits_from_code(code) {
+                    if let Err(e) = self.serial.set_data_bits(data_bits) {
+                        log::warn!("unable to set data bits {}: {}", data_bits, e);
+                    }
+                }
+                let current = self.serial.data_bits().map(data_size_code);
+                Some(Command::SetDataSize(current.unwrap_or(0)))
+            }
+            Command::SetParity(code) => {
+                if let Some(parity) = parity_from_code(code) {
+                    if let Err(e) = self.serial.set_parity(parity) {
+                        log::warn!("unable to set parity {}: {}", parity, e);
+                    }
+                }
+                let current = self.serial.parity().map(parity_code);
+                Some(Command::SetParity(current.unwrap_or(0)))
+            }
+            Command::SetStopSize(code) => {
+                if let Some(stop_bits) = stop_bits_from_code(code) {
+                    if let Err(e) = self.serial.set_stop_bits(stop_bits) {
+                        log::warn!("unable to set stop bits {}: {}", stop_bits, e);
+                    }
+                }
+                let current = self.serial.stop_bits().map(stop_size_code);
+                Some(Command::SetStopSize(current.unwrap_or(0)))
+            }
+            Command::SetControl(value) => Some(Command::SetControl(self.set_control(value))),
+            Command::PurgeData(value) => {
+                let buffer = match value {
+                    PURGE_RECEIVE => Some(ClearBuffer::Input),
+                    PURGE_TRANSMIT => Some(ClearBuffer::Output),
+                    PURGE_BOTH => Some(ClearBuffer::All),
+                    _ => None,
+                };
+                if let Some(buffer) = buffer {
+                    if value != PURGE_RECEIVE {
+                        self.serial_queue.clear();
+                    }
+                    if let Err(e) = self.serial.clear(buffer) {
+                        log::warn!("unable to purge {:?}: {}", buffer, e);
+                    }
+                }
+                Some(Command::PurgeData(value))
+            }
+            Command::FlowControlSuspend | Command::FlowControlResume => {
+                if let Some(session) = self.session.as_mut() {
+                    session.suspended = command == Command::FlowControlSuspend;
+                }
+                None
+            }
+            Command::SetModemStateMask(mask) => {
+                if let Some(session) = self.session.as_mut() {
+                    session.modem_mask = mask;
+                }
+                Some(Command::SetModemStateMask(mask))
+            }
+            // Line state errors are not visible through `SerialPort`, so there is nothing
+            // to mask
+            Command::SetLineStateMask(mask) => Some(Command::SetLineStateMask(mask)),
+            Command::NotifyLineState(_) | Command::NotifyModemState(_) => None,
+        };
+        if let (Some(reply), Some(session)) = (reply, self.session.as_mut()) {
+            session.send(&reply);
+        }
+    }
+
+    /// Carry out a SET-CONTROL and return the value to answer with
+    fn set_control(&mut self, value: u8) -> u8 {
+        let flow = match value {
+            CONTROL_FLOW_NONE | CONTROL_INBOUND_NONE => Some(FlowControl::None),
+            CONTROL_FLOW_SOFTWARE | CONTROL_INBOUND_SOFTWARE => Some(FlowControl::Software),
+            CONTROL_FLOW_HARDWARE | CONTROL_INBOUND_HARDWARE => Some(FlowControl::Hardware),
+            _ => None,
+        };
+        if let Some(flow) = flow {
+            if let Err(e) = self.serial.set_flow_control(flow) {
+                log::warn!("unable to set flow control {}: {}", flow, e);
+            }
+        }
+
+        match value {
+            CONTROL_FLOW_REQUEST..=CONTROL_FLOW_HARDWARE => {
+                let current = self.serial.flow_control().map(flow_control_code);
+                current.unwrap_or(CONTROL_FLOW_NONE)
+            }
+            CONTROL_INBOUND_REQUEST..=CONTROL_INBOUND_HARDWARE => {
+                let current = self.serial.flow_control().map(flow_control_code);
+                current.unwrap_or(CONTROL_FLOW_NONE) - CONTROL_FLOW_NONE + CONTROL_INBOUND_NONE
+            }
+            CONTROL_BREAK_REQUEST..=CONTROL_BREAK_OFF => {
+                let result = match value {
+                    CONTROL_BREAK_ON => self.serial.set_break().map(|_| true),
+                    CONTROL_BREAK_OFF => self.serial.clear_break().map(|_| false),
+                    _ => Ok(self.break_state),
+                };
+                match result {
+                    Ok(state) => self.break_state = state,
+                    Err(e) => log::warn!("unable to change break state: {}", e),
+                }
+                if self.break_state {
+                    CONTROL_BREAK_ON
+                } else {
+                    CONTROL_BREAK_OFF
+                }
+            }
+            CONTROL_DTR_REQUEST..=CONTROL_DTR_OFF => {
+                if value != CONTROL_DTR_REQUEST {
+                    let level = value == CONTROL_DTR_ON;
+                    match self.serial.write_data_terminal_ready(level) {
+                        Ok(()) => self.dtr = level,
+                        Err(e) => log::warn!("unable to set DTR: {}", e),
+                    }
+                }
+                if self.dtr {
+                    CONTROL_DTR_ON
+                } else {
+                    CONTROL_DTR_OFF
+                }
+            }
+            CONTROL_RTS_REQUEST..=CONTROL_RTS_OFF => {
+                if value != CONTROL_RTS_REQUEST {
+                    let level = value == CONTROL_RTS_ON;
+                    match self.serial.write_request_to_send(level) {
+                        Ok(()) => self.rts = level,
+                        Err(e) => log::warn!("unable to set RTS: {}", e),
+                    }
+                }
+                if self.rts {
+                    CONTROL_RTS_ON
+                } else {
+                    CONTROL_RTS_OFF
+                }
+            }
+            // DCD and DSR flow control are not supported; the request is echoed back
+            value => value,
+        }
+    }
+
+    /// Read the modem lines and tell the client about changes
+    fn poll_modem(&mut self) {
+        let session = match self.session.as_mut() {
+            Some(session) => session,
+            None => return,
+        };
+        if !session.negotiation.remote_enabled(COM_PORT_OPTION) {
+            return;
+        }
+
+        // Lines that cannot be read (ptys, some USB adapters) read as off
+        let mut state = 0;
+        for (bit, level) in [
+            (MODEM_CTS, self.serial.read_clear_to_send()),
+            (MODEM_DSR, self.serial.read_data_set_ready()),
+            (MODEM_RI, self.serial.read_ring_indicator()),
+            (MODEM_CD, self.serial.read_carrier_detect()),
+        ] {
+            if level.unwrap_or(false) {
+                state |= bit;
+            }
+        }
+
+        let previous = session.modem_state.replace(state);
+        let deltas = match previous {
+            Some(previous) => {
+                let changed = previous ^ state;
+                let mut deltas = 0;
+                for (line, delta) in [
+                    (MODEM_CTS, MODEM_DELTA_CTS),
+                    (MODEM_DSR, MODEM_DELTA_DSR),
+                    (MODEM_CD, MODEM_DELTA_CD),
+                ] {
+                    if changed & line != 0 {
+                        deltas |= delta;
+                    }
+                }
+                if previous & MODEM_RI != 0 && state & MODEM_RI == 0 {
+                    deltas |= MODEM_TRAILING_RI;
+                }
+                Some(deltas)
+            }
+            None => None,
+        };
+
+        // The first report is always sent so the client knows where the lines start
+        let notify = match deltas {
+            None => Some(state & session.modem_mask),
+            Some(deltas) if (state | deltas) & session.modem_mask & 0x0F != 0 => {
+                Some((state | deltas) & session.modem_mask)
+            }
+            Some(_) => None,
+        };
+        if let Some(notify) = notify {
+            session.send(&Command::NotifyModemState(notify));
+        }
+    }
+}
@@ -0,0 +1,434 @@
+//! Transparent serial sniffer
+//!
+//! [`SerialTee`] sits between an application and a serial device.  It creates a
+//! pseudo-terminal, as [`SerialStream::pair`] does, for the application to open instead of
+//! the device, and relays bytes between the two unchanged while logging each direction
+//! with a timestamp.  Use [`SerialTee::slave_path`] to find the name of the terminal, or
+//! [`SerialTee::create_link`] to give it a fixed name the application can be pointed at.
+//!
+//! The terminal settings are polled, and baud rate changes made by the application are
+//! carried out on the device, so software that switches speed after a handshake keeps
+//! working.  The tee keeps its own handle on the terminal open, so the application may
+//! close and reopen it without ending the session.
+//!
+//! Log entries are written as a hex dump with the time since the tee was created, the
+//! direction (`>` for application to device, `<` for device to application), and the
+//! bytes as text:
+//!
+//! ```text
+//!        0.012503 > 41 54 49 0d                                      |ATI.|
+//!        0.031877 < 0d 0a 4d 6f 64 65 6d 20  76 31 2e 32 0d 0a 0d 0a |..Modem v1.2....|
+//!        0.031877 <  4f 4b 0d 0a                                     |OK..|
+//!        1.207112 = baud rate 115200
+//! ```
+//!
+//! Continuation lines of a long read have an extra space after the direction.  The
+//! session can also be recorded as a [capture](crate::capture) with
+//! [`SerialTee::with_capture`], to be replayed later.  The tee owns its [`Poll`], so
+//! everything runs in one thread and one event loop.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio_serial::tee::SerialTee;
+//! use mio_serial::SerialPortBuilderExt;
+//!
+//! let device = mio_serial::new("/dev/ttyUSB0", 9600).open_native_async().unwrap();
+//! let mut tee = SerialTee::new(device).unwrap().with_log(std::io::stdout());
+//! tee.create_link("/tmp/ttyINSTRUMENT").unwrap();
+//! tee.run().unwrap();
+//! ```
+use crate::buffered::WriteQueue;
+use crate::capture::{CaptureWriter, Settings};
+use crate::{SerialPort, SerialStream};
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Token};
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default interval between terminal settings polls
+pub const DEFAULT_SETTINGS_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Bytes per hex dump line
+pub const BYTES_PER_LINE: usize = 16;
+
+pub use crate::capture::Direction;
+
+const PTY: Token = Token(0);
+const DEVICE: Token = Token(1);
+
+/// Bytes moved per read
+const CHUNK: usize = 4096;
+
+/// Counters kept by a [`SerialTee`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TeeStats {
+    /// Bytes relayed from the application to the device
+    pub to_device: u64,
+    /// Bytes relayed from the device to the application
+    pub from_device: u64,
+    /// Baud rate changes carried out on the device
+    pub baud_changes: u64,
+}
+
+/// Format one log entry as hex dump lines, each ending in a newline
+///
+/// `elapsed` is printed in seconds with microseconds.
+pub fn format_entry(elapsed: Duration, direction: Direction, data: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in data.chunks(BYTES_PER_LINE).enumerate() {
+        let continuation = if i == 0 { "" } else { " " };
+        let _ = write!(
+            out,
+            "{:>8}.{:06} {}{} ",
+            elapsed.as_secs(),
+            elapsed.subsec_micros(),
+            direction.marker(),
+            continuation
+        );
+        let mut hex = String::new();
+        for (j, byte) in line.iter().enumerate() {
+            if j == BYTES_PER_LINE / 2 {
+                hex.push(' ');
+            }
+            let _ = write!(hex, "{byte:02x} ");
+        }
+        let text: String = line
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let width = BYTES_PER_LINE * 3 + 1 - continuation.len();
+        let _ = writeln!(out, "{hex:<width$}|{text}|");
+    }
+    out
+}
+
+/// Relays between an application on a pseudo-terminal and a serial device, logging both
+/// directions
+pub struct SerialTee<S = SerialStream> {
+    poll: Poll,
+    events: Events,
+    pty: SerialStream,
+    slave: SerialStream,
+    device: S,
+    to_device: WriteQueue,
+    to_app: WriteQueue,
+    log: Option<Box<dyn Write + Send>>,
+    capture: Option<CaptureWriter<Box<dyn Write + Send>>>,
+    link: Option<PathBuf>,
+    started: Instant,
+    baud_rate: Option<u32>,
+    settings_poll_interval: Duration,
+    next_settings_poll: Instant,
+    stats: TeeStats,
+}
+
+impl<S> std::fmt::Debug for SerialTee<S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SerialTee")
+            .field("slave", &self.slave.name())
+            .field("link", &self.link)
+            .field("baud_rate", &self.baud_rate)
+            .field("stats", &self.stats)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S> SerialTee<S>
+where
+    S: SerialPort + Source,
+{
+    /// Create a pseudo-terminal and relay it to `device`
+    ///
+    /// The terminal starts with the device's baud rate.  Nothing is logged until a log is
+    /// set with [`with_log`](Self::with_log).
+    pub fn new(mut device: S) -> io::Result<Self> {
+        let (mut pty, mut slave) = SerialStream::pair()?;
+        let baud_rate = device.baud_rate().ok();
+        if let Some(baud_rate) = baud_rate {
+            slave.set_baud_rate(baud_rate)?;
+        }
+
+        let poll = Poll::new()?;
+        poll.registry()
+            .register(&mut pty, PTY, Interest::READABLE | Interest::WRITABLE)?;
+        poll.registry()
+            .register(&mut device, DEVICE, Interest::READABLE | Interest::WRITABLE)?;
+        let now = Instant::now();
+        Ok(Self {
+            poll,
+            events: Events::with_capacity(16),
+            pty,
+            slave,
+            device,
+            to_device: WriteQueue::new(),
+            to_app: WriteQueue::new(),
+            log: None,
+            capture: None,
+            link: None,
+            started: now,
+            baud_rate,
+            settings_poll_interval: DEFAULT_SETTINGS_POLL_INTERVAL,
+            next_settings_poll: now,
+            stats: TeeStats::default(),
+        })
+    }
+
+    /// Log both directions to `log`
+    ///
+    /// A log that fails to write is dropped with a warning; relaying carries on.
+    #[must_use]
+    pub fn with_log<W: Write + Send + 'static>(mut self, log: W) -> Self {
+        self.log = Some(Box::new(log));
+        self
+    }
+
+    /// Record a [capture](crate::capture) of the session to `output`
+    ///
+    /// The capture starts with the device's settings and records each change the
+    /// application makes.  As with the log, a capture that fails to write is dropped.
+    #[must_use]
+    pub fn with_capture<W: Write + Send + 'static>(mut self, output: W) -> Self {
+        let output: Box<dyn Write + Send> = Box::new(output);
+        self.capture = Some(CaptureWriter::new(output));
+        self.capture_settings();
+        self
+    }
+
+    /// Set how often the terminal settings are polled for changes by the application
+    #[must_use]
+    pub fn with_settings_poll_interval(mut self, interval: Duration) -> Self {
+        self.settings_poll_interval = interval;
+        self
+    }
+
+    /// The path of the terminal the application should open
+    pub fn slave_path(&self) -> Option<String> {
+        self.slave.name()
+    }
+
+    /// Create a symbolic link at `path` pointing to the terminal
+    ///
+    /// An existing symbolic link at `path` is replaced, but any other file is left alone
+    /// and an `AlreadyExists` error returned.  The link is removed when the tee is dropped.
+    pub fn create_link(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        self.remove_link();
+        let target = self.slave_path().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "pseudo-terminal has no name")
+        })?;
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.file_type().is_symlink() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and is not a symbolic link", path.display()),
+                ))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        std::os::unix::fs::symlink(target, path)?;
+        self.link = Some(path.to_path_buf());
+        Ok(())
+    }
+
+    /// The symbolic link created by [`create_link`](Self::create_link), if any
+    pub fn link(&self) -> Option<&Path> {
+        self.link.as_deref()
+    }
+
+    /// The baud rate last seen on the terminal
+    pub fn baud_rate(&self) -> Option<u32> {
+        self.baud_rate
+    }
+
+    /// Byte and settings counters
+    pub fn stats(&self) -> TeeStats {
+        self.stats
+    }
+
+    /// Get a reference to the device
+    pub fn get_ref(&self) -> &S {
+        &self.device
+    }
+
+    /// Get a mutable reference to the device
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.device
+    }
+
+    /// Run the event loop until an error occurs on the terminal or the device
+    pub fn run(&mut self) -> io::Result<()> {
+        loop {
+            self.run_once(None)?;
+        }
+    }
+
+    /// Wait for events for at most `timeout` and handle them
+    ///
+    /// The wait is cut short when the terminal settings are due to be polled.
+    pub fn run_once(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        let deadline = self
+            .next_settings_poll
+            .saturating_duration_since(Instant::now());
+        let wait = timeout.map_or(deadline, |t| t.min(deadline));
+
+        match self.poll.poll(&mut self.events, Some(wait)) {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => return Ok(()),
+            Err(e) => return Err(e),
+        }
+
+        // Settings are checked before data is relayed, so bytes the application sends
+        // right after changing speed go out at the new speed
+        let now = Instant::now();
+        if now >= self.next_settings_poll {
+            self.next_settings_poll = now + self.settings_poll_interval;
+            self.poll_settings()?;
+        }
+        self.pump()
+    }
+
+    fn pump(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; CHUNK];
+        loop {
+            let mut moved = 0;
+
+            while self.to_device.remaining() >= CHUNK {
+                let n = match self.pty.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                self.to_device.push(&buf[..n]);
+                self.stats.to_device += n as u64;
+                self.log_data(Direction::ToDevice, &buf[..n]);
+                moved += n;
+            }
+            moved += self.to_device.write_to(&mut self.device)?;
+
+            while self.to_app.remaining() >= CHUNK {
+                let n = match self.device.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => n,
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(e) => return Err(e),
+                };
+                self.to_app.push(&buf[..n]);
+                self.stats.from_device += n as u64;
+                self.log_data(Direction::FromDevice, &buf[..n]);
+                moved += n;
+            }
+            moved += self.to_app.write_to(&mut self.pty)?;
+
+            if moved == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    fn poll_settings(&mut self) -> io::Result<()> {
+        let baud_rate = match self.pty.baud_rate() {
+            Ok(baud_rate) => baud_rate,
+            Err(e) => {
+                log::debug!("unable to read terminal settings: {}", e);
+                return Ok(());
+            }
+        };
+        if self.baud_rate == Some(baud_rate) {
+            return Ok(());
+        }
+        // Whatever is still queued was sent at the old speed
+        self.to_device.write_to(&mut self.device)?;
+        match self.device.set_baud_rate(baud_rate) {
+            Ok(()) => {
+                self.stats.baud_changes += 1;
+                self.capture_settings();
+                self.log_line(&format!("baud rate {baud_rate}"));
+            }
+            Err(e) => {
+                log::warn!("unable to set baud rate {}: {}", baud_rate, e);
+                self.log_line(&format!("baud rate {baud_rate} failed: {e}"));
+            }
+        }
+        self.baud_rate = Some(baud_rate);
+        Ok(())
+    }
+
+    fn log_data(&mut self, direction: Direction, data: &[u8]) {
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(e) = capture.write_data(direction, data) {
+                log::warn!("stopped capturing: {}", e);
+                self.capture = None;
+            }
+        }
+        if self.log.is_some() {
+            let entry = format_entry(self.started.elapsed(), direction, data);
+            self.write_log(&entry);
+        }
+    }
+
+    fn log_line(&mut self, text: &str) {
+        if self.log.is_some() {
+            let elapsed = self.started.elapsed();
+            let entry = format!(
+                "{:>8}.{:06} = {}\n",
+                elapsed.as_secs(),
+                elapsed.subsec_micros(),
+                text
+            );
+            self.write_log(&entry);
+        }
+    }
+
+    fn capture_settings(&mut self) {
+        let settings = match Settings::read_from(&self.device) {
+            Ok(settings) => settings,
+            Err(e) => {
+                log::debug!("unable to read device settings: {}", e);
+                return;
+            }
+        };
+        if let Some(capture) = self.capture.as_mut() {
+            if let Err(e) = capture.write_settings(&settings) {
+                log::warn!("stopped capturing: {}", e);
+                self.capture = None;
+            }
+        }
+    }
+
+    fn write_log(&mut self, entry: &str) {
+        if let Some(log) = self.log.as_mut() {
+            if let Err(e) = log.write_all(entry.as_bytes()).and_then(|()| log.flush()) {
+                log::warn!("stopped logging: {}", e);
+                self.log = None;
+            }
+        }
+    }
+}
+
+impl<S> SerialTee<S> {
+    fn remove_link(&mut self) {
+        if let Some(link) = self.link.take() {
+            let _ = std::fs::remove_file(link);
+        }
+    }
+}
+
+impl<S> Drop for SerialTee<S> {
+    fn drop(&mut self) {
+        self.remove_link();
+    }
+}
@@ -0,0 +1,177 @@
+//! State shared by the XMODEM and ZMODEM file transfers
+//!
+//! Both protocols drive the same kind of [`Link`]: bytes read from the stream are collected
+//! for the protocol to parse, replies are queued, and each wait for the other side is timed
+//! and retried a limited number of times.
+use crate::buffered::WriteQueue;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// Cancel byte, the same for both protocols (ZMODEM calls it `ZDLE`)
+const CAN: u8 = 0x18;
+
+/// Progress of a transfer, passed to the progress callback after each block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress<'a> {
+    /// Index of the current file within the batch
+    pub file_index: usize,
+    /// Name of the current file, if known
+    pub file_name: &'a str,
+    /// Bytes of the current file transferred so far
+    pub bytes: u64,
+    /// Size of the current file, if known
+    pub total: Option<u64>,
+}
+
+pub(crate) type ProgressCallback = Box<dyn FnMut(&Progress<'_>) + Send>;
+
+/// The failures a [`Link`] reports on its own
+pub(crate) trait LinkError: fmt::Debug {
+    /// The other side sent a run of `CAN`
+    fn cancelled() -> Self;
+    /// The other side did not answer after all retries
+    fn too_many_retries() -> Self;
+    /// Returns `true` for the error made by [`cancelled`](Self::cancelled)
+    fn is_cancelled(&self) -> bool;
+}
+
+/// State shared by the sender and receiver: the stream, queued output and timing
+pub(crate) struct Link<S, E> {
+    pub(crate) inner: S,
+    pub(crate) write_queue: WriteQueue,
+    pub(crate) rx: Vec<u8>,
+    pub(crate) timeout: Duration,
+    pub(crate) retries: u32,
+    pub(crate) attempts: u32,
+    pub(crate) deadline: Option<Instant>,
+    pub(crate) finished: bool,
+    pub(crate) result: Option<Result<(), E>>,
+    pub(crate) progress: Option<ProgressCallback>,
+    /// Length of the `CAN` run that cancels the transfer, if [`fill`](Self::fill) watches
+    cancel_length: Option<usize>,
+    cancels: usize,
+}
+
+impl<S: Read + Write, E: LinkError> Link<S, E> {
+    pub(crate) fn new(inner: S, timeout: Duration, retries: u32) -> Self {
+        Self {
+            inner,
+            write_queue: WriteQueue::new(),
+            rx: Vec::new(),
+            timeout,
+            retries,
+            attempts: 0,
+            deadline: None,
+            finished: false,
+            result: None,
+            progress: None,
+            cancel_length: None,
+            cancels: 0,
+        }
+    }
+
+    /// Fail the transfer when `length` `CAN` bytes arrive in a row
+    pub(crate) fn with_cancel_length(mut self, length: usize) -> Self {
+        self.cancel_length = Some(length);
+        self
+    }
+
+    pub(crate) fn send(&mut self, data: &[u8]) {
+        self.write_queue.push(data);
+    }
+
+    /// Read what is available
+    pub(crate) fn fill(&mut self) -> io::Result<()> {
+        let limit = self.cancel_length.unwrap_or(usize::MAX);
+        let mut buf = [0u8; 4096];
+        while self.cancels < limit {
+            match self.inner.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if self.cancel_length.is_some() {
+                        for &byte in &buf[..n] {
+                            if self.cancels < limit {
+                                self.cancels = if byte == CAN { self.cancels + 1 } else { 0 };
+                            }
+                        }
+                    }
+                    self.rx.extend_from_slice(&buf[..n]);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if self.cancels >= limit && !self.finished {
+            self.fail(E::cancelled());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.write_queue.write_to(&mut self.inner).map(|_| ())
+    }
+
+    /// Start waiting for a response, counting a retry unless `fresh`
+    pub(crate) fn arm(&mut self, now: Instant, fresh: bool) -> bool {
+        if fresh {
+            self.attempts = 0;
+        } else {
+            self.attempts += 1;
+            if self.attempts > self.retries {
+                self.fail(E::too_many_retries());
+                return false;
+            }
+        }
+        self.deadline = Some(now + self.timeout);
+        true
+    }
+
+    pub(crate) fn expired(&self, now: Instant) -> bool {
+        self.deadline.is_some_and(|d| now >= d)
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.deadline = None;
+        self.finished = true;
+        self.result = Some(Ok(()));
+    }
+
+    pub(crate) fn fail(&mut self, e: E) {
+        if !e.is_cancelled() {
+            self.cancel_remote();
+        }
+        self.deadline = None;
+        self.finished = true;
+        self.result = Some(Err(e));
+    }
+
+    pub(crate) fn cancel_remote(&mut self) {
+        self.rx.clear();
+        self.write_queue.clear();
+        self.send(&[CAN; 8]);
+        self.send(&[0x08; 8]);
+    }
+
+    pub(crate) fn report(&mut self, progress: Progress<'_>) {
+        if let Some(ref mut callback) = self.progress {
+            callback(&progress);
+        }
+    }
+}
+
+impl<S, E: fmt::Debug> fmt::Debug for Link<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Link")
+            .field("rx", &self.rx.len())
+            .field("write_queue", &self.write_queue.len())
+            .field("timeout", &self.timeout)
+            .field("retries", &self.retries)
+            .field("attempts", &self.attempts)
+            .field("deadline", &self.deadline)
+            .field("finished", &self.finished)
+            .field("result", &self.result)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,780 @@
+//! u-blox UBX protocol mixed with NMEA over a [`SerialStream`]
+//!
+//! u-blox GNSS receivers interleave binary UBX frames with NMEA sentences on the same
+//! port.  [`UbxCodec`] tells them apart and returns each as a [`Packet`].  A UBX frame is
+//!
+//! ```text
+//! 0xB5 0x62 class id length(u16 LE) payload checksum(CK_A CK_B)
+//! ```
+//!
+//! where the 8-bit Fletcher checksum covers class through payload.  [`Message::decode`]
+//! interprets NAV-PVT, ACK-ACK/ACK-NAK and MON-VER.
+//!
+//! [`UbxReceiver`] sends configuration (CFG) messages and matches them with the receiver's
+//! ACK or NAK.  Like the other protocol engines in this crate it never blocks: call
+//! [`UbxReceiver::check_timeouts`] when the deadline from [`UbxReceiver::next_deadline`]
+//! expires and collect results with [`UbxReceiver::poll_completion`].
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::ubx::{Message, Packet, UbxFrame, UbxReceiver};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! let stream = mio_serial::new("/dev/ttyACM0", 9600).open_native_async().unwrap();
+//! let mut receiver = UbxReceiver::new(stream);
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut receiver, Token(0), Interest::READABLE)
+//!     .unwrap();
+//!
+//! // CFG-RATE: one measurement every 200ms
+//! let rate = UbxFrame::new(0x06, 0x08, vec![200, 0, 1, 0, 1, 0]);
+//! receiver.send_cfg(rate).unwrap();
+//!
+//! loop {
+//!     let timeout = receiver
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//!     while let Some(packet) = receiver.read_packet().unwrap() {
+//!         if let Packet::Ubx(frame) = packet {
+//!             if let Ok(Message::NavPvt(pvt)) = Message::decode(&frame) {
+//!                 println!("{} {}", pvt.latitude(), pvt.longitude());
+//!             }
+//!         }
+//!     }
+//!     receiver.check_timeouts(Instant::now());
+//!     while let Some(completion) = receiver.poll_completion() {
+//!         println!("{:?}", completion.result);
+//!     }
+//! }
+//! ```
+use crate::codec::{Decoder, Encoder, FramedSerial};
+use crate::nmea::{NmeaError, RawSentence, DEFAULT_MAX_SENTENCE_LENGTH};
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+/// First UBX sync character
+pub const SYNC_1: u8 = 0xB5;
+/// Second UBX sync character
+pub const SYNC_2: u8 = 0x62;
+
+/// Message class: navigation results
+pub const CLASS_NAV: u8 = 0x01;
+/// Message class: acknowledgements
+pub const CLASS_ACK: u8 = 0x05;
+/// Message class: configuration
+pub const CLASS_CFG: u8 = 0x06;
+/// Message class: monitoring
+pub const CLASS_MON: u8 = 0x0A;
+
+/// NAV-PVT message id
+pub const NAV_PVT: u8 = 0x07;
+/// ACK-NAK message id
+pub const ACK_NAK: u8 = 0x00;
+/// ACK-ACK message id
+pub const ACK_ACK: u8 = 0x01;
+/// MON-VER message id
+pub const MON_VER: u8 = 0x04;
+
+/// Default largest UBX payload accepted by [`UbxCodec`]
+pub const DEFAULT_MAX_PAYLOAD_LENGTH: usize = 8 * 1024;
+
+/// Default time to wait for the ACK of a CFG message
+pub const DEFAULT_ACK_TIMEOUT: Duration = Duration::from_secs(1);
+
+const HEADER_LENGTH: usize = 6;
+const NAV_PVT_LENGTH: usize = 92;
+
+/// The 8-bit Fletcher checksum used by UBX
+pub fn fletcher(data: &[u8]) -> [u8; 2] {
+    let (a, b) = data.iter().fold((0u8, 0u8), |(a, b), &x| {
+        let a = a.wrapping_add(x);
+        (a, b.wrapping_add(a))
+    });
+    [a, b]
+}
+
+/// Errors reported by the UBX codec and receiver
+#[derive(Debug)]
+pub enum UbxError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// A UBX frame failed its checksum
+    BadChecksum,
+    /// A UBX frame announced a payload longer than the configured maximum
+    PayloadTooLong(usize),
+    /// An NMEA sentence on the same stream was invalid
+    Nmea(NmeaError),
+    /// A UBX payload is too short or malformed for its message type
+    InvalidPayload(&'static str),
+    /// The receiver rejected a CFG message
+    Nak,
+    /// No ACK arrived for a CFG message in time
+    Timeout,
+}
+
+impl UbxError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            UbxError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for UbxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UbxError::Io(e) => write!(f, "{e}"),
+            UbxError::BadChecksum => write!(f, "UBX checksum mismatch"),
+            UbxError::PayloadTooLong(len) => write!(f, "UBX payload of {len} bytes is too long"),
+            UbxError::Nmea(e) => write!(f, "{e}"),
+            UbxError::InvalidPayload(why) => write!(f, "invalid UBX payload: {why}"),
+            UbxError::Nak => write!(f, "message rejected by receiver"),
+            UbxError::Timeout => write!(f, "no acknowledgement from receiver"),
+        }
+    }
+}
+
+impl std::error::Error for UbxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UbxError::Io(e) => Some(e),
+            UbxError::Nmea(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for UbxError {
+    fn from(e: io::Error) -> Self {
+        UbxError::Io(e)
+    }
+}
+
+impl From<NmeaError> for UbxError {
+    fn from(e: NmeaError) -> Self {
+        match e {
+            NmeaError::Io(e) => UbxError::Io(e),
+            e => UbxError::Nmea(e),
+        }
+    }
+}
+
+/// A UBX frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UbxFrame {
+    /// Message class
+    pub class: u8,
+    /// Message id within the class
+    pub id: u8,
+    /// Message payload
+    pub payload: Vec<u8>,
+}
+
+impl UbxFrame {
+    /// Create a frame
+    pub fn new(class: u8, id: u8, payload: Vec<u8>) -> Self {
+        Self { class, id, payload }
+    }
+
+    /// A poll request: the message with an empty payload, answered with the current value
+    pub fn poll(class: u8, id: u8) -> Self {
+        Self::new(class, id, Vec::new())
+    }
+
+    /// Append the encoded frame to `dst`
+    pub fn encode(&self, dst: &mut Vec<u8>) {
+        dst.extend_from_slice(&[SYNC_1, SYNC_2]);
+        let start = dst.len();
+        dst.extend_from_slice(&[self.class, self.id]);
+        dst.extend_from_slice(&(self.payload.len() as u16).to_le_bytes());
+        dst.extend_from_slice(&self.payload);
+        let checksum = fletcher(&dst[start..]);
+        dst.extend_from_slice(&checksum);
+    }
+}
+
+/// One item from a mixed UBX and NMEA stream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    /// A UBX frame with a valid checksum
+    Ubx(UbxFrame),
+    /// An NMEA sentence
+    Nmea(RawSentence),
+}
+
+/// Separates UBX frames from NMEA sentences
+///
+/// Bytes belonging to neither are skipped.  After a checksum failure the codec hunts for
+/// the next sync sequence from the byte after the bad frame's sync characters.
+#[derive(Debug, Clone)]
+pub struct UbxCodec {
+    max_payload_length: usize,
+    max_sentence_length: usize,
+}
+
+impl UbxCodec {
+    /// Create a codec with the default limits
+    pub fn new() -> Self {
+        Self {
+            max_payload_length: DEFAULT_MAX_PAYLOAD_LENGTH,
+            max_sentence_length: DEFAULT_MAX_SENTENCE_LENGTH,
+        }
+    }
+
+    /// Set the largest accepted UBX payload
+    #[must_use]
+    pub fn with_max_payload_length(mut self, max_payload_length: usize) -> Self {
+        self.max_payload_length = max_payload_length;
+        self
+    }
+
+    /// Set the longest accepted NMEA sentence
+    #[must_use]
+    pub fn with_max_sentence_length(mut self, max_sentence_length: usize) -> Self {
+        self.max_sentence_length = max_sentence_length;
+        self
+    }
+
+    fn decode_ubx(&self, src: &mut Vec<u8>) -> Result<Option<Packet>, UbxError> {
+        if src.len() < HEADER_LENGTH {
+            return Ok(None);
+        }
+        let len = usize::from(u16::from_le_bytes([src[4], src[5]]));
+        if len > self.max_payload_length {
+            src.drain(..1);
+            return Err(UbxError::PayloadTooLong(len));
+        }
+        let frame_len = HEADER_LENGTH + len + 2;
+        if src.len() < frame_len {
+            return Ok(None);
+        }
+        if fletcher(&src[2..HEADER_LENGTH + len]) != src[HEADER_LENGTH + len..frame_len] {
+            src.drain(..1);
+            return Err(UbxError::BadChecksum);
+        }
+        let frame = UbxFrame {
+            class: src[2],
+            id: src[3],
+            payload: src[HEADER_LENGTH..HEADER_LENGTH + len].to_vec(),
+        };
+        src.drain(..frame_len);
+        Ok(Some(Packet::Ubx(frame)))
+    }
+
+    fn decode_nmea(&self, src: &mut Vec<u8>) -> Result<Option<Packet>, UbxError> {
+        let limit = src.len().min(self.max_sentence_length);
+        let end = match src[..limit].iter().position(|&b| b == b'\n') {
+            Some(end) => end,
+            None if src.len() >= self.max_sentence_length => {
+                src.drain(..1);
+                return Err(NmeaError::SentenceTooLong(self.max_sentence_length).into());
+            }
+            None => {
+                // A UBX frame starting mid-sentence means the sentence was cut short
+                if let Some(restart) = find_start(&src[1..]) {
+                    src.drain(..=restart);
+                    return Err(NmeaError::InvalidSentence("truncated sentence").into());
+                }
+                return Ok(None);
+            }
+        };
+        if let Some(restart) = find_start(&src[1..end]) {
+            src.drain(..=restart);
+            return Err(NmeaError::InvalidSentence("truncated sentence").into());
+        }
+
+        let line: Vec<u8> = src.drain(..=end).collect();
+        let line = line
+            .strip_suffix(b"\r\n")
+            .or_else(|| line.strip_suffix(b"\n"))
+            .unwrap_or(&line);
+        Ok(Some(Packet::Nmea(RawSentence::parse(line, false)?)))
+    }
+}
+
+impl Default for UbxCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Index of the first UBX sync sequence or NMEA start character in `buf`
+fn find_start(buf: &[u8]) -> Option<usize> {
+    buf.iter().enumerate().position(|(i, &b)| match b {
+        b'$' | b'!' => true,
+        SYNC_1 => !matches!(buf.get(i + 1), Some(&next) if next != SYNC_2),
+        _ => false,
+    })
+}
+
+impl Decoder for UbxCodec {
+    type Item = Packet;
+    type Error = UbxError;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> Result<Option<Packet>, UbxError> {
+        match find_start(src) {
+            Some(start) => {
+                src.drain(..start);
+            }
+            None => {
+                src.clear();
+                return Ok(None);
+            }
+        }
+        match src[0] {
+            SYNC_1 if src.len() < 2 => Ok(None),
+            SYNC_1 => self.decode_ubx(src),
+            _ => self.decode_nmea(src),
+        }
+    }
+}
+
+impl Encoder<&UbxFrame> for UbxCodec {
+    type Error = UbxError;
+
+    fn encode(&mut self, frame: &UbxFrame, dst: &mut Vec<u8>) -> Result<(), UbxError> {
+        frame.encode(dst);
+        Ok(())
+    }
+}
+
+impl Encoder<&RawSentence> for UbxCodec {
+    type Error = UbxError;
+
+    fn encode(&mut self, sentence: &RawSentence, dst: &mut Vec<u8>) -> Result<(), UbxError> {
+        sentence.encode(dst);
+        Ok(())
+    }
+}
+
+/// NAV-PVT: navigation position, velocity and time solution
+///
+/// Field names and units follow the u-blox interface description.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NavPvt {
+    /// GPS time of week in milliseconds
+    pub itow: u32,
+    /// UTC year
+    pub year: u16,
+    /// UTC month, 1 to 12
+    pub month: u8,
+    /// UTC day of month, 1 to 31
+    pub day: u8,
+    /// UTC hour, 0 to 23
+    pub hour: u8,
+    /// UTC minute, 0 to 59
+    pub min: u8,
+    /// UTC second, 0 to 60
+    pub sec: u8,
+    /// Validity flags for date and time
+    pub valid: u8,
+    /// Time accuracy estimate in nanoseconds
+    pub t_acc: u32,
+    /// Fraction of a second in nanoseconds, may be negative
+    pub nano: i32,
+    /// 0 no fix, 1 dead reckoning, 2 2D, 3 3D, 4 GNSS and dead reckoning, 5 time only
+    pub fix_type: u8,
+    /// Fix status flags; bit 0 is `gnssFixOK`
+    pub flags: u8,
+    /// Additional flags
+    pub flags2: u8,
+    /// Number of satellites used
+    pub num_sv: u8,
+    /// Longitude in 1e-7 degrees
+    pub lon: i32,
+    /// Latitude in 1e-7 degrees
+    pub lat: i32,
+    /// Height above ellipsoid in mm
+    pub height: i32,
+    /// Height above mean sea level in mm
+    pub h_msl: i32,
+    /// Horizontal accuracy estimate in mm
+    pub h_acc: u32,
+    /// Vertical accuracy estimate in mm
+    pub v_acc: u32,
+    /// North velocity in mm/s
+    pub vel_n: i32,
+    /// East velocity in mm/s
+    pub vel_e: i32,
+    /// Down velocity in mm/s
+    pub vel_d: i32,
+    /// Ground speed in mm/s
+    pub g_speed: i32,
+    /// Heading of motion in 1e-5 degrees
+    pub head_mot: i32,
+    /// Speed accuracy estimate in mm/s
+    pub s_acc: u32,
+    /// Heading accuracy estimate in 1e-5 degrees
+    pub head_acc: u32,
+    /// Position dilution of precision in 0.01 units
+    pub p_dop: u16,
+    /// Heading of vehicle in 1e-5 degrees
+    pub head_veh: i32,
+}
+
+impl NavPvt {
+    /// Decode a NAV-PVT payload
+    pub fn decode(payload: &[u8]) -> Result<Self, UbxError> {
+        if payload.len() < NAV_PVT_LENGTH {
+            return Err(UbxError::InvalidPayload("NAV-PVT payload too short"));
+        }
+        let u16_at = |i: usize| u16::from_le_bytes([payload[i], payload[i + 1]]);
+        let u32_at = |i: usize| {
+            u32::from_le_bytes([payload[i], payload[i + 1], payload[i + 2], payload[i + 3]])
+        };
+        let i32_at = |i: usize| u32_at(i) as i32;
+        Ok(Self {
+            itow: u32_at(0),
+            year: u16_at(4),
+            month: payload[6],
+            day: payload[7],
+            hour: payload[8],
+            min: payload[9],
+            sec: payload[10],
+            valid: payload[11],
+            t_acc: u32_at(12),
+            nano: i32_at(16),
+            fix_type: payload[20],
+            flags: payload[21],
+            flags2: payload[22],
+            num_sv: payload[23],
+            lon: i32_at(24),
+            lat: i32_at(28),
+            height: i32_at(32),
+            h_msl: i32_at(36),
+            h_acc: u32_at(40),
+            v_acc: u32_at(44),
+            vel_n: i32_at(48),
+            vel_e: i32_at(52),
+            vel_d: i32_at(56),
+            g_speed: i32_at(60),
+            head_mot: i32_at(64),
+            s_acc: u32_at(68),
+            head_acc: u32_at(72),
+            p_dop: u16_at(76),
+            head_veh: i32_at(84),
+        })
+    }
+
+    /// Latitude in degrees
+    pub fn latitude(&self) -> f64 {
+        f64::from(self.lat) * 1e-7
+    }
+
+    /// Longitude in degrees
+    pub fn longitude(&self) -> f64 {
+        f64::from(self.lon) * 1e-7
+    }
+
+    /// Returns `true` if the receiver reports a valid fix (`gnssFixOK`)
+    pub fn fix_ok(&self) -> bool {
+        self.flags & 0x01 != 0
+    }
+}
+
+/// MON-VER: receiver and software version
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MonVer {
+    /// Software version
+    pub sw_version: String,
+    /// Hardware version
+    pub hw_version: String,
+    /// Extended version strings, e.g. `"PROTVER=18.00"`
+    pub extensions: Vec<String>,
+}
+
+impl MonVer {
+    /// Decode a MON-VER payload
+    pub fn decode(payload: &[u8]) -> Result<Self, UbxError> {
+        if payload.len() < 40 || !payload[40..].chunks_exact(30).remainder().is_empty() {
+            return Err(UbxError::InvalidPayload(
+                "MON-VER payload has the wrong length",
+            ));
+        }
+        let text = |bytes: &[u8]| {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            String::from_utf8_lossy(&bytes[..end]).into_owned()
+        };
+        Ok(Self {
+            sw_version: text(&payload[..30]),
+            hw_version: text(&payload[30..40]),
+            extensions: payload[40..].chunks(30).map(text).collect(),
+        })
+    }
+}
+
+/// A decoded UBX message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// NAV-PVT
+    NavPvt(NavPvt),
+    /// ACK-ACK for the message with the given class and id
+    Ack {
+        /// Class of the acknowledged message
+        class: u8,
+        /// Id of the acknowledged message
+        id: u8,
+    },
+    /// ACK-NAK for the message with the given class and id
+    Nak {
+        /// Class of the rejected message
+        class: u8,
+        /// Id of the rejected message
+        id: u8,
+    },
+    /// MON-VER
+    MonVer(MonVer),
+    /// Any other message
+    Other(UbxFrame),
+}
+
+impl Message {
+    /// Decode `frame`, returning [`Message::Other`] for unsupported messages
+    pub fn decode(frame: &UbxFrame) -> Result<Message, UbxError> {
+        match (frame.class, frame.id) {
+            (CLASS_NAV, NAV_PVT) => NavPvt::decode(&frame.payload).map(Message::NavPvt),
+            (CLASS_ACK, ACK_ACK) | (CLASS_ACK, ACK_NAK) => {
+                if frame.payload.len() != 2 {
+                    return Err(UbxError::InvalidPayload("ACK payload has the wrong length"));
+                }
+                let (class, id) = (frame.payload[0], frame.payload[1]);
+                Ok(if frame.id == ACK_ACK {
+                    Message::Ack { class, id }
+                } else {
+                    Message::Nak { class, id }
+                })
+            }
+            (CLASS_MON, MON_VER) => MonVer::decode(&frame.payload).map(Message::MonVer),
+            _ => Ok(Message::Other(frame.clone())),
+        }
+    }
+}
+
+/// Identifies a CFG message sent with [`UbxReceiver::send_cfg`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CommandId(u64);
+
+/// The outcome of a CFG message
+#[derive(Debug)]
+pub struct CfgCompletion {
+    /// The id returned by [`UbxReceiver::send_cfg`]
+    pub id: CommandId,
+    /// Class of the CFG message
+    pub class: u8,
+    /// Id of the CFG message
+    pub msg_id: u8,
+    /// `Ok` on ACK-ACK, [`UbxError::Nak`] or [`UbxError::Timeout`] otherwise
+    pub result: Result<(), UbxError>,
+}
+
+#[derive(Debug)]
+struct PendingCfg {
+    id: CommandId,
+    class: u8,
+    msg_id: u8,
+    deadline: Instant,
+}
+
+/// A u-blox receiver on a non-blocking stream
+///
+/// Reads UBX frames and NMEA sentences with [`read_packet`](Self::read_packet).  ACK and
+/// NAK frames answering a message sent with [`send_cfg`](Self::send_cfg) are consumed and
+/// reported through [`poll_completion`](Self::poll_completion) instead.
+#[derive(Debug)]
+pub struct UbxReceiver<S = SerialStream> {
+    framed: FramedSerial<UbxCodec, S>,
+    ack_timeout: Duration,
+    pending: VecDeque<PendingCfg>,
+    completions: VecDeque<CfgCompletion>,
+    next_id: u64,
+}
+
+impl<S> UbxReceiver<S>
+where
+    S: Read + Write + Source,
+{
+    /// Wrap `inner` using a default [`UbxCodec`]
+    pub fn new(inner: S) -> Self {
+        Self::with_codec(inner, UbxCodec::new())
+    }
+
+    /// Wrap `inner` using `codec`
+    pub fn with_codec(inner: S, codec: UbxCodec) -> Self {
+        Self {
+            framed: FramedSerial::new(inner, codec),
+            ack_timeout: DEFAULT_ACK_TIMEOUT,
+            pending: VecDeque::new(),
+            completions: VecDeque::new(),
+            next_id: 0,
+        }
+    }
+
+    /// Set how long to wait for the ACK of a CFG message
+    #[must_use]
+    pub fn with_ack_timeout(mut self, timeout: Duration) -> Self {
+        self.ack_timeout = timeout;
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        self.framed.get_ref()
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        self.framed.get_mut()
+    }
+
+    /// Consume the receiver, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.framed.into_inner()
+    }
+
+    /// Number of CFG messages waiting for an ACK
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Queue `frame` for transmission without waiting for an acknowledgement
+    pub fn send(&mut self, frame: &UbxFrame) -> Result<(), UbxError> {
+        self.framed.send(frame).map(|_| ())
+    }
+
+    /// Queue an NMEA sentence, e.g. a `PUBX` command, for transmission
+    pub fn send_nmea(&mut self, sentence: &RawSentence) -> Result<(), UbxError> {
+        self.framed.send(sentence).map(|_| ())
+    }
+
+    /// Queue a CFG message and track its acknowledgement
+    ///
+    /// The receiver answers with ACK-ACK or ACK-NAK naming the message's class and id.
+    /// Several messages with the same class and id are matched in the order they were sent.
+    pub fn send_cfg(&mut self, frame: UbxFrame) -> Result<CommandId, UbxError> {
+        self.framed.send(&frame)?;
+        let id = CommandId(self.next_id);
+        self.next_id += 1;
+        self.pending.push_back(PendingCfg {
+            id,
+            class: frame.class,
+            msg_id: frame.id,
+            deadline: Instant::now() + self.ack_timeout,
+        });
+        Ok(id)
+    }
+
+    /// Return the next UBX frame or NMEA sentence
+    ///
+    /// Returns `Ok(None)` when the caller should wait for the next readable event.
+    ///
+    /// ## Errors
+    ///
+    /// Checksum and framing errors affect a single packet; reading can continue.  I/O
+    /// errors are returned as [`UbxError::Io`].
+    pub fn read_packet(&mut self) -> Result<Option<Packet>, UbxError> {
+        loop {
+            let packet = match self.framed.read_frame()? {
+                Some(packet) => packet,
+                None => return Ok(None),
+            };
+            if let Packet::Ubx(ref frame) = packet {
+                if frame.class == CLASS_ACK && self.on_ack(frame) {
+                    continue;
+                }
+            }
+            return Ok(Some(packet));
+        }
+    }
+
+    fn on_ack(&mut self, frame: &UbxFrame) -> bool {
+        let (class, msg_id, acked) = match Message::decode(frame) {
+            Ok(Message::Ack { class, id }) => (class, id, true),
+            Ok(Message::Nak { class, id }) => (class, id, false),
+            _ => return false,
+        };
+        let index = match self
+            .pending
+            .iter()
+            .position(|p| p.class == class && p.msg_id == msg_id)
+        {
+            Some(index) => index,
+            None => return false,
+        };
+        let pending = self.pending.remove(index).unwrap();
+        self.completions.push_back(CfgCompletion {
+            id: pending.id,
+            class,
+            msg_id,
+            result: if acked { Ok(()) } else { Err(UbxError::Nak) },
+        });
+        true
+    }
+
+    /// Fail CFG messages whose acknowledgement is overdue
+    pub fn check_timeouts(&mut self, now: Instant) {
+        while let Some(index) = self.pending.iter().position(|p| now >= p.deadline) {
+            let pending = self.pending.remove(index).unwrap();
+            self.completions.push_back(CfgCompletion {
+                id: pending.id,
+                class: pending.class,
+                msg_id: pending.msg_id,
+                result: Err(UbxError::Timeout),
+            });
+        }
+    }
+
+    /// The earliest ACK deadline, if any CFG message is waiting
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.iter().map(|p| p.deadline).min()
+    }
+
+    /// Take the next finished CFG message
+    pub fn poll_completion(&mut self) -> Option<CfgCompletion> {
+        self.completions.pop_front()
+    }
+
+    /// Write out queued messages after a writable event
+    pub fn on_writable(&mut self) -> io::Result<usize> {
+        self.framed.on_writable()
+    }
+}
+
+impl<S> Source for UbxReceiver<S>
+where
+    S: Read + Write + Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.framed.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.framed.deregister(registry)
+    }
+}
@@ -0,0 +1,889 @@
+//! XMODEM, XMODEM-1K and YMODEM file transfer
+//!
+//! [`Sender`] and [`Receiver`] are non-blocking state machines over a serial stream.  Call
+//! `handle_io` on every event for the stream's token and whenever the deadline from
+//! `next_deadline` expires, until `poll_result` returns the outcome.
+//!
+//! Supported are:
+//!
+//! * 8-bit checksum and CRC-16 ([`Checksum`]); the receiver picks by starting with `NAK`
+//!   or `C`, and falls back from CRC to checksum if an XMODEM sender does not respond.
+//! * 128-byte (`SOH`) and 1024-byte (`STX`) blocks; a receiver accepts either.
+//! * YMODEM batches: block 0 carries the file name and size, and an empty block 0 ends
+//!   the batch.  Received YMODEM files are truncated to their announced size; XMODEM
+//!   files keep the `SUB` (`0x1A`) padding of the last block.
+//! * Retries with per-block timeouts, `CAN CAN` aborts from either side, and progress
+//!   callbacks.
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::xmodem::{Sender, TransferFile, Variant};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::time::Instant;
+//!
+//! let stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let firmware = TransferFile::new("firmware.bin", std::fs::read("firmware.bin").unwrap());
+//! let mut sender = Sender::new(stream, Variant::Xmodem1k, vec![firmware])
+//!     .with_progress(|p| println!("{} of {:?} bytes", p.bytes, p.total));
+//!
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut sender, Token(0), Interest::READABLE | Interest::WRITABLE)
+//!     .unwrap();
+//! let result = loop {
+//!     let timeout = sender
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//!     sender.handle_io(Instant::now()).unwrap();
+//!     if let Some(result) = sender.poll_result() {
+//!         break result;
+//!     }
+//! };
+//! println!("transfer finished: {:?}", result);
+//! ```
+use crate::crc::CRC_16_XMODEM;
+use crate::transfer::{Link, LinkError};
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+pub use crate::transfer::Progress;
+
+/// Start of a 128-byte block
+pub const SOH: u8 = 0x01;
+/// Start of a 1024-byte block
+pub const STX: u8 = 0x02;
+/// End of transmission
+pub const EOT: u8 = 0x04;
+/// Positive acknowledgement
+pub const ACK: u8 = 0x06;
+/// Negative acknowledgement; also requests checksum mode at the start
+pub const NAK: u8 = 0x15;
+/// Cancel; two in a row abort the transfer
+pub const CAN: u8 = 0x18;
+/// Padding for the last block
+pub const SUB: u8 = 0x1A;
+/// Requests CRC-16 mode at the start
+pub const CRC_REQUEST: u8 = b'C';
+
+/// Default time to wait for a response before retrying
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default number of retries per block
+pub const DEFAULT_RETRIES: u32 = 10;
+
+/// Protocol variant
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// XMODEM with 128-byte blocks
+    Xmodem,
+    /// XMODEM with 1024-byte blocks
+    Xmodem1k,
+    /// YMODEM batch transfer with 1024-byte blocks
+    Ymodem,
+}
+
+impl Variant {
+    fn block_size(self) -> usize {
+        match self {
+            Variant::Xmodem => 128,
+            Variant::Xmodem1k | Variant::Ymodem => 1024,
+        }
+    }
+}
+
+/// Block check
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// 8-bit arithmetic sum
+    Sum,
+    /// CRC-16/XMODEM
+    Crc16,
+}
+
+impl Checksum {
+    fn len(self) -> usize {
+        match self {
+            Checksum::Sum => 1,
+            Checksum::Crc16 => 2,
+        }
+    }
+
+    fn append(self, data: &[u8], dst: &mut Vec<u8>) {
+        match self {
+            Checksum::Sum => dst.push(data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))),
+            Checksum::Crc16 => dst.extend_from_slice(&CRC_16_XMODEM.checksum(data).to_be_bytes()),
+        }
+    }
+}
+
+/// Why a transfer failed
+#[derive(Debug)]
+pub enum XmodemError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The other side cancelled the transfer
+    Cancelled,
+    /// The transfer was cancelled locally with `cancel`
+    Aborted,
+    /// A block or the start of the transfer failed after all retries
+    TooManyRetries,
+    /// A block arrived out of sequence, so data was lost
+    OutOfSequence(u8),
+    /// A YMODEM header block could not be parsed
+    InvalidHeader(&'static str),
+}
+
+impl XmodemError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            XmodemError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl LinkError for XmodemError {
+    fn cancelled() -> Self {
+        XmodemError::Cancelled
+    }
+
+    fn too_many_retries() -> Self {
+        XmodemError::TooManyRetries
+    }
+
+    fn is_cancelled(&self) -> bool {
+        matches!(self, XmodemError::Cancelled)
+    }
+}
+
+impl fmt::Display for XmodemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XmodemError::Io(e) => write!(f, "{e}"),
+            XmodemError::Cancelled => write!(f, "transfer cancelled by remote"),
+            XmodemError::Aborted => write!(f, "transfer aborted"),
+            XmodemError::TooManyRetries => write!(f, "too many retries"),
+            XmodemError::OutOfSequence(seq) => write!(f, "block {seq} out of sequence"),
+            XmodemError::InvalidHeader(why) => write!(f, "invalid YMODEM header: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for XmodemError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            XmodemError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for XmodemError {
+    fn from(e: io::Error) -> Self {
+        XmodemError::Io(e)
+    }
+}
+
+/// A file to send or a file received
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferFile {
+    /// File name; sent in the YMODEM header, empty for files received over XMODEM
+    pub name: String,
+    /// File contents
+    pub data: Vec<u8>,
+}
+
+impl TransferFile {
+    /// Create a file
+    pub fn new(name: impl Into<String>, data: Vec<u8>) -> Self {
+        Self {
+            name: name.into(),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    /// Waiting for `C` or `NAK` before the first block (or YMODEM header)
+    WaitStart,
+    /// YMODEM: header sent, waiting for `ACK`
+    WaitHeaderAck,
+    /// YMODEM: header acknowledged, waiting for `C` before the data
+    WaitDataStart,
+    /// Data block sent, waiting for `ACK`
+    WaitBlockAck,
+    /// `EOT` sent, waiting for `ACK`
+    WaitEotAck,
+    /// YMODEM: empty header sent, waiting for `ACK`
+    WaitEndAck,
+    Done,
+}
+
+/// Non-blocking XMODEM/YMODEM sender
+#[derive(Debug)]
+pub struct Sender<S = SerialStream> {
+    link: Link<S, XmodemError>,
+    variant: Variant,
+    files: Vec<TransferFile>,
+    file_index: usize,
+    state: SendState,
+    checksum: Checksum,
+    seq: u8,
+    offset: usize,
+    block: Vec<u8>,
+    block_len: usize,
+    cancels: usize,
+}
+
+impl<S> Sender<S>
+where
+    S: Read + Write + Source,
+{
+    /// Send `files`; XMODEM sends only the first one
+    pub fn new(inner: S, variant: Variant, files: Vec<TransferFile>) -> Self {
+        Self {
+            link: Link::new(inner, DEFAULT_TIMEOUT, DEFAULT_RETRIES),
+            variant,
+            files,
+            file_index: 0,
+            state: SendState::WaitStart,
+            checksum: Checksum::Crc16,
+            seq: 0,
+            offset: 0,
+            block: Vec::new(),
+            block_len: 0,
+            cancels: 0,
+        }
+    }
+
+    /// Set how long to wait for a response before retrying
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.link.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a block, or the start of the transfer, is retried
+    #[must_use]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.link.retries = retries;
+        self
+    }
+
+    /// Call `callback` after each block
+    #[must_use]
+    pub fn with_progress(mut self, callback: impl FnMut(&Progress<'_>) + Send + 'static) -> Self {
+        self.link.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.link.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.link.inner
+    }
+
+    /// Consume the sender, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.link.inner
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.deadline
+    }
+
+    /// Returns `true` once the transfer has succeeded or failed
+    pub fn is_finished(&self) -> bool {
+        self.link.finished
+    }
+
+    /// Abort the transfer, telling the other side with `CAN`
+    pub fn cancel(&mut self) -> io::Result<()> {
+        if !self.link.finished {
+            self.link.fail(XmodemError::Aborted);
+        }
+        self.link.flush()
+    }
+
+    /// The block check chosen by the receiver, once the transfer has started
+    pub fn checksum(&self) -> Option<Checksum> {
+        (self.state != SendState::WaitStart).then_some(self.checksum)
+    }
+
+    /// Take the outcome of the transfer once it has finished
+    pub fn poll_result(&mut self) -> Option<Result<(), XmodemError>> {
+        self.link.result.take()
+    }
+
+    /// Drive the transfer
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; protocol failures end the transfer
+    /// and are reported by [`poll_result`](Self::poll_result).
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.link.flush()?;
+        if self.link.deadline.is_none() && !self.link.finished {
+            self.link.arm(now, true);
+        }
+        self.link.fill()?;
+        while !self.link.finished {
+            let byte = match self.link.rx.first() {
+                Some(&byte) => byte,
+                None => break,
+            };
+            self.link.rx.remove(0);
+            self.on_byte(byte, now);
+        }
+        if !self.link.finished && self.link.expired(now) {
+            self.on_timeout(now);
+        }
+        self.link.flush()
+    }
+
+    fn on_byte(&mut self, byte: u8, now: Instant) {
+        if byte == CAN {
+            self.cancels += 1;
+            if self.cancels >= 2 {
+                self.link.fail(XmodemError::Cancelled);
+            }
+            return;
+        }
+        self.cancels = 0;
+
+        match (self.state, byte) {
+            (SendState::WaitStart, CRC_REQUEST | NAK) => {
+                self.checksum = if byte == CRC_REQUEST {
+                    Checksum::Crc16
+                } else {
+                    Checksum::Sum
+                };
+                self.start_file(now);
+            }
+            (SendState::WaitHeaderAck, ACK) => {
+                self.state = SendState::WaitDataStart;
+                self.link.arm(now, true);
+            }
+            (SendState::WaitDataStart, CRC_REQUEST | NAK) => {
+                self.seq = 1;
+                self.next_block(now);
+            }
+            (SendState::WaitBlockAck, ACK) => {
+                self.offset += self.block_len;
+                let file = &self.files[self.file_index];
+                let progress = Progress {
+                    file_index: self.file_index,
+                    file_name: &file.name,
+                    bytes: self.offset.min(file.data.len()) as u64,
+                    total: Some(file.data.len() as u64),
+                };
+                self.link.report(progress);
+                self.seq = self.seq.wrapping_add(1);
+                self.next_block(now);
+            }
+            (SendState::WaitEotAck, ACK) => {
+                self.file_index += 1;
+                if self.variant != Variant::Ymodem {
+                    self.state = SendState::Done;
+                    self.link.finish();
+                } else {
+                    self.state = SendState::WaitStart;
+                    self.link.arm(now, true);
+                }
+            }
+            (SendState::WaitEndAck, ACK) => {
+                self.state = SendState::Done;
+                self.link.finish();
+            }
+            (SendState::WaitHeaderAck | SendState::WaitBlockAck, NAK) => self.resend(now),
+            (SendState::WaitEotAck, NAK) => self.resend(now),
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&mut self, now: Instant) {
+        match self.state {
+            SendState::WaitStart | SendState::WaitDataStart => {
+                self.link.arm(now, false);
+            }
+            _ => self.resend(now),
+        }
+    }
+
+    fn resend(&mut self, now: Instant) {
+        if self.link.arm(now, false) {
+            let block = std::mem::take(&mut self.block);
+            self.link.send(&block);
+            self.block = block;
+        }
+    }
+
+    fn transmit(&mut self, block: Vec<u8>, now: Instant) {
+        self.link.send(&block);
+        self.block = block;
+        self.link.arm(now, true);
+    }
+
+    fn start_file(&mut self, now: Instant) {
+        // Further start requests queued up while we were not listening must not be taken
+        // for responses to the first block
+        self.link.rx.retain(|&b| b != CRC_REQUEST && b != NAK);
+        self.offset = 0;
+        if self.variant != Variant::Ymodem {
+            if self.files.is_empty() {
+                self.files.push(TransferFile::new("", Vec::new()));
+            }
+            self.seq = 1;
+            self.next_block(now);
+            return;
+        }
+
+        let mut header = Vec::new();
+        if let Some(file) = self.files.get(self.file_index) {
+            header.extend_from_slice(file.name.as_bytes());
+            header.push(0);
+            header.extend_from_slice(file.data.len().to_string().as_bytes());
+            header.push(0);
+            self.state = SendState::WaitHeaderAck;
+        } else {
+            self.state = SendState::WaitEndAck;
+        }
+        let size = if header.len() <= 128 { 128 } else { 1024 };
+        header.resize(size, 0);
+        let block = self.encode_block(0, &header);
+        self.transmit(block, now);
+    }
+
+    fn next_block(&mut self, now: Instant) {
+        let data = &self.files[self.file_index].data;
+        if self.offset >= data.len() {
+            self.state = SendState::WaitEotAck;
+            self.transmit(vec![EOT], now);
+            return;
+        }
+        let remaining = data.len() - self.offset;
+        let size = if self.variant.block_size() == 1024 && remaining > 128 {
+            1024
+        } else {
+            128
+        };
+        let len = remaining.min(size);
+        let mut payload = data[self.offset..self.offset + len].to_vec();
+        payload.resize(size, SUB);
+        self.block_len = len;
+        self.state = SendState::WaitBlockAck;
+        let block = self.encode_block(self.seq, &payload);
+        self.transmit(block, now);
+    }
+
+    fn encode_block(&self, seq: u8, payload: &[u8]) -> Vec<u8> {
+        let mut block = Vec::with_capacity(payload.len() + 5);
+        block.push(if payload.len() == 1024 { STX } else { SOH });
+        block.extend_from_slice(&[seq, !seq]);
+        block.extend_from_slice(payload);
+        self.checksum.append(payload, &mut block);
+        block
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReceiveState {
+    /// Sending `C` or `NAK` until the first block arrives
+    Start,
+    /// Receiving data blocks
+    Data,
+    Done,
+}
+
+/// Non-blocking XMODEM/YMODEM receiver
+#[derive(Debug)]
+pub struct Receiver<S = SerialStream> {
+    link: Link<S, XmodemError>,
+    variant: Variant,
+    state: ReceiveState,
+    checksum: Checksum,
+    crc_attempts: u32,
+    expected_seq: u8,
+    current: Option<(TransferFile, Option<u64>)>,
+    files: Vec<TransferFile>,
+    eot_seen: bool,
+    cancels: usize,
+}
+
+/// Number of `C` requests an XMODEM receiver sends before falling back to checksum mode
+const CRC_ATTEMPTS: u32 = 3;
+
+impl<S> Receiver<S>
+where
+    S: Read + Write + Source,
+{
+    /// Receive using `variant`, requesting CRC-16 mode
+    pub fn new(inner: S, variant: Variant) -> Self {
+        Self {
+            link: Link::new(inner, DEFAULT_TIMEOUT, DEFAULT_RETRIES),
+            variant,
+            state: ReceiveState::Start,
+            checksum: Checksum::Crc16,
+            crc_attempts: 0,
+            expected_seq: 1,
+            current: None,
+            files: Vec::new(),
+            eot_seen: false,
+            cancels: 0,
+        }
+    }
+
+    /// Set how long to wait for a response before retrying
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.link.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a block, or the start of the transfer, is retried
+    #[must_use]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.link.retries = retries;
+        self
+    }
+
+    /// Call `callback` after each block
+    #[must_use]
+    pub fn with_progress(mut self, callback: impl FnMut(&Progress<'_>) + Send + 'static) -> Self {
+        self.link.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.link.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.link.inner
+    }
+
+    /// Consume the receiver, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.link.inner
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.deadline
+    }
+
+    /// Returns `true` once the transfer has succeeded or failed
+    pub fn is_finished(&self) -> bool {
+        self.link.finished
+    }
+
+    /// Abort the transfer, telling the other side with `CAN`
+    pub fn cancel(&mut self) -> io::Result<()> {
+        if !self.link.finished {
+            self.link.fail(XmodemError::Aborted);
+        }
+        self.link.flush()
+    }
+
+    /// Request `checksum` instead of CRC-16 (XMODEM only; YMODEM always uses CRC-16)
+    #[must_use]
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        if self.variant != Variant::Ymodem {
+            self.checksum = checksum;
+        }
+        self
+    }
+
+    /// The block check in use
+    pub fn checksum(&self) -> Checksum {
+        self.checksum
+    }
+
+    /// Take the received files once the transfer has finished
+    pub fn poll_result(&mut self) -> Option<Result<Vec<TransferFile>, XmodemError>> {
+        let result = self.link.result.take()?;
+        Some(result.map(|()| std::mem::take(&mut self.files)))
+    }
+
+    /// Drive the transfer
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; protocol failures end the transfer
+    /// and are reported by [`poll_result`](Self::poll_result).
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.link.flush()?;
+        if self.link.deadline.is_none() && !self.link.finished {
+            self.request_start(now);
+        }
+        self.link.fill()?;
+        while !self.link.finished {
+            if !self.process(now) {
+                break;
+            }
+        }
+        if !self.link.finished && self.link.expired(now) {
+            match self.state {
+                ReceiveState::Start => {
+                    if self.link.arm(now, false) {
+                        self.request_start(now);
+                    }
+                }
+                _ => {
+                    if self.link.arm(now, false) {
+                        self.link.send(&[NAK]);
+                    }
+                }
+            }
+        }
+        self.link.flush()
+    }
+
+    fn request_start(&mut self, now: Instant) {
+        if self.checksum == Checksum::Crc16 && self.variant != Variant::Ymodem {
+            if self.crc_attempts >= CRC_ATTEMPTS {
+                log::debug!("no response to CRC request, falling back to checksum");
+                self.checksum = Checksum::Sum;
+            }
+            self.crc_attempts += 1;
+        }
+        self.link.send(&[match self.checksum {
+            Checksum::Crc16 => CRC_REQUEST,
+            Checksum::Sum => NAK,
+        }]);
+        if self.link.deadline.is_none() {
+            self.link.arm(now, true);
+        }
+    }
+
+    /// Handle the next packet in the receive buffer; returns `false` if more input is needed
+    fn process(&mut self, now: Instant) -> bool {
+        let first = match self.link.rx.first() {
+            Some(&first) => first,
+            None => return false,
+        };
+        if first == CAN {
+            self.link.rx.remove(0);
+            self.cancels += 1;
+            if self.cancels >= 2 {
+                self.link.fail(XmodemError::Cancelled);
+            }
+            return true;
+        }
+        self.cancels = 0;
+
+        match first {
+            EOT => {
+                self.link.rx.remove(0);
+                self.on_eot(now);
+                true
+            }
+            SOH | STX => {
+                let size = if first == SOH { 128 } else { 1024 };
+                let len = 3 + size + self.checksum.len();
+                if self.link.rx.len() < len {
+                    return false;
+                }
+                let packet: Vec<u8> = self.link.rx.drain(..len).collect();
+                self.on_block(&packet, size, now);
+                true
+            }
+            _ => {
+                self.link.rx.remove(0);
+                true
+            }
+        }
+    }
+
+    fn reject(&mut self, now: Instant) {
+        // Drop whatever is left of the bad block before asking again
+        self.link.rx.clear();
+        if self.link.arm(now, false) {
+            self.link.send(&[NAK]);
+        }
+    }
+
+    fn on_block(&mut self, packet: &[u8], size: usize, now: Instant) {
+        let (seq, payload) = (packet[1], &packet[3..3 + size]);
+        let mut check = Vec::new();
+        self.checksum.append(payload, &mut check);
+        if packet[2] != !seq || check != packet[3 + size..] {
+            self.reject(now);
+            return;
+        }
+
+        let ymodem = self.variant == Variant::Ymodem;
+        if self.state == ReceiveState::Start && ymodem {
+            if seq != 0 {
+                self.reject(now);
+                return;
+            }
+            self.on_header(payload, now);
+            return;
+        }
+        if seq == self.expected_seq.wrapping_sub(1) && self.state == ReceiveState::Data {
+            // Our ACK was lost; acknowledge the duplicate again
+            self.link.send(&[ACK]);
+            self.link.arm(now, true);
+            return;
+        }
+        if seq != self.expected_seq {
+            self.link.fail(XmodemError::OutOfSequence(seq));
+            return;
+        }
+
+        self.state = ReceiveState::Data;
+        self.eot_seen = false;
+        self.expected_seq = self.expected_seq.wrapping_add(1);
+        let index = self.files.len();
+        let (file, size) = self
+            .current
+            .get_or_insert_with(|| (TransferFile::new("", Vec::new()), None));
+        file.data.extend_from_slice(payload);
+        if let Some(size) = *size {
+            file.data.truncate(size as usize);
+        }
+        let bytes = file.data.len() as u64;
+        let (name, total) = (file.name.clone(), *size);
+        self.link.send(&[ACK]);
+        self.link.arm(now, true);
+        self.link.report(Progress {
+            file_index: index,
+            file_name: &name,
+            bytes,
+            total,
+        });
+    }
+
+    fn on_header(&mut self, payload: &[u8], now: Instant) {
+        let name_end = payload
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(payload.len());
+        if name_end == 0 {
+            // Empty header: end of batch
+            self.link.send(&[ACK]);
+            self.state = ReceiveState::Done;
+            self.link.finish();
+            return;
+        }
+        let name = String::from_utf8_lossy(&payload[..name_end]).into_owned();
+        let rest = &payload[(name_end + 1).min(payload.len())..];
+        let rest = &rest[..rest.iter().position(|&b| b == 0).unwrap_or(rest.len())];
+        let size = match std::str::from_utf8(rest)
+            .ok()
+            .and_then(|s| s.split(' ').next())
+        {
+            Some("") | None => None,
+            Some(size) => match size.parse() {
+                Ok(size) => Some(size),
+                Err(_) => {
+                    self.link.fail(XmodemError::InvalidHeader("bad file size"));
+                    return;
+                }
+            },
+        };
+
+        self.current = Some((TransferFile::new(name, Vec::new()), size));
+        self.expected_seq = 1;
+        self.state = ReceiveState::Data;
+        self.link.send(&[ACK, CRC_REQUEST]);
+        self.link.arm(now, true);
+    }
+
+    fn on_eot(&mut self, now: Instant) {
+        if self.state != ReceiveState::Data {
+            return;
+        }
+        if self.variant == Variant::Ymodem && !self.eot_seen {
+            // YMODEM receivers NAK the first EOT to guard against line noise
+            self.eot_seen = true;
+            self.link.send(&[NAK]);
+            self.link.arm(now, true);
+            return;
+        }
+        self.link.send(&[ACK]);
+        if let Some((file, _)) = self.current.take() {
+            self.files.push(file);
+        }
+        if self.variant == Variant::Ymodem {
+            self.state = ReceiveState::Start;
+            self.eot_seen = false;
+            self.link.send(&[CRC_REQUEST]);
+            self.link.arm(now, true);
+        } else {
+            self.state = ReceiveState::Done;
+            self.link.finish();
+        }
+    }
+}
+
+impl<S> Source for Sender<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.link.inner.deregister(registry)
+    }
+}
+
+impl<S> Source for Receiver<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.link.inner.deregister(registry)
+    }
+}
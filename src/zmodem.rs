@@ -0,0 +1,1516 @@
+//! ZMODEM file transfer with streaming and crash recovery
+//!
+//! [`Sender`] and [`Receiver`] are non-blocking state machines over a serial stream, driven
+//! the same way as their [`xmodem`](crate::xmodem) counterparts: call `handle_io` on every
+//! event for the stream's token and whenever the deadline from `next_deadline` expires,
+//! until `poll_result` returns the outcome.
+//!
+//! Supported are:
+//!
+//! * Hex, 16-bit binary and 32-bit binary headers ([`Header`], [`Encoding`]); the sender
+//!   uses CRC-32 whenever the receiver advertises [`CANFC32`].
+//! * ZDLE escaping of the flow control characters, and of all control characters when the
+//!   receiver asks for [`ESCCTL`].
+//! * Full streaming: data subpackets are sent without waiting for acknowledgements, and the
+//!   receiver answers errors with `ZRPOS` to resume from the last good byte.  A receiver
+//!   advertising a buffer size gets segments ending in `ZCRCW` instead.
+//! * Crash recovery: a [`Receiver`] given the partial file from an interrupted transfer
+//!   asks the sender to continue at the end of it, if the sender offers the file with
+//!   `ZCRESUM`.
+//! * Detection of the `rz`/`sz` auto-start sequence in terminal output
+//!   ([`AutoStartDetector`]).
+//!
+//! ## Example
+//!
+//! ```no_run
+//! use mio::{Events, Interest, Poll, Token};
+//! use mio_serial::zmodem::{AutoStart, AutoStartDetector, Receiver};
+//! use mio_serial::SerialPortBuilderExt;
+//! use std::io::{self, Read, Write};
+//! use std::time::Instant;
+//!
+//! let mut stream = mio_serial::new("/dev/ttyUSB0", 115200).open_native_async().unwrap();
+//! let mut poll = Poll::new().unwrap();
+//! let mut events = Events::with_capacity(4);
+//! poll.registry()
+//!     .register(&mut stream, Token(0), Interest::READABLE | Interest::WRITABLE)
+//!     .unwrap();
+//!
+//! // Show terminal output until the remote side runs `sz`
+//! let mut detector = AutoStartDetector::new();
+//! let mut buf = [0u8; 1024];
+//! 'terminal: loop {
+//!     poll.poll(&mut events, None).unwrap();
+//!     loop {
+//!         let n = match stream.read(&mut buf) {
+//!             Ok(0) => return,
+//!             Ok(n) => n,
+//!             Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+//!             Err(e) => panic!("read failed: {e}"),
+//!         };
+//!         match detector.feed(&buf[..n]) {
+//!             Some((AutoStart::Receive, offset)) => {
+//!                 io::stdout().write_all(&buf[..offset]).unwrap();
+//!                 break 'terminal;
+//!             }
+//!             _ => io::stdout().write_all(&buf[..n]).unwrap(),
+//!         }
+//!     }
+//! }
+//!
+//! let mut receiver = Receiver::new(stream);
+//! let result = loop {
+//!     receiver.handle_io(Instant::now()).unwrap();
+//!     if let Some(result) = receiver.poll_result() {
+//!         break result;
+//!     }
+//!     let timeout = receiver
+//!         .next_deadline()
+//!         .map(|d| d.saturating_duration_since(Instant::now()));
+//!     poll.poll(&mut events, timeout).unwrap();
+//! };
+//! for file in result.unwrap() {
+//!     std::fs::write(&file.name, &file.data).unwrap();
+//! }
+//! ```
+use crate::crc::{CRC_16_XMODEM, CRC_32_ISO_HDLC};
+use crate::transfer::{Link, LinkError};
+use crate::SerialStream;
+use mio::{event::Source, Interest, Registry, Token};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+pub use crate::xmodem::{Progress, TransferFile};
+
+/// Padding character that starts every header
+pub const ZPAD: u8 = b'*';
+/// Escape character; also `CAN`, five in a row abort the session
+pub const ZDLE: u8 = 0x18;
+/// Escaped `ZDLE`
+pub const ZDLEE: u8 = ZDLE ^ 0x40;
+/// Binary header with CRC-16
+pub const ZBIN: u8 = b'A';
+/// Hex header with CRC-16
+pub const ZHEX: u8 = b'B';
+/// Binary header with CRC-32
+pub const ZBIN32: u8 = b'C';
+
+/// Subpacket end: frame ends, a header follows
+pub const ZCRCE: u8 = b'h';
+/// Subpacket end: frame continues nonstop
+pub const ZCRCG: u8 = b'i';
+/// Subpacket end: frame continues, `ZACK` expected
+pub const ZCRCQ: u8 = b'j';
+/// Subpacket end: frame ends, `ZACK` expected
+pub const ZCRCW: u8 = b'k';
+/// Escaped `0x7F`
+pub const ZRUB0: u8 = b'l';
+/// Escaped `0xFF`
+pub const ZRUB1: u8 = b'm';
+
+/// `ZRINIT` flag: the receiver can send and receive at the same time
+pub const CANFDX: u8 = 0x01;
+/// `ZRINIT` flag: the receiver can receive data while writing to disk
+pub const CANOVIO: u8 = 0x02;
+/// `ZRINIT` flag: the receiver can send a break signal
+pub const CANBRK: u8 = 0x04;
+/// `ZRINIT` flag: the receiver understands CRC-32
+pub const CANFC32: u8 = 0x20;
+/// `ZRINIT` flag: the receiver expects all control characters to be escaped
+pub const ESCCTL: u8 = 0x40;
+
+/// `ZFILE` conversion option: binary transfer
+pub const ZCBIN: u8 = 1;
+/// `ZFILE` conversion option: resume an interrupted transfer
+pub const ZCRESUM: u8 = 3;
+
+/// Default time to wait for a response before retrying
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default number of retries before giving up
+pub const DEFAULT_RETRIES: u32 = 10;
+
+/// Default length of the data subpackets sent
+pub const DEFAULT_SUBPACKET_SIZE: usize = 1024;
+
+/// Longest data subpacket accepted
+pub const MAX_SUBPACKET_SIZE: usize = 8192;
+
+const XON: u8 = 0x11;
+const XOFF: u8 = 0x13;
+
+/// Output queued ahead of the stream while streaming, so `ZRPOS` takes effect quickly
+const STREAM_QUEUE_LIMIT: usize = 8 * 1024;
+
+/// Consecutive `CAN` characters that abort a session
+const CANCEL_LENGTH: usize = 5;
+
+/// Header frame type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FrameType {
+    /// Sender requests `ZRINIT`
+    RqInit = 0,
+    /// Receiver is ready, with its capabilities
+    RInit = 1,
+    /// Sender session parameters
+    SInit = 2,
+    /// Acknowledgement
+    Ack = 3,
+    /// File name and information follow
+    File = 4,
+    /// Receiver skips the offered file
+    Skip = 5,
+    /// Last header was garbled
+    Nak = 6,
+    /// Abort the batch
+    Abort = 7,
+    /// End of session
+    Fin = 8,
+    /// Resume data at the given position
+    RPos = 9,
+    /// Data subpackets follow
+    Data = 10,
+    /// End of file at the given position
+    Eof = 11,
+    /// Fatal file error
+    FErr = 12,
+    /// File CRC request or response
+    Crc = 13,
+    /// Security challenge
+    Challenge = 14,
+    /// Command complete
+    Compl = 15,
+    /// Pseudo frame type for a received `CAN` sequence
+    Can = 16,
+    /// Request for free disk space
+    FreeCnt = 17,
+    /// Command from the sender
+    Command = 18,
+    /// Text for the receiver's standard error
+    Stderr = 19,
+}
+
+impl FrameType {
+    /// The frame type with code `code`, if it is defined
+    pub fn from_u8(code: u8) -> Option<Self> {
+        let frame_type = match code {
+            0 => FrameType::RqInit,
+            1 => FrameType::RInit,
+            2 => FrameType::SInit,
+            3 => FrameType::Ack,
+            4 => FrameType::File,
+            5 => FrameType::Skip,
+            6 => FrameType::Nak,
+            7 => FrameType::Abort,
+            8 => FrameType::Fin,
+            9 => FrameType::RPos,
+            10 => FrameType::Data,
+            11 => FrameType::Eof,
+            12 => FrameType::FErr,
+            13 => FrameType::Crc,
+            14 => FrameType::Challenge,
+            15 => FrameType::Compl,
+            16 => FrameType::Can,
+            17 => FrameType::FreeCnt,
+            18 => FrameType::Command,
+            19 => FrameType::Stderr,
+            _ => return None,
+        };
+        Some(frame_type)
+    }
+}
+
+/// How a header is encoded on the line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Hex digits with CRC-16; used for headers that are not followed by data
+    Hex,
+    /// Binary with CRC-16
+    Bin16,
+    /// Binary with CRC-32
+    Bin32,
+}
+
+/// Why a transfer failed, or a header or subpacket could not be decoded
+#[derive(Debug)]
+pub enum ZmodemError {
+    /// An I/O error from the underlying stream
+    Io(io::Error),
+    /// The other side cancelled or aborted the transfer
+    Cancelled,
+    /// The transfer was cancelled locally with `cancel`
+    Aborted,
+    /// The transfer made no progress after all retries
+    TooManyRetries,
+    /// A header or subpacket failed its CRC
+    BadCrc,
+    /// A header or subpacket was malformed
+    InvalidFrame(&'static str),
+}
+
+impl ZmodemError {
+    /// Returns the [`io::ErrorKind`] if this is an I/O error
+    pub fn io_kind(&self) -> Option<io::ErrorKind> {
+        match self {
+            ZmodemError::Io(e) => Some(e.kind()),
+            _ => None,
+        }
+    }
+}
+
+impl LinkError for ZmodemError {
+    fn cancelled() -> Self {
+        ZmodemError::Cancelled
+    }
+
+    fn too_many_retries() -> Self {
+        ZmodemError::TooManyRetries
+    }
+
+    fn is_cancelled(&self) -> bool {
+        matches!(self, ZmodemError::Cancelled)
+    }
+}
+
+impl fmt::Display for ZmodemError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ZmodemError::Io(e) => write!(f, "{e}"),
+            ZmodemError::Cancelled => write!(f, "transfer cancelled by remote"),
+            ZmodemError::Aborted => write!(f, "transfer aborted"),
+            ZmodemError::TooManyRetries => write!(f, "too many retries"),
+            ZmodemError::BadCrc => write!(f, "CRC mismatch"),
+            ZmodemError::InvalidFrame(why) => write!(f, "invalid ZMODEM frame: {why}"),
+        }
+    }
+}
+
+impl std::error::Error for ZmodemError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ZmodemError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ZmodemError {
+    fn from(e: io::Error) -> Self {
+        ZmodemError::Io(e)
+    }
+}
+
+/// A ZMODEM header: a frame type and four bytes of position or flags
+///
+/// The four bytes hold a little-endian file position (`ZP0` to `ZP3`) or the flags `ZF3`
+/// to `ZF0`, in that order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    /// Frame type
+    pub frame_type: FrameType,
+    /// Position or flags
+    pub data: [u8; 4],
+}
+
+impl Header {
+    /// Create a header
+    pub fn new(frame_type: FrameType, data: [u8; 4]) -> Self {
+        Self { frame_type, data }
+    }
+
+    /// Create a header carrying a file position
+    pub fn with_position(frame_type: FrameType, position: u32) -> Self {
+        Self::new(frame_type, position.to_le_bytes())
+    }
+
+    /// The file position
+    pub fn position(&self) -> u32 {
+        u32::from_le_bytes(self.data)
+    }
+
+    /// The first flags byte, `ZF0`
+    pub fn zf0(&self) -> u8 {
+        self.data[3]
+    }
+
+    /// Append the encoded header to `dst`
+    pub fn encode(&self, encoding: Encoding, dst: &mut Vec<u8>) {
+        self.encode_escaped(encoding, false, dst);
+    }
+
+    fn encode_escaped(&self, encoding: Encoding, escape_ctl: bool, dst: &mut Vec<u8>) {
+        let raw = [
+            self.frame_type as u8,
+            self.data[0],
+            self.data[1],
+            self.data[2],
+            self.data[3],
+        ];
+        match encoding {
+            Encoding::Hex => {
+                dst.extend_from_slice(&[ZPAD, ZPAD, ZDLE, ZHEX]);
+                let crc = CRC_16_XMODEM.checksum(&raw).to_be_bytes();
+                for byte in raw.iter().chain(&crc) {
+                    dst.extend_from_slice(format!("{byte:02x}").as_bytes());
+                }
+                dst.extend_from_slice(&[b'\r', b'\n' | 0x80]);
+                if !matches!(self.frame_type, FrameType::Ack | FrameType::Fin) {
+                    dst.push(XON);
+                }
+            }
+            Encoding::Bin16 => {
+                dst.extend_from_slice(&[ZPAD, ZDLE, ZBIN]);
+                escape(&raw, escape_ctl, dst);
+                escape(&CRC_16_XMODEM.checksum(&raw).to_be_bytes(), escape_ctl, dst);
+            }
+            Encoding::Bin32 => {
+                dst.extend_from_slice(&[ZPAD, ZDLE, ZBIN32]);
+                escape(&raw, escape_ctl, dst);
+                escape(
+                    &CRC_32_ISO_HDLC.checksum(&raw).to_le_bytes(),
+                    escape_ctl,
+                    dst,
+                );
+            }
+        }
+    }
+
+    /// Decode the next header from `src`, skipping anything before it
+    ///
+    /// Consumed bytes are removed from `src`.  Returns `Ok(None)` until a complete header
+    /// has arrived.
+    ///
+    /// ## Errors
+    ///
+    /// [`ZmodemError::BadCrc`] or [`ZmodemError::InvalidFrame`] for a corrupted header;
+    /// the bytes of the bad header are consumed, so decoding can simply be retried.
+    pub fn decode(src: &mut Vec<u8>) -> Result<Option<(Header, Encoding)>, ZmodemError> {
+        loop {
+            let start = match src.iter().position(|&b| b == ZPAD) {
+                Some(start) => start,
+                None => {
+                    src.clear();
+                    return Ok(None);
+                }
+            };
+            src.drain(..start);
+            let mut pos = 1;
+            while src.get(pos) == Some(&ZPAD) {
+                pos += 1;
+            }
+            match src.get(pos) {
+                None => return Ok(None),
+                Some(&ZDLE) => {}
+                Some(_) => {
+                    src.drain(..pos);
+                    continue;
+                }
+            }
+            let encoding = match src.get(pos + 1) {
+                None => return Ok(None),
+                Some(&ZHEX) => Encoding::Hex,
+                Some(&ZBIN) => Encoding::Bin16,
+                Some(&ZBIN32) => Encoding::Bin32,
+                Some(_) => {
+                    src.drain(..pos);
+                    continue;
+                }
+            };
+            pos += 2;
+            let result = match encoding {
+                Encoding::Hex => decode_hex_header(src, &mut pos),
+                _ => decode_binary_header(src, &mut pos, encoding),
+            };
+            match result {
+                Ok(None) => return Ok(None),
+                Ok(Some(header)) => {
+                    src.drain(..pos);
+                    return Ok(Some((header, encoding)));
+                }
+                Err(e) => {
+                    src.drain(..pos);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+fn decode_hex_header(src: &[u8], pos: &mut usize) -> Result<Option<Header>, ZmodemError> {
+    let digits = match src.get(*pos..*pos + 14) {
+        Some(digits) => digits,
+        None => return Ok(None),
+    };
+    let mut raw = [0u8; 7];
+    for (byte, pair) in raw.iter_mut().zip(digits.chunks_exact(2)) {
+        let text = std::str::from_utf8(pair).map_err(|_| ZmodemError::InvalidFrame("not hex"))?;
+        *byte = u8::from_str_radix(text, 16).map_err(|_| ZmodemError::InvalidFrame("not hex"))?;
+    }
+    *pos += 14;
+    // The CR LF XON trailer, as far as it has arrived
+    while *pos < src.len() && matches!(src[*pos], b'\r' | b'\n' | 0x8D | 0x8A | XON) {
+        *pos += 1;
+    }
+    if CRC_16_XMODEM.checksum(&raw[..5]) != u16::from_be_bytes([raw[5], raw[6]]) {
+        return Err(ZmodemError::BadCrc);
+    }
+    make_header(&raw[..5]).map(Some)
+}
+
+fn decode_binary_header(
+    src: &[u8],
+    pos: &mut usize,
+    encoding: Encoding,
+) -> Result<Option<Header>, ZmodemError> {
+    let len = if encoding == Encoding::Bin32 { 9 } else { 7 };
+    let mut raw = [0u8; 9];
+    let mut end = *pos;
+    for byte in raw[..len].iter_mut() {
+        match next_symbol(src, &mut end) {
+            Ok(None) => return Ok(None),
+            Ok(Some(Symbol::Byte(b))) => *byte = b,
+            Ok(Some(Symbol::End(_))) => {
+                *pos = end;
+                return Err(ZmodemError::InvalidFrame("subpacket end in header"));
+            }
+            Err(e) => {
+                *pos = end;
+                return Err(e);
+            }
+        }
+    }
+    *pos = end;
+    let valid = if encoding == Encoding::Bin32 {
+        CRC_32_ISO_HDLC.checksum(&raw[..5]) == u32::from_le_bytes([raw[5], raw[6], raw[7], raw[8]])
+    } else {
+        CRC_16_XMODEM.checksum(&raw[..5]) == u16::from_be_bytes([raw[5], raw[6]])
+    };
+    if !valid {
+        return Err(ZmodemError::BadCrc);
+    }
+    make_header(&raw[..5]).map(Some)
+}
+
+fn make_header(raw: &[u8]) -> Result<Header, ZmodemError> {
+    let frame_type =
+        FrameType::from_u8(raw[0]).ok_or(ZmodemError::InvalidFrame("unknown frame type"))?;
+    Ok(Header::new(frame_type, [raw[1], raw[2], raw[3], raw[4]]))
+}
+
+fn escape(data: &[u8], escape_ctl: bool, dst: &mut Vec<u8>) {
+    for &byte in data {
+        match byte {
+            ZDLE | 0x10 | XON | XOFF | 0x90 | 0x91 | 0x93 => {
+                dst.extend_from_slice(&[ZDLE, byte ^ 0x40])
+            }
+            _ if escape_ctl && byte & 0x60 == 0 => dst.extend_from_slice(&[ZDLE, byte ^ 0x40]),
+            _ => dst.push(byte),
+        }
+    }
+}
+
+enum Symbol {
+    Byte(u8),
+    End(u8),
+}
+
+/// Unescape the symbol at `*pos`, skipping flow control characters
+fn next_symbol(src: &[u8], pos: &mut usize) -> Result<Option<Symbol>, ZmodemError> {
+    loop {
+        let byte = match src.get(*pos) {
+            Some(&byte) => byte,
+            None => return Ok(None),
+        };
+        match byte {
+            XON | XOFF | 0x91 | 0x93 => *pos += 1,
+            ZDLE => {
+                let escaped = match src.get(*pos + 1) {
+                    Some(&escaped) => escaped,
+                    None => return Ok(None),
+                };
+                *pos += 2;
+                return match escaped {
+                    ZCRCE..=ZCRCW => Ok(Some(Symbol::End(escaped))),
+                    ZRUB0 => Ok(Some(Symbol::Byte(0x7F))),
+                    ZRUB1 => Ok(Some(Symbol::Byte(0xFF))),
+                    _ if escaped & 0x60 == 0x40 => Ok(Some(Symbol::Byte(escaped ^ 0x40))),
+                    _ => Err(ZmodemError::InvalidFrame("bad escape sequence")),
+                };
+            }
+            _ => {
+                *pos += 1;
+                return Ok(Some(Symbol::Byte(byte)));
+            }
+        }
+    }
+}
+
+fn encode_subpacket(data: &[u8], end: u8, crc32: bool, escape_ctl: bool, dst: &mut Vec<u8>) {
+    escape(data, escape_ctl, dst);
+    dst.extend_from_slice(&[ZDLE, end]);
+    if crc32 {
+        let crc = CRC_32_ISO_HDLC.update(CRC_32_ISO_HDLC.init_value(), data);
+        let crc = CRC_32_ISO_HDLC.finalize(CRC_32_ISO_HDLC.update(crc, &[end]));
+        escape(&crc.to_le_bytes(), escape_ctl, dst);
+    } else {
+        let crc = CRC_16_XMODEM.update(CRC_16_XMODEM.init_value(), data);
+        let crc = CRC_16_XMODEM.finalize(CRC_16_XMODEM.update(crc, &[end]));
+        escape(&crc.to_be_bytes(), escape_ctl, dst);
+    }
+    if end == ZCRCW {
+        dst.push(XON);
+    }
+}
+
+/// Decode a data subpacket at the start of `src`, returning its data and end character
+fn decode_subpacket(src: &mut Vec<u8>, crc32: bool) -> Result<Option<(Vec<u8>, u8)>, ZmodemError> {
+    let mut pos = 0;
+    let mut data = Vec::new();
+    let result = loop {
+        match next_symbol(src, &mut pos) {
+            Ok(None) => return Ok(None),
+            Ok(Some(Symbol::Byte(byte))) => {
+                if data.len() == MAX_SUBPACKET_SIZE {
+                    break Err(ZmodemError::InvalidFrame("subpacket too long"));
+                }
+                data.push(byte);
+            }
+            Ok(Some(Symbol::End(end))) => break Ok(end),
+            Err(e) => break Err(e),
+        }
+    };
+    let end = match result {
+        Ok(end) => end,
+        Err(e) => {
+            src.drain(..pos);
+            return Err(e);
+        }
+    };
+    let mut crc = [0u8; 4];
+    let len = if crc32 { 4 } else { 2 };
+    for byte in crc[..len].iter_mut() {
+        match next_symbol(src, &mut pos) {
+            Ok(None) => return Ok(None),
+            Ok(Some(Symbol::Byte(b))) => *byte = b,
+            Ok(Some(Symbol::End(_))) => {
+                src.drain(..pos);
+                return Err(ZmodemError::InvalidFrame("subpacket end in CRC"));
+            }
+            Err(e) => {
+                src.drain(..pos);
+                return Err(e);
+            }
+        }
+    }
+    src.drain(..pos);
+    let valid = if crc32 {
+        let computed = CRC_32_ISO_HDLC.update(CRC_32_ISO_HDLC.init_value(), &data);
+        let computed = CRC_32_ISO_HDLC.finalize(CRC_32_ISO_HDLC.update(computed, &[end]));
+        computed == u32::from_le_bytes(crc)
+    } else {
+        let computed = CRC_16_XMODEM.update(CRC_16_XMODEM.init_value(), &data);
+        let computed = CRC_16_XMODEM.finalize(CRC_16_XMODEM.update(computed, &[end]));
+        computed == u16::from_be_bytes([crc[0], crc[1]])
+    };
+    if !valid {
+        return Err(ZmodemError::BadCrc);
+    }
+    Ok(Some((data, end)))
+}
+
+/// What the local side should start after seeing the remote side begin a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AutoStart {
+    /// The remote side runs `sz` and sent `ZRQINIT`: start a [`Receiver`]
+    Receive,
+    /// The remote side runs `rz` and sent `ZRINIT`: start a [`Sender`]
+    Send,
+}
+
+/// Spots the start of a ZMODEM session in terminal output
+///
+/// `sz` announces itself with a hex `ZRQINIT` header and `rz` with a hex `ZRINIT` header;
+/// the detector looks for either, including when they are split across reads.
+#[derive(Debug, Clone, Default)]
+pub struct AutoStartDetector {
+    tail: Vec<u8>,
+}
+
+impl AutoStartDetector {
+    /// Create a detector
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Scan the next chunk of terminal output
+    ///
+    /// Returns what to start and the offset in `data` at which the session begins; the
+    /// bytes before it are ordinary terminal output.  The offset is 0 if the sequence began
+    /// in an earlier chunk.
+    pub fn feed(&mut self, data: &[u8]) -> Option<(AutoStart, usize)> {
+        let mut buf = std::mem::take(&mut self.tail);
+        let carried = buf.len();
+        buf.extend_from_slice(data);
+        let found = buf.windows(5).enumerate().find_map(|(i, w)| {
+            if w[..4] != [ZPAD, ZDLE, ZHEX, b'0'] {
+                return None;
+            }
+            match w[4] {
+                b'0' => Some((AutoStart::Receive, i)),
+                b'1' => Some((AutoStart::Send, i)),
+                _ => None,
+            }
+        });
+        match found {
+            Some((kind, mut start)) => {
+                while start > 0 && buf[start - 1] == ZPAD {
+                    start -= 1;
+                }
+                Some((kind, start.saturating_sub(carried)))
+            }
+            None => {
+                let keep = buf.len().min(5);
+                self.tail = buf.split_off(buf.len() - keep);
+                None
+            }
+        }
+    }
+}
+
+impl<S: Read + Write> Link<S, ZmodemError> {
+    fn send_header(&mut self, header: Header, encoding: Encoding) {
+        let mut buf = Vec::new();
+        header.encode(encoding, &mut buf);
+        self.send(&buf);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SendState {
+    /// `ZRQINIT` sent, waiting for `ZRINIT`
+    Init,
+    /// `ZFILE` sent, waiting for `ZRPOS` or `ZSKIP`
+    FileOffered,
+    /// Sending data subpackets
+    Streaming,
+    /// Segment ended with `ZCRCW`, waiting for `ZACK`
+    WaitAck,
+    /// `ZEOF` sent, waiting for `ZRINIT`
+    Eof,
+    /// `ZFIN` sent, waiting for the receiver's `ZFIN`
+    Fin,
+    Done,
+}
+
+/// Non-blocking ZMODEM sender
+#[derive(Debug)]
+pub struct Sender<S = SerialStream> {
+    link: Link<S, ZmodemError>,
+    files: Vec<TransferFile>,
+    file_index: usize,
+    state: SendState,
+    started: bool,
+    autostart: bool,
+    resume: bool,
+    subpacket_size: usize,
+    encoding: Encoding,
+    escape_ctl: bool,
+    segment_size: usize,
+    offset: usize,
+    segment_start: usize,
+    last_rpos: Option<usize>,
+    last_frame: Vec<u8>,
+}
+
+impl<S> Sender<S>
+where
+    S: Read + Write + Source,
+{
+    /// Send `files`
+    pub fn new(inner: S, files: Vec<TransferFile>) -> Self {
+        Self {
+            link: Link::new(inner, DEFAULT_TIMEOUT, DEFAULT_RETRIES)
+                .with_cancel_length(CANCEL_LENGTH),
+            files,
+            file_index: 0,
+            state: SendState::Init,
+            started: false,
+            autostart: true,
+            resume: false,
+            subpacket_size: DEFAULT_SUBPACKET_SIZE,
+            encoding: Encoding::Bin16,
+            escape_ctl: false,
+            segment_size: 0,
+            offset: 0,
+            segment_start: 0,
+            last_rpos: None,
+            last_frame: Vec::new(),
+        }
+    }
+
+    /// Set how long to wait for a response before retrying
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.link.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a header is repeated, or an error recovered from, before giving up
+    #[must_use]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.link.retries = retries;
+        self
+    }
+
+    /// Call `callback` after each data subpacket
+    #[must_use]
+    pub fn with_progress(mut self, callback: impl FnMut(&Progress<'_>) + Send + 'static) -> Self {
+        self.link.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Set the length of the data subpackets, at most [`MAX_SUBPACKET_SIZE`]
+    #[must_use]
+    pub fn with_subpacket_size(mut self, size: usize) -> Self {
+        self.subpacket_size = size.clamp(1, MAX_SUBPACKET_SIZE);
+        self
+    }
+
+    /// Ask the receiver to resume files it already has part of (`ZCRESUM`)
+    #[must_use]
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Send `rz` and a carriage return first, to start the receiver on a remote shell
+    ///
+    /// Enabled by default; disable it when answering an [`AutoStart::Send`].
+    #[must_use]
+    pub fn with_autostart(mut self, autostart: bool) -> Self {
+        self.autostart = autostart;
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.link.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.link.inner
+    }
+
+    /// Consume the sender, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.link.inner
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.deadline
+    }
+
+    /// Returns `true` once the transfer has succeeded or failed
+    pub fn is_finished(&self) -> bool {
+        self.link.finished
+    }
+
+    /// Abort the transfer, telling the other side with `CAN`
+    pub fn cancel(&mut self) -> io::Result<()> {
+        if !self.link.finished {
+            self.link.fail(ZmodemError::Aborted);
+        }
+        self.link.flush()
+    }
+
+    /// Take the outcome of the transfer once it has finished
+    pub fn poll_result(&mut self) -> Option<Result<(), ZmodemError>> {
+        self.link.result.take()
+    }
+
+    /// Drive the transfer
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; protocol failures end the transfer
+    /// and are reported by [`poll_result`](Self::poll_result).
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.link.flush()?;
+        if !self.started && !self.link.finished {
+            self.started = true;
+            if self.autostart {
+                self.link.send(b"rz\r");
+            }
+            let mut frame = Vec::new();
+            Header::new(FrameType::RqInit, [0; 4]).encode(Encoding::Hex, &mut frame);
+            self.transmit(frame, now);
+        }
+        self.link.fill()?;
+        while !self.link.finished {
+            match Header::decode(&mut self.link.rx) {
+                Ok(Some((header, _))) => self.on_header(header, now),
+                Ok(None) => break,
+                Err(_) => {}
+            }
+        }
+        if !self.link.finished && self.link.expired(now) {
+            self.on_timeout(now);
+        }
+        loop {
+            while self.state == SendState::Streaming
+                && !self.link.finished
+                && self.link.write_queue.len() < STREAM_QUEUE_LIMIT
+            {
+                self.next_subpacket(now);
+            }
+            let written = self.link.write_queue.write_to(&mut self.link.inner)?;
+            if self.state != SendState::Streaming || self.link.finished || written == 0 {
+                break;
+            }
+            // The receiver is keeping up, so the stream is not stuck
+            self.link.deadline = Some(now + self.link.timeout);
+        }
+        Ok(())
+    }
+
+    fn on_header(&mut self, header: Header, now: Instant) {
+        match (self.state, header.frame_type) {
+            (_, FrameType::Can | FrameType::Abort | FrameType::FErr) => {
+                self.state = SendState::Done;
+                self.link.fail(ZmodemError::Cancelled);
+            }
+            (SendState::Init, FrameType::RInit) => {
+                let flags = header.zf0();
+                self.encoding = if flags & CANFC32 != 0 {
+                    Encoding::Bin32
+                } else {
+                    Encoding::Bin16
+                };
+                self.escape_ctl = flags & ESCCTL != 0;
+                self.segment_size = u16::from_le_bytes([header.data[0], header.data[1]]) as usize;
+                self.start_file(now);
+            }
+            (SendState::FileOffered, FrameType::RInit) => self.resend(now),
+            (SendState::Eof, FrameType::RInit) => {
+                self.file_index += 1;
+                self.start_file(now);
+            }
+            (SendState::FileOffered | SendState::Eof, FrameType::Skip) => {
+                self.file_index += 1;
+                self.start_file(now);
+            }
+            (
+                SendState::FileOffered | SendState::Streaming | SendState::WaitAck | SendState::Eof,
+                FrameType::RPos,
+            ) => self.start_data(header.position() as usize, now),
+            (SendState::WaitAck, FrameType::Ack) if header.position() as usize == self.offset => {
+                self.link.arm(now, true);
+                self.restart_data(self.offset);
+            }
+            (SendState::Fin, FrameType::Fin) => {
+                self.link.send(b"OO");
+                self.state = SendState::Done;
+                self.link.finish();
+            }
+            (SendState::Init | SendState::FileOffered | SendState::Eof, FrameType::Nak) => {
+                self.resend(now);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_timeout(&mut self, now: Instant) {
+        match self.state {
+            SendState::Streaming => {
+                self.link.arm(now, false);
+            }
+            SendState::WaitAck => {
+                if self.link.arm(now, false) {
+                    let segment_start = self.segment_start;
+                    self.restart_data(segment_start);
+                }
+            }
+            // All files were delivered, so a missing ZFIN from the receiver is not a failure
+            SendState::Fin if self.link.attempts >= self.link.retries => {
+                self.state = SendState::Done;
+                self.link.finish();
+            }
+            _ => self.resend(now),
+        }
+    }
+
+    fn resend(&mut self, now: Instant) {
+        if self.link.arm(now, false) {
+            let frame = std::mem::take(&mut self.last_frame);
+            self.link.send(&frame);
+            self.last_frame = frame;
+        }
+    }
+
+    fn transmit(&mut self, frame: Vec<u8>, now: Instant) {
+        self.link.send(&frame);
+        self.last_frame = frame;
+        self.link.arm(now, true);
+    }
+
+    fn start_file(&mut self, now: Instant) {
+        self.last_rpos = None;
+        let mut frame = Vec::new();
+        let file = match self.files.get(self.file_index) {
+            Some(file) => file,
+            None => {
+                self.state = SendState::Fin;
+                Header::new(FrameType::Fin, [0; 4]).encode(Encoding::Hex, &mut frame);
+                self.transmit(frame, now);
+                return;
+            }
+        };
+        let remaining = &self.files[self.file_index + 1..];
+        let mut info = file.name.as_bytes().to_vec();
+        info.push(0);
+        info.extend_from_slice(
+            format!(
+                "{} 0 0 0 {} {}",
+                file.data.len(),
+                remaining.len() + 1,
+                remaining.iter().map(|f| f.data.len()).sum::<usize>() + file.data.len()
+            )
+            .as_bytes(),
+        );
+        info.push(0);
+        let conversion = if self.resume { ZCRESUM } else { ZCBIN };
+        Header::new(FrameType::File, [0, 0, 0, conversion]).encode_escaped(
+            self.encoding,
+            self.escape_ctl,
+            &mut frame,
+        );
+        encode_subpacket(
+            &info,
+            ZCRCW,
+            self.encoding == Encoding::Bin32,
+            self.escape_ctl,
+            &mut frame,
+        );
+        self.state = SendState::FileOffered;
+        self.transmit(frame, now);
+    }
+
+    /// Answer `ZRPOS`: resume sending from `position`
+    fn start_data(&mut self, position: usize, now: Instant) {
+        // Repeated requests for the same position count as retries; progress resets them
+        let fresh = match self.last_rpos {
+            Some(last) => position > last,
+            None => true,
+        };
+        self.last_rpos = Some(position);
+        if !self.link.arm(now, fresh) {
+            return;
+        }
+        self.restart_data(position);
+    }
+
+    fn restart_data(&mut self, position: usize) {
+        // Whatever is still queued follows the data the receiver rejected
+        self.link.write_queue.clear();
+        let len = self.files[self.file_index].data.len();
+        self.offset = position.min(len);
+        self.segment_start = self.offset;
+        let mut frame = Vec::new();
+        if self.offset == len {
+            Header::with_position(FrameType::Eof, len as u32).encode_escaped(
+                self.encoding,
+                self.escape_ctl,
+                &mut frame,
+            );
+            self.link.send(&frame);
+            self.last_frame = frame;
+            self.state = SendState::Eof;
+        } else {
+            Header::with_position(FrameType::Data, self.offset as u32).encode_escaped(
+                self.encoding,
+                self.escape_ctl,
+                &mut frame,
+            );
+            self.link.send(&frame);
+            self.state = SendState::Streaming;
+        }
+    }
+
+    fn next_subpacket(&mut self, now: Instant) {
+        let file = &self.files[self.file_index];
+        let len = file.data.len();
+        let segment_end = if self.segment_size == 0 {
+            len
+        } else {
+            len.min(self.segment_start + self.segment_size)
+        };
+        let end_offset = segment_end.min(self.offset + self.subpacket_size);
+        let end = if end_offset == len {
+            ZCRCE
+        } else if end_offset == segment_end {
+            ZCRCW
+        } else {
+            ZCRCG
+        };
+        let mut packet = Vec::new();
+        encode_subpacket(
+            &file.data[self.offset..end_offset],
+            end,
+            self.encoding == Encoding::Bin32,
+            self.escape_ctl,
+            &mut packet,
+        );
+        self.link.send(&packet);
+        self.offset = end_offset;
+        let progress = Progress {
+            file_index: self.file_index,
+            file_name: &file.name,
+            bytes: end_offset as u64,
+            total: Some(len as u64),
+        };
+        self.link.report(progress);
+
+        if end == ZCRCE {
+            let mut frame = Vec::new();
+            Header::with_position(FrameType::Eof, len as u32).encode_escaped(
+                self.encoding,
+                self.escape_ctl,
+                &mut frame,
+            );
+            self.state = SendState::Eof;
+            self.transmit(frame, now);
+        } else if end == ZCRCW {
+            self.state = SendState::WaitAck;
+            self.link.arm(now, true);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReceiveState {
+    /// Sending `ZRINIT` until a file or `ZFIN` arrives
+    Init,
+    /// `ZSINIT` received, waiting for its subpacket
+    SessionInfo,
+    /// `ZFILE` received, waiting for the file information subpacket
+    FileInfo,
+    /// Waiting for `ZDATA` at our position, or `ZEOF`
+    WaitData,
+    /// Receiving data subpackets
+    Data,
+    Done,
+}
+
+/// Non-blocking ZMODEM receiver
+#[derive(Debug)]
+pub struct Receiver<S = SerialStream> {
+    link: Link<S, ZmodemError>,
+    state: ReceiveState,
+    started: bool,
+    crc32: bool,
+    allow_crc32: bool,
+    buffer_size: u16,
+    current: Option<(TransferFile, Option<u64>)>,
+    partial: Vec<TransferFile>,
+    /// The last `ZFILE` asked for crash recovery (`ZCRESUM`)
+    resume: bool,
+    files: Vec<TransferFile>,
+}
+
+impl<S> Receiver<S>
+where
+    S: Read + Write + Source,
+{
+    /// Receive files, advertising full streaming and CRC-32
+    pub fn new(inner: S) -> Self {
+        Self {
+            link: Link::new(inner, DEFAULT_TIMEOUT, DEFAULT_RETRIES)
+                .with_cancel_length(CANCEL_LENGTH),
+            state: ReceiveState::Init,
+            started: false,
+            crc32: false,
+            allow_crc32: true,
+            buffer_size: 0,
+            current: None,
+            partial: Vec::new(),
+            resume: false,
+            files: Vec::new(),
+        }
+    }
+
+    /// Set how long to wait for the sender before repeating a request
+    #[must_use]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.link.timeout = timeout;
+        self
+    }
+
+    /// Set how many times a request is repeated before giving up
+    #[must_use]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.link.retries = retries;
+        self
+    }
+
+    /// Call `callback` after each data subpacket
+    #[must_use]
+    pub fn with_progress(mut self, callback: impl FnMut(&Progress<'_>) + Send + 'static) -> Self {
+        self.link.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Advertise CRC-32 support (the default); without it the sender uses CRC-16
+    #[must_use]
+    pub fn with_crc32(mut self, crc32: bool) -> Self {
+        self.allow_crc32 = crc32;
+        self
+    }
+
+    /// Advertise a receive buffer of `size` bytes instead of full streaming
+    ///
+    /// The sender then waits for an acknowledgement after every `size` bytes.
+    #[must_use]
+    pub fn with_buffer_size(mut self, size: u16) -> Self {
+        self.buffer_size = size;
+        self
+    }
+
+    /// Resume `file`, the partial result of an interrupted transfer
+    ///
+    /// When the sender offers a file with the same name and asks for crash recovery
+    /// (`ZCRESUM`, see [`Sender::with_resume`]), the receiver asks for the data after the
+    /// bytes it already has.  Otherwise the partial file is dropped and the whole file is
+    /// received again.
+    #[must_use]
+    pub fn with_partial_file(mut self, file: TransferFile) -> Self {
+        self.partial.push(file);
+        self
+    }
+
+    /// Get a reference to the underlying stream
+    pub fn get_ref(&self) -> &S {
+        &self.link.inner
+    }
+
+    /// Get a mutable reference to the underlying stream
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.link.inner
+    }
+
+    /// Consume the receiver, returning the underlying stream
+    pub fn into_inner(self) -> S {
+        self.link.inner
+    }
+
+    /// The next time `handle_io` must be called even without an event
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.link.deadline
+    }
+
+    /// Returns `true` once the transfer has succeeded or failed
+    pub fn is_finished(&self) -> bool {
+        self.link.finished
+    }
+
+    /// Abort the transfer, telling the other side with `CAN`
+    pub fn cancel(&mut self) -> io::Result<()> {
+        if !self.link.finished {
+            self.link.fail(ZmodemError::Aborted);
+        }
+        self.link.flush()
+    }
+
+    /// The file being received, with the data that has arrived so far
+    ///
+    /// After a failed transfer, this is what to pass to
+    /// [`with_partial_file`](Self::with_partial_file) to resume.
+    pub fn partial_file(&self) -> Option<&TransferFile> {
+        self.current.as_ref().map(|(file, _)| file)
+    }
+
+    /// Take the received files once the transfer has finished
+    pub fn poll_result(&mut self) -> Option<Result<Vec<TransferFile>, ZmodemError>> {
+        let result = self.link.result.take()?;
+        Some(result.map(|()| std::mem::take(&mut self.files)))
+    }
+
+    /// Drive the transfer
+    ///
+    /// ## Errors
+    ///
+    /// Only I/O errors from the stream are returned; protocol failures end the transfer
+    /// and are reported by [`poll_result`](Self::poll_result).
+    pub fn handle_io(&mut self, now: Instant) -> io::Result<()> {
+        self.link.flush()?;
+        if !self.started && !self.link.finished {
+            self.started = true;
+            self.send_rinit();
+            self.link.arm(now, true);
+        }
+        self.link.fill()?;
+        while !self.link.finished {
+            if !self.process(now) {
+                break;
+            }
+        }
+        if !self.link.finished && self.link.expired(now) && self.link.arm(now, false) {
+            if self.current.is_some() {
+                self.state = ReceiveState::WaitData;
+                self.send_rpos();
+            } else {
+                self.state = ReceiveState::Init;
+                self.send_rinit();
+            }
+        }
+        self.link.flush()
+    }
+
+    /// Handle one header or subpacket; returns `false` when more input is needed
+    fn process(&mut self, now: Instant) -> bool {
+        match self.state {
+            ReceiveState::SessionInfo | ReceiveState::FileInfo | ReceiveState::Data => {
+                match decode_subpacket(&mut self.link.rx, self.crc32) {
+                    Ok(Some((data, end))) => self.on_subpacket(data, end, now),
+                    Ok(None) => return false,
+                    Err(_) => self.on_bad_subpacket(now),
+                }
+            }
+            _ => match Header::decode(&mut self.link.rx) {
+                Ok(Some((header, encoding))) => {
+                    self.crc32 = encoding == Encoding::Bin32;
+                    self.on_header(header, now);
+                }
+                Ok(None) => return false,
+                Err(_) => {}
+            },
+        }
+        true
+    }
+
+    fn on_header(&mut self, header: Header, now: Instant) {
+        match header.frame_type {
+            FrameType::RqInit | FrameType::Nak if self.state == ReceiveState::Init => {
+                self.send_rinit();
+            }
+            FrameType::SInit => self.state = ReceiveState::SessionInfo,
+            FrameType::File => {
+                self.resume = header.zf0() == ZCRESUM;
+                self.state = ReceiveState::FileInfo;
+            }
+            FrameType::Data => {
+                let offset = match self.current {
+                    Some((ref file, _)) => file.data.len(),
+                    None => return,
+                };
+                if header.position() as usize == offset {
+                    self.state = ReceiveState::Data;
+                    self.link.arm(now, true);
+                } else if self.link.arm(now, false) {
+                    self.send_rpos();
+                }
+            }
+            FrameType::Eof => {
+                let complete = matches!(
+                    self.current,
+                    Some((ref file, _)) if header.position() as usize == file.data.len()
+                );
+                // A ZEOF at another position is for data we rejected; ZRPOS is on its way
+                if complete {
+                    let (file, _) = self.current.take().unwrap();
+                    self.files.push(file);
+                    self.state = ReceiveState::Init;
+                    self.send_rinit();
+                    self.link.arm(now, true);
+                }
+            }
+            FrameType::Fin => {
+                self.link
+                    .send_header(Header::new(FrameType::Fin, [0; 4]), Encoding::Hex);
+                self.state = ReceiveState::Done;
+                self.link.finish();
+            }
+            FrameType::Can | FrameType::Abort => {
+                self.state = ReceiveState::Done;
+                self.link.fail(ZmodemError::Cancelled);
+            }
+            _ => {}
+        }
+    }
+
+    fn on_subpacket(&mut self, data: Vec<u8>, end: u8, now: Instant) {
+        match self.state {
+            ReceiveState::SessionInfo => {
+                self.link
+                    .send_header(Header::new(FrameType::Ack, [0; 4]), Encoding::Hex);
+                self.state = ReceiveState::Init;
+            }
+            ReceiveState::FileInfo => match parse_file_info(&data) {
+                Some((name, size)) => {
+                    self.open_file(name, size);
+                    self.state = ReceiveState::WaitData;
+                    self.send_rpos();
+                    self.link.arm(now, true);
+                }
+                None => {
+                    self.link
+                        .send_header(Header::new(FrameType::Nak, [0; 4]), Encoding::Hex);
+                    self.state = ReceiveState::Init;
+                }
+            },
+            _ => {
+                let (file, total) = match self.current {
+                    Some((ref mut file, total)) => (file, total),
+                    None => return,
+                };
+                file.data.extend_from_slice(&data);
+                let offset = file.data.len() as u32;
+                let progress = Progress {
+                    file_index: self.files.len(),
+                    file_name: &file.name,
+                    bytes: u64::from(offset),
+                    total,
+                };
+                self.link.report(progress);
+                if end == ZCRCQ || end == ZCRCW {
+                    self.link
+                        .send_header(Header::with_position(FrameType::Ack, offset), Encoding::Hex);
+                }
+                if end == ZCRCE || end == ZCRCW {
+                    self.state = ReceiveState::WaitData;
+                }
+                self.link.arm(now, true);
+            }
+        }
+    }
+
+    fn on_bad_subpacket(&mut self, now: Instant) {
+        if self.state == ReceiveState::Data {
+            self.state = ReceiveState::WaitData;
+            if self.link.arm(now, false) {
+                self.send_rpos();
+            }
+        } else {
+            self.link
+                .send_header(Header::new(FrameType::Nak, [0; 4]), Encoding::Hex);
+            self.state = ReceiveState::Init;
+        }
+    }
+
+    fn open_file(&mut self, name: String, size: Option<u64>) {
+        if let Some((ref file, _)) = self.current {
+            // The sender repeated ZFILE; carry on with what we have
+            if file.name == name {
+                return;
+            }
+        }
+        // A partial file the sender did not ask to resume is received again from the start
+        let resume = self.resume;
+        let resumed = self
+            .partial
+            .iter()
+            .position(|f| f.name == name)
+            .and_then(|i| {
+                let file = self.partial.remove(i);
+                let fits = match size {
+                    Some(size) => file.data.len() as u64 <= size,
+                    None => true,
+                };
+                (resume && fits).then_some(file)
+            });
+        let file = resumed.unwrap_or_else(|| TransferFile::new(name, Vec::new()));
+        self.current = Some((file, size));
+    }
+
+    fn send_rinit(&mut self) {
+        let [low, high] = self.buffer_size.to_le_bytes();
+        let mut flags = CANFDX | CANOVIO;
+        if self.allow_crc32 {
+            flags |= CANFC32;
+        }
+        self.link.send_header(
+            Header::new(FrameType::RInit, [low, high, 0, flags]),
+            Encoding::Hex,
+        );
+    }
+
+    fn send_rpos(&mut self) {
+        let offset = self.current.as_ref().map_or(0, |(file, _)| file.data.len());
+        self.link.send_header(
+            Header::with_position(FrameType::RPos, offset as u32),
+            Encoding::Hex,
+        );
+    }
+}
+
+/// Split the `ZFILE` subpacket into the file name and, if given, its size
+fn parse_file_info(data: &[u8]) -> Option<(String, Option<u64>)> {
+    let mut fields = data.split(|&b| b == 0);
+    let name = std::str::from_utf8(fields.next()?).ok()?;
+    if name.is_empty() {
+        return None;
+    }
+    let size = fields
+        .next()
+        .and_then(|info| std::str::from_utf8(info).ok())
+        .and_then(|info| info.split_whitespace().next())
+        .and_then(|size| size.parse().ok());
+    Some((name.to_string(), size))
+}
+
+impl<S> Source for Sender<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.link.inner.deregister(registry)
+    }
+}
+
+impl<S> Source for Receiver<S>
+where
+    S: Source,
+{
+    fn register(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.register(registry, token, interests)
+    }
+
+    fn reregister(
+        &mut self,
+        registry: &Registry,
+        token: Token,
+        interests: Interest,
+    ) -> io::Result<()> {
+        self.link.inner.reregister(registry, token, interests)
+    }
+
+    fn deregister(&mut self, registry: &Registry) -> io::Result<()> {
+        self.link.inner.deregister(registry)
+    }
+}
@@ -1,11 +1,11 @@
 //! Common test code.  Adapted from `mio/tests/util/mod.rs`
 #![allow(dead_code)]
 use mio::{event::Event, Events, Interest, Poll, Token};
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::ops::BitOr;
 use std::panic;
-use std::sync::Once;
-use std::time::Duration;
+use std::sync::{Arc, Mutex, Once};
+use std::time::{Duration, Instant};
 
 use serialport::SerialPort;
 
@@ -99,6 +99,82 @@ pub fn init_with_poll() -> (Poll, Events) {
     (poll, events)
 }
 
+/// Call `step` with the current time until it returns `true`, polling for at most
+/// `interval` between calls
+///
+/// Panics if `step` is not done within `limit`.
+pub fn drive(
+    poll: &mut Poll,
+    events: &mut Events,
+    interval: Duration,
+    limit: Duration,
+    mut step: impl FnMut(Instant) -> bool,
+) {
+    let deadline = Instant::now() + limit;
+    while !step(Instant::now()) {
+        assert!(Instant::now() < deadline, "timed out");
+        poll.poll(events, Some(interval)).expect("unable to poll");
+    }
+}
+
+/// `len` bytes of test data that do not repeat for a long stretch
+#[must_use]
+pub fn pattern(len: usize, seed: u8) -> Vec<u8> {
+    (0..len)
+        .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed) ^ (i >> 8) as u8)
+        .collect()
+}
+
+/// A writer appending to a buffer the test can still inspect
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far
+    pub fn bytes(&self) -> Vec<u8> {
+        self.0.lock().unwrap().clone()
+    }
+
+    /// Everything written so far, as text
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Read `len` bytes from a non-blocking serial stream
+///
+/// Panics if they do not arrive within 10 seconds.
+pub fn read_serial(stream: &mut mio_serial::SerialStream, len: usize) -> Vec<u8> {
+    let started = Instant::now();
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    while data.len() < len {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "serial timed out"
+        );
+        match stream.read(&mut buf[..(len - data.len()).min(4096)]) {
+            Ok(n) => data.extend_from_slice(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => panic!("serial read failed: {e}"),
+        }
+    }
+    data
+}
+
 /// An event that is expected to show up when `Poll` is polled, see
 /// `expect_events`.
 #[derive(Debug)]
@@ -188,9 +264,9 @@ impl Drop for Fixture {
         self.process.kill().ok();
         thread::sleep(Duration::from_millis(1000));
         log::trace!("removing link: {:?}", self.port_a);
-        std::fs::remove_file(&self.port_a).ok();
+        std::fs::remove_file(self.port_a).ok();
         log::trace!("removing link: {:?}", self.port_b);
-        std::fs::remove_file(&self.port_b).ok();
+        std::fs::remove_file(self.port_b).ok();
         thread::sleep(Duration::from_millis(1000));
     }
 }
@@ -200,7 +276,7 @@ impl Fixture {
     pub fn new(port_a: &'static str, port_b: &'static str) -> Self {
         use std::sync::atomic::{AtomicUsize, Ordering};
         static N: AtomicUsize = AtomicUsize::new(0);
-        LOGGING_INIT.call_once(|| env_logger::init());
+        LOGGING_INIT.call_once(env_logger::init);
         let n = N.fetch_add(1, Ordering::Relaxed);
         let port_a = format!("{}{}", port_a, n).leak();
         let port_b = format!("{}{}", port_b, n).leak();
@@ -225,7 +301,7 @@ impl Fixture {
 
     #[cfg(not(unix))]
     pub fn new(port_a: &'static str, port_b: &'static str) -> Self {
-        LOGGING_INIT.call_once(|| env_logger::init());
+        LOGGING_INIT.call_once(env_logger::init);
         Self { port_a, port_b }
     }
 }
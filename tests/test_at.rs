@@ -0,0 +1,217 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::at::{AtEngine, AtError, Command, Completion, Urc, CTRL_Z};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A scripted modem: waits for each expected command (terminated by CR or Ctrl-Z) and
+/// answers with the given reply
+fn spawn_modem(
+    mut port: SerialStream,
+    script: Vec<(&'static [u8], &'static [u8])>,
+) -> thread::JoinHandle<SerialStream> {
+    thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        for (expected, reply) in script {
+            loop {
+                if let Some(end) = received.iter().position(|&b| b == b'\r' || b == CTRL_Z) {
+                    let command: Vec<u8> = received.drain(..=end).collect();
+                    assert_eq!(
+                        String::from_utf8_lossy(&command),
+                        String::from_utf8_lossy(expected)
+                    );
+                    break;
+                }
+                assert!(Instant::now() < deadline, "modem timed out");
+                let mut buf = [0u8; 256];
+                match port.read(&mut buf) {
+                    Ok(n) => received.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5))
+                    }
+                    Err(e) => panic!("modem read failed: {e}"),
+                }
+            }
+            port.write_all(reply).unwrap();
+        }
+        port
+    })
+}
+
+fn run_until(
+    engine: &mut AtEngine,
+    mut done: impl FnMut(&[Completion], &[Urc]) -> bool,
+) -> (Vec<Completion>, Vec<Urc>) {
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(engine, Token(0), Interest::READABLE)
+        .expect("unable to register engine");
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut completions = Vec::new();
+    let mut urcs = Vec::new();
+    engine.handle_io(Instant::now()).unwrap();
+    while !done(&completions, &urcs) {
+        assert!(Instant::now() < deadline, "timed out");
+        let timeout = engine
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()))
+            .unwrap_or(Duration::from_millis(100));
+        poll.poll(&mut events, Some(timeout))
+            .expect("unable to poll");
+        engine.handle_io(Instant::now()).unwrap();
+        completions.extend(std::iter::from_fn(|| engine.poll_completion()));
+        urcs.extend(std::iter::from_fn(|| engine.poll_urc()));
+    }
+    poll.registry().deregister(engine).unwrap();
+    (completions, urcs)
+}
+
+#[test]
+fn test_commands_and_urcs() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(
+        master,
+        vec![
+            (b"AT+CSQ\r", b"AT+CSQ\r\r\n+CSQ: 20,99\r\n\r\nOK\r\n"),
+            (
+                b"AT+CREG?\r",
+                b"\r\n+CMTI: \"SM\",3\r\n\r\n+CREG: 0,1\r\nRING\r\n\r\nOK\r\n",
+            ),
+            (b"AT+CPIN?\r", b"\r\n+CME ERROR: 10\r\n"),
+            (b"ATI\r", b"\r\nQuectel\r\n"),
+            (b"ATH\r", b"\r\nOK\r\n+CREG: 5\r\n"),
+        ],
+    );
+
+    let mut engine = AtEngine::new(slave);
+    let csq = engine.submit(Command::new("AT+CSQ"));
+    engine.submit(Command::new("AT+CREG?"));
+    engine.submit(Command::new("AT+CPIN?"));
+    let ati = engine.submit(Command::new("ATI").with_timeout(Duration::from_millis(200)));
+    engine.submit(Command::new("ATH"));
+    assert_eq!(engine.pending(), 5);
+
+    let (completions, urcs) = run_until(&mut engine, |c, u| c.len() == 5 && u.len() == 3);
+    modem.join().unwrap();
+
+    assert_eq!(completions[0].id, csq);
+    let csq = completions[0].result.as_ref().unwrap();
+    assert_eq!(csq.lines, vec!["+CSQ: 20,99"]);
+    assert_eq!(csq.result_code, "OK");
+
+    // +CREG: answers AT+CREG? here, but is a URC at any other time
+    assert_eq!(
+        completions[1].result.as_ref().unwrap().lines,
+        vec!["+CREG: 0,1"]
+    );
+    assert!(matches!(
+        completions[2].result,
+        Err(AtError::CmeError(ref code)) if code == "10"
+    ));
+    assert_eq!(completions[3].id, ati);
+    assert!(matches!(completions[3].result, Err(AtError::Timeout)));
+    assert_eq!(completions[4].command, "ATH");
+    assert!(completions[4].result.is_ok());
+
+    assert_eq!(urcs[0].name(), "+CMTI");
+    assert_eq!(urcs[0].value(), Some("\"SM\",3"));
+    assert_eq!(urcs[1].line, "RING");
+    assert_eq!(urcs[2].line, "+CREG: 5");
+    assert!(engine.is_idle());
+}
+
+#[test]
+fn test_prompt_and_data_mode() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(
+        master,
+        vec![
+            (b"AT+CMGS=\"+15550100\"\r", b"\r\n> "),
+            (b"hello\x1a", b"\r\n+CMGS: 42\r\n\r\nOK\r\n"),
+            (b"ATD*99#\r", b"\r\nCONNECT 150000000\r\n~\x7e\xff\x7d"),
+        ],
+    );
+
+    let mut engine = AtEngine::new(slave);
+    engine.submit(Command::new("AT+CMGS=\"+15550100\"").with_prompt_data("hello"));
+    engine.submit(Command::new("ATD*99#"));
+    engine.submit(Command::new("AT"));
+
+    let (completions, _) = run_until(&mut engine, |c, _| c.len() == 2);
+    let sms = completions[0].result.as_ref().unwrap();
+    assert_eq!(sms.lines, vec!["+CMGS: 42"]);
+    let dial = completions[1].result.as_ref().unwrap();
+    assert_eq!(dial.result_code, "CONNECT 150000000");
+    assert!(engine.is_data_mode());
+    assert_eq!(engine.pending(), 1, "commands wait while in data mode");
+
+    let mut master = modem.join().unwrap();
+    master.write_all(b"\r\nOK\r\n").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    engine.handle_io(Instant::now()).unwrap();
+    let mut data = [0u8; 16];
+    let n = engine.read_data(&mut data);
+    assert_eq!(&data[..n], b"~\x7e\xff\x7d\r\nOK\r\n");
+
+    engine.enter_command_mode();
+    engine.handle_io(Instant::now()).unwrap();
+    assert!(!engine.is_data_mode());
+    assert_eq!(engine.pending(), 1);
+    assert!(
+        engine.next_deadline().is_some(),
+        "AT should now be in flight"
+    );
+}
+
+#[test]
+fn test_prompt_payload_larger_than_write_queue() {
+    let payload = vec![b'x'; 3 * mio_serial::buffered::DEFAULT_HIGH_WATER_MARK / 2];
+    let mut expected = payload.clone();
+    expected.push(CTRL_Z);
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(
+        master,
+        vec![
+            (b"AT+CMGW\r", b"\r\n> "),
+            (expected.leak(), b"\r\n+CMGW: 7\r\n\r\nOK\r\n"),
+        ],
+    );
+
+    let mut engine = AtEngine::new(slave);
+    engine.submit(Command::new("AT+CMGW").with_prompt_data(payload));
+    let (completions, _) = run_until(&mut engine, |c, _| c.len() == 1);
+    modem.join().unwrap();
+    assert_eq!(
+        completions[0].result.as_ref().unwrap().lines,
+        vec!["+CMGW: 7"]
+    );
+}
+
+#[test]
+fn test_overlong_line_split_from_terminator() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = thread::spawn(move || {
+        let script = vec![(
+            &b"AT+QENG\r"[..],
+            &b"\r\n+QENG: 0123456789abcdef0123456789"[..],
+        )];
+        let mut port = spawn_modem(master, script).join().unwrap();
+        // Let the engine drop the long line before its terminator arrives
+        thread::sleep(Duration::from_millis(100));
+        port.write_all(b"\r\nOK\r\n").unwrap();
+        port
+    });
+
+    let mut engine = AtEngine::new(slave).with_max_line_length(16);
+    engine.submit(Command::new("AT+QENG").with_timeout(Duration::from_secs(2)));
+    let (completions, _) = run_until(&mut engine, |c, _| c.len() == 1);
+    modem.join().unwrap();
+    let response = completions[0].result.as_ref().unwrap();
+    assert!(response.lines.is_empty());
+    assert_eq!(response.result_code, "OK");
+}
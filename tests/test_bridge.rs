@@ -0,0 +1,216 @@
+#![cfg(unix)]
+mod common;
+use mio::net::TcpListener;
+use mio_serial::bridge::{Bridge, BridgePort, ConnectionPolicy};
+use mio_serial::{SerialPort, SerialStream};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn listener() -> TcpListener {
+    TcpListener::bind("127.0.0.1:0".parse().unwrap()).expect("unable to bind")
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let started = Instant::now();
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                return stream;
+            }
+            Err(e) if started.elapsed() > Duration::from_secs(5) => {
+                panic!("unable to connect to bridge: {e}")
+            }
+            Err(_) => thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+fn write_serial(stream: &mut SerialStream, mut data: &[u8]) {
+    let started = Instant::now();
+    while !data.is_empty() {
+        assert!(
+            started.elapsed() < Duration::from_secs(10),
+            "serial timed out"
+        );
+        match stream.write(data) {
+            Ok(n) => data = &data[n..],
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => panic!("serial write failed: {e}"),
+        }
+    }
+}
+
+fn read_tcp(stream: &mut TcpStream, len: usize) -> Vec<u8> {
+    let mut data = vec![0u8; len];
+    stream
+        .read_exact(&mut data)
+        .expect("short read from bridge");
+    data
+}
+
+/// Wait for the bridge to close `stream`
+fn assert_closed(stream: &mut TcpStream) {
+    let mut buf = [0u8; 64];
+    assert_eq!(stream.read(&mut buf).expect("no EOF from bridge"), 0);
+}
+
+#[test]
+fn test_monitor_kick_old_and_idle_timeout() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let port = BridgePort::new(slave, listener())
+        .with_monitor(listener())
+        .with_policy(ConnectionPolicy::KickOld)
+        .with_idle_timeout(Some(Duration::from_millis(500)));
+    let addr = port.local_addr().unwrap();
+    let monitor_addr = port.monitor_addr().unwrap().unwrap();
+    let mut bridge = Bridge::new().unwrap();
+    bridge.add(port).unwrap();
+    thread::spawn(move || bridge.run());
+
+    let mut a = connect(addr);
+    let mut monitor = connect(monitor_addr);
+    thread::sleep(Duration::from_millis(50));
+
+    // Serial data goes to everybody; only the read-write client can send
+    write_serial(&mut master, b"login: ");
+    assert_eq!(read_tcp(&mut a, 7), b"login: ");
+    assert_eq!(read_tcp(&mut monitor, 7), b"login: ");
+    monitor.write_all(b"ignored").unwrap();
+    a.write_all(b"root\r").unwrap();
+    assert_eq!(common::read_serial(&mut master, 5), b"root\r");
+
+    // A second client replaces the first
+    let mut b = connect(addr);
+    assert_closed(&mut a);
+    b.write_all(b"ls\r").unwrap();
+    assert_eq!(common::read_serial(&mut master, 3), b"ls\r");
+    write_serial(&mut master, b"$ ");
+    assert_eq!(read_tcp(&mut b, 2), b"$ ");
+    assert_eq!(read_tcp(&mut monitor, 2), b"$ ");
+
+    // Then both go quiet and are closed
+    let quiet = Instant::now();
+    assert_closed(&mut b);
+    assert_closed(&mut monitor);
+    assert!(quiet.elapsed() >= Duration::from_millis(400));
+}
+
+#[test]
+fn test_reject_new_and_backpressure() {
+    let mut bridge = Bridge::new().unwrap();
+    let mut masters = Vec::new();
+    let mut addrs = Vec::new();
+    for _ in 0..2 {
+        let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+        let port = BridgePort::new(slave, listener()).with_max_clients(2);
+        addrs.push(port.local_addr().unwrap());
+        bridge.add(port).unwrap();
+        masters.push(master);
+    }
+    assert_eq!(bridge.len(), 2);
+    thread::spawn(move || bridge.run());
+
+    let mut first = connect(addrs[0]);
+    let mut second = connect(addrs[0]);
+    let mut other = connect(addrs[1]);
+    let mut third = connect(addrs[0]);
+    assert_closed(&mut third);
+
+    // Far more than the pty and the queues hold, in both directions at once, with the
+    // TCP side read late so the serial side has to wait for it
+    let outbound = common::pattern(1 << 20, 1);
+    let inbound = common::pattern(1 << 20, 2);
+    let mut master = masters.remove(0);
+    let sender = {
+        let inbound = inbound.clone();
+        thread::spawn(move || {
+            write_serial(&mut master, &inbound);
+            master
+        })
+    };
+    let writer = {
+        let outbound = outbound.clone();
+        let mut first = first.try_clone().unwrap();
+        thread::spawn(move || first.write_all(&outbound).unwrap())
+    };
+    thread::sleep(Duration::from_millis(200));
+    let to_second = thread::spawn(move || read_tcp(&mut second, 1 << 20));
+    assert!(
+        read_tcp(&mut first, 1 << 20) == inbound,
+        "data to client corrupted"
+    );
+    assert!(
+        to_second.join().unwrap() == inbound,
+        "data to second client corrupted"
+    );
+
+    let mut master = sender.join().unwrap();
+    assert!(
+        common::read_serial(&mut master, 1 << 20) == outbound,
+        "data to port corrupted"
+    );
+    writer.join().unwrap();
+
+    // The other port was not disturbed
+    other.write_all(b"ping").unwrap();
+    assert_eq!(common::read_serial(&mut masters[0], 4), b"ping");
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_bridge_binary() {
+    // The bridge opens the slave side by name; the test talks to the master
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let device = slave.name().expect("pty has no name");
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .expect("unable to find a free port");
+
+    let dir = std::env::temp_dir();
+    let config = dir.join(format!("mio-serial-bridge-{}.conf", std::process::id()));
+    std::fs::write(
+        &config,
+        format!(
+            "# test bench\n[port]\ndevice = {device}\nlisten = {addr}\nbaud = 115200\n\
+             policy = kick-old\n"
+        ),
+    )
+    .unwrap();
+    let bad = dir.join(format!("mio-serial-bridge-{}.bad", std::process::id()));
+    std::fs::write(&bad, "[port]\ndevice = /dev/null\nbaud = fast\n").unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_mio-serial-bridge");
+    let status = Command::new(binary).args(["--check"]).arg(&config).status();
+    assert!(status.unwrap().success());
+    let output = Command::new(binary).arg(&bad).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("line 3"));
+
+    let _bridge = KillOnDrop(Command::new(binary).arg(&config).spawn().unwrap());
+    let mut client = connect(addr);
+    client.write_all(b"AT\r").unwrap();
+    assert_eq!(common::read_serial(&mut master, 3), b"AT\r");
+    write_serial(&mut master, b"OK\r\n");
+    assert_eq!(read_tcp(&mut client, 4), b"OK\r\n");
+    assert_eq!(master.baud_rate().unwrap(), 115_200);
+
+    let _ = std::fs::remove_file(config);
+    let _ = std::fs::remove_file(bad);
+    drop(slave);
+}
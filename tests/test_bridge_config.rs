@@ -0,0 +1,235 @@
+use mio_serial::bridge::config::{self, ConfigError, PortConfig, SerialConfig};
+use mio_serial::bridge::ConnectionPolicy;
+use mio_serial::{DataBits, Delimiter, FlowControl, Parity, StopBits};
+use std::process::Command;
+use std::time::Duration;
+
+fn serial(device: &str, baud_rate: u32) -> SerialConfig {
+    SerialConfig {
+        device: device.to_string(),
+        baud_rate,
+        data_bits: DataBits::Eight,
+        parity: Parity::None,
+        stop_bits: StopBits::One,
+        flow_control: FlowControl::None,
+    }
+}
+
+#[test]
+fn test_parse() {
+    let text = "\
+# bench
+[port]
+device = /dev/ttyUSB0
+listen = 0.0.0.0:7000
+
+[port]
+device = /dev/ttyUSB1
+baud = 115200
+data-bits = 7
+parity = even
+stop-bits = 2
+flow-control = hardware
+listen = 127.0.0.1:7002
+monitor = 127.0.0.1:7003
+max-clients = 2
+max-monitors = 8
+policy = kick-old
+idle-timeout = 600
+
+[udp]
+device = /dev/ttyUSB2
+baud = 4800
+destination = 10.0.0.255:10110
+destination = 239.192.0.1:10110
+bind = 0.0.0.0:10110
+records = crlf
+max-datagram = 512
+receive = true
+multicast-ttl = 4
+";
+    let ports = config::parse(text).unwrap();
+    assert_eq!(
+        ports,
+        vec![
+            PortConfig::Tcp {
+                serial: serial("/dev/ttyUSB0", config::DEFAULT_BAUD_RATE),
+                listen: "0.0.0.0:7000".parse().unwrap(),
+                monitor: None,
+                max_clients: mio_serial::bridge::DEFAULT_MAX_CLIENTS,
+                max_monitors: mio_serial::bridge::DEFAULT_MAX_MONITORS,
+                policy: ConnectionPolicy::RejectNew,
+                idle_timeout: None,
+            },
+            PortConfig::Tcp {
+                serial: SerialConfig {
+                    data_bits: DataBits::Seven,
+                    parity: Parity::Even,
+                    stop_bits: StopBits::Two,
+                    flow_control: FlowControl::Hardware,
+                    ..serial("/dev/ttyUSB1", 115_200)
+                },
+                listen: "127.0.0.1:7002".parse().unwrap(),
+                monitor: Some("127.0.0.1:7003".parse().unwrap()),
+                max_clients: 2,
+                max_monitors: 8,
+                policy: ConnectionPolicy::KickOld,
+                idle_timeout: Some(Duration::from_secs(600)),
+            },
+            PortConfig::Udp {
+                serial: serial("/dev/ttyUSB2", 4800),
+                bind: "0.0.0.0:10110".parse().unwrap(),
+                destinations: vec![
+                    "10.0.0.255:10110".parse().unwrap(),
+                    "239.192.0.1:10110".parse().unwrap(),
+                ],
+                records: Some(Delimiter::CrLf),
+                max_datagram: 512,
+                receive: true,
+                multicast_ttl: Some(4),
+            },
+        ]
+    );
+
+    // An idle timeout of zero means never
+    let ports = config::parse("[port]\ndevice = d\nlisten = 0.0.0.0:1\nidle-timeout = 0").unwrap();
+    assert!(matches!(
+        ports[0],
+        PortConfig::Tcp {
+            idle_timeout: None,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn test_parse_errors() {
+    let cases: &[(&str, Option<usize>, &str)] = &[
+        ("", None, "no [port] or [udp] sections"),
+        ("# only comments\n\n", None, "no [port] or [udp] sections"),
+        (
+            "device = /dev/ttyUSB0\n",
+            Some(1),
+            "expected [port] or [udp]",
+        ),
+        ("[serial]\n", Some(1), "expected [port] or [udp]"),
+        (
+            "[port]\ndevice /dev/ttyUSB0\n",
+            Some(2),
+            "expected key = value",
+        ),
+        ("[port]\nspeed = 9600\n", Some(2), "unknown key \"speed\""),
+        ("[port]\nbaud = fast\n", Some(2), "invalid baud \"fast\""),
+        ("[port]\nbaud = -1\n", Some(2), "invalid baud \"-1\""),
+        (
+            "[port]\ndata-bits = 9\n",
+            Some(2),
+            "invalid data-bits \"9\"",
+        ),
+        (
+            "[port]\nparity = mark\n",
+            Some(2),
+            "invalid parity \"mark\"",
+        ),
+        (
+            "[port]\nstop-bits = 1.5\n",
+            Some(2),
+            "invalid stop-bits \"1.5\"",
+        ),
+        (
+            "[port]\nflow-control = xon\n",
+            Some(2),
+            "invalid flow-control \"xon\"",
+        ),
+        (
+            "[port]\nlisten = 7000\n",
+            Some(2),
+            "invalid listen \"7000\"",
+        ),
+        (
+            "[port]\npolicy = kick-new\n",
+            Some(2),
+            "invalid policy \"kick-new\"",
+        ),
+        (
+            "[port]\nidle-timeout = 1m\n",
+            Some(2),
+            "invalid idle-timeout \"1m\"",
+        ),
+        (
+            "[port]\nbind = 0.0.0.0:1\n",
+            Some(2),
+            "\"bind\" does not belong in [port]",
+        ),
+        (
+            "[udp]\nlisten = 0.0.0.0:1\n",
+            Some(2),
+            "\"listen\" does not belong in [udp]",
+        ),
+        ("[udp]\nrecords = nul\n", Some(2), "invalid records \"nul\""),
+        ("[udp]\nreceive = yes\n", Some(2), "invalid receive \"yes\""),
+        (
+            "[udp]\nmulticast-ttl = 256k\n",
+            Some(2),
+            "invalid multicast-ttl",
+        ),
+        (
+            "[port]\nlisten = 0.0.0.0:1\n",
+            Some(1),
+            "[port] has no device",
+        ),
+        (
+            "[port]\ndevice = d\n",
+            Some(1),
+            "[port] has no listen address",
+        ),
+        ("[udp]\ndevice = d\n", Some(1), "[udp] has no destination"),
+        // A section is checked when the next one starts
+        (
+            "[port]\ndevice = d\nlisten = 0.0.0.0:1\n\n[udp]\ndevice = d\n",
+            Some(5),
+            "[udp] has no destination",
+        ),
+        (
+            "[udp]\ndevice = d\n[port]\ndevice = d\nlisten = 0.0.0.0:1\n",
+            Some(1),
+            "[udp] has no destination",
+        ),
+    ];
+    for &(text, line, message) in cases {
+        let err: ConfigError = config::parse(text).unwrap_err();
+        assert_eq!(err.line, line, "{text:?}");
+        assert!(err.message.starts_with(message), "{text:?}: {err}");
+    }
+
+    let err = config::parse("[port]\nbaud = fast\n").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2: invalid baud \"fast\": invalid digit found in string"
+    );
+}
+
+#[test]
+fn test_bridge_arguments() {
+    let binary = env!("CARGO_BIN_EXE_mio-serial-bridge");
+    let help = Command::new(binary).arg("--help").output().unwrap();
+    assert!(help.status.success());
+    assert!(String::from_utf8_lossy(&help.stdout).starts_with("Usage: mio-serial-bridge"));
+
+    for args in [
+        &[][..],
+        &["--check"][..],
+        &["--verbose", "bridge.conf"][..],
+        &["bridge.conf", "other.conf"][..],
+    ] {
+        let output = Command::new(binary).args(args).output().unwrap();
+        assert_eq!(output.status.code(), Some(2), "{args:?}");
+        assert!(String::from_utf8_lossy(&output.stderr).starts_with("error: "));
+    }
+
+    let missing =
+        std::env::temp_dir().join(format!("mio-serial-bridge-{}.none", std::process::id()));
+    let output = Command::new(binary).arg(&missing).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unable to read"));
+}
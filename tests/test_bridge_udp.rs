@@ -0,0 +1,174 @@
+#![cfg(unix)]
+mod common;
+use mio::net::UdpSocket;
+use mio_serial::bridge::{Bridge, UdpPort, UdpStats};
+use mio_serial::{Delimiter, SerialPort, SerialStream};
+use std::io::Write;
+use std::net::SocketAddr;
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+fn receiver() -> (std::net::UdpSocket, SocketAddr) {
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("unable to bind");
+    socket
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let addr = socket.local_addr().unwrap();
+    (socket, addr)
+}
+
+fn udp_port(slave: SerialStream) -> UdpPort {
+    let socket = UdpSocket::bind("127.0.0.1:0".parse().unwrap()).expect("unable to bind");
+    UdpPort::new(slave, socket)
+}
+
+/// Run `bridge` until `done` returns true
+fn run_until(bridge: &mut Bridge, mut done: impl FnMut(&mut Bridge) -> bool) {
+    let started = Instant::now();
+    while !done(bridge) {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "bridge timed out"
+        );
+        bridge
+            .run_once(Some(Duration::from_millis(10)))
+            .expect("bridge failed");
+    }
+}
+
+fn recv(socket: &std::net::UdpSocket) -> Vec<u8> {
+    let mut buf = [0u8; 2048];
+    let n = socket.recv(&mut buf).expect("no datagram");
+    buf[..n].to_vec()
+}
+
+#[test]
+fn test_chunks_to_two_destinations() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (first, first_addr) = receiver();
+    let (second, second_addr) = receiver();
+    let port = udp_port(slave)
+        .with_destination(first_addr)
+        .with_destination(second_addr)
+        .with_max_datagram(4);
+    assert_eq!(port.destinations(), [first_addr, second_addr]);
+    let mut bridge = Bridge::new().unwrap();
+    let index = bridge.add_udp(port).unwrap();
+    assert_eq!(bridge.len(), 1);
+
+    // Reads longer than the maximum datagram are split
+    master.write_all(b"abcdef").unwrap();
+    run_until(&mut bridge, |b| {
+        b.udp_port(index).unwrap().stats().sent >= 4
+    });
+    for socket in [&first, &second] {
+        let mut data = Vec::new();
+        while data.len() < 6 {
+            let datagram = recv(socket);
+            assert!(datagram.len() <= 4);
+            data.extend(datagram);
+        }
+        assert_eq!(data, b"abcdef");
+    }
+}
+
+#[test]
+fn test_records_and_receive() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (listener, listener_addr) = receiver();
+    let port = udp_port(slave)
+        .with_destination(listener_addr)
+        .with_records(Delimiter::CrLf)
+        .with_max_datagram(16)
+        .with_receive(true);
+    let port_addr = port.local_addr().unwrap();
+    let mut bridge = Bridge::new().unwrap();
+    let index = bridge.add_udp(port).unwrap();
+
+    // One datagram per record, and records too long for a datagram are dropped
+    master
+        .write_all(b"$GPGGA,1*00\r\n$GPGSV,a very long sentence*00\r\n$GPRMC,2*")
+        .unwrap();
+    master.write_all(b"00\r\n").unwrap();
+    run_until(&mut bridge, |b| {
+        b.udp_port(index).unwrap().stats().sent >= 2
+    });
+    assert_eq!(recv(&listener), b"$GPGGA,1*00");
+    assert_eq!(recv(&listener), b"$GPRMC,2*00");
+
+    // Datagrams from anyone go to the serial port
+    listener.send_to(b"$PMTK,ACK\r\n", port_addr).unwrap();
+    run_until(&mut bridge, |b| {
+        b.udp_port(index).unwrap().stats().received >= 1
+    });
+    assert_eq!(common::read_serial(&mut master, 11), b"$PMTK,ACK\r\n");
+    assert_eq!(
+        bridge.udp_port(index).unwrap().stats(),
+        UdpStats {
+            sent: 2,
+            received: 1,
+            dropped: 1,
+        }
+    );
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_bridge_binary_udp() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let device = slave.name().expect("pty has no name");
+    let (listener, listener_addr) = receiver();
+
+    let dir = std::env::temp_dir();
+    let config = dir.join(format!("mio-serial-bridge-udp-{}.conf", std::process::id()));
+    std::fs::write(
+        &config,
+        format!(
+            "[udp]\ndevice = {device}\nbaud = 4800\ndestination = {listener_addr}\n\
+             records = lf\n"
+        ),
+    )
+    .unwrap();
+    let bad = dir.join(format!("mio-serial-bridge-udp-{}.bad", std::process::id()));
+    std::fs::write(
+        &bad,
+        format!("[udp]\ndevice = /dev/null\nlisten = {listener_addr}\n"),
+    )
+    .unwrap();
+
+    let binary = env!("CARGO_BIN_EXE_mio-serial-bridge");
+    let output = Command::new(binary).arg(&bad).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 3"), "{stderr}");
+
+    let _bridge = KillOnDrop(Command::new(binary).arg(&config).spawn().unwrap());
+    // Lines written before the bridge opens the port are lost, so keep sending until one
+    // arrives
+    listener
+        .set_read_timeout(Some(Duration::from_millis(100)))
+        .unwrap();
+    let started = Instant::now();
+    let mut buf = [0u8; 64];
+    let n = loop {
+        assert!(started.elapsed() < Duration::from_secs(5), "no datagram");
+        master.write_all(b"hello\n").unwrap();
+        if let Ok(n) = listener.recv(&mut buf) {
+            break n;
+        }
+    };
+    assert_eq!(&buf[..n], b"hello");
+    assert_eq!(master.baud_rate().unwrap(), 4800);
+
+    let _ = std::fs::remove_file(config);
+    let _ = std::fs::remove_file(bad);
+    drop(slave);
+}
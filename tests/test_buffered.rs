@@ -0,0 +1,89 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::{BufferedSerialStream, SerialStream};
+use std::io::{self, Read};
+use std::time::Duration;
+
+const TOKEN_WRITER: Token = Token(0);
+const TOKEN_READER: Token = Token(1);
+
+#[test]
+fn test_queue_write_small_is_not_queued() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (poll, _events) = common::init_with_poll();
+    let mut port = BufferedSerialStream::new(master);
+    poll.registry()
+        .register(&mut port, TOKEN_WRITER, Interest::READABLE)
+        .expect("unable to register port");
+
+    let n = port.queue_write(b"hello").expect("unable to queue write");
+    assert_eq!(n, 5);
+    assert!(!port.is_write_pending());
+
+    std::thread::sleep(Duration::from_millis(50));
+    let mut buf = [0u8; 16];
+    common::checked_read(&mut slave, &mut buf, b"hello");
+}
+
+#[test]
+fn test_backpressure_over_pair() {
+    const HIGH_WATER_MARK: usize = 16 * 1024;
+    const TOTAL: usize = 256 * 1024;
+
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut port = BufferedSerialStream::with_high_water_mark(master, HIGH_WATER_MARK);
+
+    poll.registry()
+        .register(&mut port, TOKEN_WRITER, Interest::READABLE)
+        .expect("unable to register writer");
+    poll.registry()
+        .register(&mut slave, TOKEN_READER, Interest::READABLE)
+        .expect("unable to register reader");
+
+    let data: Vec<u8> = (0..TOTAL).map(|i| (i % 251) as u8).collect();
+    let mut sent = 0;
+    let mut received = Vec::with_capacity(TOTAL);
+    let mut saw_backpressure = false;
+    let mut buf = [0u8; 4096];
+
+    while received.len() < TOTAL {
+        // Offer data until the queue pushes back
+        while sent < TOTAL {
+            match port.queue_write(&data[sent..]) {
+                Ok(n) => sent += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    saw_backpressure = true;
+                    break;
+                }
+                Err(e) => panic!("unexpected write error: {e}"),
+            }
+        }
+        assert!(port.write_queue().len() <= HIGH_WATER_MARK);
+
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .expect("unable to poll");
+        assert!(!events.is_empty(), "timed out waiting for events");
+
+        for event in events.iter() {
+            match event.token() {
+                TOKEN_WRITER if event.is_writable() => {
+                    port.on_writable().expect("unable to flush write queue");
+                }
+                TOKEN_READER => loop {
+                    match slave.read(&mut buf) {
+                        Ok(n) => received.extend_from_slice(&buf[..n]),
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                        Err(e) => panic!("unexpected read error: {e}"),
+                    }
+                },
+                _ => {}
+            }
+        }
+    }
+
+    assert!(saw_backpressure, "queue never reached its high-water mark");
+    assert!(!port.is_write_pending());
+    assert_eq!(received, data);
+}
@@ -0,0 +1,227 @@
+#![cfg(unix)]
+mod common;
+use mio_serial::capture::{
+    replay, CaptureReader, CaptureWriter, ControlLines, Direction, Entry, Event, Settings,
+};
+use mio_serial::tee::SerialTee;
+use mio_serial::{
+    DataBits, FlowControl, Parity, SerialPort, SerialPortBuilderExt, SerialStream, StopBits,
+};
+use std::io::{self, Cursor, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn data(ms: u64, direction: Direction, data: &[u8]) -> Entry {
+    Entry {
+        offset: Duration::from_millis(ms),
+        event: Event::Data {
+            direction,
+            data: data.to_vec(),
+        },
+    }
+}
+
+fn settings(ms: u64, baud_rate: u32) -> Entry {
+    Entry {
+        offset: Duration::from_millis(ms),
+        event: Event::Settings(Settings {
+            baud_rate,
+            data_bits: DataBits::Eight,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            flow_control: FlowControl::None,
+        }),
+    }
+}
+
+fn capture(entries: &[Entry]) -> Vec<u8> {
+    let mut writer = CaptureWriter::new(Vec::new());
+    for entry in entries {
+        writer.write_entry(entry).unwrap();
+    }
+    writer.flush().unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn test_round_trip() {
+    let entries = vec![
+        settings(0, 9600),
+        data(10, Direction::ToDevice, b"ATI\r"),
+        data(25, Direction::FromDevice, b"OK\r\n"),
+        Entry {
+            offset: Duration::from_millis(40),
+            event: Event::ControlLines(ControlLines {
+                dtr: true,
+                cd: true,
+                break_state: true,
+                ..ControlLines::default()
+            }),
+        },
+        data(1_000_000, Direction::FromDevice, &[0u8; 70_000]),
+    ];
+    let mut bytes = capture(&entries);
+    assert_eq!(&bytes[..8], b"MIOSCAP1");
+
+    // A record of a kind added later is skipped
+    bytes.extend_from_slice(&2_000_000_000u64.to_le_bytes());
+    bytes.push(200);
+    bytes.extend_from_slice(&3u32.to_le_bytes());
+    bytes.extend_from_slice(b"new");
+    let extra = data(2_000_001, Direction::ToDevice, b"ATH\r");
+    bytes.extend_from_slice(&capture(std::slice::from_ref(&extra))[16..]);
+
+    let reader = CaptureReader::new(&bytes[..]).unwrap();
+    let started = reader
+        .start_time()
+        .elapsed()
+        .expect("capture starts in the future");
+    assert!(started < Duration::from_secs(5));
+    let read: Vec<Entry> = reader.collect::<io::Result<_>>().unwrap();
+    assert_eq!(read[..entries.len()], entries[..]);
+    assert_eq!(read[entries.len()..], [extra]);
+
+    // Live records are timestamped from when the writer was created
+    let mut writer = CaptureWriter::new(Vec::new());
+    thread::sleep(Duration::from_millis(20));
+    writer.write_data(Direction::FromDevice, b"$").unwrap();
+    let live = writer.into_inner();
+    let entry = CaptureReader::new(&live[..])
+        .unwrap()
+        .read_entry()
+        .unwrap()
+        .unwrap();
+    assert!(entry.offset >= Duration::from_millis(20));
+
+    let mut reader = CaptureReader::new(&bytes[..bytes.len() - 1]).unwrap();
+    let error = reader.find_map(Result::err).expect("no error");
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    let error = CaptureReader::new(&b"PCAPNG\0\0\0\0\0\0\0\0\0\0"[..]).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn test_replay_timing() {
+    let bytes = capture(&[
+        data(0, Direction::FromDevice, b"$GPGGA*00\r\n"),
+        data(50, Direction::ToDevice, b"not played"),
+        settings(100, 4800),
+        data(200, Direction::FromDevice, b"$GPRMC*00\r\n"),
+        data(400, Direction::FromDevice, b"$GPGSV*00\r\n"),
+    ]);
+
+    // Twice as fast, so the sentences are due at 0, 100 and 200 ms
+    let (mut port, player) = replay(
+        CaptureReader::new(Cursor::new(bytes)).unwrap(),
+        Direction::FromDevice,
+        2.0,
+    )
+    .unwrap();
+    let started = Instant::now();
+    let mut received = Vec::new();
+    let mut arrivals = Vec::new();
+    let mut buf = [0u8; 256];
+    while received.len() < 33 {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "replay timed out"
+        );
+        match port.read(&mut buf) {
+            Ok(n) => {
+                received.extend_from_slice(&buf[..n]);
+                arrivals.push((received.len(), started.elapsed()));
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1))
+            }
+            Err(e) => panic!("read failed: {e}"),
+        }
+    }
+    assert_eq!(received, b"$GPGGA*00\r\n$GPRMC*00\r\n$GPGSV*00\r\n");
+    for (len, due) in [(11, 0), (22, 100), (33, 200)] {
+        let (_, at) = arrivals.iter().find(|(n, _)| *n >= len).unwrap();
+        let due = Duration::from_millis(due);
+        assert!(
+            *at + Duration::from_millis(5) >= due,
+            "{len} bytes at {at:?}"
+        );
+        assert!(
+            *at < due + Duration::from_millis(80),
+            "{len} bytes at {at:?}"
+        );
+    }
+    let master = player.join().unwrap().unwrap();
+    assert_eq!(master.baud_rate().unwrap(), 4800);
+
+    // Without delays
+    let bytes = capture(&[
+        data(0, Direction::ToDevice, b"AT\r"),
+        data(60_000, Direction::ToDevice, b"ATZ\r"),
+    ]);
+    let started = Instant::now();
+    let (mut port, player) = replay(
+        CaptureReader::new(Cursor::new(bytes)).unwrap(),
+        Direction::ToDevice,
+        f64::INFINITY,
+    )
+    .unwrap();
+    let _master = player.join().unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    let mut received = [0u8; 7];
+    port.read_exact(&mut received).unwrap();
+    assert_eq!(&received, b"AT\rATZ\r");
+}
+
+#[test]
+fn test_tee_capture() {
+    let (mut device, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let output = common::SharedBuffer::default();
+    let mut tee = SerialTee::new(slave)
+        .unwrap()
+        .with_capture(output.clone())
+        .with_settings_poll_interval(Duration::from_millis(10));
+    let mut app = mio_serial::new(tee.slave_path().unwrap(), 9600)
+        .open_native_async()
+        .unwrap();
+    thread::spawn(move || tee.run());
+
+    app.write_all(b"*IDN?\n").unwrap();
+    assert_eq!(common::read_serial(&mut device, 6), b"*IDN?\n");
+    device.write_all(b"ACME\n").unwrap();
+    assert_eq!(common::read_serial(&mut app, 5), b"ACME\n");
+    app.set_baud_rate(57600).unwrap();
+    let started = Instant::now();
+    while device.baud_rate().unwrap() != 57600 {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "baud rate not mirrored"
+        );
+        thread::sleep(Duration::from_millis(10));
+    }
+    thread::sleep(Duration::from_millis(50));
+
+    let bytes = output.bytes();
+    let entries: Vec<Entry> = CaptureReader::new(&bytes[..])
+        .unwrap()
+        .collect::<io::Result<_>>()
+        .unwrap();
+    let events: Vec<String> = entries
+        .iter()
+        .filter_map(|entry| match &entry.event {
+            Event::Data { direction, data } => Some(format!(
+                "{} {}",
+                direction.marker(),
+                String::from_utf8_lossy(data).trim_end()
+            )),
+            Event::Settings(settings) => Some(format!("= {}", settings.baud_rate)),
+            Event::ControlLines(_) => None,
+        })
+        .collect();
+    // The device's settings come first, then whatever the application changed
+    assert!(events[0].starts_with("= "), "{events:?}");
+    assert!(
+        events.ends_with(&["> *IDN?".into(), "< ACME".into(), "= 57600".into()]),
+        "{events:?}"
+    );
+    assert!(entries.windows(2).all(|w| w[0].offset <= w[1].offset));
+}
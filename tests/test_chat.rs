@@ -0,0 +1,224 @@
+#![cfg(all(unix, feature = "expect"))]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::chat::{Chat, ChatError, Script, SendPart, SendString, Step, DELAY, PAUSE};
+use mio_serial::expect::Expect;
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A scripted modem: waits for each expected command (terminated by CR) and answers with
+/// the given reply
+fn spawn_modem(
+    mut port: SerialStream,
+    script: Vec<(&'static [u8], &'static [u8])>,
+) -> thread::JoinHandle<SerialStream> {
+    thread::spawn(move || {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        for (expected, reply) in script {
+            loop {
+                if let Some(end) = received.iter().position(|&b| b == b'\r') {
+                    let command: Vec<u8> = received.drain(..=end).collect();
+                    assert_eq!(
+                        String::from_utf8_lossy(&command),
+                        String::from_utf8_lossy(expected)
+                    );
+                    break;
+                }
+                assert!(Instant::now() < deadline, "modem timed out");
+                let mut buf = [0u8; 256];
+                match port.read(&mut buf) {
+                    Ok(n) => received.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5))
+                    }
+                    Err(e) => panic!("modem read failed: {e}"),
+                }
+            }
+            port.write_all(reply).unwrap();
+        }
+        port
+    })
+}
+
+fn bytes(text: &str) -> SendString {
+    SendString {
+        parts: vec![SendPart::Bytes(text.as_bytes().to_vec())],
+        carriage_return: true,
+    }
+}
+
+#[test]
+fn test_parse() {
+    let script = Script::parse(
+        r#"
+        # GPRS dial-up
+        ABORT BUSY ABORT 'NO CARRIER' REPORT +CSQ: TIMEOUT 12
+        ECHO ON
+        SAY "Dialing...\n"
+        '' \dAT&F\pE0
+        OK-AT-OK ATD*99#
+        CONNECT \c
+        ^C-EOT-\s\101\\\-
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        script.steps,
+        vec![
+            Step::Abort(b"BUSY".to_vec()),
+            Step::Abort(b"NO CARRIER".to_vec()),
+            Step::Report(b"+CSQ:".to_vec()),
+            Step::Timeout(Duration::from_secs(12)),
+            Step::Say("Dialing...\n".to_string()),
+            Step::Expect {
+                expect: Vec::new(),
+                fallbacks: Vec::new()
+            },
+            Step::Send(SendString {
+                parts: vec![
+                    SendPart::Delay(DELAY),
+                    SendPart::Bytes(b"AT&F".to_vec()),
+                    SendPart::Delay(PAUSE),
+                    SendPart::Bytes(b"E0".to_vec()),
+                ],
+                carriage_return: true,
+            }),
+            Step::Expect {
+                expect: b"OK".to_vec(),
+                fallbacks: vec![(bytes("AT"), b"OK".to_vec())]
+            },
+            Step::Send(bytes("ATD*99#")),
+            Step::Expect {
+                expect: b"CONNECT".to_vec(),
+                fallbacks: Vec::new()
+            },
+            Step::Send(SendString {
+                parts: Vec::new(),
+                carriage_return: false,
+            }),
+            Step::Expect {
+                expect: vec![0x03],
+                fallbacks: vec![(
+                    SendString {
+                        parts: vec![SendPart::Bytes(vec![0x04])],
+                        carriage_return: false,
+                    },
+                    b" A\\-".to_vec()
+                )]
+            },
+        ]
+    );
+
+    for bad in [
+        "ABORT",
+        "'unterminated",
+        "'' \\K",
+        "OK-AT",
+        "TIMEOUT soon",
+        "'' BREAK",
+    ] {
+        assert!(
+            matches!(Script::parse(bad), Err(ChatError::Parse(_))),
+            "{bad:?} parsed"
+        );
+    }
+}
+
+#[test]
+fn test_dial_with_sub_expect_and_report() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(
+        master,
+        vec![
+            // The first AT goes unanswered; the sub-expect sends it again
+            (b"ATZ\r", b""),
+            (b"AT\r", b"\r\nOK\r\n"),
+            (b"AT+CSQ\r", b"\r\n+CSQ: 21,99\r\n\r\nOK\r\n"),
+            (b"ATD*99#\r", b"\r\nCONNECT 150000000\r\n"),
+        ],
+    );
+    let script = Script::parse(
+        "ABORT BUSY REPORT +CSQ: TIMEOUT 1 SAY 'starting' \
+         '' ATZ OK-AT-OK AT+CSQ OK ATD*99# CONNECT ''",
+    )
+    .unwrap();
+    let mut chat = Chat::new(Expect::new(slave), script);
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(&mut chat, Token(0), Interest::READABLE | Interest::WRITABLE)
+        .unwrap();
+
+    let started = Instant::now();
+    let result = loop {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "script timed out"
+        );
+        chat.handle_io(Instant::now()).unwrap();
+        if let Some(result) = chat.poll_result() {
+            break result;
+        }
+        let timeout = chat
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).unwrap();
+    };
+    assert!(chat.is_finished());
+    let outcome = result.unwrap();
+    assert!(started.elapsed() >= Duration::from_secs(1));
+    assert_eq!(outcome.reports, vec!["+CSQ: 21,99"]);
+    assert_eq!(outcome.messages, vec!["starting"]);
+    modem.join().unwrap();
+}
+
+#[test]
+fn test_abort_and_timeout() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(master, vec![(b"ATD5550100\r", b"\r\nBUSY\r\n")]);
+    let script = Script::parse("ABORT 'NO CARRIER' ABORT BUSY '' ATD5550100 CONNECT ''").unwrap();
+    let err = Chat::new(Expect::new(slave), script)
+        .run_blocking()
+        .unwrap_err();
+    assert!(matches!(err, ChatError::Aborted { index: 1, ref text } if text == "BUSY"));
+    assert_eq!(err.exit_code(), 5);
+
+    modem.join().unwrap();
+
+    // A silent modem
+    let (_master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let script = Script::parse("TIMEOUT 1 ABORT ERROR '' ATH OK").unwrap();
+    let err = Chat::new(Expect::new(slave), script)
+        .run_blocking()
+        .unwrap_err();
+    assert!(matches!(err, ChatError::Timeout(ref expect) if expect == "OK"));
+    assert_eq!(err.exit_code(), 3);
+}
+
+#[test]
+fn test_send_larger_than_write_queue() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let command = "A".repeat(96 * 1024);
+    let expected: &'static [u8] = format!("{command}\r").into_bytes().leak();
+    let modem = spawn_modem(master, vec![(expected, b"\r\nOK\r\n")]);
+    let script = Script::parse(&format!("'' {command} OK ''")).unwrap();
+    let outcome = Chat::new(Expect::new(slave), script)
+        .run_blocking()
+        .unwrap();
+    assert!(outcome.reports.is_empty());
+    modem.join().unwrap();
+}
+
+#[test]
+fn test_report_same_as_expect() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let modem = spawn_modem(master, vec![(b"ATD*99#\r", b"\r\nCONNECT 115200\r\n")]);
+    let script = Script::parse("REPORT CONNECT ABORT BUSY '' ATD*99# CONNECT ''").unwrap();
+    let outcome = Chat::new(Expect::new(slave), script)
+        .run_blocking()
+        .unwrap();
+    assert_eq!(outcome.reports, vec!["CONNECT 115200"]);
+    modem.join().unwrap();
+}
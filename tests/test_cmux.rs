@@ -0,0 +1,309 @@
+#![cfg(unix)]
+mod common;
+use mio::{Events, Interest, Poll, Token};
+use mio_serial::cmux::{
+    Channel, CmuxCodec, CmuxError, Frame, FrameType, Mode, ModemSignals, Mux, MuxEvent,
+    MAX_BASIC_FRAME_SIZE,
+};
+use mio_serial::codec::{Decoder, Encoder};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const HOST: Token = Token(0);
+const MODEM: Token = Token(1);
+
+fn frame(dlci: u8, frame_type: FrameType, info: &[u8]) -> Frame {
+    Frame {
+        dlci,
+        cr: true,
+        frame_type,
+        poll_final: frame_type != FrameType::Uih,
+        info: info.to_vec(),
+    }
+}
+
+#[test]
+fn test_codec() {
+    let mut basic = CmuxCodec::new(Mode::Basic);
+    let mut dst = Vec::new();
+    basic
+        .encode(&frame(0, FrameType::Sabm, &[]), &mut dst)
+        .unwrap();
+    assert_eq!(dst, [0xF9, 0x03, 0x3F, 0x01, 0x1C, 0xF9]);
+
+    let mut src = vec![0x00, 0xF9, 0xF9, 0x03, 0x73, 0x01, 0xD7, 0xF9];
+    let ua = basic.decode(&mut src).unwrap().expect("missing frame");
+    assert_eq!(
+        (ua.dlci, ua.frame_type, ua.poll_final),
+        (0, FrameType::Ua, true)
+    );
+
+    // Corrupted FCS, then a valid UIH frame sharing its flags with the previous one
+    let mut src = vec![0xF9, 0x07, 0xEF, 0x05, b'A', b'T', 0x00, 0xF9];
+    let data = frame(1, FrameType::Uih, b"AT\r");
+    basic.encode(&data, &mut src).unwrap();
+    assert!(matches!(basic.decode(&mut src), Err(CmuxError::BadFcs)));
+    assert_eq!(basic.decode(&mut src).unwrap(), Some(data));
+    assert!(basic.decode(&mut src).unwrap().is_none());
+
+    // Long frames use a two byte length field
+    let mut basic = CmuxCodec::new(Mode::Basic).with_max_frame_size(1024);
+    let long = frame(2, FrameType::Uih, &[0x55; 300]);
+    let mut src = Vec::new();
+    basic.encode(&long, &mut src).unwrap();
+    assert_eq!(&src[3..5], &[(300u16 << 1) as u8, (300 >> 7) as u8]);
+    assert_eq!(basic.decode(&mut src).unwrap(), Some(long));
+
+    // The two byte length field holds at most 15 bits, so larger sizes are clamped
+    let mut basic = CmuxCodec::new(Mode::Basic).with_max_frame_size(usize::MAX);
+    assert_eq!(basic.max_frame_size(), MAX_BASIC_FRAME_SIZE);
+    let mut src = Vec::new();
+    for len in [128, MAX_BASIC_FRAME_SIZE] {
+        let long = frame(2, FrameType::Uih, &vec![0x55; len]);
+        basic.encode(&long, &mut src).unwrap();
+        assert_eq!(basic.decode(&mut src).unwrap(), Some(long));
+    }
+    let too_long = frame(2, FrameType::Uih, &vec![0x55; MAX_BASIC_FRAME_SIZE + 1]);
+    assert!(matches!(
+        basic.encode(&too_long, &mut src),
+        Err(CmuxError::FrameTooLong(_))
+    ));
+
+    // Advanced option escapes flags, escapes and XON/XOFF
+    let mut advanced = CmuxCodec::new(Mode::Advanced);
+    let data = frame(3, FrameType::Ui, &[0x7E, 0x7D, 0x11, 0x13, 0xF9]);
+    let mut src = Vec::new();
+    advanced.encode(&data, &mut src).unwrap();
+    assert_eq!(src.iter().filter(|&&b| b == 0x7E).count(), 2);
+    assert!(!src.contains(&0x11) && !src.contains(&0x13));
+    assert_eq!(advanced.decode(&mut src).unwrap(), Some(data));
+}
+
+struct Harness {
+    poll: Poll,
+    events: Events,
+    host: Mux,
+    modem: Mux,
+    host_events: Vec<MuxEvent>,
+    modem_events: Vec<MuxEvent>,
+}
+
+impl Harness {
+    fn new(mode: Mode, max_frame_size: usize) -> Self {
+        let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+        let (poll, events) = common::init_with_poll();
+        let mut host = Mux::new(slave, mode).with_max_frame_size(max_frame_size);
+        let mut modem = Mux::responder(master, mode).with_max_frame_size(max_frame_size);
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        poll.registry().register(&mut host, HOST, interest).unwrap();
+        poll.registry()
+            .register(&mut modem, MODEM, interest)
+            .unwrap();
+        Self {
+            poll,
+            events,
+            host,
+            modem,
+            host_events: Vec::new(),
+            modem_events: Vec::new(),
+        }
+    }
+
+    fn turn(&mut self) {
+        self.poll
+            .poll(&mut self.events, Some(Duration::from_millis(20)))
+            .expect("unable to poll");
+        let now = Instant::now();
+        self.host.handle_io(now).unwrap();
+        self.modem.handle_io(now).unwrap();
+        self.host_events
+            .extend(std::iter::from_fn(|| self.host.poll_event()));
+        self.modem_events
+            .extend(std::iter::from_fn(|| self.modem.poll_event()));
+    }
+
+    fn run_until(&mut self, mut done: impl FnMut(&mut Self) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !done(self) {
+            assert!(Instant::now() < deadline, "timed out");
+            self.turn();
+        }
+    }
+
+    fn take_incoming(&mut self) -> Channel {
+        let index = self
+            .modem_events
+            .iter()
+            .position(|e| matches!(e, MuxEvent::Incoming(_)))
+            .expect("no incoming channel");
+        match self.modem_events.remove(index) {
+            MuxEvent::Incoming(channel) => channel,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read `len` bytes from `channel`, driving both muxes meanwhile
+    fn read_channel(&mut self, channel: &mut Channel, len: usize) -> Vec<u8> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        self.run_until(|_| {
+            loop {
+                match channel.read(&mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => received.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                    Err(e) => panic!("channel read failed: {e}"),
+                }
+            }
+            received.len() >= len
+        });
+        received
+    }
+}
+
+#[test]
+fn test_channels_and_signals() {
+    let mut h = Harness::new(Mode::Basic, 31);
+    assert!(matches!(h.host.open_channel(1), Err(CmuxError::NotStarted)));
+    h.host.start().unwrap();
+    h.run_until(|h| h.host.is_started() && h.modem.is_started());
+    assert!(matches!(h.host_events[..], [MuxEvent::Started]));
+
+    let mut at = h.host.open_channel(1).unwrap();
+    let mut gnss = h.host.open_channel(2).unwrap();
+    h.run_until(|h| h.host_events.len() == 3 && h.modem_events.len() == 3);
+    assert!(matches!(
+        h.host_events[1..],
+        [MuxEvent::Opened(1), MuxEvent::Opened(2)]
+    ));
+    let mut modem_at = h.take_incoming();
+    let mut modem_gnss = h.take_incoming();
+    assert_eq!((modem_at.dlci(), modem_gnss.dlci()), (1, 2));
+
+    // Data longer than one frame, in both directions and on two channels at once
+    at.write_all(b"AT+CGMI;+CGMM;+CGMR;+CGSN\r").unwrap();
+    modem_gnss
+        .write_all(b"$GPHDT,274.07,T*03\r\n$GPHDT,274.08,T*0C\r\n")
+        .unwrap();
+    assert_eq!(
+        h.read_channel(&mut modem_at, 26),
+        b"AT+CGMI;+CGMM;+CGMR;+CGSN\r"
+    );
+    assert_eq!(
+        h.read_channel(&mut gnss, 40),
+        b"$GPHDT,274.07,T*03\r\n$GPHDT,274.08,T*0C\r\n"
+    );
+
+    // MSC carries the emulated control lines
+    let ring = ModemSignals {
+        incoming_call: true,
+        data_valid: true,
+        ..ModemSignals::default()
+    };
+    h.modem.set_signals(1, ring).unwrap();
+    h.run_until(|h| h.host.remote_signals(1).is_some());
+    assert_eq!(h.host.remote_signals(1), Some(ring));
+    assert!(matches!(
+        h.host_events.last(),
+        Some(MuxEvent::SignalsChanged { dlci: 1, .. })
+    ));
+
+    // Control messages beyond the write queue's capacity are held back, not dropped
+    const BURST: usize = 12_000;
+    for i in 0..BURST {
+        let signals = ModemSignals {
+            ready_to_receive: i % 2 == 0,
+            ..ring
+        };
+        h.host.set_signals(1, signals).unwrap();
+    }
+    let changes = |events: &[MuxEvent]| {
+        events
+            .iter()
+            .filter(|e| matches!(e, MuxEvent::SignalsChanged { dlci: 1, .. }))
+            .count()
+    };
+    h.run_until(|h| changes(&h.modem_events) == BURST);
+    assert_eq!(
+        h.modem.remote_signals(1).map(|s| s.ready_to_receive),
+        Some(false)
+    );
+
+    // Dropping a channel closes it, and the other side reads end-of-file
+    drop(gnss);
+    h.run_until(|h| h.host.channel_state(2).is_none() && h.modem.channel_state(2).is_none());
+    h.run_until(|_| matches!(modem_gnss.read(&mut [0u8; 8]), Ok(0)));
+
+    h.host.close().unwrap();
+    h.run_until(|h| {
+        h.host_events
+            .iter()
+            .any(|e| matches!(e, MuxEvent::CloseDown))
+    });
+    assert!(!h.host.is_started());
+    assert!(h.modem.channel_state(1).is_none());
+    h.run_until(|_| matches!(modem_at.read(&mut [0u8; 8]), Ok(0)));
+    assert_eq!(h.host.stats().bad_frames, 0);
+}
+
+#[test]
+fn test_advanced_bulk_transfer() {
+    let mut h = Harness::new(Mode::Advanced, 64);
+    h.poll.registry().deregister(&mut h.modem).unwrap();
+    h.modem = Mux::responder(h.modem.into_inner(), Mode::Advanced)
+        .with_max_frame_size(64)
+        .with_accept_incoming(false);
+    h.poll
+        .registry()
+        .register(&mut h.modem, MODEM, Interest::READABLE | Interest::WRITABLE)
+        .unwrap();
+    h.host.start().unwrap();
+    h.run_until(|h| h.host.is_started());
+
+    // The modem refuses channels
+    let mut refused = h.host.open_channel(5).unwrap();
+    h.run_until(|h| !h.host_events.is_empty() && h.host.channel_state(5).is_none());
+    assert!(matches!(
+        h.host_events[..],
+        [MuxEvent::Started, MuxEvent::Rejected(5)]
+    ));
+    assert!(matches!(refused.read(&mut [0u8; 8]), Ok(0)));
+
+    let mut h = Harness::new(Mode::Advanced, 64);
+    h.host.start().unwrap();
+    h.run_until(|h| h.host.is_started());
+    let mut channel = h.host.open_channel(1).unwrap();
+    h.run_until(|h| {
+        h.modem_events
+            .iter()
+            .any(|e| matches!(e, MuxEvent::Incoming(_)))
+    });
+    let mut remote = h.take_incoming();
+
+    let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 + i / 251) as u8).collect();
+    let mut written = 0;
+    let mut received = Vec::new();
+    let mut buf = [0u8; 4096];
+    h.run_until(|_| {
+        if written < data.len() {
+            match channel.write(&data[written..]) {
+                Ok(n) => written += n,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => panic!("channel write failed: {e}"),
+            }
+        }
+        loop {
+            match remote.read(&mut buf) {
+                Ok(n) if n > 0 => received.extend_from_slice(&buf[..n]),
+                Ok(_) => break,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("channel read failed: {e}"),
+            }
+        }
+        received.len() == data.len()
+    });
+    assert!(received == data, "data corrupted in transit");
+    assert!(h.host.stats().tx_frames >= (data.len() / 64) as u64);
+    assert_eq!(h.modem.stats().bad_frames, 0);
+}
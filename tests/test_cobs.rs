@@ -0,0 +1,138 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::cobs::{self, CobsCodec, CobsError, Variant};
+use mio_serial::codec::FramedSerial;
+use mio_serial::SerialStream;
+use std::io::Write;
+
+const TOKEN: Token = Token(0);
+
+#[test]
+fn test_cobs_reference_vectors() {
+    let seq = |range: std::ops::RangeInclusive<u8>| range.collect::<Vec<u8>>();
+    let cases: Vec<(Vec<u8>, Vec<u8>)> = vec![
+        (vec![0x00], vec![0x01, 0x01]),
+        (vec![0x00, 0x00], vec![0x01, 0x01, 0x01]),
+        (
+            vec![0x11, 0x22, 0x00, 0x33],
+            vec![0x03, 0x11, 0x22, 0x02, 0x33],
+        ),
+        (
+            vec![0x11, 0x22, 0x33, 0x44],
+            vec![0x05, 0x11, 0x22, 0x33, 0x44],
+        ),
+        (
+            vec![0x11, 0x00, 0x00, 0x00],
+            vec![0x02, 0x11, 0x01, 0x01, 0x01],
+        ),
+        (seq(0x01..=0xFE), [vec![0xFF], seq(0x01..=0xFE)].concat()),
+        (
+            seq(0x00..=0xFE),
+            [vec![0x01, 0xFF], seq(0x01..=0xFE)].concat(),
+        ),
+        (
+            seq(0x01..=0xFF),
+            [vec![0xFF], seq(0x01..=0xFE), vec![0x02, 0xFF]].concat(),
+        ),
+    ];
+
+    for (decoded, encoded) in cases {
+        let mut out = Vec::new();
+        cobs::encode(&decoded, &mut out);
+        assert_eq!(out, encoded);
+
+        let mut buf = [0u8; 300];
+        let n = cobs::decode_into(&encoded, &mut buf, Variant::Cobs).unwrap();
+        assert_eq!(&buf[..n], &decoded[..]);
+    }
+
+    // COBS/R drops the final code when the last byte can stand in for it
+    let mut out = Vec::new();
+    cobs::encode_cobsr(&[0x11, 0x22, 0x33, 0x44], &mut out);
+    assert_eq!(out, [0x44, 0x11, 0x22, 0x33]);
+    out.clear();
+    cobs::encode_cobsr(&[0x11, 0x22, 0x33, 0x05], &mut out);
+    assert_eq!(out, [0x05, 0x11, 0x22, 0x33, 0x05]);
+
+    let mut buf = [0u8; 8];
+    let n = cobs::decode_into(&[0x44, 0x11, 0x22, 0x33], &mut buf, Variant::CobsR).unwrap();
+    assert_eq!(&buf[..n], [0x11, 0x22, 0x33, 0x44]);
+    assert!(matches!(
+        cobs::decode_into(&[0x44, 0x11, 0x22, 0x33], &mut buf, Variant::Cobs),
+        Err(CobsError::InvalidEncoding)
+    ));
+}
+
+#[test]
+fn test_cobs_round_trip_over_pair() {
+    for codec in [CobsCodec::new(), CobsCodec::new_cobsr()] {
+        let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+        let (mut poll, mut events) = common::init_with_poll();
+        let mut tx = FramedSerial::new(a, codec.clone());
+        let mut rx = FramedSerial::new(b, codec);
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .expect("unable to register receiver");
+
+        let frames: Vec<Vec<u8>> = vec![
+            vec![1, 2, 3],
+            vec![0, 0, 0],
+            (0..=255).collect(),
+            vec![0xFF; 700],
+            vec![7, 0, 200],
+        ];
+        for frame in &frames {
+            tx.send(frame).expect("unable to send frame");
+        }
+
+        let mut received = Vec::new();
+        while received.len() < frames.len() {
+            common::expect_events(
+                &mut poll,
+                &mut events,
+                vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+            );
+            while let Some(frame) = rx.read_frame().expect("unable to read frame") {
+                received.push(frame);
+            }
+        }
+        assert_eq!(received, frames);
+    }
+}
+
+#[test]
+fn test_cobs_resync_and_zero_copy_decode() {
+    let (mut a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut rx = FramedSerial::new(b, CobsCodec::new().with_max_frame_length(8));
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let mut wire = vec![0x05, 0x01, 0x00];
+    // corrupt: code points past the end of the frame
+    wire.extend_from_slice(&[0x03, 0x11, 0x22, 0x02, 0x33, 0x00]);
+    // valid
+    wire.extend_from_slice(&[0x09; 20]);
+    // overlong, longer than the maximum encoded length
+    wire.push(0x00);
+    wire.extend_from_slice(&[0x03, 0xAA, 0xBB, 0x00]);
+    a.write_all(&wire).expect("unable to write");
+    common::expect_events(
+        &mut poll,
+        &mut events,
+        vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+    );
+
+    let mut buf = [0u8; 8];
+    let mut read = |rx: &mut FramedSerial<CobsCodec>| {
+        rx.read_with(|codec, src| codec.decode_into(src, &mut buf))
+            .map(|n| n.map(|n| buf[..n].to_vec()))
+    };
+    assert!(matches!(read(&mut rx), Err(CobsError::InvalidEncoding)));
+    assert_eq!(read(&mut rx).unwrap().unwrap(), [0x11, 0x22, 0x00, 0x33]);
+    assert!(matches!(read(&mut rx), Err(CobsError::FrameTooLong(8))));
+    assert_eq!(read(&mut rx).unwrap().unwrap(), [0xAA, 0xBB]);
+    assert!(read(&mut rx).unwrap().is_none());
+}
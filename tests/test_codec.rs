@@ -0,0 +1,137 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::{Decoder, Encoder, FramedSerial};
+use mio_serial::SerialStream;
+use std::io::{self, Write};
+use std::time::Duration;
+
+const TOKEN_A: Token = Token(0);
+const TOKEN_B: Token = Token(1);
+
+/// Frames are a big-endian `u16` length followed by that many bytes.  A length of
+/// `0xFFFF` is rejected to exercise error propagation.
+struct LengthCodec;
+
+impl Decoder for LengthCodec {
+    type Item = Vec<u8>;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut Vec<u8>) -> io::Result<Option<Vec<u8>>> {
+        if src.len() < 2 {
+            return Ok(None);
+        }
+        let len = u16::from_be_bytes([src[0], src[1]]);
+        if len == 0xFFFF {
+            src.drain(..2);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "bad length"));
+        }
+        let len = len as usize;
+        if src.len() < 2 + len {
+            return Ok(None);
+        }
+        let frame = src[2..2 + len].to_vec();
+        src.drain(..2 + len);
+        Ok(Some(frame))
+    }
+}
+
+impl Encoder<&[u8]> for LengthCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: &[u8], dst: &mut Vec<u8>) -> io::Result<()> {
+        dst.extend_from_slice(&(item.len() as u16).to_be_bytes());
+        dst.extend_from_slice(item);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_framed_round_trip_with_backpressure() {
+    const HIGH_WATER_MARK: usize = 8 * 1024;
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+
+    let mut tx = FramedSerial::with_high_water_mark(a, LengthCodec, HIGH_WATER_MARK);
+    let mut rx = FramedSerial::new(b, LengthCodec);
+    poll.registry()
+        .register(&mut tx, TOKEN_A, Interest::READABLE)
+        .expect("unable to register sender");
+    poll.registry()
+        .register(&mut rx, TOKEN_B, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let frames: Vec<Vec<u8>> = (0..200)
+        .map(|i| (0..(i * 37) % 4000).map(|j| (i + j) as u8).collect())
+        .collect();
+    let mut next = 0;
+    let mut received = Vec::new();
+    let mut saw_backpressure = false;
+
+    while received.len() < frames.len() {
+        while next < frames.len() {
+            match tx.send(frames[next].as_slice()) {
+                Ok(_) => next += 1,
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    saw_backpressure = true;
+                    break;
+                }
+                Err(e) => panic!("unexpected send error: {e}"),
+            }
+        }
+
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .expect("unable to poll");
+        assert!(!events.is_empty(), "timed out waiting for events");
+        for event in events.iter() {
+            match event.token() {
+                TOKEN_A if event.is_writable() => {
+                    tx.on_writable().expect("unable to write");
+                }
+                TOKEN_B => {
+                    while let Some(frame) = rx.read_frame().expect("unable to read frame") {
+                        received.push(frame);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    assert!(saw_backpressure, "sender never filled its write queue");
+    assert!(!tx.is_write_pending());
+    assert_eq!(received, frames);
+
+    // A frame that can never fit is refused outright instead of blocking forever
+    let oversized = vec![0u8; HIGH_WATER_MARK];
+    let error = tx.send(oversized.as_slice()).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    assert!(!tx.is_write_pending());
+}
+
+#[test]
+fn test_framed_decode_error_then_recovers() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+
+    let mut tx = FramedSerial::new(a, LengthCodec);
+    let mut rx = FramedSerial::new(b, LengthCodec);
+    poll.registry()
+        .register(&mut rx, TOKEN_B, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    tx.get_mut()
+        .write_all(&[0xFF, 0xFF])
+        .expect("unable to write");
+    tx.send(&b"hello"[..]).expect("unable to send");
+    common::expect_events(
+        &mut poll,
+        &mut events,
+        vec![common::ExpectEvent::new(TOKEN_B, Interest::READABLE)],
+    );
+
+    let err = rx.read_frame().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(rx.read_frame().unwrap().unwrap(), b"hello");
+    assert!(rx.read_frame().unwrap().is_none());
+}
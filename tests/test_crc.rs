@@ -0,0 +1,21 @@
+use mio_serial::crc::*;
+
+const CHECK: &[u8] = b"123456789";
+
+#[test]
+fn test_crc8_check_values() {
+    assert_eq!(CRC_8_ROHC.checksum(CHECK), 0xD0);
+}
+
+#[test]
+fn test_crc16_check_values() {
+    assert_eq!(CRC_16_IBM_SDLC.checksum(CHECK), 0x906E);
+    assert_eq!(CRC_16_MODBUS.checksum(CHECK), 0x4B37);
+    assert_eq!(CRC_16_XMODEM.checksum(CHECK), 0x31C3);
+    assert_eq!(CRC_16_IBM_3740.checksum(CHECK), 0x29B1);
+}
+
+#[test]
+fn test_crc32_check_values() {
+    assert_eq!(CRC_32_ISO_HDLC.checksum(CHECK), 0xCBF4_3926);
+}
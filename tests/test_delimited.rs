@@ -0,0 +1,84 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::{DelimitedReader, Delimiter, SerialStream};
+use std::io::{self, Write};
+use std::time::Duration;
+
+const TOKEN: Token = Token(0);
+
+/// Write `data` on `port` and wait for the reader to become readable
+fn send(poll: &mut mio::Poll, events: &mut mio::Events, port: &mut SerialStream, data: &[u8]) {
+    port.write_all(data)
+        .expect("unable to write to serial port");
+    common::expect_events(
+        poll,
+        events,
+        vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+    );
+}
+
+#[test]
+fn test_records_split_across_events() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut reader = DelimitedReader::new(slave, Delimiter::CrLf);
+    poll.registry()
+        .register(&mut reader, TOKEN, Interest::READABLE)
+        .expect("unable to register reader");
+
+    send(&mut poll, &mut events, &mut master, b"$GPGGA,1,2");
+    assert_eq!(reader.read_record().unwrap(), None);
+    assert_eq!(reader.partial_len(), 10);
+
+    // delimiter split between two events
+    send(&mut poll, &mut events, &mut master, b",3\r");
+    assert_eq!(reader.read_record().unwrap(), None);
+
+    send(&mut poll, &mut events, &mut master, b"\nsecond\r\nthi");
+    assert_eq!(reader.read_record().unwrap().unwrap(), b"$GPGGA,1,2,3");
+    assert_eq!(reader.read_record().unwrap().unwrap(), b"second");
+    assert_eq!(reader.read_record().unwrap(), None);
+    assert_eq!(reader.take_partial().unwrap(), b"thi");
+}
+
+#[test]
+fn test_max_length_resynchronizes() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut reader = DelimitedReader::new(slave, Delimiter::AnyOf(b"\r\n".to_vec()))
+        .with_max_length(8)
+        .with_skip_empty(true);
+    poll.registry()
+        .register(&mut reader, TOKEN, Interest::READABLE)
+        .expect("unable to register reader");
+
+    send(&mut poll, &mut events, &mut master, b"ok\r\nmuch too long");
+    assert_eq!(reader.read_record().unwrap().unwrap(), b"ok");
+    let err = reader.read_record().unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    send(&mut poll, &mut events, &mut master, b" still going\nnext\n");
+    assert_eq!(reader.read_record().unwrap().unwrap(), b"next");
+    assert_eq!(reader.read_record().unwrap(), None);
+}
+
+#[test]
+fn test_flush_stale_partial() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut reader = DelimitedReader::new(slave, Delimiter::Byte(b';'));
+    poll.registry()
+        .register(&mut reader, TOKEN, Interest::READABLE)
+        .expect("unable to register reader");
+
+    let timeout = Duration::from_millis(50);
+    send(&mut poll, &mut events, &mut master, b"partial");
+    assert_eq!(reader.read_record().unwrap(), None);
+    assert!(reader.partial_deadline(timeout).is_some());
+    assert_eq!(reader.flush_stale(Duration::from_secs(60)), None);
+
+    std::thread::sleep(timeout);
+    assert_eq!(reader.flush_stale(timeout).unwrap(), b"partial");
+    assert_eq!(reader.partial_len(), 0);
+}
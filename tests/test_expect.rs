@@ -0,0 +1,186 @@
+#![cfg(all(unix, feature = "expect"))]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::expect::{BlockingExpect, Expect, ExpectError, Pattern};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A scripted board: waits for each expected input and answers with the given output
+fn spawn_board(
+    mut port: SerialStream,
+    greeting: &'static [u8],
+    script: Vec<(&'static [u8], &'static [u8])>,
+) -> thread::JoinHandle<SerialStream> {
+    thread::spawn(move || {
+        port.write_all(greeting).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut received = Vec::new();
+        for (expected, reply) in script {
+            while !received.starts_with(expected) {
+                assert!(Instant::now() < deadline, "board timed out");
+                let mut buf = [0u8; 256];
+                match port.read(&mut buf) {
+                    Ok(n) => received.extend_from_slice(&buf[..n]),
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                        thread::sleep(Duration::from_millis(5))
+                    }
+                    Err(e) => panic!("board read failed: {e}"),
+                }
+            }
+            received.drain(..expected.len());
+            port.write_all(reply).unwrap();
+        }
+        port
+    })
+}
+
+#[test]
+fn test_non_blocking_expect() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let board = spawn_board(
+        master,
+        b"U-Boot 2024.01-rc3 (Jan 09 2024)\r\nHit any key to stop autoboot:  3 ",
+        vec![
+            (b" ", b"\r\n=> "),
+            (b"printenv bootcmd\n", b"bootcmd=run distro_bootcmd\r\n=> "),
+        ],
+    );
+    let transcript = common::SharedBuffer::default();
+    let mut console = Expect::new(slave)
+        .with_transcript(transcript.clone())
+        .with_line_ending("\n");
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(
+            &mut console,
+            Token(0),
+            Interest::READABLE | Interest::WRITABLE,
+        )
+        .unwrap();
+
+    let mut wait = |console: &mut Expect| loop {
+        console.handle_io(Instant::now()).unwrap();
+        if let Some(result) = console.poll_match() {
+            return result;
+        }
+        let timeout = console
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).unwrap();
+    };
+
+    let version = Pattern::regex(r"U-Boot (\d{4})\.(\d{2})").unwrap();
+    console.expect(version, Duration::from_secs(5));
+    assert!(console.is_pending());
+    assert!(console.next_deadline().is_some());
+    let found = wait(&mut console).unwrap();
+    assert_eq!(found.before, "");
+    assert_eq!(found.matched, "U-Boot 2024.01");
+    assert_eq!(
+        found.captures,
+        vec![
+            Some("U-Boot 2024.01".to_string()),
+            Some("2024".to_string()),
+            Some("01".to_string())
+        ]
+    );
+    assert!(!console.is_pending());
+
+    // The earliest match wins, whatever the order of the patterns
+    console.expect_any(
+        [
+            "=> ".into(),
+            "autoboot:".into(),
+            Pattern::regex(r"\(\w+").unwrap(),
+        ],
+        Duration::from_secs(5),
+    );
+    let found = wait(&mut console).unwrap();
+    assert_eq!((found.index, found.matched.as_str()), (2, "(Jan"));
+    assert_eq!(found.before, "-rc3 ");
+    console.expect_any(["=> ".into(), "autoboot:".into()], Duration::from_secs(5));
+    let found = wait(&mut console).unwrap();
+    assert_eq!(found.index, 1);
+    assert_eq!(found.before, " 09 2024)\r\nHit any key to stop ");
+
+    console.send(" ").unwrap();
+    console.expect("=> ", Duration::from_secs(5));
+    let found = wait(&mut console).unwrap();
+    assert_eq!(found.before, "  3 \r\n");
+    assert_eq!(found.after, "");
+
+    console.send_line("printenv bootcmd").unwrap();
+    console.expect("bootcmd=", Duration::from_secs(5));
+    let found = wait(&mut console).unwrap();
+    assert_eq!(found.before, "");
+    console.expect("=> ", Duration::from_secs(5));
+    assert_eq!(wait(&mut console).unwrap().before, "run distro_bootcmd\r\n");
+
+    // Nothing more comes
+    console.expect("login:", Duration::from_millis(100));
+    assert!(matches!(wait(&mut console), Err(ExpectError::Timeout(ref text)) if text.is_empty()));
+    board.join().unwrap();
+
+    let transcript = transcript.bytes();
+    assert!(transcript.starts_with(b"U-Boot 2024.01-rc3"));
+    assert!(transcript.ends_with(b"=> printenv bootcmd\nbootcmd=run distro_bootcmd\r\n=> "));
+}
+
+#[test]
+fn test_blocking_expect() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let board = spawn_board(
+        master,
+        b"\r\nbuildroot login: ",
+        vec![
+            (b"root\r", b"root\r\nPassword: "),
+            (b"secret\r", b"\r\nLogin incorrect\r\nbuildroot login: "),
+        ],
+    );
+    let mut console = BlockingExpect::new(Expect::new(slave)).unwrap();
+    console.expect("login: ", Duration::from_secs(5)).unwrap();
+    console.send_line("root").unwrap();
+    console
+        .expect("Password: ", Duration::from_secs(5))
+        .unwrap();
+    console.send_line("secret").unwrap();
+    let found = console
+        .expect_any(
+            [
+                Pattern::from("# "),
+                Pattern::regex(r"Login incorrect|Permission denied").unwrap(),
+            ],
+            Duration::from_secs(5),
+        )
+        .unwrap();
+    assert_eq!(found.index, 1);
+    assert_eq!(found.matched, "Login incorrect");
+    assert_eq!(found.after, "\r\nbuildroot login: ");
+
+    let started = Instant::now();
+    let err = console
+        .expect("# ", Duration::from_millis(200))
+        .unwrap_err();
+    assert!(started.elapsed() >= Duration::from_millis(200));
+    assert!(matches!(err, ExpectError::Timeout(ref text) if text == "\r\nbuildroot login: "));
+    board.join().unwrap();
+    let console = console.into_inner().unwrap();
+    assert_eq!(console.buffer(), b"\r\nbuildroot login: ");
+}
+
+#[test]
+fn test_io_error_fails_expectation() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    // Reading the master of a pseudo-terminal fails with EIO once the slave is closed
+    drop(slave);
+    let mut console = Expect::new(master);
+    console.expect("login: ", Duration::from_secs(5));
+    let err = console.handle_io(Instant::now()).unwrap_err();
+    assert!(!console.is_pending());
+    match console.poll_match() {
+        Some(Err(ExpectError::Io(e))) => assert_eq!(e.kind(), err.kind()),
+        other => panic!("expected an I/O error, got {other:?}"),
+    }
+}
@@ -0,0 +1,105 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::hdlc::{Fcs, HdlcCodec, HdlcError, HdlcStats, ESCAPE, FLAG};
+use mio_serial::codec::{Encoder, FramedSerial};
+use mio_serial::SerialStream;
+use std::io::Write;
+
+const TOKEN: Token = Token(0);
+
+fn wait_readable(poll: &mut mio::Poll, events: &mut mio::Events) {
+    common::expect_events(
+        poll,
+        events,
+        vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+    );
+}
+
+#[test]
+fn test_hdlc_round_trip_fcs16_and_fcs32() {
+    for fcs in [Fcs::Fcs16, Fcs::Fcs32, Fcs::None] {
+        let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+        let (mut poll, mut events) = common::init_with_poll();
+        let codec = HdlcCodec::new().with_fcs(fcs).with_accm(0);
+        let mut tx = FramedSerial::new(a, codec.clone().with_leading_flag(false));
+        let mut rx = FramedSerial::new(b, codec);
+        poll.registry()
+            .register(&mut rx, TOKEN, Interest::READABLE)
+            .expect("unable to register receiver");
+
+        let frames: Vec<Vec<u8>> = vec![
+            vec![0xFF, 0x03, 0xC0, 0x21, 0x01, 0x01, 0x00, 0x04],
+            vec![FLAG, ESCAPE, 0x00, 0x11, 0x13, FLAG],
+            (0..=255).collect(),
+        ];
+        for frame in &frames {
+            tx.send(frame).expect("unable to send frame");
+        }
+
+        let mut received = Vec::new();
+        while received.len() < frames.len() {
+            wait_readable(&mut poll, &mut events);
+            while let Some(frame) = rx.read_frame().expect("unable to read frame") {
+                received.push(frame);
+            }
+        }
+        assert_eq!(received, frames);
+        assert_eq!(rx.codec().stats().frames, 3);
+    }
+}
+
+#[test]
+fn test_hdlc_accm_escaping() {
+    let mut encoded = Vec::new();
+    HdlcCodec::new()
+        .with_fcs(Fcs::None)
+        .with_accm(1 << 0x11)
+        .encode(&[0x10, 0x11, 0x7E, 0x7D][..], &mut encoded)
+        .unwrap();
+    assert_eq!(
+        encoded,
+        [FLAG, 0x10, ESCAPE, 0x31, ESCAPE, 0x5E, ESCAPE, 0x5D, FLAG]
+    );
+}
+
+#[test]
+fn test_hdlc_bad_fcs_and_abort_are_counted_separately() {
+    let (mut a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut rx = FramedSerial::new(b, HdlcCodec::new().with_fcs(Fcs::Fcs32));
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let mut codec = HdlcCodec::new().with_fcs(Fcs::Fcs32);
+    let mut good = Vec::new();
+    codec.encode(&b"good frame"[..], &mut good).unwrap();
+    let mut corrupt = good.clone();
+    corrupt[3] ^= 0x01;
+
+    let mut wire = corrupt;
+    // abort sequence in the middle of a frame
+    wire.extend_from_slice(&[FLAG, 0x01, 0x02, ESCAPE, FLAG]);
+    // an unescaped control character inserted on the link is dropped
+    let mut noisy = good.clone();
+    noisy.insert(2, 0x11);
+    wire.extend_from_slice(&noisy);
+    a.write_all(&wire).expect("unable to write");
+    wait_readable(&mut poll, &mut events);
+
+    assert!(matches!(rx.read_frame(), Err(HdlcError::BadFcs)));
+    assert!(matches!(rx.read_frame(), Err(HdlcError::Aborted)));
+    assert_eq!(rx.read_frame().unwrap().unwrap(), b"good frame");
+    assert!(rx.read_frame().unwrap().is_none());
+    assert_eq!(
+        *rx.codec().stats(),
+        HdlcStats {
+            frames: 1,
+            bad_fcs: 1,
+            aborted: 1,
+            too_short: 0,
+            too_long: 0,
+        }
+    );
+}
@@ -0,0 +1,118 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::length_prefixed::{
+    ByteOrder, Checksum, CrcCoverage, LengthPrefixedCodec, LengthPrefixedError,
+};
+use mio_serial::codec::{Encoder, FramedSerial};
+use mio_serial::crc::{CRC_16_IBM_3740, CRC_32_ISO_HDLC};
+use mio_serial::SerialStream;
+use std::io::Write;
+
+const TOKEN: Token = Token(0);
+const SYNC: [u8; 2] = [0xAA, 0x55];
+
+fn codec() -> LengthPrefixedCodec {
+    LengthPrefixedCodec::new(&SYNC)
+        .with_length_width(2)
+        .with_checksum(Checksum::Crc16(CRC_16_IBM_3740))
+        .with_crc_coverage(CrcCoverage::LengthAndPayload)
+}
+
+#[test]
+fn test_encoding_layout() {
+    let mut encoded = Vec::new();
+    codec()
+        .encode(&[0x01, 0x02, 0x03][..], &mut encoded)
+        .unwrap();
+    let crc = CRC_16_IBM_3740.checksum(&[0x03, 0x00, 0x01, 0x02, 0x03]);
+    let mut expected = vec![0xAA, 0x55, 0x03, 0x00, 0x01, 0x02, 0x03];
+    expected.extend_from_slice(&crc.to_le_bytes());
+    assert_eq!(encoded, expected);
+
+    // big-endian length that also counts a big-endian CRC-32 over the whole frame
+    let mut codec = LengthPrefixedCodec::new(&[0x7E])
+        .with_length_width(2)
+        .with_length_order(ByteOrder::BigEndian)
+        .with_length_adjustment(-4)
+        .with_checksum(Checksum::Crc32(CRC_32_ISO_HDLC))
+        .with_crc_coverage(CrcCoverage::Frame)
+        .with_crc_order(ByteOrder::BigEndian);
+    encoded.clear();
+    codec.encode(&b"abc"[..], &mut encoded).unwrap();
+    let crc = CRC_32_ISO_HDLC.checksum(&[0x7E, 0x00, 0x07, b'a', b'b', b'c']);
+    let mut expected = vec![0x7E, 0x00, 0x07, b'a', b'b', b'c'];
+    expected.extend_from_slice(&crc.to_be_bytes());
+    assert_eq!(encoded, expected);
+}
+
+#[test]
+fn test_round_trip_over_pair() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut tx = FramedSerial::new(a, codec());
+    let mut rx = FramedSerial::new(b, codec());
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let frames: Vec<Vec<u8>> = vec![vec![], SYNC.to_vec(), (0..=255).collect(), vec![0xAA; 1000]];
+    for frame in &frames {
+        tx.send(frame).expect("unable to send frame");
+    }
+
+    let mut received = Vec::new();
+    while received.len() < frames.len() {
+        common::expect_events(
+            &mut poll,
+            &mut events,
+            vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+        );
+        while let Some(frame) = rx.read_frame().expect("unable to read frame") {
+            received.push(frame);
+        }
+    }
+    assert_eq!(received, frames);
+}
+
+#[test]
+fn test_hunts_for_sync_after_crc_failure() {
+    let (mut a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut rx = FramedSerial::new(b, codec());
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let mut encoder = codec();
+    let mut inner = Vec::new();
+    encoder.encode(&b"inner"[..], &mut inner).unwrap();
+    let mut after = Vec::new();
+    encoder.encode(&b"after"[..], &mut after).unwrap();
+
+    // An outer frame whose payload happens to contain a whole valid frame, then
+    // corrupted so its CRC fails
+    let mut outer = Vec::new();
+    encoder.encode(&inner, &mut outer).unwrap();
+    let last = outer.len() - 1;
+    outer[last] ^= 0xFF;
+
+    let mut wire = vec![0x00, 0xAA, 0x13];
+    wire.extend_from_slice(&outer);
+    wire.extend_from_slice(&after);
+    a.write_all(&wire).expect("unable to write");
+    common::expect_events(
+        &mut poll,
+        &mut events,
+        vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+    );
+
+    assert!(matches!(
+        rx.read_frame(),
+        Err(LengthPrefixedError::CrcMismatch { .. })
+    ));
+    assert_eq!(rx.read_frame().unwrap().unwrap(), b"inner");
+    assert_eq!(rx.read_frame().unwrap().unwrap(), b"after");
+    assert!(rx.read_frame().unwrap().is_none());
+    assert!(rx.read_buffer().is_empty());
+}
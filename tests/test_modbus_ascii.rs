@@ -0,0 +1,130 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::{Decoder, Encoder};
+use mio_serial::modbus::{
+    Adu, AsciiClient, AsciiCodec, AsciiServer, MemoryMap, ModbusError, Request, Response,
+};
+use mio_serial::SerialStream;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+const TOKEN_CLIENT: Token = Token(0);
+const TOKEN_SERVER: Token = Token(1);
+
+#[test]
+fn test_ascii_codec() {
+    let mut codec = AsciiCodec::new();
+    let adu = Adu {
+        unit: 0x11,
+        pdu: vec![0x03, 0x00, 0x6B, 0x00, 0x03],
+    };
+    let mut encoded = Vec::new();
+    codec.encode(&adu, &mut encoded).unwrap();
+    assert_eq!(encoded, b":1103006B00037E\r\n");
+
+    // Leading noise and an abandoned frame are skipped, lowercase digits are accepted
+    let mut src = b"noise:0103:1103006b00037e\r\n:11".to_vec();
+    assert_eq!(codec.decode(&mut src).unwrap(), Some(adu));
+    assert_eq!(src, b":11");
+    assert_eq!(codec.decode(&mut src).unwrap(), None);
+
+    let mut src = b":1103006B00037F\r\n".to_vec();
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ModbusError::ChecksumMismatch)
+    ));
+    assert!(src.is_empty());
+
+    let mut src = b":1103006B00037E\n".to_vec();
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(ModbusError::InvalidFrame(_))
+    ));
+}
+
+#[test]
+fn test_ascii_client_and_server() {
+    const INTER_CHAR_TIMEOUT: Duration = Duration::from_millis(100);
+
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client =
+        AsciiClient::new_ascii(master, INTER_CHAR_TIMEOUT).expect("unable to create client");
+    let mut server = AsciiServer::new_ascii(
+        slave,
+        0x11,
+        Box::new(MemoryMap::new(16)),
+        INTER_CHAR_TIMEOUT,
+    )
+    .expect("unable to create server");
+    assert_eq!(client.timing().inter_char, INTER_CHAR_TIMEOUT);
+
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(&mut client, TOKEN_CLIENT, Interest::READABLE)
+        .expect("unable to register client");
+    poll.registry()
+        .register(&mut server, TOKEN_SERVER, Interest::READABLE)
+        .expect("unable to register server");
+
+    // Start of a frame that never finishes; the server drops it after the timeout
+    client.get_mut().write_all(b":1103").unwrap();
+    let started = Instant::now();
+    while server.stats().bad_frames == 0 {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "partial frame kept"
+        );
+        let timeout = server
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout.or(Some(Duration::from_millis(10))))
+            .expect("unable to poll");
+        server.handle_io(Instant::now()).expect("server I/O failed");
+    }
+    assert!(started.elapsed() >= INTER_CHAR_TIMEOUT);
+
+    client.submit(
+        0x11,
+        Request::WriteMultipleRegisters {
+            address: 2,
+            values: vec![0xABCD, 0x1234],
+        },
+    );
+    client.submit(
+        0x11,
+        Request::ReadHoldingRegisters {
+            address: 1,
+            quantity: 3,
+        },
+    );
+
+    let mut completions = Vec::new();
+    client.handle_io(Instant::now()).expect("client I/O failed");
+    while completions.len() < 2 {
+        assert!(started.elapsed() < Duration::from_secs(10), "timed out");
+        let timeout = [client.next_deadline(), server.next_deadline()]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).expect("unable to poll");
+        server.handle_io(Instant::now()).expect("server I/O failed");
+        client.handle_io(Instant::now()).expect("client I/O failed");
+        while let Some(completion) = client.poll_completion() {
+            completions.push(completion.result);
+        }
+    }
+
+    assert!(matches!(
+        completions[0],
+        Ok(Response::WriteMultipleRegisters {
+            address: 2,
+            quantity: 2
+        })
+    ));
+    assert_eq!(
+        completions[1].as_ref().unwrap(),
+        &Response::ReadHoldingRegisters(vec![0, 0xABCD, 0x1234])
+    );
+}
@@ -0,0 +1,179 @@
+#![cfg(unix)]
+mod common;
+use mio::net::TcpListener;
+use mio::{Interest, Token};
+use mio_serial::modbus::{Gateway, MemoryMap, RtuClient, RtuServer};
+use mio_serial::{SerialPort, SerialStream};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const UNIT: u8 = 1;
+
+/// Serve unit 1 from a memory map on `stream` in a background thread
+fn spawn_slave(stream: SerialStream) {
+    thread::spawn(move || {
+        let mut map = MemoryMap::new(16);
+        map.holding_registers[..4].copy_from_slice(&[10, 11, 12, 13]);
+        let mut server =
+            RtuServer::new_rtu(stream, UNIT, Box::new(map)).expect("unable to create server");
+        let (mut poll, mut events) = common::init_with_poll();
+        poll.registry()
+            .register(&mut server, Token(0), Interest::READABLE)
+            .expect("unable to register server");
+        loop {
+            let timeout = server
+                .next_deadline()
+                .map(|d| d.saturating_duration_since(Instant::now()));
+            poll.poll(&mut events, timeout).expect("unable to poll");
+            if server.handle_io(Instant::now()).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+fn connect(addr: SocketAddr) -> TcpStream {
+    let started = Instant::now();
+    loop {
+        match TcpStream::connect(addr) {
+            Ok(stream) => {
+                stream
+                    .set_read_timeout(Some(Duration::from_secs(5)))
+                    .unwrap();
+                return stream;
+            }
+            Err(e) if started.elapsed() > Duration::from_secs(5) => {
+                panic!("unable to connect to gateway: {e}")
+            }
+            Err(_) => thread::sleep(Duration::from_millis(20)),
+        }
+    }
+}
+
+fn mbap(transaction: u16, unit: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = transaction.to_be_bytes().to_vec();
+    frame.extend_from_slice(&[0, 0]);
+    frame.extend_from_slice(&(1 + pdu.len() as u16).to_be_bytes());
+    frame.push(unit);
+    frame.extend_from_slice(pdu);
+    frame
+}
+
+fn read_response(stream: &mut TcpStream) -> (u16, u8, Vec<u8>) {
+    let mut header = [0u8; 7];
+    stream
+        .read_exact(&mut header)
+        .expect("no response from gateway");
+    let len = u16::from_be_bytes([header[4], header[5]]) as usize;
+    let mut pdu = vec![0u8; len - 1];
+    stream.read_exact(&mut pdu).expect("short response");
+    (u16::from_be_bytes([header[0], header[1]]), header[6], pdu)
+}
+
+/// Exercise a gateway listening on `addr` whose bus has unit 1 and nothing else
+fn check_gateway(addr: SocketAddr) {
+    let mut a = connect(addr);
+    let mut b = connect(addr);
+
+    // Two clients with overlapping requests; the bus handles them one at a time
+    a.write_all(&mbap(0x0100, UNIT, &[0x03, 0x00, 0x00, 0x00, 0x02]))
+        .unwrap();
+    b.write_all(&mbap(0x0100, UNIT, &[0x06, 0x00, 0x05, 0x00, 0x63]))
+        .unwrap();
+    a.write_all(&mbap(0x0101, UNIT, &[0x03, 0x00, 0x01, 0x00, 0x01]))
+        .unwrap();
+
+    assert_eq!(
+        read_response(&mut a),
+        (0x0100, UNIT, vec![0x03, 0x04, 0x00, 10, 0x00, 11])
+    );
+    assert_eq!(
+        read_response(&mut b),
+        (0x0100, UNIT, vec![0x06, 0x00, 0x05, 0x00, 0x63])
+    );
+    assert_eq!(
+        read_response(&mut a),
+        (0x0101, UNIT, vec![0x03, 0x02, 0x00, 11])
+    );
+
+    a.write_all(&mbap(0x0102, UNIT, &[0x03, 0x00, 0x05, 0x00, 0x01]))
+        .unwrap();
+    assert_eq!(
+        read_response(&mut a),
+        (0x0102, UNIT, vec![0x03, 0x02, 0x00, 0x63])
+    );
+
+    // Exceptions from the device are passed through
+    b.write_all(&mbap(7, UNIT, &[0x03, 0x00, 0x20, 0x00, 0x01]))
+        .unwrap();
+    assert_eq!(read_response(&mut b), (7, UNIT, vec![0x83, 0x02]));
+
+    // Nobody answers for unit 9
+    b.write_all(&mbap(8, 9, &[0x03, 0x00, 0x00, 0x00, 0x01]))
+        .unwrap();
+    assert_eq!(read_response(&mut b), (8, 9, vec![0x83, 0x0B]));
+
+    // Broadcasts are carried out on the bus but not answered
+    a.write_all(&mbap(10, 0, &[0x06, 0x00, 0x06, 0x00, 0x2A]))
+        .unwrap();
+    a.write_all(&mbap(11, UNIT, &[0x03, 0x00, 0x06, 0x00, 0x01]))
+        .unwrap();
+    assert_eq!(
+        read_response(&mut a),
+        (11, UNIT, vec![0x03, 0x02, 0x00, 0x2A])
+    );
+
+    // Unsupported function codes are rejected by the gateway itself
+    a.write_all(&mbap(9, UNIT, &[0x2B, 0x0E, 0x01, 0x00]))
+        .unwrap();
+    assert_eq!(read_response(&mut a), (9, UNIT, vec![0xAB, 0x01]));
+}
+
+#[test]
+fn test_gateway_in_process() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    spawn_slave(slave);
+
+    let client = RtuClient::new_rtu(master)
+        .expect("unable to create client")
+        .with_response_timeout(Duration::from_millis(200));
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).expect("unable to bind");
+    let mut gateway = Gateway::new(listener, client).expect("unable to create gateway");
+    let addr = gateway.local_addr().unwrap();
+    thread::spawn(move || gateway.run());
+
+    check_gateway(addr);
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_gateway_binary() {
+    // The gateway opens the slave side by name; the simulated device sits on the master
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let device = slave.name().expect("pty has no name");
+    spawn_slave(master);
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .expect("unable to find a free port");
+    let _gateway = KillOnDrop(
+        Command::new(env!("CARGO_BIN_EXE_mio-serial-modbus-gw"))
+            .args(["--listen", &addr.to_string(), "--timeout", "200", &device])
+            .spawn()
+            .expect("unable to start gateway"),
+    );
+
+    check_gateway(addr);
+    drop(slave);
+}
@@ -0,0 +1,351 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::FramedSerial;
+use mio_serial::modbus::{
+    Adu, Completion, Exception, ModbusError, Request, Response, RtuClient, RtuCodec,
+};
+use mio_serial::SerialStream;
+use std::time::{Duration, Instant};
+
+const TOKEN_CLIENT: Token = Token(0);
+const TOKEN_SLAVE: Token = Token(1);
+const SLAVE_UNIT: u8 = 17;
+
+/// A simulated slave with 64 coils and 64 registers
+struct Slave {
+    framed: FramedSerial<RtuCodec>,
+    coils: Vec<bool>,
+    registers: Vec<u16>,
+}
+
+impl Slave {
+    fn new(stream: SerialStream) -> Self {
+        Self {
+            framed: FramedSerial::new(stream, RtuCodec::server()),
+            coils: vec![false; 64],
+            registers: (0..64).collect(),
+        }
+    }
+
+    fn handle_io(&mut self) {
+        self.framed.on_writable().expect("unable to write response");
+        while let Some(adu) = self.framed.read_frame().expect("unable to read request") {
+            if adu.unit != SLAVE_UNIT {
+                continue;
+            }
+            let function = adu.pdu[0];
+            let mut pdu = Vec::new();
+            match Request::decode(&adu.pdu).and_then(|request| self.execute(&request)) {
+                Ok(response) => response.encode(&mut pdu),
+                Err(exception) => exception.encode(function, &mut pdu),
+            }
+            self.framed
+                .send(&Adu {
+                    unit: SLAVE_UNIT,
+                    pdu,
+                })
+                .expect("unable to send response");
+        }
+    }
+
+    fn execute(&mut self, request: &Request) -> Result<Response, Exception> {
+        fn range(
+            address: u16,
+            quantity: u16,
+            len: usize,
+        ) -> Result<std::ops::Range<usize>, Exception> {
+            let start = usize::from(address);
+            let end = start + usize::from(quantity);
+            if end > len {
+                return Err(Exception::IllegalDataAddress);
+            }
+            Ok(start..end)
+        }
+
+        match *request {
+            Request::ReadCoils { address, quantity } => Ok(Response::ReadCoils(
+                self.coils[range(address, quantity, self.coils.len())?].to_vec(),
+            )),
+            Request::ReadHoldingRegisters { address, quantity } => {
+                Ok(Response::ReadHoldingRegisters(
+                    self.registers[range(address, quantity, self.registers.len())?].to_vec(),
+                ))
+            }
+            Request::WriteSingleCoil { address, value } => {
+                let r = range(address, 1, self.coils.len())?;
+                self.coils[r.start] = value;
+                Ok(Response::write_echo(request).unwrap())
+            }
+            Request::WriteSingleRegister { address, value } => {
+                let r = range(address, 1, self.registers.len())?;
+                self.registers[r.start] = value;
+                Ok(Response::write_echo(request).unwrap())
+            }
+            Request::WriteMultipleCoils {
+                address,
+                ref values,
+            } => {
+                let r = range(address, values.len() as u16, self.coils.len())?;
+                self.coils[r].copy_from_slice(values);
+                Ok(Response::write_echo(request).unwrap())
+            }
+            Request::WriteMultipleRegisters {
+                address,
+                ref values,
+            } => {
+                let r = range(address, values.len() as u16, self.registers.len())?;
+                self.registers[r].copy_from_slice(values);
+                Ok(Response::write_echo(request).unwrap())
+            }
+            Request::ReadWriteMultipleRegisters {
+                read_address,
+                read_quantity,
+                write_address,
+                ref values,
+            } => {
+                let w = range(write_address, values.len() as u16, self.registers.len())?;
+                let r = range(read_address, read_quantity, self.registers.len())?;
+                self.registers[w].copy_from_slice(values);
+                Ok(Response::ReadWriteMultipleRegisters(
+                    self.registers[r].to_vec(),
+                ))
+            }
+            _ => Err(Exception::IllegalFunction),
+        }
+    }
+}
+
+/// Drive client and slave until all submitted requests have completed
+fn run(client: &mut RtuClient, slave: &mut Slave, count: usize) -> Vec<Completion> {
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(client, TOKEN_CLIENT, Interest::READABLE)
+        .expect("unable to register client");
+    poll.registry()
+        .register(&mut slave.framed, TOKEN_SLAVE, Interest::READABLE)
+        .expect("unable to register slave");
+
+    let give_up = Instant::now() + Duration::from_secs(10);
+    let mut completions = Vec::new();
+    client.handle_io(Instant::now()).expect("client I/O failed");
+    while completions.len() < count {
+        assert!(
+            Instant::now() < give_up,
+            "timed out waiting for completions"
+        );
+        let timeout = client
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).expect("unable to poll");
+        for event in events.iter() {
+            if event.token() == TOKEN_SLAVE {
+                slave.handle_io();
+            }
+        }
+        client.handle_io(Instant::now()).expect("client I/O failed");
+        while let Some(completion) = client.poll_completion() {
+            completions.push(completion);
+        }
+    }
+
+    poll.registry().deregister(client).unwrap();
+    poll.registry().deregister(&mut slave.framed).unwrap();
+    completions
+}
+
+#[test]
+fn test_request_frame_encoding() {
+    let (master, mut slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client = RtuClient::new_rtu(master).expect("unable to create client");
+    client.submit(
+        1,
+        Request::ReadHoldingRegisters {
+            address: 0,
+            quantity: 10,
+        },
+    );
+    client.handle_io(Instant::now()).expect("client I/O failed");
+
+    std::thread::sleep(Duration::from_millis(50));
+    let mut buf = [0u8; 16];
+    common::checked_read(
+        &mut slave,
+        &mut buf,
+        &[0x01, 0x03, 0x00, 0x00, 0x00, 0x0A, 0xC5, 0xCD],
+    );
+}
+
+#[test]
+fn test_all_function_codes() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client = RtuClient::new_rtu(master).expect("unable to create client");
+    let mut slave = Slave::new(slave);
+
+    let requests = vec![
+        Request::WriteSingleCoil {
+            address: 3,
+            value: true,
+        },
+        Request::WriteMultipleCoils {
+            address: 8,
+            values: vec![true, false, true, true, false, false, true, false, true],
+        },
+        Request::ReadCoils {
+            address: 0,
+            quantity: 20,
+        },
+        Request::WriteSingleRegister {
+            address: 1,
+            value: 0xBEEF,
+        },
+        Request::WriteMultipleRegisters {
+            address: 10,
+            values: vec![100, 200, 300],
+        },
+        Request::ReadHoldingRegisters {
+            address: 0,
+            quantity: 13,
+        },
+        Request::ReadWriteMultipleRegisters {
+            read_address: 10,
+            read_quantity: 4,
+            write_address: 13,
+            values: vec![400],
+        },
+        Request::ReadHoldingRegisters {
+            address: 60,
+            quantity: 10,
+        },
+        Request::ReadInputRegisters {
+            address: 0,
+            quantity: 1,
+        },
+    ];
+    let ids: Vec<_> = requests
+        .into_iter()
+        .map(|request| client.submit(SLAVE_UNIT, request))
+        .collect();
+    assert_eq!(client.pending(), ids.len());
+
+    let completions = run(&mut client, &mut slave, ids.len());
+    let completed: Vec<_> = completions.iter().map(|c| c.id).collect();
+    assert_eq!(completed, ids, "requests completed out of order");
+    assert!(client.is_idle());
+
+    let results: Vec<_> = completions.into_iter().map(|c| c.result).collect();
+    let mut coils = vec![false; 20];
+    coils[3] = true;
+    coils[8..17].copy_from_slice(&[true, false, true, true, false, false, true, false, true]);
+    let mut registers: Vec<u16> = (0..13).collect();
+    registers[1] = 0xBEEF;
+    registers[10..13].copy_from_slice(&[100, 200, 300]);
+
+    assert!(matches!(
+        results[0],
+        Ok(Response::WriteSingleCoil {
+            address: 3,
+            value: true
+        })
+    ));
+    assert!(matches!(
+        results[1],
+        Ok(Response::WriteMultipleCoils {
+            address: 8,
+            quantity: 9
+        })
+    ));
+    assert_eq!(results[2].as_ref().unwrap(), &Response::ReadCoils(coils));
+    assert!(matches!(
+        results[3],
+        Ok(Response::WriteSingleRegister {
+            address: 1,
+            value: 0xBEEF
+        })
+    ));
+    assert!(matches!(
+        results[4],
+        Ok(Response::WriteMultipleRegisters {
+            address: 10,
+            quantity: 3
+        })
+    ));
+    assert_eq!(
+        results[5].as_ref().unwrap(),
+        &Response::ReadHoldingRegisters(registers)
+    );
+    assert_eq!(
+        results[6].as_ref().unwrap(),
+        &Response::ReadWriteMultipleRegisters(vec![100, 200, 300, 400])
+    );
+    assert!(matches!(
+        results[7],
+        Err(ModbusError::Exception(Exception::IllegalDataAddress))
+    ));
+    assert!(matches!(
+        results[8],
+        Err(ModbusError::Exception(Exception::IllegalFunction))
+    ));
+}
+
+#[test]
+fn test_timeout_and_broadcast() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client = RtuClient::new_rtu(master)
+        .expect("unable to create client")
+        .with_response_timeout(Duration::from_millis(200))
+        .with_turnaround_delay(Duration::from_millis(50));
+    let mut slave = Slave::new(slave);
+
+    let started = Instant::now();
+    client.submit(
+        SLAVE_UNIT + 1,
+        Request::ReadCoils {
+            address: 0,
+            quantity: 1,
+        },
+    );
+    client.submit(
+        0,
+        Request::WriteSingleRegister {
+            address: 5,
+            value: 55,
+        },
+    );
+    client.submit(
+        0,
+        Request::ReadCoils {
+            address: 0,
+            quantity: 1,
+        },
+    );
+    client.submit(
+        SLAVE_UNIT,
+        Request::ReadHoldingRegisters {
+            address: 5,
+            quantity: 1,
+        },
+    );
+
+    let completions = run(&mut client, &mut slave, 4);
+    assert!(started.elapsed() >= Duration::from_millis(250));
+
+    // The invalid broadcast read completes immediately, ahead of the others
+    assert!(matches!(
+        completions[0].result,
+        Err(ModbusError::InvalidRequest(_))
+    ));
+    assert!(matches!(completions[1].result, Err(ModbusError::Timeout)));
+    assert!(matches!(
+        completions[2].result,
+        Ok(Response::WriteSingleRegister {
+            address: 5,
+            value: 55
+        })
+    ));
+    // The slave ignores broadcasts in this simulation, so the register is unchanged
+    assert_eq!(
+        completions[3].result.as_ref().unwrap(),
+        &Response::ReadHoldingRegisters(vec![5])
+    );
+}
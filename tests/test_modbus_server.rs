@@ -0,0 +1,250 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::crc::CRC_16_MODBUS;
+use mio_serial::modbus::{
+    Completion, Exception, MemoryMap, ModbusError, RegisterMap, Request, Response, RtuClient,
+    RtuServer,
+};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+const TOKEN_CLIENT: Token = Token(0);
+const TOKEN_SERVER: Token = Token(1);
+
+/// Only has holding registers, each holding its own address
+struct AddressEcho;
+
+impl RegisterMap for AddressEcho {
+    fn read_holding_registers(
+        &mut self,
+        address: u16,
+        quantity: u16,
+    ) -> Result<Vec<u16>, Exception> {
+        Ok((address..address + quantity).collect())
+    }
+}
+
+/// Drive client and server until `count` requests have completed
+fn run(client: &mut RtuClient, server: &mut RtuServer, count: usize) -> Vec<Completion> {
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(client, TOKEN_CLIENT, Interest::READABLE)
+        .expect("unable to register client");
+    poll.registry()
+        .register(server, TOKEN_SERVER, Interest::READABLE)
+        .expect("unable to register server");
+
+    let give_up = Instant::now() + Duration::from_secs(10);
+    let mut completions = Vec::new();
+    client.handle_io(Instant::now()).expect("client I/O failed");
+    while completions.len() < count {
+        assert!(
+            Instant::now() < give_up,
+            "timed out waiting for completions"
+        );
+        let timeout = [client.next_deadline(), server.next_deadline()]
+            .into_iter()
+            .flatten()
+            .min()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).expect("unable to poll");
+        server.handle_io(Instant::now()).expect("server I/O failed");
+        client.handle_io(Instant::now()).expect("client I/O failed");
+        while let Some(completion) = client.poll_completion() {
+            completions.push(completion);
+        }
+    }
+
+    poll.registry().deregister(client).unwrap();
+    poll.registry().deregister(server).unwrap();
+    completions
+}
+
+#[test]
+fn test_client_and_server() {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client = RtuClient::new_rtu(master)
+        .expect("unable to create client")
+        .with_response_timeout(Duration::from_millis(200));
+
+    let mut map = MemoryMap::new(32);
+    map.discrete_inputs[2] = true;
+    map.input_registers[7] = 0x1234;
+    let mut server = RtuServer::new_rtu(slave, 5, Box::new(map))
+        .expect("unable to create server")
+        .with_unit(6);
+    assert_eq!(server.units(), &[5, 6]);
+
+    let requests = [
+        (
+            5,
+            Request::WriteMultipleCoils {
+                address: 0,
+                values: vec![true, true, false, true],
+            },
+        ),
+        (
+            6,
+            Request::ReadCoils {
+                address: 0,
+                quantity: 4,
+            },
+        ),
+        (
+            5,
+            Request::ReadDiscreteInputs {
+                address: 0,
+                quantity: 3,
+            },
+        ),
+        (
+            5,
+            Request::ReadInputRegisters {
+                address: 7,
+                quantity: 1,
+            },
+        ),
+        (
+            5,
+            Request::ReadWriteMultipleRegisters {
+                read_address: 0,
+                read_quantity: 2,
+                write_address: 1,
+                values: vec![42],
+            },
+        ),
+        (
+            5,
+            Request::WriteSingleRegister {
+                address: 32,
+                value: 1,
+            },
+        ),
+        (
+            7,
+            Request::ReadCoils {
+                address: 0,
+                quantity: 1,
+            },
+        ),
+    ];
+    for (unit, request) in requests {
+        client.submit(unit, request);
+    }
+    let results: Vec<_> = run(&mut client, &mut server, 7)
+        .into_iter()
+        .map(|c| c.result)
+        .collect();
+
+    assert_eq!(
+        results[1].as_ref().unwrap(),
+        &Response::ReadCoils(vec![true, true, false, true])
+    );
+    assert_eq!(
+        results[2].as_ref().unwrap(),
+        &Response::ReadDiscreteInputs(vec![false, false, true])
+    );
+    assert_eq!(
+        results[3].as_ref().unwrap(),
+        &Response::ReadInputRegisters(vec![0x1234])
+    );
+    assert_eq!(
+        results[4].as_ref().unwrap(),
+        &Response::ReadWriteMultipleRegisters(vec![0, 42])
+    );
+    assert!(matches!(
+        results[5],
+        Err(ModbusError::Exception(Exception::IllegalDataAddress))
+    ));
+    assert!(matches!(results[6], Err(ModbusError::Timeout)));
+
+    let stats = server.stats();
+    assert_eq!(stats.responses, 5);
+    assert_eq!(stats.exceptions, 1);
+    assert_eq!(stats.other_units, 1);
+
+    // A map without coils answers with Illegal Function
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut client = RtuClient::new_rtu(master).expect("unable to create client");
+    let mut server =
+        RtuServer::new_rtu(slave, 9, Box::new(AddressEcho)).expect("unable to create server");
+    client.submit(
+        9,
+        Request::ReadHoldingRegisters {
+            address: 100,
+            quantity: 3,
+        },
+    );
+    client.submit(
+        9,
+        Request::WriteSingleCoil {
+            address: 0,
+            value: true,
+        },
+    );
+    let results: Vec<_> = run(&mut client, &mut server, 2)
+        .into_iter()
+        .map(|c| c.result)
+        .collect();
+    assert_eq!(
+        results[0].as_ref().unwrap(),
+        &Response::ReadHoldingRegisters(vec![100, 101, 102])
+    );
+    assert!(matches!(
+        results[1],
+        Err(ModbusError::Exception(Exception::IllegalFunction))
+    ));
+}
+
+fn rtu_frame(unit: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = vec![unit];
+    frame.extend_from_slice(pdu);
+    let crc = CRC_16_MODBUS.checksum(&frame);
+    frame.extend_from_slice(&crc.to_le_bytes());
+    frame
+}
+
+#[test]
+fn test_broadcast_and_bad_frames() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let mut server =
+        RtuServer::new_rtu(slave, 1, Box::new(MemoryMap::new(8))).expect("unable to create server");
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(&mut server, TOKEN_SERVER, Interest::READABLE)
+        .expect("unable to register server");
+
+    let mut bad_crc = rtu_frame(1, &[0x03, 0x00, 0x00, 0x00, 0x01]);
+    *bad_crc.last_mut().unwrap() ^= 0xFF;
+    let mut input = Vec::new();
+    // broadcast write, broadcast read, bad CRC, then a read of the broadcast register
+    input.extend(rtu_frame(0, &[0x06, 0x00, 0x02, 0xAB, 0xCD]));
+    input.extend(rtu_frame(0, &[0x03, 0x00, 0x00, 0x00, 0x01]));
+    input.extend(bad_crc);
+    input.extend(rtu_frame(1, &[0x03, 0x00, 0x02, 0x00, 0x01]));
+    master.write_all(&input).expect("unable to write requests");
+
+    let expected = rtu_frame(1, &[0x03, 0x02, 0xAB, 0xCD]);
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+    let give_up = Instant::now() + Duration::from_secs(5);
+    while received.len() < expected.len() {
+        assert!(Instant::now() < give_up, "timed out waiting for response");
+        poll.poll(&mut events, Some(Duration::from_millis(50)))
+            .expect("unable to poll");
+        server.handle_io(Instant::now()).expect("server I/O failed");
+        match master.read(&mut buf) {
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => panic!("unexpected read error: {e}"),
+        }
+    }
+
+    assert_eq!(received, expected);
+    let stats = server.stats();
+    assert_eq!(stats.broadcasts, 1);
+    assert_eq!(stats.bad_frames, 1);
+    assert_eq!(stats.responses, 1);
+}
@@ -0,0 +1,198 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::Decoder;
+use mio_serial::nmea::{
+    Date, NmeaCodec, NmeaError, NmeaReader, RawSentence, SatelliteInfo, Sentence, Time,
+};
+use mio_serial::SerialStream;
+use std::io::Write;
+use std::time::Duration;
+
+fn parse(line: &str) -> Sentence {
+    let raw = RawSentence::parse(line.as_bytes(), true).expect("invalid sentence");
+    Sentence::parse(raw).expect("unable to parse sentence")
+}
+
+fn assert_close(value: Option<f64>, expected: f64) {
+    let value = value.expect("missing value");
+    assert!((value - expected).abs() < 1e-6, "{value} != {expected}");
+}
+
+#[test]
+fn test_parse_sentences() {
+    let gga = match parse("$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47") {
+        Sentence::Gga(gga) => gga,
+        other => panic!("expected GGA, got {other:?}"),
+    };
+    assert_eq!(gga.talker, "GP");
+    assert_eq!(
+        gga.time,
+        Some(Time {
+            hour: 12,
+            minute: 35,
+            second: 19,
+            millisecond: 0
+        })
+    );
+    assert_close(gga.latitude, 48.0 + 7.038 / 60.0);
+    assert_close(gga.longitude, 11.0 + 31.0 / 60.0);
+    assert_eq!(gga.fix_quality, Some(1));
+    assert_eq!(gga.satellites, Some(8));
+    assert_close(gga.altitude, 545.4);
+    assert_eq!(gga.dgps_age, None);
+
+    let rmc = match parse("$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,230394,003.1,W*6A") {
+        Sentence::Rmc(rmc) => rmc,
+        other => panic!("expected RMC, got {other:?}"),
+    };
+    assert!(rmc.valid);
+    assert_close(rmc.speed_knots, 22.4);
+    assert_eq!(
+        rmc.date,
+        Some(Date {
+            year: 1994,
+            month: 3,
+            day: 23
+        })
+    );
+    assert_close(rmc.magnetic_variation, -3.1);
+
+    let vtg = match parse("$GPVTG,054.7,T,034.4,M,005.5,N,010.2,K*48") {
+        Sentence::Vtg(vtg) => vtg,
+        other => panic!("expected VTG, got {other:?}"),
+    };
+    assert_close(vtg.course_true, 54.7);
+    assert_close(vtg.course_magnetic, 34.4);
+    assert_close(vtg.speed_kmh, 10.2);
+
+    let gsa = match parse("$GPGSA,A,3,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*39") {
+        Sentence::Gsa(gsa) => gsa,
+        other => panic!("expected GSA, got {other:?}"),
+    };
+    assert_eq!(gsa.fix_type, Some(3));
+    assert_eq!(gsa.satellites, vec![4, 5, 9, 12, 24]);
+    assert_eq!(gsa.vdop, Some(2.1));
+
+    let gsv = match parse("$GPGSV,2,1,08,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*75") {
+        Sentence::Gsv(gsv) => gsv,
+        other => panic!("expected GSV, got {other:?}"),
+    };
+    assert_eq!((gsv.total_messages, gsv.message_number), (2, 1));
+    assert_eq!(gsv.satellites_in_view, 8);
+    assert_eq!(gsv.satellites.len(), 4);
+    assert_eq!(
+        gsv.satellites[3],
+        SatelliteInfo {
+            prn: 14,
+            elevation: Some(22),
+            azimuth: Some(228),
+            snr: Some(45)
+        }
+    );
+
+    let hdt = match parse("$HEHDT,274.07,T*19") {
+        Sentence::Hdt(hdt) => hdt,
+        other => panic!("expected HDT, got {other:?}"),
+    };
+    assert_eq!(hdt.talker, "HE");
+    assert_close(hdt.heading, 274.07);
+
+    match parse("$PGRME,15.0,M,45.0,M,25.0,M*1C") {
+        Sentence::Unknown(raw) => {
+            assert_eq!(raw.talker(), "P");
+            assert_eq!(raw.sentence_type(), "GRME");
+            assert_eq!(raw.to_string(), "$PGRME,15.0,M,45.0,M,25.0,M*1C");
+        }
+        other => panic!("expected unknown sentence, got {other:?}"),
+    }
+}
+
+#[test]
+fn test_codec_errors() {
+    let mut codec = NmeaCodec::new();
+    let mut src =
+        b"\xff\x00junk$GPGGA,123519*00\r\n$GPGGA,12$HEHDT,274.07,T*19\r\n$HEHDT,1.0,T\n".to_vec();
+
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(NmeaError::BadChecksum { received: 0x00, .. })
+    ));
+    assert!(matches!(
+        codec.decode(&mut src),
+        Err(NmeaError::InvalidSentence(_))
+    ));
+    let hdt = codec.decode(&mut src).unwrap().expect("missing sentence");
+    assert_eq!(hdt.address, "HEHDT");
+    assert_eq!(hdt.fields, vec!["274.07", "T"]);
+
+    // Checksums are optional unless required
+    let no_checksum = src.clone();
+    assert!(codec.decode(&mut src).unwrap().is_some());
+    let mut src = no_checksum;
+    let mut strict = NmeaCodec::new().with_require_checksum(true);
+    assert!(matches!(
+        strict.decode(&mut src),
+        Err(NmeaError::MissingChecksum)
+    ));
+    assert!(src.is_empty());
+}
+
+#[test]
+fn test_non_ascii_fields() {
+    let line = "$GPRMC,123519,A,4807.038,N,01131.000,E,022.4,084.4,1é123,003.1,W*0E";
+    assert!(matches!(
+        RawSentence::parse(line.as_bytes(), true),
+        Err(NmeaError::InvalidSentence(_))
+    ));
+
+    // Sentences built by hand never reach the ASCII check
+    let mut fields = vec![""; 11];
+    fields[8] = "1é123";
+    let raw = RawSentence::new(b'$', "GPRMC", fields);
+    assert!(Sentence::parse(raw).is_err());
+}
+
+#[test]
+fn test_ais_reassembly_over_pair() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut reader = NmeaReader::new(slave);
+    poll.registry()
+        .register(&mut reader, Token(0), Interest::READABLE)
+        .expect("unable to register reader");
+
+    let input = "!AIVDM,2,1,3,B,55P5TL01VIaAL@7WKO@mBplU@<PDhh000000001S;AJ::4A80?4i@E53,0*3E\r\n\
+                 $HEHDT,274.07,T*19\r\n\
+                 !AIVDM,2,2,3,B,1@0000000000000,2*55\r\n\
+                 !AIVDM,1,1,,B,177KQJ5000G?tO`K>RA1wUbN0TKH,0*5C\r\n";
+    master.write_all(input.as_bytes()).unwrap();
+
+    let mut sentences = Vec::new();
+    while sentences.len() < 3 {
+        poll.poll(&mut events, Some(Duration::from_secs(5)))
+            .expect("unable to poll");
+        assert!(!events.is_empty(), "timed out waiting for sentences");
+        while let Some(sentence) = reader.read_sentence().expect("unable to read sentence") {
+            sentences.push(sentence);
+        }
+    }
+
+    assert!(matches!(sentences[0], Sentence::Hdt(_)));
+    let static_data = match &sentences[1] {
+        Sentence::Ais(message) => message,
+        other => panic!("expected AIS message, got {other:?}"),
+    };
+    assert_eq!(static_data.channel, Some('B'));
+    assert_eq!(static_data.bits().unwrap().len(), 424);
+    assert_eq!(static_data.message_type(), Some(5));
+    assert_eq!(static_data.mmsi(), Some(369190000));
+
+    let position = match &sentences[2] {
+        Sentence::Ais(message) => message,
+        other => panic!("expected AIS message, got {other:?}"),
+    };
+    assert_eq!(position.message_type(), Some(1));
+    assert_eq!(position.mmsi(), Some(477553000));
+    assert_eq!(reader.ais_mut().dropped(), 0);
+}
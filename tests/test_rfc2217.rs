@@ -0,0 +1,313 @@
+#![cfg(unix)]
+mod common;
+use mio::net::TcpListener;
+use mio::{Interest, Token};
+use mio_serial::rfc2217::{
+    Client, Command, Server, COM_PORT_OPTION, CONTROL_FLOW_HARDWARE, CONTROL_INBOUND_HARDWARE,
+    CONTROL_INBOUND_REQUEST, DO, IAC, PURGE_BOTH, SB, SE, WILL,
+};
+use mio_serial::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, SerialStream};
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::process::{Child, Command as Process};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A bare Telnet client that sorts what the server sends into data, negotiations and
+/// COM-PORT-OPTION commands
+struct TelnetClient {
+    stream: TcpStream,
+    pending: Vec<u8>,
+    data: Vec<u8>,
+    negotiations: Vec<(u8, u8)>,
+    commands: Vec<Command>,
+}
+
+impl TelnetClient {
+    fn connect(addr: SocketAddr) -> Self {
+        let started = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(addr) {
+                Ok(stream) => break stream,
+                Err(e) if started.elapsed() > Duration::from_secs(5) => {
+                    panic!("unable to connect to server: {e}")
+                }
+                Err(_) => thread::sleep(Duration::from_millis(20)),
+            }
+        };
+        stream
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        Self {
+            stream,
+            pending: Vec::new(),
+            data: Vec::new(),
+            negotiations: Vec::new(),
+            commands: Vec::new(),
+        }
+    }
+
+    fn send(&mut self, command: Command) {
+        let mut frame = Vec::new();
+        command.encode(false, &mut frame);
+        self.stream.write_all(&frame).unwrap();
+    }
+
+    /// Read and sort whatever has arrived; returns false on EOF
+    fn receive(&mut self) -> bool {
+        let mut buf = [0u8; 1024];
+        match self.stream.read(&mut buf) {
+            Ok(0) => return false,
+            Ok(n) => self.pending.extend_from_slice(&buf[..n]),
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) => {}
+            Err(e) => panic!("read failed: {e}"),
+        }
+        loop {
+            match self.pending[..] {
+                [] => break,
+                [IAC, IAC, ..] => {
+                    self.data.push(IAC);
+                    self.pending.drain(..2);
+                }
+                [IAC, SB, ..] => {
+                    let end = match self.pending.windows(2).position(|w| w == [IAC, SE]) {
+                        Some(end) => end,
+                        None => break,
+                    };
+                    assert_eq!(self.pending[2], COM_PORT_OPTION);
+                    let (command, from_server) = Command::decode(&self.pending[3..end]).unwrap();
+                    assert!(from_server);
+                    self.commands.push(command);
+                    self.pending.drain(..end + 2);
+                }
+                [IAC, verb, option, ..] => {
+                    self.negotiations.push((verb, option));
+                    self.pending.drain(..3);
+                }
+                [IAC, ..] => break,
+                [b, ..] => {
+                    self.data.push(b);
+                    self.pending.remove(0);
+                }
+            }
+        }
+        true
+    }
+
+    fn wait_for<T>(&mut self, mut found: impl FnMut(&mut Self) -> Option<T>) -> T {
+        let started = Instant::now();
+        loop {
+            if let Some(value) = found(self) {
+                return value;
+            }
+            assert!(started.elapsed() < Duration::from_secs(5), "timed out");
+            assert!(self.receive(), "server closed the connection");
+        }
+    }
+
+    /// Send `command` and return the server's answer to it
+    fn request(&mut self, command: Command) -> Command {
+        let code = command.code();
+        self.send(command);
+        self.wait_for(|client| {
+            let index = client.commands.iter().position(|c| c.code() == code)?;
+            Some(client.commands.remove(index))
+        })
+    }
+}
+
+/// Negotiate, change the baud rate and pass data both ways through a server on `addr`
+/// whose port is the other end of `master`
+fn check_basics(addr: SocketAddr, master: &mut SerialStream) -> TelnetClient {
+    let mut client = TelnetClient::connect(addr);
+    client
+        .stream
+        .write_all(&[IAC, WILL, COM_PORT_OPTION])
+        .unwrap();
+    client.wait_for(|c| {
+        c.negotiations
+            .contains(&(DO, COM_PORT_OPTION))
+            .then_some(())
+    });
+
+    assert_eq!(
+        client.request(Command::SetBaudRate(115_200)),
+        Command::SetBaudRate(115_200)
+    );
+    assert_eq!(master.baud_rate().unwrap(), 115_200);
+    assert_eq!(
+        client.request(Command::SetBaudRate(0)),
+        Command::SetBaudRate(115_200)
+    );
+
+    // IAC is doubled on the wire and single on the port
+    client.stream.write_all(&[1, IAC, IAC, 2]).unwrap();
+    assert_eq!(common::read_serial(master, 3), [1, IAC, 2]);
+    master.write_all(&[IAC, 3]).unwrap();
+    client.wait_for(|c| (c.data.len() >= 2).then_some(()));
+    assert_eq!(client.data, [IAC, 3]);
+    client.data.clear();
+    client
+}
+
+#[test]
+fn test_server_in_process() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).expect("unable to bind");
+    let mut server = Server::new(listener, slave)
+        .expect("unable to create server")
+        .with_signature("lab bench 3")
+        .with_modem_poll_interval(Duration::from_millis(20));
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    let mut client = check_basics(addr, &mut master);
+
+    // Pty modem lines cannot be read, so they are reported as all off
+    client.wait_for(|c| {
+        c.commands
+            .contains(&Command::NotifyModemState(0))
+            .then_some(())
+    });
+
+    assert_eq!(
+        client.request(Command::Signature(Vec::new())),
+        Command::Signature(b"lab bench 3".to_vec())
+    );
+    assert_eq!(
+        client.request(Command::SetControl(CONTROL_FLOW_HARDWARE)),
+        Command::SetControl(CONTROL_FLOW_HARDWARE)
+    );
+    assert_eq!(master.flow_control().unwrap(), FlowControl::Hardware);
+    assert_eq!(
+        client.request(Command::SetControl(CONTROL_INBOUND_REQUEST)),
+        Command::SetControl(CONTROL_INBOUND_HARDWARE)
+    );
+    // Every setting command gets an answer, whatever the port made of it
+    assert!(matches!(
+        client.request(Command::SetDataSize(7)),
+        Command::SetDataSize(5..=8)
+    ));
+    assert!(matches!(
+        client.request(Command::SetParity(3)),
+        Command::SetParity(1..=3)
+    ));
+    assert_eq!(
+        client.request(Command::PurgeData(PURGE_BOTH)),
+        Command::PurgeData(PURGE_BOTH)
+    );
+
+    // While suspended, data from the port waits
+    client.send(Command::FlowControlSuspend);
+    assert_eq!(
+        client.request(Command::SetModemStateMask(0xF0)),
+        Command::SetModemStateMask(0xF0)
+    );
+    master.write_all(b"held").unwrap();
+    thread::sleep(Duration::from_millis(100));
+    client.receive();
+    assert!(client.data.is_empty());
+    client.send(Command::FlowControlResume);
+    client.wait_for(|c| (c.data.len() >= 4).then_some(()));
+    assert_eq!(client.data, b"held");
+
+    // A second client is turned away while the first is connected
+    let mut second = TelnetClient::connect(addr);
+    let started = Instant::now();
+    while second.receive() {
+        assert!(
+            started.elapsed() < Duration::from_secs(5),
+            "second client kept"
+        );
+    }
+}
+
+/// Settings code that knows nothing about where the port is
+fn configure(port: &mut impl SerialPort) -> mio_serial::Result<()> {
+    port.set_baud_rate(57_600)?;
+    port.set_flow_control(FlowControl::Software)?;
+    port.set_parity(Parity::None)?;
+    port.set_data_bits(DataBits::Eight)?;
+    port.clear(ClearBuffer::All)
+}
+
+#[test]
+fn test_client() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let listener = TcpListener::bind("127.0.0.1:0".parse().unwrap()).expect("unable to bind");
+    let mut server = Server::new(listener, slave).expect("unable to create server");
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+
+    assert!(Client::open(&format!("telnet://{addr}")).is_err());
+    let mut port = Client::open(&format!("rfc2217://{addr}")).expect("unable to open client");
+    assert_eq!(port.baud_rate().unwrap(), master.baud_rate().unwrap());
+    assert!(port.signature().unwrap().starts_with("mio-serial"));
+
+    configure(&mut port).expect("unable to configure remote port");
+    assert_eq!(port.baud_rate().unwrap(), 57_600);
+    assert_eq!(master.baud_rate().unwrap(), 57_600);
+    assert_eq!(port.flow_control().unwrap(), FlowControl::Software);
+    assert_eq!(master.flow_control().unwrap(), FlowControl::Software);
+    assert_eq!(port.parity().unwrap(), Parity::None);
+    assert!(!port.read_carrier_detect().unwrap());
+
+    // Data goes through unchanged and readiness comes from mio
+    let (mut poll, mut events) = common::init_with_poll();
+    poll.registry()
+        .register(&mut port, Token(0), Interest::READABLE)
+        .unwrap();
+    port.write_all(b"AT\xff\r").unwrap();
+    port.flush().unwrap();
+    assert_eq!(port.bytes_to_write().unwrap(), 0);
+    assert_eq!(common::read_serial(&mut master, 4), b"AT\xff\r");
+
+    master.write_all(b"OK\xff\r\n").unwrap();
+    let mut received = Vec::new();
+    let mut buf = [0u8; 64];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while received.len() < 5 {
+        assert!(Instant::now() < deadline, "no data from client");
+        match port.read(&mut buf) {
+            Ok(n) => received.extend_from_slice(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => poll
+                .poll(&mut events, Some(Duration::from_millis(100)))
+                .unwrap(),
+            Err(e) => panic!("client read failed: {e}"),
+        }
+    }
+    assert_eq!(received, b"OK\xff\r\n");
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_server_binary() {
+    // The server opens the slave side by name; the test talks to the master
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let device = slave.name().expect("pty has no name");
+
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|l| l.local_addr())
+        .expect("unable to find a free port");
+    let _server = KillOnDrop(
+        Process::new(env!("CARGO_BIN_EXE_mio-serial-rfc2217"))
+            .args(["--listen", &addr.to_string(), &device])
+            .spawn()
+            .expect("unable to start server"),
+    );
+
+    check_basics(addr, &mut master);
+    drop(slave);
+}
@@ -0,0 +1,91 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::slip::{SlipCodec, SlipError, END, ESC, ESC_END};
+use mio_serial::codec::{Decoder, Encoder, FramedSerial};
+use mio_serial::SerialStream;
+use std::io::Write;
+
+const TOKEN: Token = Token(0);
+
+#[test]
+fn test_slip_round_trip_over_pair() {
+    let (a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut tx = FramedSerial::new(a, SlipCodec::new().with_leading_end(true));
+    let mut rx = FramedSerial::new(b, SlipCodec::new());
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let frames: [&[u8]; 4] = [
+        b"plain",
+        &[END, ESC, END, 0x00, ESC],
+        &[ESC_END, 0xDD, 0xC0],
+        &[0x42; 300],
+    ];
+    for frame in frames {
+        tx.send(frame).expect("unable to send frame");
+    }
+
+    let mut received = Vec::new();
+    while received.len() < frames.len() {
+        common::expect_events(
+            &mut poll,
+            &mut events,
+            vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+        );
+        while let Some(frame) = rx.read_frame().expect("unable to read frame") {
+            received.push(frame);
+        }
+    }
+    assert_eq!(received, frames);
+}
+
+#[test]
+fn test_slip_escape_split_across_reads() {
+    let mut encoded = Vec::new();
+    SlipCodec::new()
+        .encode(&[1u8, END, 2, ESC, 3][..], &mut encoded)
+        .unwrap();
+    assert_eq!(encoded, [1, ESC, ESC_END, 2, ESC, 0xDD, 3, END]);
+
+    // feed the decoder one byte at a time
+    let mut codec = SlipCodec::new();
+    let mut src = Vec::new();
+    let mut frames = Vec::new();
+    for byte in encoded {
+        src.push(byte);
+        if let Some(frame) = codec.decode(&mut src).unwrap() {
+            frames.push(frame);
+        }
+        assert!(src.is_empty());
+    }
+    assert_eq!(frames, vec![vec![1, END, 2, ESC, 3]]);
+}
+
+#[test]
+fn test_slip_protocol_violations() {
+    let (mut a, b) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut rx = FramedSerial::new(b, SlipCodec::new().with_max_frame_length(4));
+    poll.registry()
+        .register(&mut rx, TOKEN, Interest::READABLE)
+        .expect("unable to register receiver");
+
+    a.write_all(&[1, ESC, 0x55, 2, END, 1, 2, 3, 4, 5, 6, END, 9, END])
+        .expect("unable to write");
+    common::expect_events(
+        &mut poll,
+        &mut events,
+        vec![common::ExpectEvent::new(TOKEN, Interest::READABLE)],
+    );
+
+    assert!(matches!(
+        rx.read_frame(),
+        Err(SlipError::InvalidEscape(0x55))
+    ));
+    assert!(matches!(rx.read_frame(), Err(SlipError::FrameTooLong(4))));
+    assert_eq!(rx.read_frame().unwrap().unwrap(), [9]);
+    assert!(rx.read_frame().unwrap().is_none());
+}
@@ -0,0 +1,149 @@
+#![cfg(unix)]
+mod common;
+use mio_serial::capture::{CaptureReader, Event};
+use mio_serial::tee::{format_entry, Direction, SerialTee};
+use mio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn wait_for(what: &str, mut done: impl FnMut() -> bool) {
+    let started = Instant::now();
+    while !done() {
+        assert!(started.elapsed() < Duration::from_secs(5), "no {what}");
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn open_app(path: impl AsRef<Path>) -> SerialStream {
+    let path = path.as_ref().to_str().unwrap();
+    mio_serial::new(path, 9600)
+        .open_native_async()
+        .expect("unable to open the tee")
+}
+
+#[test]
+fn test_relay_and_log() {
+    let entry = format_entry(
+        Duration::from_micros(1_500_002),
+        Direction::FromDevice,
+        b"\r\nModem v1.2\r\n\r\nOK\r\n",
+    );
+    assert_eq!(
+        entry,
+        "       1.500002 < 0d 0a 4d 6f 64 65 6d 20  76 31 2e 32 0d 0a 0d 0a |..Modem v1.2....|\n\
+         \x20      1.500002 <  4f 4b 0d 0a                                     |OK..|\n"
+    );
+
+    let (mut device, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let log = common::SharedBuffer::default();
+    let tee = SerialTee::new(slave)
+        .unwrap()
+        .with_log(log.clone())
+        .with_settings_poll_interval(Duration::from_millis(10));
+    let mut app = open_app(tee.slave_path().expect("tee has no name"));
+    let mut tee = tee;
+    thread::spawn(move || tee.run());
+
+    app.write_all(b"ATI\r").unwrap();
+    assert_eq!(common::read_serial(&mut device, 4), b"ATI\r");
+    device.write_all(b"v1.2\r\nOK\r\n").unwrap();
+    assert_eq!(common::read_serial(&mut app, 10), b"v1.2\r\nOK\r\n");
+
+    // The device follows the application's speed
+    app.set_baud_rate(57600).unwrap();
+    wait_for("baud rate change", || device.baud_rate().unwrap() == 57600);
+    app.write_all(b"AT\r").unwrap();
+    assert_eq!(common::read_serial(&mut device, 3), b"AT\r");
+
+    // The application can go away and come back
+    let path = app.name().unwrap();
+    drop(app);
+    let mut app = open_app(path);
+    device.write_all(b"RING\r\n").unwrap();
+    assert_eq!(common::read_serial(&mut app, 6), b"RING\r\n");
+
+    wait_for("log", || log.text().contains("52 49 4e 47"));
+    let text = log.text();
+    assert!(text.contains(" > 41 54 49 0d "), "{text}");
+    assert!(text.contains("|ATI.|"), "{text}");
+    assert!(
+        text.contains(" < 76 31 2e 32 0d 0a 4f 4b  0d 0a "),
+        "{text}"
+    );
+    assert!(text.contains(" = baud rate 57600\n"), "{text}");
+    let baud = text.find("baud rate 57600").unwrap();
+    assert!(text[baud..].contains(" > 41 54 0d "), "{text}");
+}
+
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        let _ = self.0.kill();
+        let _ = self.0.wait();
+    }
+}
+
+#[test]
+fn test_tee_binary() {
+    let (mut device, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let name = slave.name().expect("pty has no name");
+    let dir = std::env::temp_dir();
+    let link = dir.join(format!("mio-serial-tee-{}", std::process::id()));
+    let output = dir.join(format!("mio-serial-tee-{}.log", std::process::id()));
+    let capture = dir.join(format!("mio-serial-tee-{}.cap", std::process::id()));
+    let _ = std::fs::remove_file(&output);
+
+    let binary = env!("CARGO_BIN_EXE_mio-serial-tee");
+    let status = Command::new(binary).arg("--bogus").status().unwrap();
+    assert_eq!(status.code(), Some(2));
+
+    let mut tee = KillOnDrop(
+        Command::new(binary)
+            .args(["--baud", "19200", "--settings-poll", "10", "--link"])
+            .arg(&link)
+            .arg("--output")
+            .arg(&output)
+            .arg("--capture")
+            .arg(&capture)
+            .arg(&name)
+            .spawn()
+            .unwrap(),
+    );
+    wait_for("link", || link.exists());
+    assert_eq!(device.baud_rate().unwrap(), 19200);
+    let mut app = open_app(&link);
+
+    app.write_all(b"*IDN?\n").unwrap();
+    assert_eq!(common::read_serial(&mut device, 6), b"*IDN?\n");
+    device.write_all(b"ACME,42\n").unwrap();
+    assert_eq!(common::read_serial(&mut app, 8), b"ACME,42\n");
+    app.set_baud_rate(38400).unwrap();
+    wait_for("baud rate change", || device.baud_rate().unwrap() == 38400);
+
+    drop(app);
+    let _ = tee.0.kill();
+    let _ = tee.0.wait();
+    let text = std::fs::read_to_string(&output).unwrap();
+    assert!(text.contains("|*IDN?.|"), "{text}");
+    assert!(text.contains("|ACME,42.|"), "{text}");
+    assert!(text.contains("baud rate 38400"), "{text}");
+    let file = std::fs::File::open(&capture).unwrap();
+    let entries = CaptureReader::new(io::BufReader::new(file))
+        .unwrap()
+        .collect::<io::Result<Vec<_>>>()
+        .unwrap();
+    assert!(entries.iter().any(|entry| entry.event
+        == Event::Data {
+            direction: Direction::ToDevice,
+            data: b"*IDN?\n".to_vec(),
+        }));
+
+    let _ = std::fs::remove_file(link);
+    let _ = std::fs::remove_file(output);
+    let _ = std::fs::remove_file(capture);
+    drop(slave);
+}
@@ -0,0 +1,174 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::codec::Decoder;
+use mio_serial::ubx::{
+    fletcher, Message, Packet, UbxCodec, UbxError, UbxFrame, UbxReceiver, CLASS_CFG,
+};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+fn nav_pvt_payload() -> Vec<u8> {
+    let mut payload = vec![0u8; 92];
+    payload[0..4].copy_from_slice(&123_456u32.to_le_bytes());
+    payload[4..6].copy_from_slice(&2024u16.to_le_bytes());
+    payload[6..12].copy_from_slice(&[5, 17, 9, 30, 15, 0x07]);
+    payload[20..24].copy_from_slice(&[3, 0x01, 0, 11]);
+    payload[24..28].copy_from_slice(&114_616_000i32.to_le_bytes());
+    payload[28..32].copy_from_slice(&(-481_234_567i32).to_le_bytes());
+    payload[60..64].copy_from_slice(&1_250i32.to_le_bytes());
+    payload[76..78].copy_from_slice(&135u16.to_le_bytes());
+    payload
+}
+
+#[test]
+fn test_demux_and_decode() {
+    assert_eq!(
+        fletcher(&[0x05, 0x01, 0x02, 0x00, 0x06, 0x01]),
+        [0x0F, 0x38]
+    );
+
+    let mut version = vec![0u8; 100];
+    version[..8].copy_from_slice(b"ROM CORE");
+    version[30..38].copy_from_slice(b"00080000");
+    version[40..53].copy_from_slice(b"PROTVER=18.00");
+    version[70..78].copy_from_slice(b"GPS;GLO;");
+
+    let mut src = b"\x00\xffnoise".to_vec();
+    UbxFrame::new(0x01, 0x07, nav_pvt_payload()).encode(&mut src);
+    src.extend_from_slice(
+        b"$GNGGA,092725.00,4717.11399,N,00833.91590,E,1,08,1.01,499.6,M,48.0,M,,*45\r\n",
+    );
+    let mut corrupt = Vec::new();
+    UbxFrame::new(0x0A, 0x04, version.clone()).encode(&mut corrupt);
+    corrupt[20] ^= 0xff;
+    src.extend_from_slice(&corrupt);
+    UbxFrame::new(0x0A, 0x04, version).encode(&mut src);
+    src.extend_from_slice(&[0xB5, 0x62, 0x05, 0x00, 0x02, 0x00, 0x06, 0x08, 0x15, 0x3A]);
+
+    let mut codec = UbxCodec::new();
+    let pvt = match codec.decode(&mut src).unwrap() {
+        Some(Packet::Ubx(frame)) => match Message::decode(&frame).unwrap() {
+            Message::NavPvt(pvt) => pvt,
+            other => panic!("expected NAV-PVT, got {other:?}"),
+        },
+        other => panic!("expected UBX frame, got {other:?}"),
+    };
+    assert_eq!(pvt.itow, 123_456);
+    assert_eq!((pvt.year, pvt.month, pvt.day), (2024, 5, 17));
+    assert_eq!((pvt.hour, pvt.min, pvt.sec), (9, 30, 15));
+    assert_eq!(pvt.fix_type, 3);
+    assert!(pvt.fix_ok());
+    assert_eq!(pvt.num_sv, 11);
+    assert!((pvt.longitude() - 11.4616).abs() < 1e-9);
+    assert!((pvt.latitude() + 48.1234567).abs() < 1e-9);
+    assert_eq!(pvt.g_speed, 1_250);
+    assert_eq!(pvt.p_dop, 135);
+
+    match codec.decode(&mut src).unwrap() {
+        Some(Packet::Nmea(sentence)) => assert_eq!(sentence.address, "GNGGA"),
+        other => panic!("expected NMEA sentence, got {other:?}"),
+    }
+
+    assert!(matches!(codec.decode(&mut src), Err(UbxError::BadChecksum)));
+    let version = match codec.decode(&mut src).unwrap() {
+        Some(Packet::Ubx(frame)) => match Message::decode(&frame).unwrap() {
+            Message::MonVer(version) => version,
+            other => panic!("expected MON-VER, got {other:?}"),
+        },
+        other => panic!("expected UBX frame, got {other:?}"),
+    };
+    assert_eq!(version.sw_version, "ROM CORE");
+    assert_eq!(version.hw_version, "00080000");
+    assert_eq!(version.extensions, vec!["PROTVER=18.00", "GPS;GLO;"]);
+
+    match codec.decode(&mut src).unwrap() {
+        Some(Packet::Ubx(frame)) => assert_eq!(
+            Message::decode(&frame).unwrap(),
+            Message::Nak {
+                class: 0x06,
+                id: 0x08
+            }
+        ),
+        other => panic!("expected UBX frame, got {other:?}"),
+    }
+    assert!(codec.decode(&mut src).unwrap().is_none());
+    assert!(src.is_empty());
+}
+
+fn ack(ack_id: u8, class: u8, id: u8) -> Vec<u8> {
+    let mut dst = Vec::new();
+    UbxFrame::new(0x05, ack_id, vec![class, id]).encode(&mut dst);
+    dst
+}
+
+#[test]
+fn test_cfg_ack_tracking() {
+    let (mut master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut receiver = UbxReceiver::new(slave).with_ack_timeout(Duration::from_millis(300));
+    poll.registry()
+        .register(&mut receiver, Token(0), Interest::READABLE)
+        .expect("unable to register receiver");
+
+    let rate = receiver
+        .send_cfg(UbxFrame::new(CLASS_CFG, 0x08, vec![200, 0, 1, 0, 1, 0]))
+        .unwrap();
+    let msg = receiver
+        .send_cfg(UbxFrame::new(CLASS_CFG, 0x01, vec![0x01, 0x07, 1]))
+        .unwrap();
+    let nav5 = receiver
+        .send_cfg(UbxFrame::new(CLASS_CFG, 0x24, vec![0; 36]))
+        .unwrap();
+    assert_eq!(receiver.pending(), 3);
+
+    // Requests are 14, 11 and 44 bytes long on the wire
+    let mut written = [0u8; 14 + 11 + 44];
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut filled = 0;
+    while filled < written.len() {
+        assert!(Instant::now() < deadline, "timed out waiting for requests");
+        match master.read(&mut written[filled..]) {
+            Ok(n) => filled += n,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                std::thread::sleep(Duration::from_millis(10))
+            }
+            Err(e) => panic!("unable to read requests: {e}"),
+        }
+    }
+    assert_eq!(&written[..6], &[0xB5, 0x62, CLASS_CFG, 0x08, 6, 0]);
+
+    // Answer out of order, with a NAV-PVT in between; CFG-NAV5 is never answered
+    let mut reply = ack(0x00, CLASS_CFG, 0x01);
+    UbxFrame::new(0x01, 0x07, vec![0; 92]).encode(&mut reply);
+    reply.extend_from_slice(&ack(0x01, CLASS_CFG, 0x08));
+    master.write_all(&reply).unwrap();
+
+    let mut packets = Vec::new();
+    let mut completions = Vec::new();
+    while completions.len() < 3 {
+        let timeout = receiver
+            .next_deadline()
+            .map(|d| d.saturating_duration_since(Instant::now()));
+        poll.poll(&mut events, timeout).expect("unable to poll");
+        while let Some(packet) = receiver.read_packet().expect("unable to read packet") {
+            packets.push(packet);
+        }
+        receiver.check_timeouts(Instant::now());
+        while let Some(completion) = receiver.poll_completion() {
+            completions.push(completion);
+        }
+    }
+
+    assert_eq!(packets.len(), 1, "ACK frames should be consumed");
+    assert_eq!(completions[0].id, msg);
+    assert!(matches!(completions[0].result, Err(UbxError::Nak)));
+    assert_eq!(completions[1].id, rate);
+    assert!(completions[1].result.is_ok());
+    assert_eq!(completions[2].id, nav5);
+    assert_eq!(completions[2].msg_id, 0x24);
+    assert!(matches!(completions[2].result, Err(UbxError::Timeout)));
+    assert_eq!(receiver.pending(), 0);
+    assert_eq!(receiver.next_deadline(), None);
+}
@@ -0,0 +1,201 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::crc::CRC_16_XMODEM;
+use mio_serial::xmodem::{
+    Checksum, Receiver, Sender, TransferFile, Variant, XmodemError, ACK, CAN, CRC_REQUEST, EOT,
+    NAK, SOH, SUB,
+};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+type Outcome = (
+    Result<(), XmodemError>,
+    Result<Vec<TransferFile>, XmodemError>,
+);
+
+fn transfer(
+    variant: Variant,
+    files: Vec<TransferFile>,
+    checksum: Checksum,
+    progress: Arc<Mutex<Vec<(usize, u64)>>>,
+) -> Outcome {
+    let (master, slave) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut sender = Sender::new(slave, variant, files)
+        .with_timeout(Duration::from_millis(500))
+        .with_progress(move |p| progress.lock().unwrap().push((p.file_index, p.bytes)));
+    let mut receiver = Receiver::new(master, variant)
+        .with_checksum(checksum)
+        .with_timeout(Duration::from_millis(500));
+    let interest = Interest::READABLE | Interest::WRITABLE;
+    poll.registry()
+        .register(&mut sender, Token(0), interest)
+        .unwrap();
+    poll.registry()
+        .register(&mut receiver, Token(1), interest)
+        .unwrap();
+
+    let (mut sent, mut received) = (None, None);
+    common::drive(
+        &mut poll,
+        &mut events,
+        Duration::from_millis(20),
+        Duration::from_secs(10),
+        |now| {
+            sender.handle_io(now).unwrap();
+            receiver.handle_io(now).unwrap();
+            sent = sent.take().or_else(|| sender.poll_result());
+            received = received.take().or_else(|| receiver.poll_result());
+            sent.is_some() && received.is_some()
+        },
+    );
+    assert!(sender.is_finished() && receiver.is_finished());
+    (sent.unwrap(), received.unwrap())
+}
+
+#[test]
+fn test_xmodem_round_trip() {
+    let data = common::pattern(1000, 1);
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let (sent, received) = transfer(
+        Variant::Xmodem,
+        vec![TransferFile::new("", data.clone())],
+        Checksum::Crc16,
+        progress.clone(),
+    );
+    sent.unwrap();
+    let files = received.unwrap();
+    assert_eq!(files.len(), 1);
+    // XMODEM keeps the padding of the last block
+    assert_eq!(files[0].data.len(), 1024);
+    assert_eq!(&files[0].data[..1000], &data[..]);
+    assert!(files[0].data[1000..].iter().all(|&b| b == SUB));
+    let progress = progress.lock().unwrap();
+    assert_eq!(progress.len(), 8);
+    assert_eq!(progress.last(), Some(&(0, 1000)));
+
+    // XMODEM-1K in checksum mode; a short tail goes in a 128-byte block
+    let data = common::pattern(2100, 2);
+    let (sent, received) = transfer(
+        Variant::Xmodem1k,
+        vec![TransferFile::new("", data.clone())],
+        Checksum::Sum,
+        Arc::default(),
+    );
+    sent.unwrap();
+    let files = received.unwrap();
+    assert_eq!(files[0].data.len(), 2048 + 128);
+    assert_eq!(&files[0].data[..2100], &data[..]);
+}
+
+#[test]
+fn test_ymodem_batch() {
+    let files = vec![
+        TransferFile::new("firmware.bin", common::pattern(3000, 3)),
+        TransferFile::new("config.txt", b"baud=115200\n".to_vec()),
+        TransferFile::new("empty", Vec::new()),
+    ];
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let (sent, received) = transfer(
+        Variant::Ymodem,
+        files.clone(),
+        Checksum::Crc16,
+        progress.clone(),
+    );
+    sent.unwrap();
+    assert_eq!(received.unwrap(), files);
+    let progress = progress.lock().unwrap();
+    assert_eq!(progress[..3], [(0, 1024), (0, 2048), (0, 3000)]);
+    assert_eq!(progress[3], (1, 12));
+}
+
+fn block(seq: u8, payload: &[u8; 128]) -> Vec<u8> {
+    let mut block = vec![SOH, seq, !seq];
+    block.extend_from_slice(payload);
+    block.extend_from_slice(&CRC_16_XMODEM.checksum(payload).to_be_bytes());
+    block
+}
+
+/// Feed `input` to the receiver and return what it answers
+fn exchange(
+    receiver: &mut Receiver,
+    remote: &mut SerialStream,
+    input: &[u8],
+    expected: usize,
+) -> Vec<u8> {
+    remote.write_all(input).unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let mut output = Vec::new();
+    while output.len() < expected {
+        assert!(Instant::now() < deadline, "no answer from receiver");
+        std::thread::sleep(Duration::from_millis(10));
+        receiver.handle_io(Instant::now()).unwrap();
+        let mut buf = [0u8; 64];
+        match remote.read(&mut buf) {
+            Ok(n) => output.extend_from_slice(&buf[..n]),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            Err(e) => panic!("read failed: {e}"),
+        }
+    }
+    output
+}
+
+#[test]
+fn test_receiver_errors_and_cancel() {
+    let (mut remote, port) = SerialStream::pair().expect("unable to open pty pair");
+    let mut receiver = Receiver::new(port, Variant::Xmodem);
+    assert!(!receiver.is_finished());
+    assert_eq!(exchange(&mut receiver, &mut remote, &[], 1), [CRC_REQUEST]);
+
+    let mut corrupt = block(1, &[0x11; 128]);
+    corrupt[50] ^= 0x01;
+    assert_eq!(exchange(&mut receiver, &mut remote, &corrupt, 1), [NAK]);
+    assert_eq!(
+        exchange(&mut receiver, &mut remote, &block(1, &[0x11; 128]), 1),
+        [ACK]
+    );
+    // A repeated block, as if our ACK was lost, is acknowledged but not stored twice
+    assert_eq!(
+        exchange(&mut receiver, &mut remote, &block(1, &[0x11; 128]), 1),
+        [ACK]
+    );
+    assert_eq!(
+        exchange(&mut receiver, &mut remote, &block(2, &[0x22; 128]), 1),
+        [ACK]
+    );
+    assert_eq!(exchange(&mut receiver, &mut remote, &[EOT], 1), [ACK]);
+    let files = receiver.poll_result().unwrap().unwrap();
+    assert_eq!(files[0].data.len(), 256);
+    assert_eq!(files[0].data[127..129], [0x11, 0x22]);
+
+    // The sender cancels
+    let mut receiver = Receiver::new(receiver.into_inner(), Variant::Xmodem);
+    exchange(&mut receiver, &mut remote, &[], 1);
+    exchange(&mut receiver, &mut remote, &block(1, &[0; 128]), 1);
+    remote.write_all(&[CAN, CAN]).unwrap();
+    std::thread::sleep(Duration::from_millis(50));
+    receiver.handle_io(Instant::now()).unwrap();
+    assert!(matches!(
+        receiver.poll_result(),
+        Some(Err(XmodemError::Cancelled))
+    ));
+
+    // A sender without a receiver gives up after its retries
+    let (_idle, port) = SerialStream::pair().expect("unable to open pty pair");
+    let mut sender = Sender::new(port, Variant::Xmodem, Vec::new())
+        .with_timeout(Duration::from_millis(10))
+        .with_retries(2);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !sender.is_finished() {
+        assert!(Instant::now() < deadline, "sender did not give up");
+        std::thread::sleep(Duration::from_millis(5));
+        sender.handle_io(Instant::now()).unwrap();
+    }
+    assert!(matches!(
+        sender.poll_result(),
+        Some(Err(XmodemError::TooManyRetries))
+    ));
+}
@@ -0,0 +1,255 @@
+#![cfg(unix)]
+mod common;
+use mio::{Interest, Token};
+use mio_serial::zmodem::{
+    AutoStart, AutoStartDetector, Encoding, FrameType, Header, Receiver, Sender, TransferFile,
+    ZmodemError, ZDLE,
+};
+use mio_serial::SerialStream;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Copies bytes between two ptys, optionally flipping a bit in one of them
+#[derive(Default)]
+struct Relay {
+    pending: Vec<u8>,
+    count: usize,
+    corrupt_at: Option<usize>,
+}
+
+impl Relay {
+    fn pump(&mut self, from: &mut SerialStream, to: &mut SerialStream) {
+        let mut buf = [0u8; 4096];
+        loop {
+            match from.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => {
+                    if let Some(at) = self.corrupt_at {
+                        if (self.count..self.count + n).contains(&at) {
+                            buf[at - self.count] ^= 0x04;
+                        }
+                    }
+                    self.count += n;
+                    self.pending.extend_from_slice(&buf[..n]);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("relay read failed: {e}"),
+            }
+        }
+        while !self.pending.is_empty() {
+            match to.write(&self.pending) {
+                Ok(n) => drop(self.pending.drain(..n)),
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => panic!("relay write failed: {e}"),
+            }
+        }
+    }
+}
+
+/// Connect a sender and a receiver through relays and run them until `stop` or the end
+fn transfer(
+    sender: impl FnOnce(SerialStream) -> Sender,
+    receiver: impl FnOnce(SerialStream) -> Receiver,
+    corrupt_at: Option<usize>,
+    mut stop: impl FnMut(&mut Sender, &mut Receiver) -> bool,
+) -> (
+    Result<(), ZmodemError>,
+    Result<Vec<TransferFile>, ZmodemError>,
+    Receiver,
+) {
+    let (mut sender_master, sender_port) = SerialStream::pair().expect("unable to open pty pair");
+    let (mut receiver_master, receiver_port) =
+        SerialStream::pair().expect("unable to open pty pair");
+    let mut forward = Relay {
+        corrupt_at,
+        ..Relay::default()
+    };
+    let mut backward = Relay::default();
+
+    let (mut poll, mut events) = common::init_with_poll();
+    let mut sender = sender(sender_port);
+    let mut receiver = receiver(receiver_port);
+    let interest = Interest::READABLE | Interest::WRITABLE;
+    poll.registry()
+        .register(&mut sender, Token(0), interest)
+        .unwrap();
+    poll.registry()
+        .register(&mut receiver, Token(1), interest)
+        .unwrap();
+
+    let (mut sent, mut received) = (None, None);
+    common::drive(
+        &mut poll,
+        &mut events,
+        Duration::from_millis(5),
+        Duration::from_secs(20),
+        |now| {
+            sender.handle_io(now).unwrap();
+            receiver.handle_io(now).unwrap();
+            forward.pump(&mut sender_master, &mut receiver_master);
+            backward.pump(&mut receiver_master, &mut sender_master);
+            if stop(&mut sender, &mut receiver) {
+                receiver.cancel().unwrap();
+            }
+            sent = sent.take().or_else(|| sender.poll_result());
+            received = received.take().or_else(|| receiver.poll_result());
+            sent.is_some() && received.is_some()
+        },
+    );
+    (sent.unwrap(), received.unwrap(), receiver)
+}
+
+#[test]
+fn test_batch_transfer() {
+    let files = vec![
+        TransferFile::new("capture.log", common::pattern(150_000, 1)),
+        TransferFile::new("config.txt", b"baud=115200\n".to_vec()),
+        TransferFile::new("empty", Vec::new()),
+    ];
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorded = progress.clone();
+    let (sent, received, _) = transfer(
+        |port| {
+            Sender::new(port, files.clone())
+                .with_progress(move |p| recorded.lock().unwrap().push((p.file_index, p.bytes)))
+        },
+        Receiver::new,
+        None,
+        |_, _| false,
+    );
+    sent.unwrap();
+    assert!(received.unwrap() == files, "files corrupted in transit");
+    let progress = progress.lock().unwrap();
+    assert_eq!(progress.first(), Some(&(0, 1024)));
+    assert!(progress.contains(&(0, 150_000)));
+    assert_eq!(progress.last(), Some(&(1, 12)));
+
+    // CRC-16 and a receive buffer: the sender waits for ZACK after every segment, and a
+    // corrupted byte is recovered from with ZRPOS
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorded = progress.clone();
+    let (sent, received, _) = transfer(
+        |port| {
+            Sender::new(port, files[..1].to_vec())
+                .with_subpacket_size(512)
+                .with_progress(move |p| recorded.lock().unwrap().push(p.bytes))
+        },
+        |port| {
+            Receiver::new(port)
+                .with_crc32(false)
+                .with_buffer_size(4096)
+                .with_timeout(Duration::from_millis(500))
+        },
+        Some(30_000),
+        |_, _| false,
+    );
+    sent.unwrap();
+    assert!(
+        received.unwrap()[..] == files[..1],
+        "file corrupted in transit"
+    );
+    let progress = progress.lock().unwrap();
+    assert!(
+        progress.windows(2).any(|w| w[1] < w[0]),
+        "the sender never went back"
+    );
+}
+
+#[test]
+fn test_resume_interrupted_transfer() {
+    let file = TransferFile::new("gnss.ubx", common::pattern(200_000, 7));
+    let (sent, received, receiver) = transfer(
+        |port| Sender::new(port, vec![file.clone()]),
+        Receiver::new,
+        None,
+        |_, receiver| {
+            !receiver.is_finished()
+                && receiver
+                    .partial_file()
+                    .is_some_and(|f| f.data.len() >= 60_000)
+        },
+    );
+    assert!(matches!(sent, Err(ZmodemError::Cancelled)));
+    assert!(matches!(received, Err(ZmodemError::Aborted)));
+    let partial = receiver.partial_file().unwrap().clone();
+    assert!(partial.data.len() >= 60_000 && partial.data.len() < 200_000);
+    assert_eq!(partial.data[..], file.data[..partial.data.len()]);
+
+    let progress = Arc::new(Mutex::new(Vec::new()));
+    let recorded = progress.clone();
+    let resume_at = partial.data.len() as u64;
+    let (sent, received, _) = transfer(
+        |port| {
+            Sender::new(port, vec![file.clone()])
+                .with_resume(true)
+                .with_progress(move |p| recorded.lock().unwrap().push(p.bytes))
+        },
+        |port| Receiver::new(port).with_partial_file(partial),
+        None,
+        |_, _| false,
+    );
+    sent.unwrap();
+    assert_eq!(
+        received.unwrap(),
+        vec![file.clone()],
+        "resumed file corrupted"
+    );
+    let progress = progress.lock().unwrap();
+    assert!(progress[0] > resume_at && progress[0] <= resume_at + 1024);
+
+    // Without ZCRESUM a stale partial file is replaced, not extended
+    let stale = TransferFile::new("gnss.ubx", vec![0xAA; 50_000]);
+    let (sent, received, _) = transfer(
+        |port| Sender::new(port, vec![file.clone()]),
+        |port| Receiver::new(port).with_partial_file(stale),
+        None,
+        |_, _| false,
+    );
+    sent.unwrap();
+    assert!(received.unwrap() == [file], "partial file was resumed");
+}
+
+#[test]
+fn test_headers_and_autostart() {
+    let mut dst = Vec::new();
+    Header::new(FrameType::RqInit, [0; 4]).encode(Encoding::Hex, &mut dst);
+    assert_eq!(dst, b"**\x18B00000000000000\r\x8a\x11");
+
+    // Binary headers escape ZDLE and the flow control characters
+    let rpos = Header::with_position(FrameType::RPos, 0x1311_1800);
+    assert_eq!(rpos.position(), 0x1311_1800);
+    for encoding in [Encoding::Bin16, Encoding::Bin32] {
+        let mut src = b"noise*".to_vec();
+        rpos.encode(encoding, &mut src);
+        assert!(!src[4..].contains(&0x11) && !src[4..].contains(&0x13));
+        assert_eq!(Header::decode(&mut src).unwrap(), Some((rpos, encoding)));
+        assert!(src.is_empty());
+    }
+    let mut src = Vec::new();
+    rpos.encode(Encoding::Hex, &mut src);
+    src[8] ^= 0x01;
+    rpos.encode(Encoding::Hex, &mut src);
+    assert!(matches!(Header::decode(&mut src), Err(ZmodemError::BadCrc)));
+    assert_eq!(
+        Header::decode(&mut src).unwrap(),
+        Some((rpos, Encoding::Hex))
+    );
+    let mut partial = vec![b'*', b'*', ZDLE, b'B', b'0'];
+    assert!(Header::decode(&mut partial).unwrap().is_none());
+    assert_eq!(partial.len(), 5);
+
+    // `sz` on the remote shell, with its ZRQINIT split across reads
+    let mut detector = AutoStartDetector::new();
+    assert_eq!(detector.feed(b"$ sz capture.log\r\n"), None);
+    assert_eq!(detector.feed(b"rz\r**\x18"), None);
+    assert_eq!(
+        detector.feed(b"B00000000000000\r\x8a\x11"),
+        Some((AutoStart::Receive, 0))
+    );
+    let mut detector = AutoStartDetector::new();
+    assert_eq!(
+        detector.feed(b"$ rz\r\n**\x18B0100000063f694\r\x8a\x11"),
+        Some((AutoStart::Send, 6))
+    );
+}